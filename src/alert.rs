@@ -0,0 +1,134 @@
+//! Alerts delivered and not acknowledged yet, by ID, so one can be acknowledged or muted without
+//! naming its trigger.
+
+use super::event::Event;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: u64,
+    pub trigger: String,
+    pub message: String,
+    pub time: String,
+    /// Seconds since it was raised
+    pub age: u64,
+}
+
+struct Active {
+    id: u64,
+    trigger: String,
+    message: String,
+    time: String,
+    at: Instant,
+}
+
+impl Active {
+    fn alert(&self, now: Instant) -> Alert {
+        Alert {
+            id: self.id,
+            trigger: self.trigger.clone(),
+            message: self.message.clone(),
+            time: self.time.clone(),
+            age: now.saturating_duration_since(self.at).as_secs(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    next: u64,
+    active: VecDeque<Active>,
+}
+
+/// Active alerts, the oldest forgotten past [`Alerts::KEEP`].
+#[derive(Default)]
+pub struct Alerts {
+    inner: Mutex<Inner>,
+}
+
+impl Alerts {
+    const KEEP: usize = 100;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers an event delivered for a named trigger, returns its ID.
+    pub fn raise(&self, event: &Event, now: Instant) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next += 1;
+        let id = inner.next;
+        if inner.active.len() >= Self::KEEP {
+            inner.active.pop_front();
+        }
+        inner.active.push_back(Active {
+            id,
+            trigger: event.trigger.clone(),
+            message: event.message.clone(),
+            time: event.time.clone(),
+            at: now,
+        });
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Alert> {
+        let inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        inner
+            .active
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| a.alert(now))
+    }
+
+    /// The alert raised last.
+    pub fn latest(&self) -> Option<Alert> {
+        let inner = self.inner.lock().unwrap();
+        inner.active.back().map(|a| a.alert(Instant::now()))
+    }
+
+    /// Forgets the alerts of a trigger once acknowledged, returns how many there were.
+    pub fn clear(&self, trigger: &str) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.active.len();
+        inner.active.retain(|a| a.trigger != trigger);
+        before - inner.active.len()
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<Alert> {
+        let inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        inner.active.iter().rev().map(|a| a.alert(now)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts() {
+        let alerts = Alerts::new();
+        let now = Instant::now();
+        let mut event = Event::plain("挑战赛通道 即将刷新");
+        event.trigger = "maze".to_owned();
+        let first = alerts.raise(&event, now);
+        event.trigger = "boss".to_owned();
+        let second = alerts.raise(&event, now);
+        assert_eq!(alerts.get(first).unwrap().trigger, "maze");
+        assert_eq!(alerts.latest().unwrap().id, second);
+        assert_eq!(alerts.list()[0].id, second);
+        assert_eq!(alerts.clear("boss"), 1);
+        assert_eq!(alerts.latest().unwrap().id, first);
+        assert!(alerts.get(second).is_none());
+        for _ in 0..Alerts::KEEP {
+            alerts.raise(&event, now);
+        }
+        assert!(alerts.get(first).is_none());
+        assert_eq!(alerts.list().len(), Alerts::KEEP);
+    }
+}
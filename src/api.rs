@@ -0,0 +1,785 @@
+use super::bus::{Bus, Signal};
+use super::dispatcher::Dispatcher;
+use super::group::Groups;
+use super::mute::Mutes;
+use super::price::Prices;
+use super::profile::Profile;
+use super::reload::Reload;
+use super::state::TriggerState;
+use super::stats::Stats;
+use super::subscription::{Subscription, Subscriptions};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// By lowercase name
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Largest body read, the API only takes small JSON ones.
+    const MAX_BODY: usize = 64 * 1024;
+
+    /// Reads a HTTP/1.x request, only `Content-Length` bodies up to 64 KiB are supported.
+    pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |m: &str| io::Error::new(io::ErrorKind::InvalidData, m.to_owned());
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().ok_or_else(|| invalid("No method"))?.to_owned();
+        let target = parts.next().ok_or_else(|| invalid("No target"))?;
+        let url = Url::parse(&format!("http://localhost{target}"))
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((k, v)) = line.split_once(':') {
+                headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_owned());
+            }
+        }
+        let length = match headers.get("content-length") {
+            Some(v) => v.parse().map_err(|_| invalid("Bad length"))?,
+            None => 0,
+        };
+        if length > Request::MAX_BODY {
+            return Err(invalid("Body too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        Ok(Self {
+            method,
+            path: percent_decode_str(url.path())
+                .decode_utf8_lossy()
+                .into_owned(),
+            query: url.query_pairs().into_owned().collect(),
+            headers,
+            body,
+        })
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(value).unwrap_or_default(),
+        }
+    }
+    pub fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.to_owned(),
+        }
+    }
+    pub fn html(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
+
+/// Head of the dashboard, refreshing itself and posting the path of a clicked button.
+const DASHBOARD: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"><meta http-equiv="refresh" content="10"><title>cgaid</title>
+<style>body{font-family:sans-serif}td,th{padding:2px 8px;text-align:left}</style>
+<script>
+document.addEventListener("click", e => {
+  const path = e.target.dataset.path;
+  if (!path) return;
+  const url = "/" + path.split("/").map(encodeURIComponent).join("/") + location.search;
+  fetch(url, { method: "POST" }).then(() => location.reload());
+});
+</script></head><body>"#;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Acknowledges the pending alerts of a trigger, from the API or a button under a notification.
+pub fn ack(stats: &Stats, bus: &Bus, trigger: &str) -> bool {
+    let Some(delay) = stats.ack(trigger, std::time::Instant::now()) else {
+        return false;
+    };
+    log::info!("Trigger acknowledged: {trigger} after {}s", delay.as_secs());
+    bus.publish(&Signal::Acked { trigger, delay });
+    true
+}
+
+/// Local control API.
+pub struct Api {
+    subscriptions: Arc<Subscriptions>,
+    stats: Arc<Stats>,
+    groups: Arc<Groups>,
+    dispatcher: Dispatcher,
+    mutes: Arc<Mutes>,
+    reload: Reload,
+    bus: Arc<Bus>,
+    state: Option<Arc<TriggerState>>,
+    prices: Option<Arc<Prices>>,
+    profile: Option<Arc<Profile>>,
+    /// Required of every request when set
+    token: Option<String>,
+}
+
+impl Api {
+    const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(
+        subscriptions: Arc<Subscriptions>,
+        stats: Arc<Stats>,
+        groups: Arc<Groups>,
+        dispatcher: Dispatcher,
+        mutes: Arc<Mutes>,
+        reload: Reload,
+        bus: Arc<Bus>,
+    ) -> Self {
+        Self {
+            subscriptions,
+            stats,
+            groups,
+            dispatcher,
+            mutes,
+            reload,
+            bus,
+            state: None,
+            prices: None,
+            profile: None,
+            token: None,
+        }
+    }
+
+    /// Requires the token as `Authorization: Bearer {token}` or `?token={token}`, empty for none.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned()).filter(|t| !t.is_empty());
+        self
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let bearer = request
+            .headers
+            .get("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "));
+        bearer.or(request.query.get("token").map(|t| t.as_str())) == Some(token.as_str())
+    }
+
+    /// Whether a request changing state comes from the API's own pages or a tool. Browsers mark
+    /// requests of other sites with `Sec-Fetch-Site` or an `Origin` of another host, so a page
+    /// elsewhere cannot drive the API even without a token.
+    fn same_origin(request: &Request) -> bool {
+        if let Some(site) = request.headers.get("sec-fetch-site") {
+            return matches!(site.as_str(), "same-origin" | "none");
+        }
+        let Some(origin) = request.headers.get("origin") else {
+            return true;
+        };
+        let host = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"));
+        host.is_some() && host == request.headers.get("host").map(|h| h.as_str())
+    }
+
+    /// Lets one-shot triggers be reset.
+    pub fn with_state(mut self, state: Arc<TriggerState>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Serves the prices of the items sold in the auction log as `/prices`.
+    pub fn with_prices(mut self, prices: Arc<Prices>) -> Self {
+        self.prices = Some(prices);
+        self
+    }
+
+    /// Serves the self-instrumentation as `/status` and `/metrics`.
+    pub fn with_profile(mut self, profile: Arc<Profile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Acknowledges the pending alerts of a trigger, muting or snoozing it counts as well.
+    fn ack(&self, trigger: &str) -> bool {
+        ack(&self.stats, &self.bus, trigger)
+    }
+
+    /// A dashboard for a browser, switching groups and lifting mutes through the API. The
+    /// token of the page address goes along with the requests.
+    fn dashboard(&self) -> String {
+        let mut html = format!(
+            "{DASHBOARD}<h2>统计</h2><pre>{}</pre><h2>分组</h2><table>",
+            escape(&self.stats.summary())
+        );
+        for g in self.groups.list() {
+            let (state, action, label) = if g.enabled {
+                ("启用", "disable", "停用")
+            } else {
+                ("停用", "enable", "启用")
+            };
+            html.push_str(&format!(
+                r#"<tr><td>{0}</td><td>{state}</td><td><button data-path="groups/{0}/{action}">{label}</button></td></tr>"#,
+                escape(&g.name)
+            ));
+        }
+        html.push_str("</table><h2>静音</h2><table>");
+        for m in self.mutes.list() {
+            let remaining = m
+                .remaining
+                .map_or("一直".to_owned(), |s| format!("剩余 {s} 秒"));
+            html.push_str(&format!(
+                r#"<tr><td>{0}</td><td>{remaining}</td><td><button data-path="triggers/{0}/unmute">解除</button></td></tr>"#,
+                escape(&m.trigger)
+            ));
+        }
+        html.push_str("</table><h2>队列</h2><table><tr><th>通知</th><th>等待</th><th>发送中</th><th>丢弃</th><th>超时</th></tr>");
+        for q in self.dispatcher.queues() {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&q.notifier),
+                q.pending,
+                q.running,
+                q.dropped,
+                q.timeouts
+            ));
+        }
+        html.push_str("</table></body></html>");
+        html
+    }
+
+    pub fn start(self, listen: &str) -> io::Result<()> {
+        let listener = TcpListener::bind(listen)?;
+        log::info!("Api listening on {}", listener.local_addr()?);
+        let api = Arc::new(self);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(s) => {
+                        let ac = Arc::clone(&api);
+                        thread::spawn(move || {
+                            if let Err(e) = ac.serve(s) {
+                                log::debug!("Api connection error: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => log::error!("Api accept error: {e}"),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        // a client sending nothing must not hold its thread for ever
+        stream.set_read_timeout(Some(Api::READ_TIMEOUT))?;
+        let request = match Request::read(&mut BufReader::new(&stream)) {
+            Ok(r) => r,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                return Response::text(400, &e.to_string()).write(&mut stream);
+            }
+            Err(e) => return Err(e),
+        };
+        log::debug!("Api request: {} {}", request.method, request.path);
+        self.handle(&request).write(&mut stream)
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        if !self.authorized(request) {
+            return Response::text(401, "Unauthorized");
+        }
+        if request.method != "GET" && !Api::same_origin(request) {
+            return Response::text(403, "Forbidden");
+        }
+        let segments: Vec<_> = request.path.split('/').filter(|v| !v.is_empty()).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", []) => Response::html(200, self.dashboard()),
+            ("GET", ["healthz"]) => {
+                let health = self.stats.health();
+                Response::json(if health.alive { 200 } else { 503 }, &health)
+            }
+            ("GET", ["subscriptions"]) => Response::json(200, &self.subscriptions.list()),
+            ("POST", ["subscriptions"]) => {
+                let parsed = if request.body.is_empty() {
+                    Subscription::from_query(&request.query)
+                } else {
+                    serde_json::from_slice::<Subscription>(&request.body).map_err(|e| e.to_string())
+                };
+                let cfg = self.dispatcher.cfg();
+                let unknown = |s: &Subscription| {
+                    s.notifier
+                        .iter()
+                        .find(|n| !cfg.notifier.defines(n))
+                        .cloned()
+                };
+                match parsed {
+                    Ok(s) if s.keyword.is_empty() => Response::text(400, "Empty keyword"),
+                    Ok(s) => match unknown(&s) {
+                        Some(n) => Response::text(400, &format!("Unknown notifier {n}")),
+                        None => Response::json(201, &self.subscriptions.add(s)),
+                    },
+                    Err(e) => Response::text(400, &e),
+                }
+            }
+            ("DELETE", ["subscriptions", id]) => match id.parse() {
+                Ok(id) if self.subscriptions.remove(id) => Response::text(200, "OK"),
+                _ => Response::text(404, "Not found"),
+            },
+            (_, ["subscriptions", ..]) => Response::text(405, "Method not allowed"),
+            ("GET", ["groups"]) => Response::json(200, &self.groups.list()),
+            ("POST", ["groups", name, action @ ("enable" | "disable")]) => {
+                if self.groups.set_enabled(name, *action == "enable") {
+                    Response::text(200, "OK")
+                } else {
+                    Response::text(404, "Not found")
+                }
+            }
+            (_, ["groups", ..]) => Response::text(405, "Method not allowed"),
+            ("GET", ["queues"]) => Response::json(200, &self.dispatcher.queues()),
+            ("GET", [page @ ("status" | "metrics")]) => match &self.profile {
+                Some(p) => {
+                    let report = p.report(self.dispatcher.queues());
+                    if *page == "status" {
+                        Response::json(200, &report)
+                    } else {
+                        Response::text(200, &report.prometheus())
+                    }
+                }
+                None => Response::text(404, "Not found"),
+            },
+            ("GET", ["prices"]) => match &self.prices {
+                Some(p) => Response::json(200, &p.list()),
+                None => Response::text(404, "Not found"),
+            },
+            ("GET", ["prices", item]) => match self.prices.as_ref().and_then(|p| p.get(item)) {
+                Some(p) => Response::json(200, &p),
+                None => Response::text(404, "Not found"),
+            },
+            ("GET", ["mutes"]) => Response::json(200, &self.mutes.list()),
+            ("GET", ["alerts"]) => Response::json(200, &self.dispatcher.alerts().list()),
+            ("POST", ["alerts", id, "ack"]) => {
+                let alerts = self.dispatcher.alerts();
+                let alert = match *id {
+                    "latest" => alerts.latest(),
+                    id => id.parse().ok().and_then(|id| alerts.get(id)),
+                };
+                let Some(alert) = alert else {
+                    return Response::text(404, "Not found");
+                };
+                if let Some(m) = request
+                    .query
+                    .get("minutes")
+                    .and_then(|v| v.parse::<u64>().ok())
+                {
+                    self.mutes.mute(
+                        &alert.trigger,
+                        Some(Duration::from_secs(m.saturating_mul(60))),
+                    );
+                }
+                self.ack(&alert.trigger);
+                Response::json(200, &alert)
+            }
+            (_, ["alerts", ..]) => Response::text(405, "Method not allowed"),
+            ("POST", ["triggers", name, "mute"]) => {
+                let minutes = request.query.get("minutes").and_then(|v| v.parse().ok());
+                self.mutes.mute(
+                    name,
+                    minutes.map(|m: u64| Duration::from_secs(m.saturating_mul(60))),
+                );
+                self.ack(name);
+                Response::text(200, "OK")
+            }
+            ("POST", ["triggers", name, "ack"]) => {
+                if self.ack(name) {
+                    Response::text(200, "OK")
+                } else {
+                    Response::text(404, "Nothing to acknowledge")
+                }
+            }
+            ("POST", ["triggers", name, "reset"]) => {
+                if self.state.as_ref().is_some_and(|s| s.reset(name)) {
+                    Response::text(200, "OK")
+                } else {
+                    Response::text(404, "Not found")
+                }
+            }
+            ("POST", ["triggers", name, "unmute"]) => {
+                if self.mutes.unmute(name) {
+                    Response::text(200, "OK")
+                } else {
+                    Response::text(404, "Not found")
+                }
+            }
+            (_, ["triggers", ..]) => Response::text(405, "Method not allowed"),
+            ("POST", ["power", "cancel"]) => match crate::notifier::power::cancel() {
+                Some(left) => Response::text(200, &format!("Cancelled, {left}s left")),
+                None => Response::text(404, "Nothing pending"),
+            },
+            ("GET", ["reload", "preview"]) => match self.reload.preview() {
+                Ok(changes) => Response::json(200, &changes),
+                Err(e) => Response::text(400, &e.to_string()),
+            },
+            _ => Response::text(404, "Not found"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn api(stats: Arc<Stats>, groups: HashMap<String, crate::config::Group>) -> Api {
+        let cfg = Arc::new(crate::config::Config::load("config.toml").unwrap());
+        let bus = Arc::new(Bus::new());
+        bus.subscribe(Arc::clone(&stats) as _);
+        let recent = Arc::new(crate::reload::Recent::new(Duration::from_secs(60)));
+        recent.push(
+            &["12:00:01丂[世界]盛明兰oO: 收玄铁".to_owned()],
+            std::time::Instant::now(),
+        );
+        Api::new(
+            Arc::new(Subscriptions::new()),
+            Arc::clone(&stats),
+            Arc::new(Groups::new(&groups).unwrap()),
+            Dispatcher::new(Arc::clone(&cfg), None, &bus),
+            Arc::new(Mutes::new()),
+            Reload::new(
+                Arc::new(crate::reload::Current::new(cfg)),
+                "config.toml".into(),
+                recent,
+            ),
+            bus,
+        )
+    }
+
+    fn request(text: &str) -> Request {
+        Request::read(&mut Cursor::new(text.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_request_read() {
+        let body = r#"{"keyword":"玄铁"}"#;
+        let req = request(&format!(
+            "POST /subscriptions?a=%E7%8E%84 HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ));
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, "/subscriptions");
+        assert_eq!(req.query["a"], "玄");
+        assert_eq!(req.headers["host"], "localhost");
+        assert_eq!(req.body, body.as_bytes());
+
+        let large = format!(
+            "POST /subscriptions HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            Request::MAX_BODY + 1
+        );
+        let e = Request::read(&mut Cursor::new(large.into_bytes()))
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_api_token() {
+        let api = api(Arc::new(Stats::new()), HashMap::new()).with_token("s3cret");
+        let res = api.handle(&request("GET /queues HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 401);
+        let res = api.handle(&request(
+            "GET /queues HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n",
+        ));
+        assert_eq!(res.status, 401);
+        let res = api.handle(&request(
+            "GET /queues HTTP/1.1\r\nauthorization: Bearer s3cret\r\n\r\n",
+        ));
+        assert_eq!(res.status, 200);
+        let res = api.handle(&request("GET /queues?token=s3cret HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+    }
+
+    #[test]
+    fn test_api_subscriptions() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let body = r#"{"keyword":"玄铁","minutes":120,"notifier":["ringtone"]}"#;
+        let res = api.handle(&request(&format!(
+            "POST /subscriptions HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )));
+        assert_eq!(res.status, 201);
+
+        let res = api.handle(&request(
+            "POST /subscriptions?keyword=%E9%AD%94%E7%9F%B3&minutes=30&notifier=console,ringtone HTTP/1.1\r\n\r\n",
+        ));
+        assert_eq!(res.status, 201);
+
+        let res = api.handle(&request("GET /subscriptions HTTP/1.1\r\n\r\n"));
+        let list: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(list[0]["keyword"], "玄铁");
+        assert_eq!(list[0]["id"], 1);
+        assert_eq!(list[1]["keyword"], "魔石");
+        assert_eq!(list[1]["notifier"][1], "ringtone");
+
+        let res = api.handle(&request("DELETE /subscriptions/1 HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        let res = api.handle(&request("DELETE /subscriptions/1 HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+
+        let res = api.handle(&request(
+            "POST /subscriptions?keyword=a&minutes=30&notifier=console,nowhere HTTP/1.1\r\n\r\n",
+        ));
+        assert_eq!(res.status, 400);
+        assert_eq!(res.body, "Unknown notifier nowhere");
+        let res = api.handle(&request(&format!(
+            "POST /subscriptions?keyword=a&minutes={}&notifier=console HTTP/1.1\r\n\r\n",
+            u64::MAX
+        )));
+        assert_eq!(res.status, 201);
+    }
+
+    #[test]
+    fn test_api_cross_site() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let post = |headers: &str| {
+            api.handle(&request(&format!(
+                "POST /triggers/boss/mute HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n{headers}\r\n"
+            )))
+            .status
+        };
+        assert_eq!(post("Origin: https://evil.example\r\n"), 403);
+        assert_eq!(post("Origin: null\r\n"), 403);
+        assert_eq!(post("Sec-Fetch-Site: cross-site\r\n"), 403);
+        assert_eq!(post("Sec-Fetch-Site: same-site\r\n"), 403);
+        assert_eq!(post("Origin: http://127.0.0.1:8080\r\n"), 200);
+        assert_eq!(post("Sec-Fetch-Site: same-origin\r\n"), 200);
+        let res = api.handle(&request(&format!(
+            "POST /triggers/boss/mute?minutes={} HTTP/1.1\r\n\r\n",
+            u64::MAX
+        )));
+        assert_eq!(res.status, 200);
+        // tools send neither
+        assert_eq!(post(""), 200);
+        let res = api.handle(&request(
+            "GET /queues HTTP/1.1\r\nSec-Fetch-Site: cross-site\r\n\r\n",
+        ));
+        assert_eq!(res.status, 200);
+    }
+
+    #[test]
+    fn test_api_healthz() {
+        let stats = Arc::new(Stats::new());
+        let api = api(Arc::clone(&stats), HashMap::new());
+        stats.set_last_line("abc");
+        let res = api.handle(&request("GET /healthz HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        let health: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(health["alive"], true);
+        assert_eq!(health["last_line"]["ago"], 0);
+        assert!(!res.body.contains("abc"));
+        assert!(health["last_sent"].is_null());
+    }
+
+    #[test]
+    fn test_api_groups() {
+        let groups = HashMap::from([("boss".to_owned(), crate::config::Group::default())]);
+        let api = api(Arc::new(Stats::new()), groups);
+        let res = api.handle(&request("POST /groups/boss/disable HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        let res = api.handle(&request("GET /groups HTTP/1.1\r\n\r\n"));
+        let list: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(list[0]["name"], "boss");
+        assert_eq!(list[0]["enabled"], false);
+        let res = api.handle(&request("POST /groups/other/enable HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let res = api.handle(&request("GET / HTTP/1.1\r\n\r\n"));
+        assert!(res.content_type.starts_with("text/html"));
+        assert!(res.body.contains("<td>boss</td><td>停用</td>"));
+        assert!(res.body.contains(r#"data-path="groups/boss/enable""#));
+    }
+
+    #[test]
+    fn test_api_prices() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let res = api.handle(&request("GET /prices HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let prices = Arc::new(Prices::new());
+        prices.sold("玄铁", 300, 1000);
+        prices.sold("玄铁", 500, 2000);
+        let api = api.with_prices(prices);
+        let res = api.handle(&request("GET /prices HTTP/1.1\r\n\r\n"));
+        let list: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(list[0]["item"], "玄铁");
+        assert_eq!(list[0]["average"], 400);
+        assert_eq!(list[0]["min"], 300);
+        let res = api.handle(&request("GET /prices/%E7%8E%84%E9%93%81 HTTP/1.1\r\n\r\n"));
+        let price: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(price["last"], 500);
+        let res = api.handle(&request("GET /prices/秘银 HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+    }
+
+    #[test]
+    fn test_api_mutes() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let res = api.handle(&request(
+            "POST /triggers/%E8%BF%B7%E5%AE%AB/mute?minutes=10 HTTP/1.1\r\n\r\n",
+        ));
+        assert_eq!(res.status, 200);
+        let res = api.handle(&request("GET /mutes HTTP/1.1\r\n\r\n"));
+        let list: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(list[0]["trigger"], "迷宫");
+        assert!(list[0]["remaining"].as_u64().unwrap() > 590);
+        let res = api.handle(&request("POST /triggers/迷宫/unmute HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+    }
+
+    #[test]
+    fn test_api_ack() {
+        let stats = Arc::new(Stats::new());
+        let api = api(Arc::clone(&stats), HashMap::new());
+        let res = api.handle(&request("POST /triggers/maze/ack HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let mut trigger = crate::config::Trigger::new("挑战赛通道");
+        trigger.name = "maze".to_owned();
+        let event = Arc::new(crate::notifier::testutil::matched(
+            "12:00:02丂挑战赛通道即将刷新",
+            &trigger,
+            "迷宫",
+        ));
+        api.bus.publish(&Signal::Matched {
+            trigger: "maze",
+            event: &event,
+            notifiers: &[],
+        });
+        let res = api.handle(&request("POST /triggers/maze/ack HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        api.bus.publish(&Signal::Matched {
+            trigger: "maze",
+            event: &event,
+            notifiers: &[],
+        });
+        api.handle(&request("POST /triggers/maze/mute HTTP/1.1\r\n\r\n"));
+        let acks = &stats.health().acks;
+        assert_eq!(acks["maze"].matches, 2);
+        assert_eq!(acks["maze"].acked, 2);
+    }
+
+    #[test]
+    fn test_api_alerts() {
+        let stats = Arc::new(Stats::new());
+        let api = api(Arc::clone(&stats), HashMap::new());
+        let mut event = crate::event::Event::plain("挑战赛通道 即将刷新");
+        event.trigger = "maze".to_owned();
+        let id = api
+            .dispatcher
+            .alerts()
+            .raise(&event, std::time::Instant::now());
+        api.bus.publish(&Signal::Matched {
+            trigger: "maze",
+            event: &Arc::new(event),
+            notifiers: &[],
+        });
+        let res = api.handle(&request("GET /alerts HTTP/1.1\r\n\r\n"));
+        let list: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(list[0]["id"], id);
+        assert_eq!(list[0]["trigger"], "maze");
+        let res = api.handle(&request("POST /alerts/9/ack HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let res = api.handle(&request(&format!(
+            "POST /alerts/{id}/ack?minutes=10 HTTP/1.1\r\n\r\n"
+        )));
+        assert_eq!(res.status, 200);
+        assert_eq!(api.mutes.list()[0].trigger, "maze");
+        assert_eq!(stats.health().acks["maze"].acked, 1);
+    }
+
+    #[test]
+    fn test_api_reset() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let res = api.handle(&request("POST /triggers/boss/reset HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let state = Arc::new(TriggerState::load(None).unwrap());
+        state.fire("boss", 1000);
+        let api = api.with_state(Arc::clone(&state));
+        let res = api.handle(&request("POST /triggers/boss/reset HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        assert_eq!(state.entry("boss").count, 0);
+    }
+
+    #[test]
+    fn test_api_status() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let res = api.handle(&request("GET /status HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 404);
+        let profile = Arc::new(Profile::new());
+        profile.add_record(&[("maze", Duration::from_micros(30))]);
+        profile.end_batch(4);
+        let api = api.with_profile(profile);
+        let res = api.handle(&request("GET /status HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        let status: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(status["lines"], 4);
+        assert_eq!(status["slowest"][0]["trigger"], "maze");
+        let res = api.handle(&request("GET /metrics HTTP/1.1\r\n\r\n"));
+        assert!(res.body.contains("cgaid_lines_total 4\n"));
+    }
+
+    #[test]
+    fn test_api_reload_preview() {
+        let api = api(Arc::new(Stats::new()), HashMap::new());
+        let res = api.handle(&request("GET /reload/preview HTTP/1.1\r\n\r\n"));
+        assert_eq!(res.status, 200);
+        let changes: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(changes["lines"], 1);
+        assert_eq!(changes["added"].as_array().unwrap().len(), 0);
+    }
+}
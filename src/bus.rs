@@ -0,0 +1,172 @@
+//! Central event bus, the pipeline publishes what happens and sinks such as notifiers, the
+//! history store and the stats subscribe to what they need.
+use super::chat::record::Record;
+use super::error::Error;
+use super::event::Event;
+use super::suppressed::Reason;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Watcher state changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    /// Chat quiet for the idle time, polling slowed down
+    Idle,
+    /// Chat lines coming again
+    Active,
+    Stopped,
+}
+
+/// What happened in the pipeline, borrowed from the publisher for the time of the call.
+#[derive(Debug)]
+pub enum Signal<'a> {
+    /// Lines read from the chat log of a client, before they are parsed into records
+    Lines {
+        client: &'a str,
+        count: usize,
+    },
+    /// A chat record read by a client
+    Record {
+        client: &'a str,
+        record: &'a Record,
+    },
+    /// A trigger matched and the event is to be sent to `notifiers`. `trigger` is its name, or
+    /// its regex when unnamed.
+    Matched {
+        trigger: &'a str,
+        event: &'a Arc<Event>,
+        notifiers: &'a [String],
+    },
+    /// A match reached none of its notifiers
+    Suppressed {
+        trigger: &'a str,
+        reason: Reason,
+        message: &'a str,
+    },
+    /// A notifier delivered the event, or failed to
+    Delivered {
+        notifier: &'a str,
+        event: &'a Event,
+        result: &'a Result<bool, Error>,
+        /// Time the notifier took
+        elapsed: Duration,
+    },
+    /// The alerts of a trigger were acknowledged, `delay` after the first of them
+    Acked {
+        trigger: &'a str,
+        delay: Duration,
+    },
+    Watcher(State),
+    /// Something in cgaid itself went wrong, `fatal` when it cannot go on
+    Fault {
+        source: &'a str,
+        detail: &'a str,
+        fatal: bool,
+    },
+}
+
+/// Receives the signals of a bus. Called on the publishing thread, so slow work belongs on a
+/// queue of the sink's own.
+pub trait Sink: Send + Sync {
+    fn handle(&self, signal: &Signal);
+}
+
+/// Passes every signal to all subscribed sinks, in subscription order.
+pub struct Bus {
+    sinks: RwLock<Vec<Arc<dyn Sink>>>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self {
+            sinks: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self, sink: Arc<dyn Sink>) {
+        self.sinks.write().unwrap().push(sink);
+    }
+
+    pub fn publish(&self, signal: &Signal) {
+        // copied so sinks may publish or subscribe in turn
+        let sinks = self.sinks.read().unwrap().clone();
+        for s in &sinks {
+            s.handle(signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Names(Mutex<Vec<String>>);
+
+    impl Sink for Names {
+        fn handle(&self, signal: &Signal) {
+            let name = match signal {
+                Signal::Lines { client, count } => format!("lines {client} {count}"),
+                Signal::Record { client, .. } => format!("record {client}"),
+                Signal::Matched { trigger, .. } => format!("matched {trigger}"),
+                Signal::Suppressed { reason, .. } => format!("suppressed {reason}"),
+                Signal::Delivered { notifier, .. } => format!("delivered {notifier}"),
+                Signal::Acked { trigger, .. } => format!("acked {trigger}"),
+                Signal::Watcher(s) => format!("watcher {s:?}"),
+                Signal::Fault { source, .. } => format!("fault {source}"),
+            };
+            self.0.lock().unwrap().push(name);
+        }
+    }
+
+    /// Publishes a follow-up signal for every match.
+    struct Relay(Arc<Bus>);
+
+    impl Sink for Relay {
+        fn handle(&self, signal: &Signal) {
+            if let Signal::Matched { trigger, event, .. } = signal {
+                self.0.publish(&Signal::Suppressed {
+                    trigger,
+                    reason: Reason::Dedup,
+                    message: &event.message,
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_bus() {
+        let bus = Arc::new(Bus::new());
+        let names = Arc::new(Names(Mutex::new(Vec::new())));
+        bus.subscribe(Arc::new(Relay(Arc::clone(&bus))));
+        bus.subscribe(Arc::clone(&names) as Arc<dyn Sink>);
+
+        let record = Record::from("12:00:02丂画眉鸟离开了队伍。").unwrap();
+        bus.publish(&Signal::Record {
+            client: "main",
+            record: &record,
+        });
+        let event = Arc::new(Event::plain("画眉鸟 掉线了"));
+        bus.publish(&Signal::Matched {
+            trigger: "leave",
+            event: &event,
+            notifiers: &["simple".to_owned()],
+        });
+        bus.publish(&Signal::Watcher(State::Idle));
+        assert_eq!(
+            *names.0.lock().unwrap(),
+            vec![
+                "record main",
+                "suppressed 重复",
+                "matched leave",
+                "watcher Idle"
+            ]
+        );
+    }
+}
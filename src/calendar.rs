@@ -0,0 +1,379 @@
+//! When each notifier is on duty, by weekday with exception dates such as holidays.
+use super::config;
+use super::error::Error;
+use super::group::Period;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Weekday};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Exception dates of an ICS file, read again whenever the file changes.
+struct Ics {
+    path: PathBuf,
+    loaded: Mutex<(Option<SystemTime>, HashSet<NaiveDate>)>,
+}
+
+impl Ics {
+    fn new(path: &str) -> Result<Self, Error> {
+        let ics = Self {
+            path: PathBuf::from(path),
+            loaded: Mutex::new((None, HashSet::new())),
+        };
+        // a missing or broken file is a mistake in the config, later ones are only logged
+        let text = std::fs::read_to_string(&ics.path)
+            .map_err(|e| Error::Config(format!("Invalid calendar file {path}: {e}")))?;
+        *ics.loaded.lock().unwrap() = (ics.modified(), parse_ics(&text, &Local));
+        Ok(ics)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    fn contains(&self, date: NaiveDate) -> bool {
+        let mut loaded = self.loaded.lock().unwrap();
+        let modified = self.modified();
+        if modified.is_some() && modified != loaded.0 {
+            match std::fs::read_to_string(&self.path) {
+                Ok(text) => {
+                    *loaded = (modified, parse_ics(&text, &Local));
+                    log::info!("Calendar reloaded: {}", self.path.display());
+                }
+                Err(e) => log::warn!("Calendar {} unreadable: {e}", self.path.display()),
+            }
+        }
+        loaded.1.contains(&date)
+    }
+}
+
+/// The dates in the time zone `local` covered by the events of an ICS file. Times in UTC or of a
+/// TZID defined in the file are converted, with the standard offset of the zone as daylight
+/// saving rules are not applied, other times are taken as local. Recurrence rules are not
+/// expanded.
+fn parse_ics<Tz: TimeZone>(text: &str, local: &Tz) -> HashSet<NaiveDate> {
+    // long lines continue on the next ones, indented by one space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    let zones = zones(&lines);
+    let time = |value: &str, params: &str| {
+        if !value.contains('T') {
+            let date = NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()?;
+            return date.and_hms_opt(0, 0, 0);
+        }
+        let time = NaiveDateTime::parse_from_str(value.get(..15)?, "%Y%m%dT%H%M%S").ok()?;
+        let offset = if value.ends_with('Z') {
+            Some(0)
+        } else {
+            params
+                .split(';')
+                .find_map(|p| p.strip_prefix("TZID="))
+                .and_then(|id| zones.get(id.trim_matches('"')).copied())
+        };
+        Some(match offset {
+            Some(seconds) => {
+                let utc = time - TimeDelta::seconds(seconds.into());
+                local.from_utc_datetime(&utc).naive_local()
+            }
+            None => time,
+        })
+    };
+    let mut dates = HashSet::new();
+    let mut event: Option<(Option<NaiveDateTime>, Option<NaiveDateTime>)> = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        match (name, value.trim(), event.as_mut()) {
+            ("BEGIN", "VEVENT", _) => event = Some((None, None)),
+            ("DTSTART", v, Some(e)) => e.0 = time(v, params),
+            ("DTEND", v, Some(e)) => e.1 = time(v, params),
+            ("END", "VEVENT", Some(&mut (Some(start), end))) => {
+                // the end is not part of the event, the end of a whole day one is the next day
+                let end = match end {
+                    Some(end) if end > start => (end - TimeDelta::seconds(1)).date(),
+                    _ => start.date(),
+                };
+                let mut d = start.date();
+                while d <= end {
+                    dates.insert(d);
+                    match d.succ_opt() {
+                        Some(next) => d = next,
+                        None => break,
+                    }
+                }
+                event = None;
+            }
+            ("END", "VEVENT", _) => event = None,
+            _ => {}
+        }
+    }
+    dates
+}
+
+/// The standard offsets in seconds of the time zones defined in the file, by TZID.
+fn zones(lines: &[String]) -> HashMap<String, i32> {
+    let mut zones = HashMap::new();
+    let mut tzid = None;
+    let mut standard = false;
+    for line in lines {
+        match line.split_once(':').map(|(n, v)| (n, v.trim())) {
+            Some(("TZID", v)) => tzid = Some(v.to_owned()),
+            Some(("BEGIN", "STANDARD")) => standard = true,
+            Some(("END", "STANDARD")) => standard = false,
+            Some(("END", "VTIMEZONE")) => tzid = None,
+            Some(("TZOFFSETTO", v)) if standard => {
+                if let (Some(id), Some(offset)) = (&tzid, offset(v)) {
+                    zones.insert(id.clone(), offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    zones
+}
+
+/// Seconds of a UTC offset like `+0800` or `-053000`.
+fn offset(value: &str) -> Option<i32> {
+    let sign = match value.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let part = |i: usize| value.get(1 + i * 2..3 + i * 2)?.parse::<i32>().ok();
+    let seconds = part(2).unwrap_or_default();
+    Some(sign * (part(0)? * 3600 + part(1)? * 60 + seconds))
+}
+
+/// Periods of the day, `None` for all day.
+type Periods = Option<Vec<Period>>;
+
+fn periods(name: &str, list: &[String]) -> Result<Vec<Period>, Error> {
+    list.iter()
+        .map(|v| {
+            Period::parse(v).ok_or_else(|| Error::Config(format!("Invalid calendar: {name} {v}")))
+        })
+        .collect()
+}
+
+pub struct Calendar {
+    days: HashMap<Weekday, Vec<Period>>,
+    default: Periods,
+    holidays: HashSet<NaiveDate>,
+    ics: Option<Ics>,
+    holiday: Vec<Period>,
+}
+
+impl Calendar {
+    pub fn new(name: &str, cfg: &config::Calendar) -> Result<Self, Error> {
+        let mut days = HashMap::new();
+        for (day, list) in &cfg.days {
+            let weekday = day
+                .parse::<Weekday>()
+                .map_err(|_| Error::Config(format!("Invalid calendar day: {name} {day}")))?;
+            days.insert(weekday, periods(name, list)?);
+        }
+        let default = match &cfg.default {
+            Some(list) => Some(periods(name, list)?),
+            None => None,
+        };
+        let holidays = cfg
+            .holidays
+            .iter()
+            .map(|d| {
+                NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                    .map_err(|_| Error::Config(format!("Invalid calendar date: {name} {d}")))
+            })
+            .collect::<Result<_, _>>()?;
+        let ics = if cfg.ics.is_empty() {
+            None
+        } else {
+            Some(Ics::new(&cfg.ics)?)
+        };
+        Ok(Self {
+            days,
+            default,
+            holidays,
+            ics,
+            holiday: periods(name, &cfg.holiday)?,
+        })
+    }
+
+    /// Whether the calendar is on duty at the given local time. A period crossing midnight
+    /// belongs to the day it starts on.
+    pub fn on_duty(&self, at: NaiveDateTime) -> bool {
+        let date = at.date();
+        let time = at.time();
+        let today = match self.periods(date) {
+            Some(list) => list.iter().any(|p| p.starts_by(time)),
+            None => true,
+        };
+        today
+            || date
+                .pred_opt()
+                .and_then(|d| self.periods(d))
+                .is_some_and(|list| list.iter().any(|p| p.carries_over(time)))
+    }
+
+    /// The periods of the date, `None` for all day.
+    fn periods(&self, date: NaiveDate) -> Option<&[Period]> {
+        if self.holidays.contains(&date) || self.ics.as_ref().is_some_and(|i| i.contains(date)) {
+            return Some(&self.holiday);
+        }
+        self.days
+            .get(&date.weekday())
+            .or(self.default.as_ref())
+            .map(|list| list.as_slice())
+    }
+}
+
+/// The calendars by name, notifiers refer to them in their `calendar` setting.
+#[derive(Default)]
+pub struct Calendars {
+    calendars: HashMap<String, Calendar>,
+}
+
+impl Calendars {
+    pub fn new(cfg: &HashMap<String, config::Calendar>) -> Result<Self, Error> {
+        let mut calendars = HashMap::new();
+        for (name, c) in cfg {
+            calendars.insert(name.clone(), Calendar::new(name, c)?);
+        }
+        Ok(Self { calendars })
+    }
+
+    /// Whether the notifier may send at the given local time. Notifiers without a calendar, or
+    /// with an unknown one, always may.
+    pub fn on_duty(&self, common: Option<&config::Common>, at: NaiveDateTime) -> bool {
+        common
+            .and_then(|c| self.calendars.get(&c.calendar))
+            .is_none_or(|c| c.on_duty(at))
+    }
+
+    /// Whether the calendar of the name is on duty, an unknown one always is.
+    pub fn is_on(&self, name: &str, at: NaiveDateTime) -> bool {
+        self.calendars.get(name).is_none_or(|c| c.on_duty(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_ics() {
+        let local = chrono::FixedOffset::east_opt(8 * 3600).unwrap();
+        let dates = parse_ics(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:国庆\r\n\
+             DTSTART;VALUE=DATE:20261001\r\n\
+             DTEND;VALUE=DATE:20261004\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Day\r\n\
+             \x20shift\r\n\
+             DTSTART;TZID=Asia/Shanghai:20261010T080000\r\n\
+             DTEND;TZID=Asia/Shanghai:20261010T200000\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VTIMEZONE\r\n\
+             TZID:America/New_York\r\n\
+             BEGIN:DAYLIGHT\r\n\
+             TZOFFSETTO:-0400\r\n\
+             END:DAYLIGHT\r\n\
+             BEGIN:STANDARD\r\n\
+             TZOFFSETTO:-0500\r\n\
+             END:STANDARD\r\n\
+             END:VTIMEZONE\r\n\
+             BEGIN:VEVENT\r\n\
+             DTSTART:20261011T200000Z\r\n\
+             DTEND:20261011T210000Z\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             DTSTART;TZID=America/New_York:20261013T200000\r\n\
+             DTEND;TZID=America/New_York:20261013T210000\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+            &local,
+        );
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        assert_eq!(dates.len(), 6);
+        assert!(dates.contains(&date("2026-10-01")));
+        assert!(dates.contains(&date("2026-10-03")));
+        assert!(!dates.contains(&date("2026-10-04")));
+        // a TZID not defined in the file is taken as local
+        assert!(dates.contains(&date("2026-10-10")));
+        // UTC and the standard offset of a defined zone, a day later here
+        assert!(dates.contains(&date("2026-10-12")));
+        assert!(dates.contains(&date("2026-10-14")));
+        assert_eq!(offset("-053000"), Some(-19800));
+    }
+
+    #[test]
+    fn test_calendar() {
+        let dir = std::env::temp_dir().join("cgaid_test_calendar.ics");
+        std::fs::write(
+            &dir,
+            "BEGIN:VEVENT\nDTSTART;VALUE=DATE:20261016\nEND:VEVENT\n",
+        )
+        .unwrap();
+        let cfg: HashMap<String, config::Calendar> = toml::from_str(&format!(
+            r#"
+            [shift]
+            default = ["18:00-24:00"]
+            holidays = ["2026-10-01"]
+            ics = {:?}
+            holiday = ["09:00-12:00"]
+            [shift.days]
+            fri = ["22:00-02:00"]
+            sat = ["09:00-23:00"]
+            sun = []
+            "#,
+            dir.display().to_string()
+        ))
+        .unwrap();
+        let calendars = Calendars::new(&cfg).unwrap();
+        let common = config::Common {
+            calendar: "shift".to_owned(),
+            ..Default::default()
+        };
+        let on = |date, time| calendars.on_duty(Some(&common), at(date, time));
+        // Wednesday
+        assert!(!on("2026-10-14", "12:00"));
+        assert!(on("2026-10-14", "23:59"));
+        // Saturday and Sunday
+        assert!(on("2026-10-17", "12:00"));
+        assert!(!on("2026-10-18", "20:00"));
+        // a period crossing midnight goes on into the next day, not the other way round
+        assert!(on("2026-10-09", "23:00"));
+        assert!(on("2026-10-10", "01:00"));
+        assert!(!on("2026-10-10", "03:00"));
+        assert!(!on("2026-10-09", "01:00"));
+        // exception dates, from the config and the file
+        assert!(!on("2026-10-01", "20:00"));
+        assert!(on("2026-10-01", "10:00"));
+        assert!(!on("2026-10-16", "20:00"));
+        assert!(on("2026-10-16", "10:00"));
+
+        assert!(calendars.on_duty(None, at("2026-10-14", "12:00")));
+        assert!(calendars.on_duty(Some(&config::Common::default()), at("2026-10-14", "12:00")));
+
+        let mut bad = cfg.clone();
+        bad.get_mut("shift").unwrap().days = HashMap::from([("someday".to_owned(), vec![])]);
+        assert!(Calendars::new(&bad).is_err());
+        std::fs::remove_file(&dir).unwrap();
+        assert!(Calendars::new(&cfg).is_err());
+    }
+}
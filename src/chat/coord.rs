@@ -0,0 +1,75 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A map coordinate mentioned in chat, e.g. `(123,456)` or `123.456`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Coord {
+    pub x: u32,
+    pub y: u32,
+}
+
+fn pattern() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"[(（]\s*(\d{1,3})\s*[,，]\s*(\d{1,3})\s*[)）]|(?:^|[^\d.])(\d{1,3})[.。](\d{1,3})(?:$|[^\d.])",
+        )
+        .unwrap()
+    })
+}
+
+impl Coord {
+    pub fn find(text: &str) -> Option<Self> {
+        let caps = pattern().captures(text)?;
+        let (x, y) = match (caps.get(1), caps.get(2)) {
+            (Some(x), Some(y)) => (x, y),
+            _ => (caps.get(3)?, caps.get(4)?),
+        };
+        Some(Self {
+            x: x.as_str().parse().ok()?,
+            y: y.as_str().parse().ok()?,
+        })
+    }
+
+    /// Renders `text` with `x` and `y`.
+    pub fn fill(&self, text: &str) -> String {
+        let mut ctx = crate::template::Context::new();
+        self.insert(&mut ctx);
+        crate::template::render(text, &ctx)
+    }
+
+    /// Adds `x` and `y` to the values of a template.
+    pub fn insert(&self, ctx: &mut crate::template::Context) {
+        ctx.insert("x", &self.x);
+        ctx.insert("y", &self.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coord_find() {
+        assert_eq!(
+            Coord::find("BOSS 在 (123,456) 快来"),
+            Some(Coord { x: 123, y: 456 })
+        );
+        assert_eq!(
+            Coord::find("BOSS 在（12， 34）"),
+            Some(Coord { x: 12, y: 34 })
+        );
+        assert_eq!(Coord::find("东门 123.456"), Some(Coord { x: 123, y: 456 }));
+        assert_eq!(Coord::find("123.456"), Some(Coord { x: 123, y: 456 }));
+        assert_eq!(Coord::find("魔石 1.5.2"), None);
+        assert_eq!(Coord::find("收魔石 12345.6"), None);
+        assert_eq!(Coord::find("半山来个合格车头"), None);
+    }
+
+    #[test]
+    fn test_coord_fill() {
+        let c = Coord { x: 1, y: 2 };
+        assert_eq!(c.fill("({x},{y})"), "(1,2)");
+    }
+}
@@ -0,0 +1,214 @@
+//! Steps applied to records between parsing and matching, chosen and ordered by a profile.
+use super::super::config::{self, Priority};
+use super::super::error::Error;
+use super::record::Record;
+use regex::Regex;
+
+/// A record after the pipeline, with what the steps found out about it.
+#[derive(Debug, Clone)]
+pub struct Enriched {
+    pub record: Record,
+    /// Added to the tags of the events
+    pub tags: Vec<String>,
+    /// Overrides the priority of the triggers
+    pub priority: Option<Priority>,
+}
+
+impl From<Record> for Enriched {
+    fn from(record: Record) -> Self {
+        Self {
+            record,
+            tags: Vec::new(),
+            priority: None,
+        }
+    }
+}
+
+enum Step {
+    /// Full width letters, digits and punctuation to half width
+    Width,
+    Markup(Regex),
+    /// Replacements, longest first
+    Translate(Vec<(String, String)>),
+    /// Tags friends, drops the ignored
+    Friends {
+        friends: Vec<String>,
+        ignore: Vec<String>,
+    },
+    Priority(Vec<(Regex, Priority)>),
+}
+
+impl Step {
+    /// Tag of records sent by a friend.
+    const FRIEND: &'static str = "friend";
+
+    fn new(name: &str, cfg: &config::Enrich) -> Result<Self, Error> {
+        Ok(match name {
+            "width" => Step::Width,
+            "markup" => Step::Markup(Regex::new(&cfg.markup)?),
+            "translate" => {
+                let mut table: Vec<_> = cfg
+                    .translate
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                table.sort_by_key(|(k, _)| (std::cmp::Reverse(k.chars().count()), k.clone()));
+                Step::Translate(table)
+            }
+            "friends" => Step::Friends {
+                friends: cfg.friends.clone(),
+                ignore: cfg.ignore.clone(),
+            },
+            "priority" => {
+                let mut rules = Vec::new();
+                for (regex, priority) in &cfg.priority {
+                    rules.push((Regex::new(regex)?, *priority));
+                }
+                // highest first, so the strongest rule wins
+                rules.sort_by_key(|r| std::cmp::Reverse(r.1));
+                Step::Priority(rules)
+            }
+            _ => return Err(Error::Config(format!("Unknown enrich step {name}"))),
+        })
+    }
+
+    /// Applies the step, `false` to drop the record.
+    fn apply(&self, e: &mut Enriched) -> bool {
+        let replace = |e: &mut Enriched, message: String| {
+            if message != e.record.msg() {
+                e.record = e.record.clone().with_message(message);
+            }
+        };
+        match self {
+            Step::Width => replace(e, e.record.msg().chars().map(half_width).collect()),
+            Step::Markup(re) => replace(e, re.replace_all(e.record.msg(), "").into_owned()),
+            Step::Translate(table) => {
+                let mut message = e.record.msg().to_owned();
+                for (from, to) in table {
+                    message = message.replace(from.as_str(), to);
+                }
+                replace(e, message);
+            }
+            Step::Friends { friends, ignore } => {
+                let Some(sender) = e.record.sender() else {
+                    return true;
+                };
+                if ignore.iter().any(|i| i == sender) {
+                    return false;
+                }
+                if friends.iter().any(|f| f == sender) {
+                    e.tags.push(Step::FRIEND.to_owned());
+                }
+            }
+            Step::Priority(rules) => {
+                if let Some((_, p)) = rules.iter().find(|(re, _)| re.is_match(e.record.msg())) {
+                    e.priority = Some(*p);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// The record as the game would write it in half width. The full width colon stays, the game
+/// separates senders with it.
+fn half_width(c: char) -> char {
+    match c {
+        '：' => c,
+        '\u{3000}' => ' ',
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// The ordered steps of the chosen profile.
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    /// The pipeline of the profile in use, `None` when no profile is chosen.
+    pub fn new(cfg: &config::Enrich) -> Result<Option<Self>, Error> {
+        if cfg.profile.is_empty() {
+            return Ok(None);
+        }
+        let names = cfg
+            .profiles
+            .get(&cfg.profile)
+            .ok_or_else(|| Error::Config(format!("Unknown enrich profile {}", cfg.profile)))?;
+        let steps = names
+            .iter()
+            .map(|n| Step::new(n, cfg))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Self { steps }))
+    }
+
+    /// Runs the steps in order, `None` if one of them dropped the record.
+    pub fn run(&self, record: Record) -> Option<Enriched> {
+        let mut e = Enriched::from(record);
+        for s in &self.steps {
+            if !s.apply(&mut e) {
+                return None;
+            }
+        }
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(profile: &str) -> config::Enrich {
+        let mut cfg: config::Enrich = toml::from_str(
+            r#"
+            markup = "<[^>]*>"
+            friends = ["画眉鸟"]
+            ignore = ["广告君"]
+            [translate]
+            "組隊" = "组队"
+            "組" = "组"
+            [priority]
+            "(?i)boss" = "high"
+            "组队" = "low"
+            [profiles]
+            full = ["width", "markup", "translate", "friends", "priority"]
+            light = ["friends"]
+            "#,
+        )
+        .unwrap();
+        cfg.profile = profile.to_owned();
+        cfg
+    }
+
+    #[test]
+    fn test_enrich() {
+        assert!(Pipeline::new(&cfg("")).unwrap().is_none());
+        let pipeline = Pipeline::new(&cfg("full")).unwrap().unwrap();
+        let run = |line: &str| pipeline.run(Record::from(line).unwrap());
+
+        let e = run("12:00:00丂[世界]画眉鸟：<c=red>ＢＯＳＳ</c>　組隊").unwrap();
+        assert_eq!(e.record.msg(), "[世界]画眉鸟：BOSS 组队");
+        assert_eq!(e.record.sender(), Some("画眉鸟"));
+        assert_eq!(e.tags, vec!["friend"]);
+        assert_eq!(e.priority, Some(Priority::High));
+
+        let e = run("12:00:00丂[世界]盛明兰oO: 来组队").unwrap();
+        assert!(e.tags.is_empty());
+        assert_eq!(e.priority, Some(Priority::Low));
+        assert!(run("12:00:00丂[世界]广告君: 便宜金币").is_none());
+
+        let pipeline = Pipeline::new(&cfg("light")).unwrap().unwrap();
+        let e = pipeline
+            .run(Record::from("12:00:00丂画眉鸟: ＢＯＳＳ").unwrap())
+            .unwrap();
+        assert_eq!(e.record.msg(), "画眉鸟: ＢＯＳＳ");
+        assert_eq!(e.priority, None);
+
+        assert!(Pipeline::new(&cfg("other")).is_err());
+        let mut bad = cfg("full");
+        bad.profiles
+            .insert("full".to_owned(), vec!["spell".to_owned()]);
+        assert!(Pipeline::new(&bad).is_err());
+    }
+}
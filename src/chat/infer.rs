@@ -0,0 +1,140 @@
+//! Guesses the channel of records whose tag the client wrote differently or left out.
+use super::super::config;
+use super::super::error::Error;
+use super::record::{Channel, Record};
+use std::collections::HashMap;
+
+/// Bracket pairs other clients put around the channel tag.
+const BRACKETS: [(char, char); 4] = [('[', ']'), ('【', '】'), ('<', '>'), ('［', '］')];
+
+pub struct Inferrer {
+    tags: HashMap<String, Channel>,
+    /// Message beginnings by channel, longest first
+    prefixes: Vec<(String, Channel)>,
+    sender: Option<Channel>,
+}
+
+impl Inferrer {
+    pub fn new(cfg: &config::Infer) -> Result<Self, Error> {
+        let channel = |name: &str| {
+            Channel::from_name(name).ok_or_else(|| Error::Config(format!("Unknown channel {name}")))
+        };
+        let mut tags = HashMap::new();
+        for (tag, name) in &cfg.tags {
+            tags.insert(tag.clone(), channel(name)?);
+        }
+        let mut prefixes = Vec::new();
+        for (name, list) in &cfg.prefixes {
+            let c = channel(name)?;
+            prefixes.extend(list.iter().map(|p| (p.clone(), c.clone())));
+        }
+        prefixes.sort_by_key(|(p, _)| std::cmp::Reverse(p.chars().count()));
+        let sender = if cfg.sender.is_empty() {
+            None
+        } else {
+            Some(channel(&cfg.sender)?)
+        };
+        Ok(Self {
+            tags,
+            prefixes,
+            sender,
+        })
+    }
+
+    /// The channel of a record the client did not tag as the game does, `None` if nothing
+    /// applies. Tagged records are left alone.
+    pub fn infer(&self, record: &Record) -> Option<Channel> {
+        if record.get_channel() != &Channel::Common {
+            return None;
+        }
+        let msg = record.msg();
+        if let Some(c) =
+            Inferrer::tag(msg).and_then(|t| self.tags.get(t).cloned().or_else(|| known(t)))
+        {
+            return Some(c);
+        }
+        if let Some((_, c)) = self
+            .prefixes
+            .iter()
+            .find(|(p, _)| msg.starts_with(p.as_str()))
+        {
+            return Some(c.clone());
+        }
+        self.sender.clone().filter(|_| record.sender().is_some())
+    }
+
+    /// Sets the inferred channel, if any.
+    pub fn apply(&self, record: Record) -> Record {
+        match self.infer(&record) {
+            Some(c) => record.with_channel(c),
+            None => record,
+        }
+    }
+
+    /// The leading tag in any of the brackets.
+    fn tag(msg: &str) -> Option<&str> {
+        let (_, close) = BRACKETS.iter().find(|(open, _)| msg.starts_with(*open))?;
+        let rest = &msg[msg.chars().next()?.len_utf8()..];
+        let end = rest.find(*close)?;
+        Some(rest[..end].trim())
+    }
+}
+
+/// A tag the game itself uses, in brackets it does not.
+fn known(tag: &str) -> Option<Channel> {
+    match tag.parse() {
+        Ok(Channel::Common) | Err(_) => None,
+        Ok(c) => Some(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer() {
+        let cfg: config::Infer = toml::from_str(
+            r#"
+            enabled = true
+            sender = "region"
+            [tags]
+            "队伍" = "group"
+            W = "world"
+            [prefixes]
+            world = ["[系统] 世界频道"]
+            group = ["队长", "队长已"]
+            "#,
+        )
+        .unwrap();
+        let inferrer = Inferrer::new(&cfg).unwrap();
+        let infer = |line: &str| inferrer.infer(&Record::from(line).unwrap());
+        assert_eq!(infer("12:00:00丂[队伍]画眉鸟: 走了"), Some(Channel::Group));
+        assert_eq!(
+            infer("12:00:00丂【世界】画眉鸟: 收玄铁"),
+            Some(Channel::World)
+        );
+        assert_eq!(infer("12:00:00丂<W> 画眉鸟: 收玄铁"), Some(Channel::World));
+        assert_eq!(
+            infer("12:00:00丂[系统] 世界频道将在 5 分钟后维护"),
+            Some(Channel::World)
+        );
+        assert_eq!(infer("12:00:00丂队长已解散队伍"), Some(Channel::Group));
+        assert_eq!(
+            infer("12:00:00丂画眉鸟: 王在(123,456)"),
+            Some(Channel::Region)
+        );
+        assert_eq!(infer("12:00:00丂注销回到传送点。"), None);
+        assert_eq!(infer("12:00:00丂[世界]画眉鸟: 收玄铁"), None);
+
+        let record = inferrer.apply(Record::from("12:00:00丂[队伍]画眉鸟: 走了").unwrap());
+        assert_eq!(record.get_channel(), &Channel::Group);
+        assert_eq!(record.body(), "画眉鸟: 走了");
+
+        let cfg = config::Infer {
+            sender: "guild".to_owned(),
+            ..Default::default()
+        };
+        assert!(Inferrer::new(&cfg).is_err());
+    }
+}
@@ -1 +1,5 @@
+pub mod coord;
+pub mod enrich;
+pub mod infer;
 pub mod record;
+pub mod trade;
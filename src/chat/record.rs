@@ -1,13 +1,22 @@
+use super::coord::Coord;
 use chrono::NaiveTime;
 use core::fmt::Display;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Channel {
     World,
     Region,
     Group,
     Common,
+    /// Game window title changes
+    Title,
+    /// Sales of the auction log
+    Auction,
+    /// Received mail of the mail log
+    Mail,
 }
 
 impl Display for Channel {
@@ -17,9 +26,42 @@ impl Display for Channel {
             Self::Region => write!(f, "地图"),
             Self::Group => write!(f, "队伍"),
             Self::Common => write!(f, "普通"),
+            Self::Title => write!(f, "标题"),
+            Self::Auction => write!(f, "拍卖"),
+            Self::Mail => write!(f, "邮件"),
+        }
+    }
+}
+impl Channel {
+    /// The lowercase name used in config and JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::World => "world",
+            Self::Region => "region",
+            Self::Group => "group",
+            Self::Common => "common",
+            Self::Title => "title",
+            Self::Auction => "auction",
+            Self::Mail => "mail",
         }
     }
+
+    /// The channel of a lowercase name, see [`Channel::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::World,
+            Self::Region,
+            Self::Group,
+            Self::Common,
+            Self::Title,
+            Self::Auction,
+            Self::Mail,
+        ]
+        .into_iter()
+        .find(|c| c.name() == name)
+    }
 }
+
 impl FromStr for Channel {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -27,6 +69,8 @@ impl FromStr for Channel {
             "世界" => Ok(Self::World),
             "地图" => Ok(Self::Region),
             "GP" => Ok(Self::Group),
+            "拍卖" => Ok(Self::Auction),
+            "邮件" => Ok(Self::Mail),
             _ => Ok(Self::Common),
         }
     }
@@ -64,12 +108,58 @@ impl Record {
             message,
         })
     }
+    pub fn new(time: NaiveTime, channel: Channel, message: String) -> Self {
+        Self {
+            time,
+            channel,
+            message,
+        }
+    }
+    /// The record moved to another channel, e.g. one inferred from the message.
+    pub fn with_channel(self, channel: Channel) -> Self {
+        Self { channel, ..self }
+    }
+    /// The record with its message rewritten, e.g. normalized.
+    pub fn with_message(self, message: String) -> Self {
+        Self { message, ..self }
+    }
     pub fn msg(&self) -> &str {
         &self.message
     }
+    /// The message without its leading channel tag.
+    pub fn body(&self) -> &str {
+        if self.message.starts_with('[') {
+            if let Some(index) = self.message.find(']') {
+                return self.message[index + 1..].trim_start();
+            }
+        }
+        &self.message
+    }
+    /// The speaker of a chat message, e.g. `盛明兰oO` in `[世界]盛明兰oO: 半山来个合格车头`.
+    pub fn sender(&self) -> Option<&str> {
+        let body = self.body();
+        let (name, _) = body.split_once(": ").or_else(|| body.split_once('：'))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            None
+        } else {
+            Some(name)
+        }
+    }
     pub fn fmt_time(&self) -> String {
         self.time.format(Record::TIME_FORMAT).to_string().to_owned()
     }
+    /// The first coordinate mentioned in a world or region message.
+    pub fn coord(&self) -> Option<Coord> {
+        match self.channel {
+            Channel::World | Channel::Region => Coord::find(self.body()),
+            _ => None,
+        }
+    }
+    pub fn get_time(&self) -> NaiveTime {
+        self.time
+    }
+
     pub fn get_channel(&self) -> &Channel {
         &self.channel
     }
@@ -101,6 +191,8 @@ impl Ord for Record {
 }
 
 #[cfg(test)]
+// written against an older chrono
+#[allow(deprecated, clippy::useless_vec)]
 mod tests {
     use super::*;
     #[test]
@@ -130,6 +222,25 @@ mod tests {
         assert_eq!(record.message, "你好");
     }
 
+    #[test]
+    fn test_record_sender() {
+        let record = Record::from(" 21:40:12丂[世界]盛明兰oO: 半山来个合格车头  大号3带2").unwrap();
+        assert_eq!(record.body(), "盛明兰oO: 半山来个合格车头  大号3带2");
+        assert_eq!(record.sender(), Some("盛明兰oO"));
+
+        let record = Record::from("15:27:24丂注销回到传送点。").unwrap();
+        assert_eq!(record.body(), "注销回到传送点。");
+        assert_eq!(record.sender(), None);
+    }
+
+    #[test]
+    fn test_record_coord() {
+        let record = Record::from("21:40:12丂[地图]盛明兰oO: 王在(123,456)").unwrap();
+        assert_eq!(record.coord(), Some(Coord { x: 123, y: 456 }));
+        let record = Record::from("21:40:12丂盛明兰oO: 王在(123,456)").unwrap();
+        assert_eq!(record.coord(), None);
+    }
+
     #[test]
     fn test_record_hash() {
         let line = "12:34:56丂[世界] 你好";
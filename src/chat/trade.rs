@@ -0,0 +1,143 @@
+use super::record::Record;
+use crate::config;
+use crate::error::Error;
+use crate::price::Prices;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Something that happened in the mail or auction log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Trade {
+    /// An auction item was bought by someone
+    Sold {
+        item: String,
+        price: u64,
+        buyer: String,
+    },
+    /// A mail arrived
+    Mail { from: String, subject: String },
+}
+
+impl Trade {
+    /// The text of the converted chat record, what triggers match against.
+    pub fn message(&self) -> String {
+        match self {
+            Trade::Sold { item, price, buyer } if buyer.is_empty() => {
+                format!("{item} 已卖出, 价格 {price}")
+            }
+            Trade::Sold { item, price, buyer } => {
+                format!("{item} 已卖出, 价格 {price}, 买家 {buyer}")
+            }
+            Trade::Mail { from, subject } => format!("收到 {from} 的邮件: {subject}"),
+        }
+    }
+
+    /// The channel tag of the converted chat record.
+    fn tag(&self) -> &'static str {
+        match self {
+            Trade::Sold { .. } => "拍卖",
+            Trade::Mail { .. } => "邮件",
+        }
+    }
+}
+
+/// Parses lines of the mail and auction logs, which are written like the chat log.
+pub struct Parser {
+    sold: Regex,
+    received: Regex,
+    /// Fed with the sales converted
+    prices: Option<Arc<Prices>>,
+}
+
+impl Parser {
+    pub fn new(cfg: &config::Trade) -> Result<Self, Error> {
+        Ok(Self {
+            sold: Regex::new(&cfg.sold)?,
+            received: Regex::new(&cfg.received)?,
+            prices: None,
+        })
+    }
+
+    /// Records the prices of the sales in `prices`.
+    pub fn with_prices(mut self, prices: Arc<Prices>) -> Self {
+        self.prices = Some(prices);
+        self
+    }
+
+    pub fn parse(&self, text: &str) -> Option<Trade> {
+        let group = |caps: &Captures, name: &str| {
+            caps.name(name)
+                .map_or(String::new(), |m| m.as_str().trim().to_owned())
+        };
+        if let Some(caps) = self.sold.captures(text) {
+            return Some(Trade::Sold {
+                item: group(&caps, "item"),
+                price: group(&caps, "price").parse().ok()?,
+                buyer: group(&caps, "buyer"),
+            });
+        }
+        let caps = self.received.captures(text)?;
+        Some(Trade::Mail {
+            from: group(&caps, "from"),
+            subject: group(&caps, "subject"),
+        })
+    }
+
+    /// Converts a log line to a chat line of the `auction` or `mail` channel, `None` if it is
+    /// neither a sale nor a mail.
+    pub fn convert(&self, line: &str) -> Option<String> {
+        let record = Record::from(line)?;
+        let trade = self.parse(record.msg())?;
+        if let (Some(prices), Trade::Sold { item, price, .. }) = (&self.prices, &trade) {
+            prices.sold(item, *price, chrono::Local::now().timestamp());
+        }
+        Some(format!(
+            "{}丂[{}]{}",
+            record.fmt_time(),
+            trade.tag(),
+            trade.message()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::record::Channel;
+    use super::*;
+
+    #[test]
+    fn test_trade_convert() {
+        let prices = Arc::new(Prices::new());
+        let parser = Parser::new(&config::Trade::default())
+            .unwrap()
+            .with_prices(Arc::clone(&prices));
+        assert_eq!(
+            parser.parse("您寄售的 大地之铠 已售出, 获得 12000 魔币, 买家: 盛明兰oO"),
+            Some(Trade::Sold {
+                item: "大地之铠".to_owned(),
+                price: 12000,
+                buyer: "盛明兰oO".to_owned(),
+            })
+        );
+        let line = parser
+            .convert("21:40:12丂您寄售的 玄铁 已售出, 获得 300 魔币")
+            .unwrap();
+        assert_eq!(line, "21:40:12丂[拍卖]玄铁 已卖出, 价格 300");
+        let record = Record::from(&line).unwrap();
+        assert_eq!(record.get_channel(), &Channel::Auction);
+        assert_eq!(record.body(), "玄铁 已卖出, 价格 300");
+        assert_eq!(prices.get("玄铁").unwrap().last, 300);
+        // only what is converted is a sale, not what is parsed
+        assert!(prices.get("大地之铠").is_none());
+
+        let line = parser
+            .convert("21:41:00丂收到 画眉鸟 的邮件: 明天一起刷迷宫")
+            .unwrap();
+        let record = Record::from(&line).unwrap();
+        assert_eq!(record.get_channel(), &Channel::Mail);
+        assert_eq!(record.body(), "收到 画眉鸟 的邮件: 明天一起刷迷宫");
+        assert!(parser.convert("21:42:00丂寄售成功").is_none());
+    }
+}
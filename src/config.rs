@@ -1,42 +1,1574 @@
 use super::chat::record::Channel;
+use super::error::Error;
+use super::group::Groups;
+use super::secret;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
-use toml;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Client {
+    pub name: String,
+    pub path: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Game {
     pub path: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, rename = "client")]
+    pub clients: Vec<Client>,
+    #[serde(default = "Game::default_merge")]
+    pub merge: u64,
+    /// Seconds without chat after which the watch loop wakes up less often, 0 to never slow down
+    #[serde(default = "Game::default_idle")]
+    pub idle: u64,
+    /// Read a copy of the chat file when the game keeps it locked
+    #[serde(default)]
+    pub shadow: bool,
+}
+
+impl Game {
+    fn default_merge() -> u64 {
+        2
+    }
+    fn default_idle() -> u64 {
+        300
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Dedup {
+    pub window: u64,
+    /// What makes two notifications the same
+    pub key: DedupKey,
+}
+
+/// What identical notifications are told apart by.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupKey {
+    /// The notification message
+    #[default]
+    Message,
+    /// The chat line, whatever the format of the trigger
+    Line,
+    /// The trigger and its capture groups
+    Captures,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Dispatch {
+    /// Threads sending notifications, shared by all notifiers
+    pub workers: usize,
+    /// Milliseconds to wait for room in a full notifier queue before dropping, 0 to drop at once
+    pub wait: u64,
+    /// Language of the formats sent by notifiers setting none, every format by language has it
+    pub lang: String,
+}
+
+impl Default for Dispatch {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            wait: 500,
+            lang: Template::DEFAULT_LANG.to_owned(),
+        }
+    }
+}
+
+/// Proxy of the HTTP based notifiers.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Proxy {
+    /// `http://`, `https://` or `socks5://` address, empty for the system proxy
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// Connects directly, ignoring the system proxy too
+    pub disabled: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Outbox {
+    pub path: String,
+    pub interval: u64,
+    pub format: String,
+    /// Resends before a notification is given up, 0 for no limit
+    pub attempts: u32,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            interval: 60,
+            format: "[{time}] {message}".to_owned(),
+            attempts: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct History {
+    /// File of matched events, empty to disable
+    pub path: String,
+    /// Passphrase to encrypt the events with, empty to store plain text
+    pub key: String,
+    /// Days to keep events, 0 for ever
+    pub days: u64,
+}
+
+/// Log of every delivery attempt.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Deliveries {
+    /// File of the attempts, empty to disable
+    pub path: String,
+    /// KB the file may grow to before it is rotated, 0 for no limit
+    pub size: u64,
+}
+
+impl Default for Deliveries {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Api {
+    pub listen: String,
+    /// Required of every request as `Authorization: Bearer` or `?token=`, empty for none
+    pub token: String,
+}
+
+/// Global hotkey acknowledging the latest alert.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Hotkey {
+    /// Combination like `Ctrl+Alt+A`, empty for none
+    pub ack: String,
+    /// Minutes to mute the trigger of the alert for, 0 to only acknowledge
+    pub minutes: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Summary {
+    pub notifier: Vec<String>,
+}
+
+/// Applying an edited config without a restart, and chat kept in memory to check an edit
+/// against before it is applied.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Reload {
+    /// Whether to apply the config file once it changes
+    pub watch: bool,
+    /// Minutes of chat to keep, 0 to keep none
+    pub minutes: u64,
+}
+
+impl Default for Reload {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            minutes: 10,
+        }
+    }
+}
+
+/// Steps applied to records before matching, see [`crate::chat::enrich`].
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Enrich {
+    /// Profile in use, empty to not enrich
+    pub profile: String,
+    /// Ordered step names by profile
+    pub profiles: HashMap<String, Vec<String>>,
+    /// Regex of the markup the `markup` step strips
+    pub markup: String,
+    /// Replacements of the `translate` step
+    pub translate: HashMap<String, String>,
+    /// Senders the `friends` step tags as `friend`
+    pub friends: Vec<String>,
+    /// Senders the `friends` step drops
+    pub ignore: Vec<String>,
+    /// Priority by message regex, for the `priority` step
+    pub priority: HashMap<String, Priority>,
+}
+
+/// Channel guessing for clients tagging channels differently or not at all.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Infer {
+    pub enabled: bool,
+    /// Channel names by the tags such clients use, e.g. `队伍` for `group`
+    pub tags: HashMap<String, String>,
+    /// Message beginnings by channel name, e.g. known system messages
+    pub prefixes: HashMap<String, Vec<String>>,
+    /// Channel of untagged messages with a sender, empty to leave them `common`
+    pub sender: String,
+}
+
+/// Mail and auction logs, converted to records of the `mail` and `auction` channels.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Trade {
+    /// File name prefix of the mail log, e.g. `mail` for `mail_240501.txt`, empty to not watch
+    pub mail: String,
+    /// File name prefix of the auction log, empty to not watch
+    pub auction: String,
+    /// Regex of a sale with the groups `item`, `price` and optionally `buyer`
+    pub sold: String,
+    /// Regex of a received mail with the groups `from` and `subject`
+    pub received: String,
+}
+
+impl Default for Trade {
+    fn default() -> Self {
+        Self {
+            mail: String::new(),
+            auction: String::new(),
+            sold: r"(?P<item>\S+)\s*已售出[,，]\s*获得\s*(?P<price>\d+)\s*魔币(?:[,，]\s*买家[:：]\s*(?P<buyer>\S+))?".to_owned(),
+            received: r"收到\s*(?P<from>\S+?)\s*的邮件[:：]\s*(?P<subject>.*)".to_owned(),
+        }
+    }
+}
+
+/// Periodic summary of matches that reached none of their notifiers.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Suppressed {
+    /// Notifiers of the summary, empty to not collect
+    pub notifier: Vec<String>,
+    /// Minutes between summaries
+    pub interval: u64,
+    /// Suppressed matches listed in a summary at most
+    pub list: usize,
+    pub format: String,
+}
+
+impl Default for Suppressed {
+    fn default() -> Self {
+        Self {
+            notifier: Vec::new(),
+            interval: 30,
+            list: 20,
+            format: "期间有 {count} 条匹配被抑制:\n{list}".to_owned(),
+        }
+    }
+}
+
+/// Alerts about cgaid's own repeated failures.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Watchdog {
+    /// Fallback notifiers of the alerts, empty to disable
+    pub notifier: Vec<String>,
+    /// Consecutive failures before alerting
+    pub failures: u32,
+    /// Minutes between alerts about the same source
+    pub interval: u64,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            notifier: Vec::new(),
+            failures: 3,
+            interval: 30,
+        }
+    }
+}
+
+/// Whispers received while away from the keyboard.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Afk {
+    /// Minutes without keyboard or mouse input to be away, 0 to disable
+    pub minutes: u64,
+    /// Regex of a whisper in the common channel
+    pub regex: String,
+    pub format: String,
+    pub notifier: Vec<String>,
+}
+
+impl Default for Afk {
+    fn default() -> Self {
+        Self {
+            minutes: 0,
+            regex: r"^(\S+?)[:：]\s*(.+)$".to_owned(),
+            format: "{time}. 离开时收到 {1} 的密语: {2}".to_owned(),
+            notifier: Vec::new(),
+        }
+    }
+}
+
+impl Afk {
+    pub const TRIGGER: &'static str = "afk";
+
+    /// The trigger matching whispers, `None` when disabled.
+    pub fn trigger(&self) -> Option<Trigger> {
+        if self.minutes == 0 {
+            return None;
+        }
+        let mut trigger = Trigger::new(&self.regex);
+        trigger.name = Afk::TRIGGER.to_owned();
+        trigger.format = self.format.as_str().into();
+        trigger.channel = "common".to_owned();
+        trigger.notifier = self.notifier.clone();
+        trigger.priority = Priority::High;
+        Some(trigger)
+    }
+
+    /// Whether the input has been idle long enough, never when idle time is unknown.
+    pub fn is_away(&self, idle: Option<std::time::Duration>) -> bool {
+        idle.is_some_and(|d| d.as_secs() >= self.minutes * 60)
+    }
+}
+
+/// Window titles watched as a secondary source, changes go to triggers of the `title` channel.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Title {
+    /// Text the titles of the game windows contain, empty to disable
+    pub filter: String,
+    /// Seconds between polls
+    pub interval: u64,
+    /// Prefix of the message when a window is closed
+    pub closed: String,
+}
+
+impl Default for Title {
+    fn default() -> Self {
+        Self {
+            filter: String::new(),
+            interval: 2,
+            closed: "窗口已关闭: ".to_owned(),
+        }
+    }
+}
+
+/// Settings shared by the triggers of a group.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Group {
+    pub enabled: bool,
+    /// Notifiers added to every trigger of the group
+    pub notifier: Vec<String>,
+    /// Seconds after one trigger of the group fired during which the group stays silent
+    pub cooldown: u64,
+    /// Periods of the day the group is active, as `HH:MM-HH:MM`, empty for all day
+    pub schedule: Vec<String>,
+    /// Global hotkey enabling or disabling the group, like `Ctrl+Alt+B`, empty for none
+    pub hotkey: String,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            notifier: Vec::new(),
+            cooldown: 0,
+            schedule: Vec::new(),
+            hotkey: String::new(),
+        }
+    }
+}
+
+/// Styling of one priority in chat platforms.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Look {
+    /// RGB as `#rrggbb`, empty for the default of the priority
+    pub color: String,
+    pub emoji: String,
+    /// Starts plain messages with the trigger name in bold
+    pub bold: bool,
+}
+
+/// Styling of priorities shared by the chat platform notifiers.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Style {
+    pub enabled: bool,
+    pub low: Look,
+    pub normal: Look,
+    pub high: Look,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low: Look::default(),
+            normal: Look {
+                emoji: "🔔".to_owned(),
+                ..Default::default()
+            },
+            high: Look {
+                emoji: "🚨".to_owned(),
+                bold: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// When a notifier is on duty, by weekday with exception dates.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Calendar {
+    /// Periods by weekday (`mon` to `sun`) as `HH:MM-HH:MM`, other days use `default`
+    pub days: HashMap<String, Vec<String>>,
+    /// Periods of the days not listed, all day when not set
+    pub default: Option<Vec<String>>,
+    /// Exception dates as `YYYY-MM-DD`
+    pub holidays: Vec<String>,
+    /// ICS file whose events are exception dates too
+    pub ics: String,
+    /// Periods of the exception dates, none when empty
+    pub holiday: Vec<String>,
+}
+
+/// Where trigger state is kept across restarts.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct State {
+    /// JSON file, empty to keep the state in memory only
+    pub path: String,
+}
+
+/// My character names, any message mentioning them raises a high priority event.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Me {
+    pub names: Vec<String>,
+    pub format: String,
+    pub notifier: Vec<String>,
+}
+
+impl Default for Me {
+    fn default() -> Self {
+        Self {
+            names: Vec::new(),
+            format: "{time}. 有人提到你: {0}".to_owned(),
+            notifier: Vec::new(),
+        }
+    }
+}
+
+impl Me {
+    pub const TRIGGER: &'static str = "me";
+
+    /// Regex for a name, also matching full-width letters and spaces or @ in between.
+    fn pattern(name: &str) -> String {
+        let chars: Vec<_> = name
+            .chars()
+            .map(|c| match c {
+                '!'..='~' => {
+                    let wide = char::from_u32(c as u32 + 0xFEE0).unwrap_or(c);
+                    format!("[{}{}]", regex::escape(&c.to_string()), wide)
+                }
+                _ => regex::escape(&c.to_string()),
+            })
+            .collect();
+        format!("[@＠]?[\\s　]*{}", chars.join("[\\s　]*"))
+    }
+
+    /// The trigger matching any of my names, `None` when no name is configured.
+    pub fn trigger(&self) -> Option<Trigger> {
+        let names: Vec<_> = self
+            .names
+            .iter()
+            .filter(|n| !n.is_empty())
+            .map(|n| Me::pattern(n))
+            .collect();
+        if names.is_empty() {
+            return None;
+        }
+        let mut trigger = Trigger::new(&format!(".*(?:{}).*", names.join("|")));
+        trigger.name = Me::TRIGGER.to_owned();
+        trigger.format = self.format.as_str().into();
+        trigger.notifier = self.notifier.clone();
+        trigger.priority = Priority::High;
+        Some(trigger)
+    }
+
+    /// Whether the sender is one of my characters, my own messages do not mention me.
+    pub fn is_me(&self, sender: &str) -> bool {
+        self.names.iter().any(|n| n == sender)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Crash {
+    pub process: String,
+    pub recent: u64,
+    pub normal: String,
+    pub format: String,
+    pub notifier: Vec<String>,
+    pub interval: u64,
+}
+
+impl Default for Crash {
+    fn default() -> Self {
+        Self {
+            process: String::new(),
+            recent: 300,
+            normal: String::new(),
+            format: "游戏崩溃了, 最后的消息: {last}".to_owned(),
+            notifier: Vec::new(),
+            interval: 5,
+        }
+    }
+}
+
+/// Maps an in-game character name to contact identities.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Mention {
+    pub name: String,
+    /// Mobile number to @ in DingTalk
+    pub dingtalk: String,
+    /// Telegram username
+    pub telegram: String,
+}
+
+/// Settings shared by all notifiers.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Common {
+    pub max_length: usize,
+    /// Language of the trigger formats to send, empty for the default one
+    pub lang: String,
+    /// Notifications sent at the same time, 0 for one
+    pub concurrency: usize,
+    /// Notifications waiting at most, more are dropped, 0 for 100
+    pub queue: usize,
+    /// Calendar of the times the notifier sends, empty for always
+    pub calendar: String,
+    /// Notifications per minute, 0 for unlimited
+    pub rate: u32,
+    /// Notifications sent at once after a quiet while, 0 for `rate`
+    pub burst: u32,
+    /// What becomes of notifications over the rate
+    pub overflow: Overflow,
+    /// Seconds to collect notifications for, which are then sent as one listing them all, 0 to
+    /// send each
+    pub digest: u64,
+    /// Seconds the network sends of a notification may take before they are given up, 0 for 60
+    pub timeout: u64,
+    /// Title of the game window to attach a screenshot of, taken when the trigger matched,
+    /// empty for none. Only for the notifiers in [`Common::SCREENSHOT`]
+    pub screenshot: String,
+}
+
+impl Common {
+    /// Types of the notifiers able to attach a screenshot.
+    pub const SCREENSHOT: [&'static str; 3] = ["discord", "telegram", "pushover"];
+}
+
+/// What to do with notifications over a notifier's rate.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    Drop,
+    /// Wait in the notifier's queue
+    #[default]
+    Queue,
+    /// Wait, then go as one notification noting how many more there were
+    Collapse,
+}
+
+/// A message format, either one text or one per language.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(from = "TemplateRaw")]
+pub struct Template {
+    text: String,
+    langs: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TemplateRaw {
+    Plain(String),
+    Localized(HashMap<String, String>),
+}
+
+impl From<TemplateRaw> for Template {
+    fn from(raw: TemplateRaw) -> Self {
+        match raw {
+            TemplateRaw::Plain(text) => text.into(),
+            TemplateRaw::Localized(langs) => {
+                let mut template = Self {
+                    text: String::new(),
+                    langs,
+                };
+                template.localize(Template::DEFAULT_LANG);
+                template
+            }
+        }
+    }
+}
+
+impl From<String> for Template {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            langs: HashMap::new(),
+        }
+    }
+}
+
+impl From<&str> for Template {
+    fn from(text: &str) -> Self {
+        text.to_owned().into()
+    }
+}
+
+impl Template {
+    /// Default of the `lang` setting.
+    pub const DEFAULT_LANG: &'static str = "zh";
+
+    /// The default text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.langs.is_empty()
+    }
+
+    /// Makes the text of the language the default one. False when a format by language has no
+    /// such language.
+    pub fn localize(&mut self, lang: &str) -> bool {
+        if self.langs.is_empty() {
+            return true;
+        }
+        match self.langs.get(lang) {
+            Some(text) => {
+                self.text = text.clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Texts by language, empty for a plain format.
+    pub fn langs(&self) -> &HashMap<String, String> {
+        &self.langs
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Simple {
+    #[serde(flatten)]
+    pub common: Common,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Console {
+    #[serde(flatten)]
+    pub common: Common,
+    pub color: String,
+    /// Colors by priority or channel, overriding `color`
+    #[serde(default)]
+    pub colors: HashMap<String, String>,
+    pub format: String,
+    /// Display width of the sender column, 0 to disable alignment
+    #[serde(default)]
+    pub align: usize,
+    pub by_log: bool,
+    /// Also write the output to the socket notifier's clients
+    #[serde(default)]
+    pub mirror: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Ringtone {
+    #[serde(flatten)]
+    pub common: Common,
+    pub audio: String,
+    pub device: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Dingtalk {
+    #[serde(flatten)]
+    pub common: Common,
+    pub webhook: String,
+    pub template: String,
+    #[serde(default)]
+    pub merge: bool,
+    /// `text`, `markdown`, `link` or `actionCard`
+    #[serde(default)]
+    pub msgtype: String,
+    #[serde(default)]
+    pub title: String,
+    /// Opened by link and actionCard messages
+    #[serde(default)]
+    pub url: String,
+    /// Picture of link messages
+    #[serde(default)]
+    pub picture: String,
+    /// Secret of the "加签" security setting
+    #[serde(default)]
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Http {
+    #[serde(flatten)]
+    pub common: Common,
+    pub url: String,
+    pub payload: String,
+    pub template: String,
+    /// HMAC-SHA256 signing key, empty to send unsigned
+    pub secret: String,
+    /// Header carrying the signature
+    pub header: String,
+    /// Request method, empty for `POST`
+    pub method: String,
+    /// Extra request headers
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FileLog {
+    #[serde(flatten)]
+    pub common: Common,
+    pub path: String,
+    pub template: String,
+    /// Megabytes before the file is rotated, 0 to never rotate
+    pub size: u64,
+    /// Rotated files kept
+    pub keep: usize,
+}
+
+impl Default for FileLog {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            path: "matches.log".to_owned(),
+            template: "{date} {time} [{channel}] {sender} {message}".to_owned(),
+            size: 10,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Sqlite {
+    #[serde(flatten)]
+    pub common: Common,
+    pub path: String,
+}
+
+impl Default for Sqlite {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            path: "matches.db".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Socket {
+    #[serde(flatten)]
+    pub common: Common,
+    pub listen: String,
+    pub format: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Toast {
+    #[serde(flatten)]
+    pub common: Common,
+    pub title: String,
+    /// Minutes the snooze button mutes the trigger
+    pub snooze: u64,
+    /// Windows sound name, empty for the default one, `none` for silence
+    pub sound: String,
+    /// Scenario of high priority events: `default`, `reminder` or `alarm`
+    pub scenario: String,
+    /// Titles events with the name of their trigger instead of `title`
+    pub named: bool,
+}
+
+impl Default for Toast {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            title: "cgaid".to_owned(),
+            snooze: 10,
+            sound: String::new(),
+            scenario: "reminder".to_owned(),
+            named: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Power {
+    #[serde(flatten)]
+    pub common: Common,
+    /// `wake`, `sleep` or `shutdown`
+    pub action: String,
+    /// MAC address of the machine to wake
+    pub mac: String,
+    /// Broadcast address and port of the magic packet, empty for `255.255.255.255:9`
+    pub target: String,
+    /// Seconds before sleeping or shutting down, cancellable until then
+    pub delay: u64,
+    /// Shuts down even if applications refuse to close
+    pub force: bool,
+    /// Only logs what would be done
+    pub dry_run: bool,
+}
+
+impl Default for Power {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            action: "shutdown".to_owned(),
+            mac: String::new(),
+            target: String::new(),
+            delay: 60,
+            force: false,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Serial {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Port name such as `COM3` or `/dev/ttyUSB0`
+    pub port: String,
+    pub baud: u32,
+    /// Line to write
+    pub text: String,
+    /// Bytes in hex written instead of the text when not empty
+    pub hex: String,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            port: "COM3".to_owned(),
+            baud: 9600,
+            text: "{priority}\n".to_owned(),
+            hex: String::new(),
+        }
+    }
+}
+
+/// Where the player is, for the conditional notifiers.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Presence {
+    /// Text the title of the game window contains, empty for the one of `[title]`
+    pub window: String,
+}
+
+/// A rule of a conditional notifier, holding when all its conditions do.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Condition {
+    /// Whether the game window is in the foreground, either when unset
+    pub focused: Option<bool>,
+    /// Seconds at least without keyboard or mouse input, or the screen locked, 0 for any
+    pub idle: u64,
+    /// Whether the screen is locked, either when unset
+    pub locked: Option<bool>,
+    /// Calendar on duty, empty for any time
+    pub calendar: String,
+    /// Notifiers to send to when the rule holds
+    pub notifier: Vec<String>,
+}
+
+/// Notifiers in dynamic libraries, see [`crate::notifier::library`].
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Library {
+    /// Directory of the libraries, each a notifier named after its file
+    pub dir: String,
+    /// Settings by library name, handed to it as JSON
+    #[serde(flatten)]
+    pub settings: HashMap<String, toml::Value>,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Self {
+            dir: "plugins".to_owned(),
+            settings: HashMap::new(),
+        }
+    }
+}
+
+impl Library {
+    /// The file of the library named `name`, e.g. `plugins/wechat.dll`.
+    pub fn file(&self, name: &str) -> PathBuf {
+        let file = format!("{DLL_PREFIX}{name}{DLL_SUFFIX}");
+        Path::new(&self.dir).join(file)
+    }
+
+    /// Whether a notifier library of the name is configured or dropped into the directory.
+    pub fn provides(&self, name: &str) -> bool {
+        self.settings.contains_key(name) || self.file(name).is_file()
+    }
+}
+
+/// External program receiving the events as JSON lines on stdin, see
+/// [`crate::notifier::plugin::Plugin`].
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Plugin {
+    #[serde(flatten)]
+    pub common: Common,
+    pub path: String,
+    pub args: Vec<String>,
+    pub workdir: String,
+    /// Seconds to wait for the answer to an event, the program is restarted after
+    pub wait: u64,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            path: String::new(),
+            args: Vec::new(),
+            workdir: String::new(),
+            wait: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Flash {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Text the titles of the game windows contain
+    pub window: String,
+    /// Times to flash, 0 until the window is brought to the foreground
+    pub count: u32,
+    /// Flashes only the taskbar button
+    pub taskbar: bool,
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            window: "魔力宝贝".to_owned(),
+            count: 0,
+            taskbar: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Beep {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Sound of the Windows sound scheme: default, info, warning, error, question or simple
+    pub sound: String,
+    /// Tone of the speaker in Hz instead of the sound, 0 for the sound
+    pub frequency: u32,
+    /// Milliseconds of each beep
+    pub duration: u64,
+    pub count: u32,
+}
+
+impl Default for Beep {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            sound: "warning".to_owned(),
+            frequency: 0,
+            duration: 500,
+            count: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Overlay {
+    #[serde(flatten)]
+    pub common: Common,
+    /// top-left, top-right, bottom-left or bottom-right
+    pub corner: String,
+    /// Seconds the last match stays on the screen
+    pub seconds: u64,
+    /// Opacity in percent
+    pub opacity: u8,
+    /// Font height in pixels
+    pub size: u32,
+    /// Maximum width in pixels, longer lines wrap
+    pub width: u32,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            corner: "top-right".to_owned(),
+            seconds: 8,
+            opacity: 80,
+            size: 20,
+            width: 420,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Clipboard {
+    #[serde(flatten)]
+    pub common: Common,
+    pub template: String,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            template: "{message}".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Tray {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Shows the tray icon at start
+    pub enabled: bool,
+    pub title: String,
+}
+
+impl Default for Tray {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            enabled: false,
+            title: "cgaid".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Desktop {
+    #[serde(flatten)]
+    pub common: Common,
+    pub title: String,
+    /// Icon name of the theme or path of an image
+    pub icon: String,
+    /// Seconds before normal and low priority notifications close, 0 for the server default
+    pub timeout: u64,
+    /// Titles events with the name of their trigger instead of `title`
+    pub named: bool,
+}
+
+impl Default for Desktop {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            title: "cgaid".to_owned(),
+            icon: String::new(),
+            timeout: 0,
+            named: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Discord {
+    #[serde(flatten)]
+    pub common: Common,
+    pub webhook: String,
+    pub template: String,
+    /// Send as an embed with the trigger name as title
+    pub embed: bool,
+    /// Name shown as the sender, empty for the webhook's own
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Feishu {
+    #[serde(flatten)]
+    pub common: Common,
+    pub webhook: String,
+    /// Secret of the signature check, empty when not enabled
+    pub secret: String,
+    pub template: String,
+    /// Send as an interactive card with the trigger name as title
+    pub card: bool,
+    /// Color of the card header
+    pub color: String,
+}
+
+impl Default for Feishu {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            webhook: String::new(),
+            secret: String::new(),
+            template: "{message}".to_owned(),
+            card: false,
+            color: "blue".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Ntfy {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Server address, empty for ntfy.sh
+    pub server: String,
+    pub topic: String,
+    /// Access token of protected topics
+    pub token: String,
+    pub title: String,
+    pub template: String,
+    /// Tags or emoji short codes of every message, added to those of the trigger
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Email {
+    #[serde(flatten)]
+    pub common: Common,
+    /// SMTP server
+    pub host: String,
+    /// 0 for the usual port of the security
+    pub port: u16,
+    /// `tls`, `starttls` or `none`
+    pub security: String,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub template: String,
+}
+
+impl Default for Email {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            host: String::new(),
+            port: 0,
+            security: "tls".to_owned(),
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+            to: Vec::new(),
+            subject: "[cgaid] {message}".to_owned(),
+            template: "{message}".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PushPlus {
+    #[serde(flatten)]
+    pub common: Common,
+    /// API address, empty for the official one
+    pub api: String,
+    pub token: String,
+    /// Group code to push to all its subscribers, empty to push to the token owner only
+    pub topic: String,
+    pub title: String,
+    pub template: String,
+    /// Content type, `txt`, `html` or `markdown`, empty for `txt`
+    pub format: String,
+}
+
+/// Other notifiers sent to under one name.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Composite {
+    #[serde(flatten)]
+    pub common: Common,
+    pub notifier: Vec<String>,
+}
+
+/// Other notifiers tried in order until one sends.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Fallback {
+    #[serde(flatten)]
+    pub common: Common,
+    pub notifier: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Pushgateway {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Pushgateway address such as `http://localhost:9091`
+    pub url: String,
+    /// Job label, empty for `cgaid`
+    pub job: String,
+    /// Instance label, empty for none
+    pub instance: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Nats {
+    #[serde(flatten)]
+    pub common: Common,
+    /// `host:port` of the server
+    pub server: String,
+    /// Subject, `{trigger}` and `{channel}` are filled
+    pub subject: String,
+    pub user: String,
+    pub password: String,
+    pub token: String,
+    /// Reconnects after a failed publish
+    pub retries: u32,
+}
+
+impl Default for Nats {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            server: "localhost:4222".to_owned(),
+            subject: "cgaid.{channel}.{trigger}".to_owned(),
+            user: String::new(),
+            password: String::new(),
+            token: String::new(),
+            retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Influx {
+    #[serde(flatten)]
+    pub common: Common,
+    /// InfluxDB address such as `http://localhost:8086`
+    pub url: String,
+    /// Bucket of InfluxDB 2
+    pub bucket: String,
+    pub org: String,
+    pub token: String,
+    /// Database of InfluxDB 1, used when there is no bucket
+    pub database: String,
+}
+
+impl Default for Influx {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            url: "http://localhost:8086".to_owned(),
+            bucket: String::new(),
+            org: String::new(),
+            token: String::new(),
+            database: "cgaid".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Loki {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Loki address such as `http://localhost:3100`
+    pub url: String,
+    /// Labels added to `trigger` and `channel`, `job` is `cgaid` unless set
+    pub labels: BTreeMap<String, String>,
+    pub template: String,
+    pub user: String,
+    pub password: String,
+    /// Tenant of a multi-tenant Loki
+    pub tenant: String,
+}
+
+impl Default for Loki {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            url: "http://localhost:3100".to_owned(),
+            labels: BTreeMap::new(),
+            template: "{message}".to_owned(),
+            user: String::new(),
+            password: String::new(),
+            tenant: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HomeAssistant {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Base address such as `http://homeassistant.local:8123`
+    pub url: String,
+    /// Webhook ID, posts the whole event to it instead of calling the service
+    pub webhook: String,
+    /// Long-lived access token
+    pub token: String,
+    /// Service as `domain.service`
+    pub service: String,
+    /// Service data
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Ifttt {
+    #[serde(flatten)]
+    pub common: Common,
+    /// API address, empty for the official one
+    pub api: String,
+    /// Key of the Webhooks service
+    pub key: String,
+    /// Event name, `{trigger}` for the trigger name
+    pub event: String,
+    /// Templates of `value1` to `value3`
+    pub values: Vec<String>,
+}
+
+impl Default for Ifttt {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            api: String::new(),
+            key: String::new(),
+            event: "cgaid".to_owned(),
+            values: vec!["{message}".to_owned(), "{1}".to_owned(), "{2}".to_owned()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Pushover {
+    #[serde(flatten)]
+    pub common: Common,
+    /// API address, empty for the official one
+    pub api: String,
+    /// Application API token
+    pub token: String,
+    /// User or group key
+    pub user: String,
+    pub title: String,
+    pub template: String,
+    /// Device names of the user, all devices when empty
+    pub device: String,
+    /// Sends high priority events as emergencies, repeated until acknowledged
+    pub emergency: bool,
+    /// Seconds between the repeats of an emergency
+    pub retry: u64,
+    /// Seconds an emergency is repeated for
+    pub expire: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct Simple {}
+#[serde(default)]
+pub struct Sms {
+    #[serde(flatten)]
+    pub common: Common,
+    /// API address, empty for the official one
+    pub api: String,
+    /// Twilio account SID
+    pub sid: String,
+    pub token: String,
+    /// Twilio number or messaging service SID
+    pub from: String,
+    /// Numbers to send to, in E.164 format
+    pub to: Vec<String>,
+    pub template: String,
+}
+
+impl Default for Sms {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            api: String::new(),
+            sid: String::new(),
+            token: String::new(),
+            from: String::new(),
+            to: Vec::new(),
+            template: "{message}".to_owned(),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct Console {
-    pub color: String,
-    pub format: String,
-    pub by_log: bool,
+#[serde(default)]
+pub struct Irc {
+    #[serde(flatten)]
+    pub common: Common,
+    /// `host:port` of the server
+    pub server: String,
+    pub tls: bool,
+    pub nick: String,
+    /// Server password
+    pub password: String,
+    /// Channel to send to, or a nick for private messages
+    pub channel: String,
+    pub template: String,
+}
+
+impl Default for Irc {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            server: "irc.libera.chat:6697".to_owned(),
+            tls: true,
+            nick: "cgaid".to_owned(),
+            password: String::new(),
+            channel: String::new(),
+            template: "{message}".to_owned(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct Ringtone {
-    pub audio: String,
-    pub device: String,
+#[serde(default)]
+pub struct Xmpp {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Account sending the messages
+    pub jid: String,
+    pub password: String,
+    /// Accounts to send to
+    pub to: Vec<String>,
+    pub template: String,
+}
+
+impl Default for Xmpp {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            jid: String::new(),
+            password: String::new(),
+            to: Vec::new(),
+            template: "{message}".to_owned(),
+        }
+    }
+}
+
+impl Default for Pushover {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            api: String::new(),
+            token: String::new(),
+            user: String::new(),
+            title: "cgaid".to_owned(),
+            template: "{message}".to_owned(),
+            device: String::new(),
+            emergency: false,
+            retry: 60,
+            expire: 3600,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct Dingtalk {
-    pub webhook: String,
+#[serde(default)]
+pub struct Telegram {
+    #[serde(flatten)]
+    pub common: Common,
+    /// Bot API address, empty for the official one
+    pub api: String,
+    pub token: String,
+    pub chat_id: String,
     pub template: String,
+    /// Adds acknowledge and mute buttons under the messages of triggers
+    pub buttons: bool,
+    /// Minutes the mute button mutes the trigger for
+    pub mute: u64,
+}
+
+impl Default for Telegram {
+    fn default() -> Self {
+        Self {
+            common: Common::default(),
+            api: String::new(),
+            token: String::new(),
+            chat_id: String::new(),
+            template: String::new(),
+            buttons: false,
+            mute: 60,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Invoke {
+    #[serde(flatten)]
+    pub common: Common,
     pub path: String,
     pub workdir: String,
     pub args: Vec<String>,
+    /// Runs the command in a job object or process group, killing what it leaves running
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Memory limit of each sandboxed process in MB, 0 for unlimited
+    #[serde(default)]
+    pub memory: u64,
+    /// CPU limit of the sandbox in percent, 0 for unlimited
+    #[serde(default)]
+    pub cpu: u8,
+    /// Seconds the sandboxed command may run before it is killed, 0 for no limit
+    #[serde(default = "Invoke::default_kill")]
+    pub kill: u64,
+}
+
+impl Invoke {
+    fn default_kill() -> u64 {
+        300
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -46,71 +1578,988 @@ pub struct Notifier {
     pub ringtone: Ringtone,
     pub dingtalk: Dingtalk,
     pub invoke: Invoke,
+    #[serde(default)]
+    pub http: Http,
+    #[serde(default)]
+    pub socket: Socket,
+    #[serde(default)]
+    pub file: FileLog,
+    #[serde(default)]
+    pub sqlite: Sqlite,
+    #[serde(default)]
+    pub toast: Toast,
+    #[serde(default)]
+    pub desktop: Desktop,
+    #[serde(default)]
+    pub tray: Tray,
+    #[serde(default)]
+    pub clipboard: Clipboard,
+    #[serde(default)]
+    pub flash: Flash,
+    #[serde(default)]
+    pub beep: Beep,
+    #[serde(default)]
+    pub overlay: Overlay,
+    #[serde(default)]
+    pub serial: Serial,
+    #[serde(default)]
+    pub plugin: Plugin,
+    #[serde(default)]
+    pub library: Library,
+    #[serde(default)]
+    pub power: Power,
+    #[serde(default)]
+    pub telegram: Telegram,
+    #[serde(default)]
+    pub discord: Discord,
+    #[serde(default)]
+    pub feishu: Feishu,
+    #[serde(default)]
+    pub ntfy: Ntfy,
+    #[serde(default)]
+    pub pushover: Pushover,
+    #[serde(default)]
+    pub sms: Sms,
+    #[serde(default)]
+    pub xmpp: Xmpp,
+    #[serde(default)]
+    pub irc: Irc,
+    #[serde(default)]
+    pub pushplus: PushPlus,
+    #[serde(default)]
+    pub email: Email,
+    #[serde(default)]
+    pub ifttt: Ifttt,
+    #[serde(default)]
+    pub homeassistant: HomeAssistant,
+    #[serde(default)]
+    pub pushgateway: Pushgateway,
+    #[serde(default)]
+    pub loki: Loki,
+    #[serde(default)]
+    pub influx: Influx,
+    #[serde(default)]
+    pub nats: Nats,
+    /// Composite notifiers by name
+    #[serde(default)]
+    pub composite: HashMap<String, Composite>,
+    /// Fallback notifiers by name
+    #[serde(default)]
+    pub fallback: HashMap<String, Fallback>,
+    /// Named instances of `[[notifier]]`, e.g. a second DingTalk robot
+    #[serde(skip)]
+    pub instance: HashMap<String, Instance>,
+}
+
+/// A `[[notifier]]` whose name differs from its type, such as `guild` of type `dingtalk`.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub kind: String,
+    /// The notifier settings with the section of the type replaced by the instance's
+    pub notifier: Box<Notifier>,
+}
+
+#[derive(
+    Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+    }
+}
+
+/// A regex written as one string or as an array of alternatives.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(from = "PatternRaw")]
+pub struct Pattern(pub String);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PatternRaw {
+    One(String),
+    Any(Vec<String>),
+}
+
+impl From<PatternRaw> for Pattern {
+    fn from(raw: PatternRaw) -> Self {
+        match raw {
+            PatternRaw::One(text) => Self(text),
+            PatternRaw::Any(list) => Self(list.join("|")),
+        }
+    }
+}
+
+impl Pattern {
+    /// Fragments may reference others this deep.
+    const MAX_DEPTH: usize = 8;
+
+    fn deserialize_str<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+        Ok(Pattern::deserialize(d)?.0)
+    }
+
+    /// Replaces `%{name}` with the fragment of that name as a non-capturing group.
+    pub fn expand(text: &str, fragments: &HashMap<String, Pattern>) -> Result<String, Error> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"%\{(\w+)\}").unwrap());
+        let mut text = text.to_owned();
+        for _ in 0..Pattern::MAX_DEPTH {
+            if !re.is_match(&text) {
+                return Ok(text);
+            }
+            let mut missing = None;
+            text = re
+                .replace_all(&text, |caps: &regex::Captures| {
+                    match fragments.get(&caps[1]) {
+                        Some(f) => format!("(?:{})", f.0),
+                        None => {
+                            missing.get_or_insert_with(|| caps[1].to_owned());
+                            String::new()
+                        }
+                    }
+                })
+                .into_owned();
+            if let Some(name) = missing {
+                return Err(Error::Config(format!("Unknown regex fragment: {name}")));
+            }
+        }
+        Err(Error::Config(format!(
+            "Regex fragments nested too deep: {text}"
+        )))
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Trigger {
+    #[serde(default)]
+    pub name: String,
+    /// A regex or an array of alternatives, may reference fragments as `%{name}`
+    #[serde(deserialize_with = "Pattern::deserialize_str")]
     pub regex: String,
-    pub format: String,
+    pub format: Template,
     pub channel: String,
+    #[serde(default)]
     pub notifier: Vec<String>,
+    #[serde(default)]
+    pub group: String,
+    /// Variables to set from the captures, name to template like `{1}`
+    #[serde(default)]
+    pub store: HashMap<String, String>,
+    #[serde(default)]
+    pub map: String,
+    #[serde(default)]
+    pub deadline: Option<usize>,
+    #[serde(default)]
+    pub remind: u64,
+    #[serde(default)]
+    pub remind_format: Template,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Tags sent along, such as ntfy emoji short codes
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Fires only the first time, ever, until reset from the control API
+    #[serde(default)]
+    pub once: bool,
+    /// Seconds after firing during which the trigger stays silent
+    #[serde(default)]
+    pub cooldown: u64,
+    /// Seconds to suppress the same notification in, instead of the global `[dedup]` window
+    #[serde(default)]
+    pub dedup: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub game: Game,
+    #[serde(default)]
+    pub dedup: Dedup,
+    #[serde(default)]
+    pub dispatch: Dispatch,
+    #[serde(default)]
+    pub proxy: Proxy,
+    #[serde(default)]
+    pub hotkey: Hotkey,
+    #[serde(default)]
+    pub outbox: Outbox,
+    #[serde(default)]
+    pub history: History,
+    #[serde(default)]
+    pub deliveries: Deliveries,
+    #[serde(default)]
+    pub api: Api,
+    #[serde(default)]
+    pub summary: Summary,
+    #[serde(default)]
+    pub crash: Crash,
+    #[serde(default)]
+    pub mention: Vec<Mention>,
+    #[serde(default)]
+    pub me: Me,
+    #[serde(default)]
+    pub afk: Afk,
+    #[serde(default)]
+    pub title: Title,
+    #[serde(default)]
+    pub reload: Reload,
+    #[serde(default)]
+    pub trade: Trade,
+    #[serde(default)]
+    pub infer: Infer,
+    #[serde(default)]
+    pub enrich: Enrich,
+    #[serde(default)]
+    pub suppressed: Suppressed,
+    #[serde(default)]
+    pub watchdog: Watchdog,
+    /// Named regex parts referenced by triggers as `%{name}`
+    #[serde(default)]
+    pub fragment: HashMap<String, Pattern>,
+    #[serde(default)]
+    pub group: HashMap<String, Group>,
+    #[serde(default)]
+    pub calendar: HashMap<String, Calendar>,
+    #[serde(default)]
+    pub presence: Presence,
+    /// Notifier names resolved when sending to the notifiers of the first of their rules holding
+    #[serde(default)]
+    pub conditional: HashMap<String, Vec<Condition>>,
+    #[serde(default)]
+    pub style: Style,
+    #[serde(default)]
+    pub state: State,
+    /// Default notifiers by channel name, for triggers without any
+    #[serde(default)]
+    pub routing: HashMap<String, Vec<String>>,
+    /// Notifiers added by the priority of the match
+    #[serde(default)]
+    pub priority: HashMap<Priority, Vec<String>>,
+    #[serde(deserialize_with = "Notifier::deserialize_any")]
     pub notifier: Notifier,
     pub trigger: Vec<Trigger>,
 }
 
 impl Notifier {
-    pub fn find(
-        cfg: &Config,
-        name: &str,
-    ) -> Result<Box<dyn super::Notifiable>, Box<dyn std::error::Error>> {
+    pub fn common(&self, name: &str) -> Option<&Common> {
+        self.builtin(name)
+            .or_else(|| {
+                self.instance
+                    .get(name)
+                    .and_then(|i| i.notifier.builtin(&i.kind))
+            })
+            .or_else(|| self.composite.get(name).map(|c| &c.common))
+            .or_else(|| self.fallback.get(name).map(|f| &f.common))
+    }
+
+    /// Reads either `[notifier.<type>]` tables or a `[[notifier]]` array of tables with a `type`
+    /// and a `name`, which defaults to the type. A name other than the type defines an instance
+    /// with its own settings, composite and fallback entries are named as in their tables.
+    fn deserialize_any<'de, D: Deserializer<'de>>(d: D) -> Result<Notifier, D::Error> {
+        match toml::Value::deserialize(d)? {
+            toml::Value::Array(list) => Notifier::from_list(list).map_err(serde::de::Error::custom),
+            value => Notifier::deserialize(value).map_err(serde::de::Error::custom),
+        }
+    }
+
+    fn from_list(list: Vec<toml::Value>) -> Result<Notifier, String> {
+        let mut base = toml::Table::new();
+        let mut named = Vec::new();
+        for entry in list {
+            let toml::Value::Table(mut table) = entry else {
+                return Err("[[notifier]] must be a table".to_owned());
+            };
+            let Some(toml::Value::String(kind)) = table.remove("type") else {
+                return Err("[[notifier]] needs a type".to_owned());
+            };
+            let name = match table.remove("name") {
+                Some(toml::Value::String(name)) => name,
+                None => kind.clone(),
+                Some(_) => return Err(format!("Name of {kind} notifier must be a string")),
+            };
+            let duplicate = if kind == "composite" || kind == "fallback" {
+                let group = base
+                    .entry(kind.as_str())
+                    .or_insert_with(|| toml::Table::new().into());
+                let Some(group) = group.as_table_mut() else {
+                    return Err(format!("Invalid {kind} notifiers"));
+                };
+                group.insert(name.clone(), table.into()).is_some()
+            } else if name == kind {
+                base.insert(kind, table.into()).is_some()
+            } else {
+                named.push((name.clone(), kind, table));
+                false
+            };
+            if duplicate {
+                return Err(format!("Duplicate notifier {name}"));
+            }
+        }
+
+        let mut notifier =
+            Notifier::deserialize(toml::Value::Table(base.clone())).map_err(|e| e.to_string())?;
+        for key in base.keys() {
+            let grouped = ["composite", "fallback", "library"].contains(&key.as_str());
+            if notifier.builtin(key).is_none() && !grouped {
+                return Err(format!("Unknown notifier type {key}"));
+            }
+        }
+        for (name, kind, table) in named {
+            if notifier.common(&name).is_some() || notifier.combines(&name) {
+                return Err(format!("Duplicate notifier {name}"));
+            }
+            let mut settings = base.clone();
+            settings.insert(kind.clone(), table.into());
+            let instance = Notifier::deserialize(toml::Value::Table(settings))
+                .map_err(|e| format!("Notifier {name}: {e}"))?;
+            if instance.builtin(&kind).is_none() {
+                return Err(format!("Unknown notifier type {kind} of {name}"));
+            }
+            notifier.instance.insert(
+                name,
+                Instance {
+                    kind,
+                    notifier: Box::new(instance),
+                },
+            );
+        }
+        Ok(notifier)
+    }
+
+    /// The notifiers a composite one sends to, nested composites expanded, or just `name` for
+    /// any other. A cycle is cut where it closes.
+    pub fn members(&self, name: &str) -> Vec<String> {
+        let mut list = Vec::new();
+        self.expand(name, &mut Vec::new(), &mut list);
+        list
+    }
+
+    fn expand(&self, name: &str, path: &mut Vec<String>, list: &mut Vec<String>) {
+        let composite = self
+            .composite
+            .get(name)
+            .filter(|_| self.builtin(name).is_none());
+        match composite {
+            Some(c) if !path.iter().any(|p| p == name) => {
+                path.push(name.to_owned());
+                for n in &c.notifier {
+                    self.expand(n, path, list);
+                }
+                path.pop();
+            }
+            Some(_) => {}
+            None if list.iter().any(|n| n == name) => {}
+            None => list.push(name.to_owned()),
+        }
+    }
+
+    /// Whether a notifier of the name is set up, one of the types, an instance, a composite,
+    /// a fallback or a library one.
+    pub fn defines(&self, name: &str) -> bool {
+        self.builtin(name).is_some()
+            || self.instance.contains_key(name)
+            || self.composite.contains_key(name)
+            || self.fallback.contains_key(name)
+            || self.library.provides(name)
+    }
+
+    /// Whether the notifier is a composite or fallback one, made of other notifiers.
+    fn combines(&self, name: &str) -> bool {
+        self.builtin(name).is_none()
+            && (self.composite.contains_key(name) || self.fallback.contains_key(name))
+    }
+
+    /// The common settings of a notifier other than composite and fallback ones.
+    fn builtin(&self, name: &str) -> Option<&Common> {
+        match name {
+            "simple" => Some(&self.simple.common),
+            "console" => Some(&self.console.common),
+            "ringtone" => Some(&self.ringtone.common),
+            "dingtalk" => Some(&self.dingtalk.common),
+            "http" => Some(&self.http.common),
+            "socket" => Some(&self.socket.common),
+            "file" => Some(&self.file.common),
+            "sqlite" => Some(&self.sqlite.common),
+            "toast" => Some(&self.toast.common),
+            "desktop" => Some(&self.desktop.common),
+            "tray" => Some(&self.tray.common),
+            "clipboard" => Some(&self.clipboard.common),
+            "flash" => Some(&self.flash.common),
+            "beep" => Some(&self.beep.common),
+            "overlay" => Some(&self.overlay.common),
+            "serial" => Some(&self.serial.common),
+            "plugin" => Some(&self.plugin.common),
+            "power" => Some(&self.power.common),
+            "telegram" => Some(&self.telegram.common),
+            "discord" => Some(&self.discord.common),
+            "feishu" => Some(&self.feishu.common),
+            "ntfy" => Some(&self.ntfy.common),
+            "pushover" => Some(&self.pushover.common),
+            "sms" => Some(&self.sms.common),
+            "xmpp" => Some(&self.xmpp.common),
+            "irc" => Some(&self.irc.common),
+            "pushplus" => Some(&self.pushplus.common),
+            "ifttt" => Some(&self.ifttt.common),
+            "homeassistant" => Some(&self.homeassistant.common),
+            "pushgateway" => Some(&self.pushgateway.common),
+            "loki" => Some(&self.loki.common),
+            "influx" => Some(&self.influx.common),
+            "nats" => Some(&self.nats.common),
+            "email" => Some(&self.email.common),
+            "invoke" => Some(&self.invoke.common),
+            _ => None,
+        }
+    }
+
+    /// Template the notifier `name` puts the message in, `None` for those sending it as it is.
+    pub fn template(&self, name: &str) -> Option<&str> {
+        match self.instance.get(name) {
+            Some(i) => i.notifier.builtin_template(&i.kind),
+            None => self.builtin_template(name),
+        }
+    }
+
+    fn builtin_template(&self, name: &str) -> Option<&str> {
+        let template = match name {
+            "console" => &self.console.format,
+            "dingtalk" => &self.dingtalk.template,
+            "http" => &self.http.template,
+            "file" => &self.file.template,
+            "clipboard" => &self.clipboard.template,
+            "discord" => &self.discord.template,
+            "feishu" => &self.feishu.template,
+            "ntfy" => &self.ntfy.template,
+            "email" => &self.email.template,
+            "pushplus" => &self.pushplus.template,
+            "loki" => &self.loki.template,
+            "pushover" => &self.pushover.template,
+            "sms" => &self.sms.template,
+            "irc" => &self.irc.template,
+            "xmpp" => &self.xmpp.template,
+            "telegram" => &self.telegram.template,
+            _ => return None,
+        };
+        Some(template)
+    }
+
+    pub fn find(cfg: &Config, name: &str) -> Result<Box<dyn super::Notifiable>, Error> {
+        match cfg.notifier.instance.get(name) {
+            Some(instance) => Self::build(cfg, &instance.notifier, &instance.kind),
+            None => Self::build(cfg, &cfg.notifier, name),
+        }
+    }
+
+    /// Builds the notifier `name` from the settings in `nc`, which are those of a named instance
+    /// or of the whole config.
+    fn build(cfg: &Config, nc: &Notifier, name: &str) -> Result<Box<dyn super::Notifiable>, Error> {
         match name {
             "simple" => Ok(Box::new(super::notifier::Simple::new())),
             "console" => {
-                let cc = &cfg.notifier.console;
+                let cc = &nc.console;
+                let listen = &nc.socket.listen;
+                let mirror = if cc.mirror && !listen.is_empty() {
+                    Some(super::notifier::socket::Hub::get(listen)?)
+                } else {
+                    None
+                };
                 Ok(Box::new(super::notifier::Console::new(
                     cc.color.clone(),
+                    &cc.colors,
                     cc.format.clone(),
+                    cc.align,
                     cc.by_log,
+                    mirror,
                 )))
             }
             "ringtone" => {
-                let rc = &cfg.notifier.ringtone;
+                let rc = &nc.ringtone;
                 let o = super::notifier::Ringtone::new(rc.audio.clone(), rc.device.clone())?;
                 Ok(Box::new(o))
             }
             "dingtalk" => {
-                let dc = &cfg.notifier.dingtalk;
-                Ok(Box::new(super::notifier::webhook::DingTalk::new(
+                let dc = &nc.dingtalk;
+                let Some(msgtype) = super::notifier::webhook::MsgType::parse(&dc.msgtype) else {
+                    return Err(Error::Config(format!(
+                        "Invalid DingTalk msgtype {}, expect text, markdown, link or actionCard",
+                        dc.msgtype
+                    )));
+                };
+                if msgtype.needs_url() && dc.url.is_empty() {
+                    return Err(Error::Config(format!(
+                        "DingTalk msgtype {} needs url",
+                        dc.msgtype
+                    )));
+                }
+                Ok(Box::new(
+                    super::notifier::webhook::DingTalk::new(
+                        dc.webhook.clone(),
+                        dc.template.clone(),
+                        // the robot's own queue keeps to the rate the dispatcher paces it at
+                        dc.common.rate as usize,
+                        dc.merge,
+                        cfg.mention.clone(),
+                    )
+                    .with_msgtype(
+                        msgtype,
+                        dc.title.clone(),
+                        dc.url.clone(),
+                        dc.picture.clone(),
+                    )
+                    .with_secret(dc.secret.clone())
+                    .with_styles(super::notifier::style::Styles::new(&cfg.style)?),
+                ))
+            }
+            "http" => {
+                let hc = &nc.http;
+                let payload = match hc.payload.as_str() {
+                    "json" => super::notifier::webhook::Payload::Json,
+                    "template" => super::notifier::webhook::Payload::Template,
+                    _ => super::notifier::webhook::Payload::Text,
+                };
+                let method = if hc.method.is_empty() {
+                    reqwest::Method::POST
+                } else {
+                    reqwest::Method::from_bytes(hc.method.to_uppercase().as_bytes())
+                        .map_err(|_| Error::Config(format!("Invalid http method {}", hc.method)))?
+                };
+                let signer = if hc.secret.is_empty() {
+                    None
+                } else {
+                    Some(super::notifier::webhook::Signer::new(
+                        hc.secret.clone(),
+                        hc.header.clone(),
+                    ))
+                };
+                Ok(Box::new(
+                    super::notifier::webhook::Http::new(
+                        hc.url.clone(),
+                        payload,
+                        hc.template.clone(),
+                        signer,
+                    )
+                    .with_request(
+                        method,
+                        hc.headers
+                            .iter()
+                            .map(|(k, v)| (k.clone(), v.clone()))
+                            .collect(),
+                    ),
+                ))
+            }
+            "file" => {
+                let fc = &nc.file;
+                Ok(Box::new(super::notifier::filelog::FileLog::new(
+                    fc.path.clone().into(),
+                    fc.template.clone(),
+                    fc.size * 1024 * 1024,
+                    fc.keep,
+                )))
+            }
+            "sqlite" => Ok(Box::new(super::notifier::sqlite::Sqlite::new(
+                nc.sqlite.path.clone().into(),
+            ))),
+            "socket" => {
+                let sc = &nc.socket;
+                Ok(Box::new(super::notifier::socket::Socket::new(
+                    &sc.listen,
+                    sc.format.clone(),
+                )?))
+            }
+            "toast" => {
+                let tc = &nc.toast;
+                Ok(Box::new(
+                    super::notifier::toast::Toast::new(
+                        tc.title.clone(),
+                        tc.snooze,
+                        cfg.api.listen.clone(),
+                        tc.sound.clone(),
+                        tc.scenario.clone(),
+                        tc.named,
+                    )
+                    .with_token(&cfg.api.token),
+                ))
+            }
+            "tray" => Ok(Box::new(super::notifier::tray::Tray::new())),
+            "power" => {
+                let pc = &nc.power;
+                let action = super::notifier::power::Action::parse(
+                    &pc.action, &pc.mac, &pc.target, pc.force,
+                )?;
+                let wake = matches!(action, super::notifier::power::Action::Wake { .. });
+                if !wake && !pc.dry_run && cfg.api.listen.is_empty() {
+                    return Err(Error::Config(format!(
+                        "Power {} needs [api] listen, the only way to cancel it",
+                        pc.action
+                    )));
+                }
+                Ok(Box::new(super::notifier::power::Power::new(
+                    action,
+                    std::time::Duration::from_secs(pc.delay),
+                    pc.dry_run,
+                )))
+            }
+            "plugin" => {
+                let pc = &nc.plugin;
+                if pc.path.is_empty() {
+                    return Err(Error::Config("Plugin path is empty".to_owned()));
+                }
+                Ok(Box::new(super::notifier::plugin::Plugin::new(
+                    pc.path.clone(),
+                    pc.args.clone(),
+                    pc.workdir.clone(),
+                    std::time::Duration::from_secs(pc.wait.max(1)),
+                )))
+            }
+            "serial" => {
+                let sc = &nc.serial;
+                Ok(Box::new(super::notifier::serial::Serial::new(
+                    sc.port.clone(),
+                    sc.baud,
+                    sc.text.clone(),
+                    &sc.hex,
+                )?))
+            }
+            "flash" => {
+                let fc = &nc.flash;
+                Ok(Box::new(super::notifier::flash::FlashWindow::new(
+                    fc.window.clone(),
+                    fc.count,
+                    fc.taskbar,
+                )))
+            }
+            "beep" => {
+                let bc = &nc.beep;
+                Ok(Box::new(super::notifier::beep::Beep::new(
+                    super::notifier::beep::Sound::parse(&bc.sound)?,
+                    bc.frequency,
+                    std::time::Duration::from_millis(bc.duration),
+                    bc.count,
+                )))
+            }
+            "overlay" => {
+                let oc = &nc.overlay;
+                Ok(Box::new(super::notifier::overlay::Overlay::new(
+                    super::notifier::overlay::Corner::parse(&oc.corner)?,
+                    std::time::Duration::from_secs(oc.seconds),
+                    oc.opacity,
+                    oc.size,
+                    oc.width,
+                )))
+            }
+            "clipboard" => Ok(Box::new(super::notifier::clipboard::Clipboard::new(
+                nc.clipboard.template.clone(),
+            ))),
+            "desktop" => {
+                let dc = &nc.desktop;
+                Ok(Box::new(super::notifier::desktop::Desktop::new(
+                    dc.title.clone(),
+                    dc.icon.clone(),
+                    dc.timeout,
+                    dc.named,
+                )))
+            }
+            "discord" => {
+                let dc = &nc.discord;
+                let discord = super::notifier::webhook::Discord::new(
                     dc.webhook.clone(),
                     dc.template.clone(),
+                    dc.embed,
+                    dc.username.clone(),
+                )
+                .with_screenshot(dc.common.screenshot.clone());
+                Ok(Box::new(discord.with_styles(
+                    super::notifier::style::Styles::new(&cfg.style)?,
+                )))
+            }
+            "feishu" => {
+                let fc = &nc.feishu;
+                let feishu = super::notifier::webhook::Feishu::new(
+                    fc.webhook.clone(),
+                    fc.secret.clone(),
+                    fc.template.clone(),
+                    fc.card,
+                    fc.color.clone(),
+                );
+                Ok(Box::new(feishu.with_styles(
+                    super::notifier::style::Styles::new(&cfg.style)?,
+                )))
+            }
+            "ntfy" => {
+                let nc = &nc.ntfy;
+                Ok(Box::new(super::notifier::ntfy::Ntfy::new(
+                    nc.server.clone(),
+                    nc.topic.clone(),
+                    nc.token.clone(),
+                    nc.title.clone(),
+                    nc.template.clone(),
+                    nc.tags.clone(),
+                )))
+            }
+            "sms" => {
+                let sc = &nc.sms;
+                Ok(Box::new(super::notifier::sms::Sms::new(
+                    sc.api.clone(),
+                    sc.sid.clone(),
+                    sc.token.clone(),
+                    sc.from.clone(),
+                    sc.to.clone(),
+                    sc.template.clone(),
+                )))
+            }
+            "xmpp" => {
+                let xc = &nc.xmpp;
+                let xmpp = super::notifier::xmpp::Xmpp::new(
+                    &xc.jid,
+                    xc.password.clone(),
+                    &xc.to,
+                    xc.template.clone(),
+                )?;
+                Ok(Box::new(xmpp.with_styles(
+                    super::notifier::style::Styles::new(&cfg.style)?,
+                )))
+            }
+            "irc" => {
+                let ic = &nc.irc;
+                if ic.channel.is_empty() {
+                    return Err(Error::Config("No IRC channel to send to".to_owned()));
+                }
+                let irc = super::notifier::irc::Irc::new(
+                    ic.server.clone(),
+                    ic.tls,
+                    ic.nick.clone(),
+                    ic.password.clone(),
+                    ic.channel.clone(),
+                    ic.template.clone(),
+                );
+                Ok(Box::new(irc.with_styles(
+                    super::notifier::style::Styles::new(&cfg.style)?,
+                )))
+            }
+            "pushover" => {
+                let pc = &nc.pushover;
+                let pushover = super::notifier::pushover::Pushover::new(
+                    pc.api.clone(),
+                    pc.token.clone(),
+                    pc.user.clone(),
+                    pc.title.clone(),
+                    pc.template.clone(),
+                    pc.device.clone(),
+                    pc.emergency,
+                    pc.retry,
+                    pc.expire,
+                );
+                Ok(Box::new(
+                    pushover.with_screenshot(pc.common.screenshot.clone()),
+                ))
+            }
+            "pushplus" => {
+                let pc = &nc.pushplus;
+                Ok(Box::new(super::notifier::pushplus::PushPlus::new(
+                    pc.api.clone(),
+                    pc.token.clone(),
+                    pc.topic.clone(),
+                    pc.title.clone(),
+                    pc.template.clone(),
+                    pc.format.clone(),
+                )))
+            }
+            "ifttt" => {
+                let ic = &nc.ifttt;
+                Ok(Box::new(super::notifier::ifttt::Ifttt::new(
+                    ic.api.clone(),
+                    ic.key.clone(),
+                    ic.event.clone(),
+                    ic.values.clone(),
+                )))
+            }
+            "homeassistant" => {
+                let hc = &nc.homeassistant;
+                let service = hc.service.split_once('.');
+                if hc.webhook.is_empty()
+                    && service.is_none_or(|(d, s)| d.is_empty() || s.is_empty())
+                {
+                    return Err(Error::Config(format!(
+                        "Invalid Home Assistant service {}, expect domain.service",
+                        hc.service
+                    )));
+                }
+                Ok(Box::new(
+                    super::notifier::homeassistant::HomeAssistant::new(
+                        hc.url.clone(),
+                        hc.webhook.clone(),
+                        hc.token.clone(),
+                        hc.service.clone(),
+                        hc.data.clone(),
+                    ),
+                ))
+            }
+            "pushgateway" => {
+                let pc = &nc.pushgateway;
+                Ok(Box::new(super::notifier::pushgateway::Pushgateway::new(
+                    pc.url.clone(),
+                    pc.job.clone(),
+                    pc.instance.clone(),
+                )))
+            }
+            "loki" => {
+                let lc = &nc.loki;
+                Ok(Box::new(super::notifier::loki::Loki::new(
+                    lc.url.clone(),
+                    lc.labels.clone(),
+                    lc.template.clone(),
+                    lc.user.clone(),
+                    lc.password.clone(),
+                    lc.tenant.clone(),
+                )))
+            }
+            "influx" => {
+                let ic = &nc.influx;
+                Ok(Box::new(super::notifier::influx::Influx::new(
+                    ic.url.clone(),
+                    ic.bucket.clone(),
+                    ic.org.clone(),
+                    ic.token.clone(),
+                    ic.database.clone(),
+                )))
+            }
+            "nats" => {
+                let nc = &nc.nats;
+                Ok(Box::new(super::notifier::nats::Nats::new(
+                    nc.server.clone(),
+                    nc.subject.clone(),
+                    nc.user.clone(),
+                    nc.password.clone(),
+                    nc.token.clone(),
+                    nc.retries,
+                )))
+            }
+            "email" => {
+                let ec = &nc.email;
+                Ok(Box::new(super::notifier::email::Email::new(
+                    ec.host.clone(),
+                    ec.port,
+                    ec.security.parse()?,
+                    ec.username.clone(),
+                    ec.password.clone(),
+                    &ec.from,
+                    &ec.to,
+                    ec.subject.clone(),
+                    ec.template.clone(),
+                )?))
+            }
+            "telegram" => {
+                let tc = &nc.telegram;
+                let mut telegram = super::notifier::telegram::Telegram::new(
+                    tc.api.clone(),
+                    tc.token.clone(),
+                    tc.chat_id.clone(),
+                    tc.template.clone(),
+                    cfg.mention.clone(),
+                );
+                if tc.buttons {
+                    telegram = telegram.with_buttons(tc.mute.max(1));
+                }
+                telegram = telegram.with_screenshot(tc.common.screenshot.clone());
+                Ok(Box::new(telegram.with_styles(
+                    super::notifier::style::Styles::new(&cfg.style)?,
                 )))
             }
             "invoke" => {
-                let ic = &cfg.notifier.invoke;
-                Ok(Box::new(super::notifier::Invoke::new(
+                let ic = &nc.invoke;
+                let invoke = super::notifier::Invoke::new(
                     ic.path.clone(),
                     ic.args.clone(),
                     ic.workdir.clone(),
+                );
+                if !ic.sandbox {
+                    return Ok(Box::new(invoke));
+                }
+                Ok(Box::new(invoke.with_sandbox(
+                    super::notifier::sandbox::Limits {
+                        memory: ic.memory,
+                        cpu: ic.cpu,
+                        timeout: ic.kill,
+                    },
                 )))
             }
-            _ => Err(format!("Not found notifier {name}").into()),
+            _ if cfg.notifier.combines(name) => Self::combined(cfg, name, &mut Vec::new()),
+            _ if nc.library.provides(name) => super::notifier::library::build(&nc.library, name),
+            _ => Err(Error::Config(format!("Not found notifier {name}"))),
+        }
+    }
+
+    /// Builds a composite or fallback notifier and the ones nested in it, `path` holds the
+    /// notifiers being built to find cycles.
+    fn combined(
+        cfg: &Config,
+        name: &str,
+        path: &mut Vec<String>,
+    ) -> Result<Box<dyn super::Notifiable>, Error> {
+        if path.iter().any(|p| p == name) {
+            path.push(name.to_owned());
+            return Err(Error::Config(format!(
+                "Notifier cycle {}",
+                path.join(" -> ")
+            )));
+        }
+        let (list, fallback) = match (
+            cfg.notifier.composite.get(name),
+            cfg.notifier.fallback.get(name),
+        ) {
+            (Some(c), _) => (&c.notifier, false),
+            (None, Some(f)) => (&f.notifier, true),
+            (None, None) => return Err(Error::Config(format!("Not found notifier {name}"))),
+        };
+        path.push(name.to_owned());
+        let mut children = Vec::new();
+        for n in list {
+            let child = if cfg.notifier.combines(n) {
+                Self::combined(cfg, n, path)?
+            } else {
+                Self::find(cfg, n)?
+            };
+            children.push((n.clone(), child));
+        }
+        path.pop();
+        if fallback {
+            Ok(Box::new(super::notifier::fallback::Fallback::new(children)))
+        } else {
+            Ok(Box::new(super::notifier::composite::Composite::new(
+                children,
+            )))
         }
     }
 }
 
 impl Trigger {
-    #[allow(dead_code)]
-    fn new(regex: &str) -> Self {
+    pub fn new(regex: &str) -> Self {
         Self {
+            name: String::new(),
             regex: regex.to_owned(),
-            format: String::new(),
+            format: Template::default(),
             channel: String::new(),
             notifier: Vec::new(),
+            group: String::new(),
+            store: HashMap::new(),
+            map: String::new(),
+            deadline: None,
+            remind: 0,
+            remind_format: Template::default(),
+            priority: Priority::Normal,
+            tags: Vec::new(),
+            once: false,
+            cooldown: 0,
+            dedup: None,
         }
     }
 
@@ -129,12 +2578,34 @@ impl Trigger {
         }
     }
 
-    pub fn format(&self, matched: &Vec<String>) -> String {
-        let mut fmt = self.format.clone();
-        for (i, m) in matched.iter().enumerate() {
-            fmt = fmt.replace(&format!("{{{}}}", i), m);
+    pub fn format(&self, matched: &[String]) -> String {
+        Trigger::render(self.format.text(), matched)
+    }
+
+    /// Renders `template` with the matched groups, `{0}`, `{1}` ... or `{{ captures.1 }}`.
+    pub fn render(template: &str, matched: &[String]) -> String {
+        crate::template::render(template, &crate::template::captures(matched))
+    }
+
+    /// Expands the regex fragments of `triggers` and checks that their regexes and formats
+    /// compile.
+    pub fn expand_all(
+        triggers: &mut [Trigger],
+        fragments: &HashMap<String, Pattern>,
+    ) -> Result<(), Error> {
+        for t in triggers {
+            t.regex = Pattern::expand(&t.regex, fragments)?;
+            Regex::new(&t.regex)
+                .map_err(|e| Error::Config(format!("Invalid regex of trigger {}: {e}", t.name)))?;
+            for text in std::iter::once(t.format.text())
+                .chain(t.format.langs().values().map(|s| s.as_str()))
+            {
+                crate::template::check(text).map_err(|e| {
+                    Error::Config(format!("Invalid format of trigger {}: {e}", t.name))
+                })?;
+            }
         }
-        fmt
+        Ok(())
     }
 
     pub fn accept(&self, channel: &Channel) -> bool {
@@ -143,20 +2614,187 @@ impl Trigger {
             "group" => channel == &Channel::Group,
             "region" => channel == &Channel::Region,
             "common" => channel == &Channel::Common,
+            "title" => channel == &Channel::Title,
+            "auction" => channel == &Channel::Auction,
+            "mail" => channel == &Channel::Mail,
             _ => true,
         }
     }
 }
 
 impl Config {
-    pub fn parse(text: &mut String) -> Result<Self, toml::de::Error> {
-        toml::from_str(&text)
+    /// Notifiers of the trigger followed by those of its group and of its priority, or the
+    /// routing of the channel when none has any.
+    pub fn notifiers(&self, trigger: &Trigger, channel: &Channel) -> Vec<String> {
+        let mut list = trigger.notifier.clone();
+        let group = self.group.get(&trigger.group).map(|g| &g.notifier);
+        for n in group
+            .into_iter()
+            .chain(self.priority.get(&trigger.priority))
+            .flatten()
+        {
+            if !list.contains(n) {
+                list.push(n.clone());
+            }
+        }
+        if list.is_empty() {
+            if let Some(r) = self.routing.get(channel.name()) {
+                list = r.clone();
+            }
+        }
+        list
+    }
+
+    /// Every notifier named by the triggers, groups, routing and the other features sending
+    /// notifications. The ones nested in composite and fallback notifiers are left out.
+    pub fn notifier_names(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        for t in self.triggers() {
+            names.extend(t.notifier);
+        }
+        for g in self.group.values() {
+            names.extend(g.notifier.iter().cloned());
+        }
+        for r in self.routing.values().chain(self.priority.values()) {
+            names.extend(r.iter().cloned());
+        }
+        for list in [
+            &self.summary.notifier,
+            &self.suppressed.notifier,
+            &self.watchdog.notifier,
+            &self.crash.notifier,
+        ] {
+            names.extend(list.iter().cloned());
+        }
+        for rules in self.conditional.values() {
+            for r in rules {
+                names.extend(r.notifier.iter().cloned());
+            }
+        }
+        names.retain(|n| !self.conditional.contains_key(n));
+        names
+    }
+
+    /// The configured triggers followed by the enabled presets.
+    pub fn triggers(&self) -> Vec<Trigger> {
+        let mut triggers = self.trigger.clone();
+        triggers.extend(self.me.trigger());
+        triggers.extend(self.afk.trigger());
+        triggers
+    }
+
+    /// Sections whose values may be secrets from the environment or the keyring.
+    const SECRETS: [&'static str; 4] = ["notifier", "proxy", "history", "api"];
+
+    /// Parses, resolving the secrets, and expands the regex fragments of the triggers, checking
+    /// that they compile.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut doc: toml::Table = toml::from_str(text)?;
+        for section in Self::SECRETS {
+            if let Some(v) = doc.get_mut(section) {
+                secret::resolve_all(v)?;
+            }
+        }
+        let mut cfg: Config = doc.try_into()?;
+        Trigger::expand_all(&mut cfg.trigger, &cfg.fragment)?;
+        // the whisper trigger is matched as written, so it is checked without the fragments
+        Trigger::expand_all(&mut Vec::from_iter(cfg.afk.trigger()), &HashMap::new())?;
+        cfg.check_calendars()?;
+        cfg.check_groups()?;
+        cfg.check_screenshots()?;
+        cfg.localize()?;
+        Ok(cfg)
+    }
+
+    /// Checks that the groups of the triggers are defined, and the schedules and hotkeys of the
+    /// groups are valid.
+    fn check_groups(&self) -> Result<(), Error> {
+        for t in &self.trigger {
+            if !t.group.is_empty() && !self.group.contains_key(&t.group) {
+                let label = if t.name.is_empty() { &t.regex } else { &t.name };
+                return Err(Error::Config(format!(
+                    "Unknown group {} of trigger {label}",
+                    t.group
+                )));
+            }
+        }
+        Groups::new(&self.group).map_err(Error::Config)?;
+        for g in self.group.values().filter(|g| !g.hotkey.is_empty()) {
+            super::hotkey::Hotkey::parse(&g.hotkey)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that only notifiers able to attach screenshots ask for one.
+    fn check_screenshots(&self) -> Result<(), Error> {
+        let nc = &self.notifier;
+        let mut names = self.notifier_names();
+        names.extend(nc.instance.keys().cloned());
+        for name in names {
+            let kind = nc
+                .instance
+                .get(&name)
+                .map_or(name.as_str(), |i| i.kind.as_str());
+            let screenshot = nc.common(&name).is_some_and(|c| !c.screenshot.is_empty());
+            if screenshot && !Common::SCREENSHOT.contains(&kind) {
+                return Err(Error::Config(format!(
+                    "Notifier {name} cannot attach screenshots, only {} can",
+                    Common::SCREENSHOT.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the default text of the formats by language to the one of `dispatch.lang`.
+    fn localize(&mut self) -> Result<(), Error> {
+        let lang = &self.dispatch.lang;
+        for t in &mut self.trigger {
+            if !t.format.localize(lang) || !t.remind_format.localize(lang) {
+                let label = if t.name.is_empty() { &t.regex } else { &t.name };
+                return Err(Error::Config(format!(
+                    "Format of trigger {label} has no language {lang}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the calendars named by the notifiers and conditional rules are defined.
+    fn check_calendars(&self) -> Result<(), Error> {
+        let nc = &self.notifier;
+        let mut names = self.notifier_names();
+        names.extend(nc.instance.keys().cloned());
+        for (name, list) in nc
+            .composite
+            .iter()
+            .map(|(n, c)| (n, &c.notifier))
+            .chain(nc.fallback.iter().map(|(n, f)| (n, &f.notifier)))
+        {
+            names.insert(name.clone());
+            names.extend(list.iter().cloned());
+        }
+        let notifiers = names
+            .iter()
+            .filter_map(|n| nc.common(n).map(|c| (n.as_str(), &c.calendar)));
+        let rules = self
+            .conditional
+            .iter()
+            .flat_map(|(n, rules)| rules.iter().map(move |r| (n.as_str(), &r.calendar)));
+        for (name, calendar) in notifiers.chain(rules) {
+            if !calendar.is_empty() && !self.calendar.contains_key(calendar) {
+                return Err(Error::Config(format!(
+                    "Unknown calendar {calendar} of {name}"
+                )));
+            }
+        }
+        Ok(())
     }
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let mut file = File::open(path)?;
         let mut text = String::new();
         file.read_to_string(&mut text)?;
-        Self::parse(&mut text).map_err(|e| e.into())
+        Self::parse(&text)
     }
 }
 
@@ -169,6 +2807,8 @@ mod tests {
         let path = "config.toml";
         let config = Config::load(path).unwrap();
         println!("{:?}", config);
+        assert_eq!(config.trade.sold, Trade::default().sold);
+        assert_eq!(config.trade.received, Trade::default().received);
     }
 
     #[test]
@@ -191,6 +2831,318 @@ mod tests {
         assert!(matched.is_none());
     }
 
+    #[test]
+    fn test_me_trigger() {
+        let me = Me {
+            names: vec!["盛明兰oO".to_owned()],
+            ..Default::default()
+        };
+        let trigger = me.trigger().unwrap();
+        assert_eq!(trigger.priority, Priority::High);
+        assert!(trigger.try_match("[世界]路人: 盛明兰oO 在吗").is_some());
+        assert!(trigger.try_match("[队伍]路人: ＠盛 明兰ｏＯ 来").is_some());
+        assert!(trigger.try_match("[世界]路人: 盛明兰 在吗").is_none());
+        assert!(me.is_me("盛明兰oO"));
+        assert!(Me::default().trigger().is_none());
+    }
+
+    #[test]
+    fn test_composite_notifier() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        let composite = |list: &[&str]| Composite {
+            common: Common {
+                max_length: 50,
+                ..Default::default()
+            },
+            notifier: list.iter().map(|s| s.to_string()).collect(),
+        };
+        cfg.notifier
+            .composite
+            .insert("urgent".to_owned(), composite(&["simple", "local"]));
+        cfg.notifier
+            .composite
+            .insert("local".to_owned(), composite(&["simple"]));
+        assert!(Notifier::find(&cfg, "urgent").is_ok());
+        assert_eq!(cfg.notifier.common("urgent").unwrap().max_length, 50);
+        assert_eq!(cfg.notifier.members("urgent"), ["simple"]);
+        assert_eq!(cfg.notifier.members("console"), ["console"]);
+
+        cfg.notifier
+            .composite
+            .insert("local".to_owned(), composite(&["simple", "urgent"]));
+        let e = Notifier::find(&cfg, "urgent").err().unwrap();
+        assert!(e.to_string().contains("urgent -> local -> urgent"));
+        assert_eq!(cfg.notifier.members("urgent"), ["simple"]);
+        cfg.notifier
+            .composite
+            .insert("local".to_owned(), composite(&["nothing"]));
+        assert!(Notifier::find(&cfg, "urgent").is_err());
+
+        cfg.notifier.fallback.insert(
+            "local".to_owned(),
+            Fallback {
+                notifier: vec!["simple".to_owned(), "console".to_owned()],
+                ..Default::default()
+            },
+        );
+        cfg.notifier.composite.remove("local");
+        assert!(Notifier::find(&cfg, "local").is_ok());
+        assert!(Notifier::find(&cfg, "urgent").is_ok());
+        assert_eq!(cfg.notifier.common("local").unwrap().max_length, 0);
+    }
+
+    #[test]
+    fn test_power_needs_api() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        cfg.notifier.power.dry_run = false;
+        assert!(Notifier::find(&cfg, "power").is_ok());
+        cfg.api.listen.clear();
+        assert!(Notifier::find(&cfg, "power").is_err());
+        cfg.notifier.power.dry_run = true;
+        assert!(Notifier::find(&cfg, "power").is_ok());
+    }
+
+    #[test]
+    fn test_homeassistant_service() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        let hc = &mut cfg.notifier.homeassistant;
+        hc.webhook.clear();
+        hc.service = "light.turn_on".to_owned();
+        assert!(Notifier::find(&cfg, "homeassistant").is_ok());
+        for service in ["turn_on", "light.", ""] {
+            cfg.notifier.homeassistant.service = service.to_owned();
+            let e = Notifier::find(&cfg, "homeassistant").err().unwrap();
+            assert!(e.to_string().contains("expect domain.service"));
+        }
+        // the service is not called with a webhook
+        cfg.notifier.homeassistant.webhook = "cgaid".to_owned();
+        assert!(Notifier::find(&cfg, "homeassistant").is_ok());
+    }
+
+    #[test]
+    fn test_afk_regex() {
+        let base = std::fs::read_to_string("config.toml").unwrap();
+        let (head, afk) = base.split_once("[afk]").unwrap();
+        let text = |minutes: &str, regex: &str| {
+            let afk = afk
+                .replacen("minutes = 0", &format!("minutes = {minutes}"), 1)
+                .replacen(
+                    r#"regex = "^(\\S+?)[:：]\\s*(.+)$""#,
+                    &format!("regex = '{regex}'"),
+                    1,
+                );
+            format!("{head}[afk]{afk}")
+        };
+        Config::parse(&text("5", r"^(\S+)[:：](.+)$")).unwrap();
+        let e = Config::parse(&text("5", "(unclosed")).err().unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("Config error: Invalid regex of trigger afk"));
+        // only checked when enabled
+        assert!(Config::parse(&text("0", "(unclosed")).is_ok());
+    }
+
+    #[test]
+    fn test_notifier_instances() {
+        let text = r#"
+            [game]
+            path = ""
+            [[notifier]]
+            type = "simple"
+            [[notifier]]
+            type = "console"
+            color = ""
+            format = "{message}"
+            by_log = false
+            [[notifier]]
+            type = "ringtone"
+            audio = "urgent.mp3"
+            device = ""
+            [[notifier]]
+            name = "soft"
+            type = "ringtone"
+            audio = "soft.mp3"
+            device = ""
+            [[notifier]]
+            type = "dingtalk"
+            webhook = "https://oapi.dingtalk.com/robot/send?access_token=guild"
+            template = "{message}"
+            [[notifier]]
+            name = "personal"
+            type = "dingtalk"
+            webhook = "https://oapi.dingtalk.com/robot/send?access_token=me"
+            template = "{message}"
+            max_length = 50
+            [[notifier]]
+            type = "invoke"
+            path = ""
+            workdir = ""
+            args = []
+            [[notifier]]
+            name = "both"
+            type = "composite"
+            notifier = ["dingtalk", "personal"]
+
+            [[trigger]]
+            regex = "BOSS"
+            format = "{0}"
+            channel = "world"
+            notifier = ["both", "soft"]
+            "#;
+        let cfg = Config::parse(text).unwrap();
+        assert_eq!(cfg.notifier.ringtone.audio, "urgent.mp3");
+        let soft = &cfg.notifier.instance["soft"];
+        assert_eq!(soft.kind, "ringtone");
+        assert_eq!(soft.notifier.ringtone.audio, "soft.mp3");
+        let personal = &cfg.notifier.instance["personal"].notifier.dingtalk;
+        assert!(personal.webhook.ends_with("=me"));
+        assert_eq!(cfg.notifier.common("personal").unwrap().max_length, 50);
+        assert_eq!(cfg.notifier.common("dingtalk").unwrap().max_length, 0);
+        assert!(Notifier::find(&cfg, "personal").is_ok());
+        assert!(Notifier::find(&cfg, "both").is_ok());
+
+        let duplicate = text.replace("name = \"soft\"", "name = \"console\"");
+        assert!(Config::parse(&duplicate).is_err());
+        let unknown = text.replace("type = \"ringtone\"", "type = \"bell\"");
+        assert!(Config::parse(&unknown).is_err());
+        let calendar = text.replace("max_length = 50", "max_length = 50\ncalendar = \"shift\"");
+        let e = Config::parse(&calendar).err().unwrap();
+        assert!(e.to_string().contains("Unknown calendar shift of personal"));
+        assert!(Config::parse(&format!("{calendar}\n[calendar.shift]")).is_ok());
+        let screenshot = text.replace(
+            "max_length = 50",
+            "max_length = 50\nscreenshot = \"魔力宝贝\"",
+        );
+        let e = Config::parse(&screenshot).err().unwrap();
+        assert!(e
+            .to_string()
+            .contains("Notifier personal cannot attach screenshots"));
+    }
+
+    #[test]
+    fn test_regex_fragments() {
+        let cfg = Config::parse(
+            r#"
+            [game]
+            path = ""
+            [notifier.simple]
+            [notifier.console]
+            color = ""
+            format = "{message}"
+            by_log = false
+            [notifier.ringtone]
+            audio = ""
+            device = ""
+            [notifier.dingtalk]
+            webhook = ""
+            template = ""
+            [notifier.invoke]
+            path = ""
+            workdir = ""
+            args = []
+
+            [fragment]
+            ore = ["玄铁", "秘银"]
+            buy = '(?:收|求)%{ore}'
+
+            [[trigger]]
+            regex = ["%{buy}", "急求"]
+            format = "{0}"
+            channel = "world"
+            "#,
+        )
+        .unwrap();
+        let trigger = &cfg.trigger[0];
+        assert_eq!(trigger.regex, "(?:(?:收|求)(?:玄铁|秘银))|急求");
+        assert!(trigger.try_match("收秘银").is_some());
+        assert!(trigger.try_match("急求").is_some());
+        assert!(trigger.try_match("卖玄铁").is_none());
+
+        let fragments = HashMap::from([("a".to_owned(), Pattern("%{a}".to_owned()))]);
+        assert!(Pattern::expand("%{a}", &fragments).is_err());
+        assert!(Pattern::expand("%{b}", &fragments).is_err());
+        let mut triggers = vec![Trigger::new("(")];
+        assert!(Trigger::expand_all(&mut triggers, &fragments).is_err());
+    }
+
+    #[test]
+    fn test_notifiers_routing() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        cfg.routing
+            .insert("world".to_owned(), vec!["console".to_owned()]);
+        cfg.group.insert(
+            "boss".to_owned(),
+            Group {
+                notifier: vec!["ringtone".to_owned()],
+                ..Default::default()
+            },
+        );
+        let mut trigger = Trigger::new("玄铁");
+        assert_eq!(cfg.notifiers(&trigger, &Channel::World), vec!["console"]);
+        assert!(cfg.notifiers(&trigger, &Channel::Common).is_empty());
+        cfg.trigger = vec![trigger.clone()];
+        cfg.trigger[0].group = "hunt".to_owned();
+        let e = cfg.check_groups().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Config error: Unknown group hunt of trigger 玄铁"
+        );
+        cfg.trigger[0].group = "boss".to_owned();
+        assert!(cfg.check_groups().is_ok());
+        cfg.group.get_mut("boss").unwrap().hotkey = "Ctrl+Alt".to_owned();
+        assert!(cfg.check_groups().is_err());
+        trigger.group = "boss".to_owned();
+        assert_eq!(cfg.notifiers(&trigger, &Channel::World), vec!["ringtone"]);
+        trigger.notifier = vec!["dingtalk".to_owned()];
+        assert_eq!(
+            cfg.notifiers(&trigger, &Channel::World),
+            vec!["dingtalk", "ringtone"]
+        );
+    }
+
+    #[test]
+    fn test_notifiers_priority() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        cfg.routing
+            .insert("world".to_owned(), vec!["console".to_owned()]);
+        cfg.priority.insert(
+            Priority::High,
+            vec!["ringtone".to_owned(), "dingtalk".to_owned()],
+        );
+        let mut trigger = Trigger::new("玄铁");
+        assert_eq!(cfg.notifiers(&trigger, &Channel::World), vec!["console"]);
+        trigger.priority = Priority::High;
+        assert_eq!(
+            cfg.notifiers(&trigger, &Channel::World),
+            vec!["ringtone", "dingtalk"]
+        );
+        trigger.notifier = vec!["dingtalk".to_owned(), "toast".to_owned()];
+        assert_eq!(
+            cfg.notifiers(&trigger, &Channel::World),
+            vec!["dingtalk", "toast", "ringtone"]
+        );
+        assert!(cfg.notifier_names().contains("ringtone"));
+    }
+
+    #[test]
+    fn test_afk_trigger() {
+        assert!(Afk::default().trigger().is_none());
+        let afk = Afk {
+            minutes: 10,
+            ..Default::default()
+        };
+        let trigger = afk.trigger().unwrap();
+        let matched = trigger.try_match("盛明兰oO: 在吗").unwrap();
+        assert_eq!(
+            trigger.format(&matched),
+            "{time}. 离开时收到 盛明兰oO 的密语: 在吗"
+        );
+        assert!(trigger.try_match("注销回到传送点。").is_none());
+        assert!(afk.is_away(Some(std::time::Duration::from_secs(600))));
+        assert!(!afk.is_away(Some(std::time::Duration::from_secs(599))));
+        assert!(!afk.is_away(None));
+    }
+
     #[test]
     fn test_notify_match_group() {
         let text = r#"画眉鸟离开了队伍。"#;
@@ -217,4 +3169,75 @@ mod tests {
         let fmt = trigger.format(&matched);
         println!("{:?}", fmt);
     }
+
+    #[test]
+    fn test_trigger_localized_format() {
+        #[derive(Deserialize)]
+        struct Triggers {
+            trigger: Vec<Trigger>,
+        }
+        let text = r#"
+            [[trigger]]
+            regex = "(\\w+)离开了队伍。"
+            format = "{1} 掉线了"
+            channel = "common"
+            notifier = []
+
+            [[trigger]]
+            regex = "(\\w+)离开了队伍。"
+            format.en = "{1} disconnected"
+            format.zh = "{1} 掉线了"
+            channel = "common"
+            notifier = []
+        "#;
+        let list = toml::from_str::<Triggers>(text).unwrap().trigger;
+        let matched = list[0].try_match("画眉鸟离开了队伍。").unwrap();
+        assert_eq!(list[0].format(&matched), "画眉鸟 掉线了");
+        assert!(list[0].format.langs().is_empty());
+        assert_eq!(list[1].format(&matched), "画眉鸟 掉线了");
+        assert_eq!(
+            Trigger::render(&list[1].format.langs()["en"], &matched),
+            "画眉鸟 disconnected"
+        );
+
+        let text = |lang: &str| {
+            format!(
+                r#"
+                [game]
+                path = ""
+                [dispatch]
+                lang = "{lang}"
+                [notifier.simple]
+                [notifier.console]
+                color = ""
+                format = "{{message}}"
+                by_log = false
+                [notifier.ringtone]
+                audio = ""
+                device = ""
+                [notifier.dingtalk]
+                webhook = ""
+                template = ""
+                [notifier.invoke]
+                path = ""
+                workdir = ""
+                args = []
+
+                [[trigger]]
+                regex = "(\\w+)离开了队伍。"
+                format.en = "{{1}} disconnected"
+                format.zh = "{{1}} 掉线了"
+                remind_format = "{{deadline}}"
+                channel = "common"
+                notifier = []
+                "#
+            )
+        };
+        let cfg = Config::parse(&text("en")).unwrap();
+        assert_eq!(cfg.trigger[0].format(&matched), "画眉鸟 disconnected");
+        assert_eq!(cfg.trigger[0].remind_format.text(), "{deadline}");
+        // no guessing which language to send
+        let e = Config::parse(&text("ja")).err().unwrap();
+        assert!(e.to_string().contains("has no language ja"));
+    }
 }
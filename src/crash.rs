@@ -0,0 +1,106 @@
+use super::config::Crash as Config;
+use super::dispatcher::Dispatcher;
+use super::error::Error;
+use super::event::Event;
+use super::stats::Stats;
+use super::system;
+use super::template;
+use regex::Regex;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Raises an alert when the game process exits while chat was still active,
+/// a normal quit usually goes through the character select screen first.
+pub struct CrashMonitor {
+    process: String,
+    recent: Duration,
+    normal: Option<Regex>,
+    format: String,
+    notifier: Vec<String>,
+    interval: Duration,
+}
+
+impl CrashMonitor {
+    pub fn new(cfg: &Config) -> Result<Self, Error> {
+        let normal = if cfg.normal.is_empty() {
+            None
+        } else {
+            Some(Regex::new(&cfg.normal)?)
+        };
+        Ok(Self {
+            process: cfg.process.clone(),
+            recent: Duration::from_secs(cfg.recent),
+            normal,
+            format: cfg.format.clone(),
+            notifier: cfg.notifier.clone(),
+            interval: Duration::from_secs(cfg.interval.max(1)),
+        })
+    }
+
+    /// Whether the exit looks like a crash, given how long ago the last chat line was read.
+    pub fn is_crash(&self, since_last: Option<Duration>, last: &str) -> bool {
+        let Some(since) = since_last else {
+            return false;
+        };
+        if since > self.recent {
+            return false;
+        }
+        !self.normal.as_ref().is_some_and(|re| re.is_match(last))
+    }
+
+    pub fn start(self, stats: Arc<Stats>, dispatcher: Dispatcher) {
+        log::info!("Watching process: {}", self.process);
+        thread::spawn(move || {
+            let mut running = false;
+            loop {
+                let now = system::process_running(&self.process);
+                if running && !now {
+                    let (since, last) = stats.last_line();
+                    if self.is_crash(since, &last) {
+                        let mut values = template::Context::new();
+                        values.insert("last", &last);
+                        let message = template::render(&self.format, &values);
+                        log::error!("{message}");
+                        let event = Event::plain(&message);
+                        for name in &self.notifier {
+                            let _ = dispatcher.send(name, &event);
+                        }
+                    } else {
+                        log::info!("Game exited: {}", self.process);
+                    }
+                }
+                running = now;
+                thread::sleep(self.interval);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_crash() {
+        let cfg = Config {
+            process: "cg.exe".to_owned(),
+            recent: 60,
+            normal: "注销回到传送点".to_owned(),
+            format: "{last}".to_owned(),
+            notifier: Vec::new(),
+            interval: 5,
+        };
+        let monitor = CrashMonitor::new(&cfg).unwrap();
+        assert!(monitor.is_crash(
+            Some(Duration::from_secs(10)),
+            "[世界]盛明兰oO: 半山来个合格车头"
+        ));
+        assert!(!monitor.is_crash(
+            Some(Duration::from_secs(120)),
+            "[世界]盛明兰oO: 半山来个合格车头"
+        ));
+        assert!(!monitor.is_crash(Some(Duration::from_secs(10)), "注销回到传送点。"));
+        assert!(!monitor.is_crash(None, ""));
+    }
+}
@@ -0,0 +1,163 @@
+use super::bus::{Signal, Sink};
+use super::error::Error;
+use super::event::Event;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// One attempt of a notifier to deliver an event.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Delivery {
+    /// Local time of the attempt
+    pub at: String,
+    pub notifier: String,
+    pub trigger: String,
+    pub message: String,
+    /// Milliseconds the notifier took
+    pub millis: u64,
+    /// Whether the notifier sent it, it may also have chosen not to without failing
+    pub sent: bool,
+    /// Error kind, e.g. `rejected`, `unreachable` or `timeout`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Delivery {
+    pub fn new(
+        notifier: &str,
+        event: &Event,
+        result: &Result<bool, Error>,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            at: Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            notifier: notifier.to_owned(),
+            trigger: event.trigger.clone(),
+            message: event.message.clone(),
+            millis: elapsed.as_millis() as u64,
+            sent: matches!(result, Ok(true)),
+            kind: result.as_ref().err().map(|e| e.kind().to_owned()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Every delivery attempt, one JSON per line, to find out why an alert never arrived.
+///
+/// Once the file grows past `max_size` it is renamed with a `.1` suffix, replacing the one
+/// before, so at most twice that is kept.
+pub struct Deliveries {
+    path: PathBuf,
+    /// Bytes, 0 for no limit
+    max_size: u64,
+    lock: Mutex<()>,
+}
+
+impl Deliveries {
+    pub fn new(path: PathBuf, max_size: u64) -> Self {
+        Self {
+            path,
+            max_size,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn backup(&self) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(".1");
+        name.into()
+    }
+
+    pub fn record(&self, delivery: &Delivery) -> Result<(), Error> {
+        let line = serde_json::to_string(delivery)?;
+        let _guard = self.lock.lock().unwrap();
+        let size = fs::metadata(&self.path).map_or(0, |m| m.len());
+        if self.max_size > 0 && size >= self.max_size {
+            fs::rename(&self.path, self.backup())?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+
+    /// The attempts kept, oldest first, the backup included.
+    pub fn read(&self) -> Result<Vec<Delivery>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        let mut list = Vec::new();
+        for path in [self.backup(), self.path.clone()] {
+            let text = match fs::read_to_string(&path) {
+                Ok(t) => t,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                list.push(serde_json::from_str(line)?);
+            }
+        }
+        Ok(list)
+    }
+}
+
+impl Sink for Deliveries {
+    fn handle(&self, signal: &Signal) {
+        let Signal::Delivered {
+            notifier,
+            event,
+            result,
+            elapsed,
+        } = signal
+        else {
+            return;
+        };
+        if let Err(e) = self.record(&Delivery::new(notifier, event, result, *elapsed)) {
+            log::error!("Delivery log error: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deliveries() {
+        let path = std::env::temp_dir().join("cgaid_deliveries.log");
+        let _ = fs::remove_file(&path);
+        let deliveries = Deliveries::new(path.clone(), 100);
+        let _ = fs::remove_file(deliveries.backup());
+        let mut event = Event::plain("挑战赛通道 即将刷新");
+        event.trigger = "maze".to_owned();
+        let failed = Err(crate::error::NotifierError::Timeout(Duration::from_secs(60)).into());
+        deliveries.handle(&Signal::Delivered {
+            notifier: "dingtalk",
+            event: &event,
+            result: &failed,
+            elapsed: Duration::from_secs(60),
+        });
+        deliveries.handle(&Signal::Delivered {
+            notifier: "ringtone",
+            event: &event,
+            result: &Ok(true),
+            elapsed: Duration::from_millis(1500),
+        });
+        let list = deliveries.read().unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].notifier, "dingtalk");
+        assert_eq!(list[0].trigger, "maze");
+        assert!(!list[0].sent);
+        assert_eq!(list[0].kind.as_deref(), Some("timeout"));
+        assert_eq!(list[1].millis, 1500);
+        assert!(list[1].sent && list[1].error.is_none());
+        assert!(deliveries.backup().exists());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+}
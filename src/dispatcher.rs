@@ -0,0 +1,1133 @@
+use super::alert::Alerts;
+use super::bus::{Bus, Signal, Sink};
+use super::calendar::Calendars;
+use super::config::{self, Config, Overflow};
+use super::error::{Error, NotifierError};
+use super::event::Event;
+use super::notifier::dedup::Dedup;
+use super::notifier::outbox::Outbox;
+use super::notifier::ratelimit::Bucket;
+use super::notifier::truncate::truncate;
+use super::reload::Current;
+use super::suppressed::Reason;
+use super::template;
+use super::Notifiable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Pending events of one notifier, of which at most `concurrency` are sent at once, so a slow
+/// notifier only delays itself.
+struct Lane {
+    pending: VecDeque<Arc<Event>>,
+    capacity: usize,
+    concurrency: usize,
+    running: usize,
+    dropped: u64,
+    timeouts: u64,
+    /// Rate of the notifier, `None` when unlimited
+    bucket: Option<Bucket>,
+    overflow: Overflow,
+    /// Window to collect events for, sent as one digest
+    digest: Option<Duration>,
+    /// When the first of the pending events came
+    since: Option<Instant>,
+}
+
+impl Lane {
+    const DEFAULT_CAPACITY: usize = 100;
+
+    fn new(capacity: usize, concurrency: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            capacity: if capacity == 0 {
+                Lane::DEFAULT_CAPACITY
+            } else {
+                capacity
+            },
+            concurrency: concurrency.max(1),
+            running: 0,
+            dropped: 0,
+            timeouts: 0,
+            bucket: None,
+            overflow: Overflow::default(),
+            digest: None,
+            since: None,
+        }
+    }
+
+    /// The lane of a notifier with the settings in `common`.
+    fn of(common: Option<&config::Common>) -> Self {
+        Lane::new(
+            common.map_or(0, |c| c.queue),
+            common.map_or(1, |c| c.concurrency),
+        )
+        .with_rate(
+            common.map_or(0, |c| c.rate),
+            common.map_or(0, |c| c.burst),
+            common.map_or(Overflow::default(), |c| c.overflow),
+        )
+        .with_digest(Duration::from_secs(common.map_or(0, |c| c.digest)))
+    }
+
+    /// Collects the events of `window` into one, 0 to send each.
+    fn with_digest(mut self, window: Duration) -> Self {
+        self.digest = Some(window).filter(|w| !w.is_zero());
+        self
+    }
+
+    /// The pending event a new one of the same trigger and message is merged into, none for a
+    /// digest lane, which lists each anyway.
+    fn merges(&self, event: &Event) -> Option<usize> {
+        if self.digest.is_some() || event.trigger.is_empty() {
+            return None;
+        }
+        self.pending
+            .iter()
+            .position(|p| p.trigger == event.trigger && Lane::unrepeated(p) == event.message)
+    }
+
+    /// The message of an event without the count of repeats merged into it.
+    fn unrepeated(event: &Event) -> &str {
+        let more = Lane::repeated(event.repeats);
+        match event.message.strip_suffix(&more) {
+            Some(m) if event.repeats > 0 => m,
+            _ => &event.message,
+        }
+    }
+
+    fn repeated(repeats: u32) -> String {
+        format!("\n(重复 {repeats} 次)")
+    }
+
+    fn push(&mut self, event: Arc<Event>, now: Instant) {
+        if self.pending.is_empty() {
+            self.since = Some(now);
+        }
+        // the trigger fired again before its last one went out, tell the latest once, counted
+        if let Some(i) = self.merges(&event) {
+            let repeats = self.pending[i].repeats + 1;
+            let mut merged = (*event).clone();
+            merged.repeats = repeats;
+            let more = Lane::repeated(repeats);
+            merged.message.push_str(&more);
+            for m in merged.localized.values_mut() {
+                m.push_str(&more);
+            }
+            self.pending[i] = Arc::new(merged);
+            return;
+        }
+        self.pending.push_back(event);
+    }
+
+    /// Limits the lane to `rate` events per minute, 0 for unlimited.
+    fn with_rate(mut self, rate: u32, burst: u32, overflow: Overflow) -> Self {
+        if rate > 0 {
+            self.bucket = Some(Bucket::new(rate, burst, Instant::now()));
+        }
+        self.overflow = overflow;
+        self
+    }
+
+    fn is_full(&self) -> bool {
+        self.pending.len() >= self.capacity
+    }
+
+    /// Whether an event over the rate is refused, which takes a token when it is not.
+    fn refuses(&mut self, now: Instant) -> bool {
+        self.overflow == Overflow::Drop
+            && self
+                .bucket
+                .as_mut()
+                .is_some_and(|b| b.acquire(now).is_err())
+    }
+
+    /// The next event to send, unless there is none or the lane is sending as many as it may.
+    /// Returns how long to wait when the lane is over its rate.
+    fn take(&mut self, now: Instant) -> Result<Option<Arc<Event>>, Duration> {
+        if self.running >= self.concurrency || self.pending.is_empty() {
+            return Ok(None);
+        }
+        if let Some(window) = self.digest {
+            let due = self.since.map_or(now, |s| s + window);
+            if now < due {
+                return Err(due - now);
+            }
+        }
+        let mut collapse = false;
+        // dropped ones took their tokens when dispatched
+        if let Some(b) = self
+            .bucket
+            .as_mut()
+            .filter(|_| self.overflow != Overflow::Drop)
+        {
+            b.acquire(now)?;
+            // the rest would have to wait
+            collapse =
+                self.overflow == Overflow::Collapse && b.available(now) < self.pending.len() - 1;
+        }
+        let event = if self.digest.is_some() {
+            Lane::digest(self.pending.drain(..).collect())
+        } else if collapse {
+            Lane::collapse(self.pending.drain(..).collect())
+        } else {
+            self.pending.pop_front().unwrap()
+        };
+        self.since = None;
+        self.running += 1;
+        Ok(Some(event))
+    }
+
+    /// One event listing the time and message of each, otherwise the first of the highest
+    /// priority of them, so its trigger can still be acknowledged and muted.
+    fn digest(events: Vec<Arc<Event>>) -> Arc<Event> {
+        if events.len() == 1 {
+            return Arc::clone(&events[0]);
+        }
+        let mut text = format!("{} 条匹配:", events.len());
+        for e in &events {
+            text.push_str(&format!("\n{} {}", e.time, e.message));
+        }
+        // the last of the reversed is the first
+        let top = events.iter().rev().max_by_key(|e| e.priority).unwrap();
+        let mut event = Event::clone(top);
+        event.message = text;
+        event.hint = None;
+        event.localized.clear();
+        Arc::new(event)
+    }
+
+    /// The first event, noting how many more came while waiting for the rate.
+    fn collapse(events: Vec<Arc<Event>>) -> Arc<Event> {
+        let mut event = (*events[0]).clone();
+        let more = format!("\n(另有 {} 条匹配)", events.len() - 1);
+        event.message.push_str(&more);
+        for m in event.localized.values_mut() {
+            m.push_str(&more);
+        }
+        Arc::new(event)
+    }
+}
+
+/// The lanes of all notifiers, taken in turn by a fixed number of workers.
+#[derive(Default)]
+struct Lanes {
+    lanes: HashMap<String, Lane>,
+    /// Names in the order the lanes are served
+    order: Vec<String>,
+    next: usize,
+    /// Set on shutdown, the workers leave once nothing is ready to send
+    stopping: bool,
+}
+
+impl Lanes {
+    /// The next event to send, from the lane after the one served last. Otherwise returns how
+    /// long until a lane over its rate may send, `None` when none is waiting for that.
+    fn take(&mut self, now: Instant) -> Result<(String, Arc<Event>), Option<Duration>> {
+        let mut wait: Option<Duration> = None;
+        for i in 0..self.order.len() {
+            let index = (self.next + i) % self.order.len();
+            let name = &self.order[index];
+            let Some(lane) = self.lanes.get_mut(name) else {
+                continue;
+            };
+            match lane.take(now) {
+                Ok(Some(event)) => {
+                    self.next = index + 1;
+                    return Ok((name.clone(), event));
+                }
+                Ok(None) => {}
+                Err(d) => wait = Some(wait.map_or(d, |w| w.min(d))),
+            }
+        }
+        Err(wait)
+    }
+
+    fn done(&mut self, name: &str) {
+        if let Some(l) = self.lanes.get_mut(name) {
+            l.running -= 1;
+        }
+    }
+}
+
+/// Lanes shared by the workers, with a signal for either side to wait on.
+#[derive(Default)]
+struct Pool {
+    lanes: Mutex<Lanes>,
+    /// Signalled when an event is queued or a send ends
+    work: Condvar,
+    /// Signalled when an event leaves its lane
+    room: Condvar,
+    started: OnceLock<()>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+/// Time the network sends of a notification may take when its notifier sets none.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Chars of `max` left for the message, after the text the template of the notifier and the
+/// map hint add to it.
+fn room(cfg: &Config, name: &str, event: &Event, max: usize) -> usize {
+    let mut bare = event.clone();
+    bare.message.clear();
+    bare.hint = None;
+    let template = cfg.notifier.template(name).map_or(0, |t| {
+        template::render(t, &template::context(&bare))
+            .chars()
+            .count()
+    });
+    let hint = event.hint.as_ref().map_or(0, |h| h.chars().count() + 1);
+    // at least the ellipsis
+    max.saturating_sub(template + hint).max(1)
+}
+
+/// Notifies on the worker, giving up on the notifier's network sends after `timeout`, so a hung
+/// request frees the lane while a ringtone still plays to its end. The lane stays busy until
+/// the notifier returns, keeping its sends one at a time.
+fn call(notifier: &dyn Notifiable, event: &Event, timeout: Duration) -> Result<bool, Error> {
+    super::notifier::within(timeout, || notifier.notify_event(event))
+}
+
+/// Queue depth of a notifier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueStatus {
+    pub notifier: String,
+    pub pending: usize,
+    pub running: usize,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+    /// Notifications given up on for taking too long
+    #[serde(default)]
+    pub timeouts: u64,
+}
+
+/// Notifiers by name, built once and shared, as building one may open an audio device, a
+/// connection or an HTTP client.
+#[derive(Clone)]
+pub struct Registry {
+    current: Arc<Current>,
+    built: Arc<Mutex<HashMap<String, Arc<dyn Notifiable>>>>,
+    /// Bumped by [`Registry::clear`], so a build started before is not kept
+    generation: Arc<AtomicU64>,
+    build: Build,
+}
+
+/// Builds a notifier by name, [`config::Notifier::find`] but for tests.
+type Build = fn(&Config, &str) -> Result<Box<dyn Notifiable>, Error>;
+
+impl Registry {
+    pub fn new(cfg: Arc<Config>) -> Self {
+        Self::shared(Arc::new(Current::new(cfg)))
+    }
+
+    /// Notifiers of the running config, which a reload may replace.
+    pub fn shared(current: Arc<Current>) -> Self {
+        Self {
+            current,
+            built: Arc::new(Mutex::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+            build: config::Notifier::find,
+        }
+    }
+
+    pub fn cfg(&self) -> Arc<Config> {
+        self.current.get()
+    }
+
+    /// Drops the notifiers built so far, so the next uses build them from a reloaded config.
+    pub fn clear(&self) {
+        let mut built = self.built.lock().unwrap();
+        built.clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Builds the notifiers named in the config, logging the ones failing.
+    pub fn build_all(&self) {
+        for name in self.cfg().notifier_names() {
+            if let Err(e) = self.get(&name) {
+                log::error!("Notifier {name}: {e}");
+            }
+        }
+    }
+
+    /// The notifier of the name, built on first use. A failure is not kept, so the next use
+    /// tries again, e.g. once the audio device is plugged in. Building is done without the lock,
+    /// so a slow or failing notifier does not hold up the others; of two concurrent builds the
+    /// first kept wins.
+    pub fn get(&self, name: &str) -> Result<Arc<dyn Notifiable>, Error> {
+        let generation = {
+            let built = self.built.lock().unwrap();
+            if let Some(n) = built.get(name) {
+                return Ok(Arc::clone(n));
+            }
+            self.generation.load(Ordering::Relaxed)
+        };
+        let notifier: Arc<dyn Notifiable> = (self.build)(&self.cfg(), name)?.into();
+        let mut built = self.built.lock().unwrap();
+        if self.generation.load(Ordering::Relaxed) != generation {
+            // built from the config before a reload, used this once
+            return Ok(notifier);
+        }
+        Ok(Arc::clone(built.entry(name.to_owned()).or_insert(notifier)))
+    }
+}
+
+/// Delivers events to notifiers by name on a fixed pool of workers, publishing the results on
+/// the bus.
+#[derive(Clone)]
+pub struct Dispatcher {
+    registry: Registry,
+    outbox: Option<Arc<Outbox>>,
+    /// Weak as the dispatcher itself is usually subscribed to the bus through [`Delivery`]
+    bus: Weak<Bus>,
+    pool: Arc<Pool>,
+    alerts: Arc<Alerts>,
+}
+
+impl Dispatcher {
+    pub fn new(cfg: Arc<Config>, outbox: Option<Arc<Outbox>>, bus: &Arc<Bus>) -> Self {
+        Self {
+            registry: Registry::new(cfg),
+            outbox,
+            bus: Arc::downgrade(bus),
+            pool: Arc::new(Pool::default()),
+            alerts: Arc::new(Alerts::new()),
+        }
+    }
+
+    /// Matches delivered and not acknowledged yet.
+    pub fn alerts(&self) -> &Alerts {
+        &self.alerts
+    }
+
+    /// Shares the notifiers of a registry built earlier, e.g. one also flushing the outbox,
+    /// and follows its config.
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// The running config.
+    pub fn cfg(&self) -> Arc<Config> {
+        self.registry.cfg()
+    }
+
+    /// Builds notifiers again from the config once it was reloaded, while the queues and what
+    /// they hold stay.
+    pub fn reload(&self) {
+        self.registry.clear();
+        let cfg = self.cfg();
+        let mut lanes = self.pool.lanes.lock().unwrap();
+        let names: Vec<_> = lanes.lanes.keys().cloned().collect();
+        for name in names {
+            let fresh = Lane::of(cfg.notifier.common(&name));
+            let lane = lanes.lanes.get_mut(&name).unwrap();
+            lane.capacity = fresh.capacity;
+            lane.concurrency = fresh.concurrency;
+            lane.bucket = fresh.bucket;
+            lane.overflow = fresh.overflow;
+            lane.digest = fresh.digest;
+        }
+    }
+
+    fn publish(&self, signal: &Signal) {
+        if let Some(b) = self.bus.upgrade() {
+            b.publish(signal);
+        }
+    }
+
+    /// Starts the workers on first use.
+    fn start(&self) {
+        self.pool.started.get_or_init(|| {
+            let mut workers = self.pool.workers.lock().unwrap();
+            for _ in 0..self.cfg().dispatch.workers.max(1) {
+                let dc = self.clone();
+                workers.push(thread::spawn(move || dc.work()));
+            }
+        });
+    }
+
+    /// Sends what is ready and waits for the workers to leave. Events held back by a rate or
+    /// a digest window are dropped, and later dispatches are only queued.
+    pub fn shutdown(&self) {
+        self.pool.lanes.lock().unwrap().stopping = true;
+        self.pool.work.notify_all();
+        let workers: Vec<_> = self.pool.workers.lock().unwrap().drain(..).collect();
+        for w in workers {
+            if w.join().is_err() {
+                log::error!("Dispatch worker panicked");
+            }
+        }
+    }
+
+    fn work(&self) {
+        loop {
+            let (name, event) = {
+                let mut lanes = self.pool.lanes.lock().unwrap();
+                loop {
+                    match lanes.take(Instant::now()) {
+                        Ok(next) => break next,
+                        Err(_) if lanes.stopping => return,
+                        Err(Some(d)) => lanes = self.pool.work.wait_timeout(lanes, d).unwrap().0,
+                        Err(None) => lanes = self.pool.work.wait(lanes).unwrap(),
+                    }
+                }
+            };
+            self.pool.room.notify_all();
+            let _ = self.send(&name, &event);
+            self.pool.lanes.lock().unwrap().done(&name);
+            // the lane may have more waiting for this send to end
+            self.pool.work.notify_all();
+        }
+    }
+
+    /// Delivers in the background, on the notifier's own queue. Waits a while for room when
+    /// the queue is full, returns why the event was dropped if there was none or the notifier
+    /// drops what is over its rate.
+    pub fn dispatch(&self, name: &str, event: &Arc<Event>) -> Result<(), Reason> {
+        self.start();
+        let cfg = self.cfg();
+        if cfg.notifier.composite.contains_key(name) {
+            // each member on its own queue, dropped only when all of them drop it
+            let members = cfg.notifier.members(name);
+            let results: Vec<_> = members.iter().map(|m| self.dispatch(m, event)).collect();
+            return match results.iter().find_map(|r| r.err()) {
+                Some(r) if results.iter().all(|r| r.is_err()) => Err(r),
+                _ => Ok(()),
+            };
+        }
+        let deadline = Instant::now() + Duration::from_millis(cfg.dispatch.wait);
+        let mut lanes = self.pool.lanes.lock().unwrap();
+        if !lanes.lanes.contains_key(name) {
+            let lane = Lane::of(cfg.notifier.common(name));
+            lanes.lanes.insert(name.to_owned(), lane);
+            lanes.order.push(name.to_owned());
+        }
+        let lane = lanes.lanes.get_mut(name).unwrap();
+        if lane.refuses(Instant::now()) {
+            lane.dropped += 1;
+            log::warn!("{name} over its rate, dropped: {}", event.message);
+            return Err(Reason::Rate);
+        }
+        while lanes.lanes[name].is_full() && lanes.lanes[name].merges(event).is_none() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                lanes.lanes.get_mut(name).unwrap().dropped += 1;
+                log::warn!("{name} queue full, dropped: {}", event.message);
+                return Err(Reason::Queue);
+            }
+            lanes = self.pool.room.wait_timeout(lanes, left).unwrap().0;
+        }
+        let lane = lanes.lanes.get_mut(name).unwrap();
+        lane.push(Arc::clone(event), Instant::now());
+        self.pool.work.notify_one();
+        Ok(())
+    }
+
+    pub fn queues(&self) -> Vec<QueueStatus> {
+        let lanes = self.pool.lanes.lock().unwrap();
+        let mut list: Vec<_> = lanes
+            .lanes
+            .iter()
+            .map(|(name, l)| QueueStatus {
+                notifier: name.clone(),
+                pending: l.pending.len(),
+                running: l.running,
+                dropped: l.dropped,
+                timeouts: l.timeouts,
+            })
+            .collect();
+        list.sort_by(|a, b| a.notifier.cmp(&b.notifier));
+        list
+    }
+
+    /// Delivers and waits for the result, of any member of a composite notifier.
+    pub fn send(&self, name: &str, event: &Event) -> Result<bool, Error> {
+        let cfg = self.cfg();
+        if cfg.notifier.composite.contains_key(name) {
+            let mut sent = false;
+            let mut first = None;
+            for member in cfg.notifier.members(name) {
+                match self.send(&member, event) {
+                    Ok(b) => sent |= b,
+                    Err(e) => {
+                        first.get_or_insert(e);
+                    }
+                }
+            }
+            return match first {
+                Some(e) if !sent => Err(e),
+                _ => Ok(sent),
+            };
+        }
+        let common = cfg.notifier.common(name);
+        let mut event = Cow::Borrowed(event);
+        if let Some(m) = common.and_then(|c| event.localized.get(&c.lang)) {
+            event.to_mut().message = m.clone();
+        }
+        let max = common.map_or(0, |c| c.max_length);
+        if max > 0 {
+            let room = room(&cfg, name, &event, max);
+            if event.message.chars().count() > room {
+                let keep = event.captures.get(1..).unwrap_or(&[]);
+                let message = truncate(&event.message, room, keep);
+                event.to_mut().message = message;
+            }
+        }
+        let timeout = match common.map_or(0, |c| c.timeout) {
+            0 => DEFAULT_TIMEOUT,
+            s => Duration::from_secs(s),
+        };
+        let start = Instant::now();
+        let result = self
+            .registry
+            .get(name)
+            .and_then(|o| call(o.as_ref(), &event, timeout));
+        let elapsed = start.elapsed();
+        match &result {
+            Ok(b) => log::debug!("{name} notified: {b}"),
+            Err(e) if e.is_deferred() => log::info!("{name} {e}"),
+            Err(Error::Notifier(NotifierError::Timeout(_))) => {
+                log::warn!("{name} timed out after {timeout:?}: {}", event.message);
+                let mut lanes = self.pool.lanes.lock().unwrap();
+                if let Some(lane) = lanes.lanes.get_mut(name) {
+                    lane.timeouts += 1;
+                }
+            }
+            Err(e) => {
+                log::error!("Notify error: {e}");
+                if let Some(o) = self.outbox.as_ref().filter(|_| e.is_transient()) {
+                    match o.push(name, &event) {
+                        Ok(_) => log::info!("{name} offline, saved to outbox"),
+                        Err(e) => log::error!("Outbox error: {e}"),
+                    }
+                }
+            }
+        }
+        self.publish(&Signal::Delivered {
+            notifier: name,
+            event: &event,
+            result: &result,
+            elapsed,
+        });
+        result
+    }
+}
+
+/// Sends matches to their notifiers, skipping the ones off duty or which got the same message
+/// lately.
+pub struct Delivery {
+    dispatcher: Dispatcher,
+    dedup: Dedup,
+    /// The config the calendars and dedup settings were taken from
+    applied: Mutex<(Arc<Config>, Arc<Calendars>)>,
+}
+
+impl Delivery {
+    pub fn new(dispatcher: Dispatcher, dedup: Dedup, calendars: Calendars) -> Self {
+        let cfg = dispatcher.cfg();
+        Self {
+            dispatcher,
+            dedup,
+            applied: Mutex::new((cfg, Arc::new(calendars))),
+        }
+    }
+
+    /// The calendars of `cfg`, built again once it was reloaded, when the dedup settings are
+    /// taken too.
+    fn calendars(&self, cfg: &Arc<Config>) -> Arc<Calendars> {
+        let mut applied = self.applied.lock().unwrap();
+        if !Arc::ptr_eq(&applied.0, cfg) {
+            match Calendars::new(&cfg.calendar) {
+                Ok(c) => applied.1 = Arc::new(c),
+                Err(e) => log::error!("Calendar error, keeping the previous ones: {e}"),
+            }
+            let window = Duration::from_secs(cfg.dedup.window);
+            self.dedup.configure(window, cfg.dedup.key);
+            applied.0 = Arc::clone(cfg);
+        }
+        Arc::clone(&applied.1)
+    }
+}
+
+impl Delivery {
+    /// Replaces conditional notifiers by the notifiers of their first rule holding now.
+    fn route(&self, notifiers: &[String], calendars: &Calendars) -> Vec<String> {
+        let cfg = self.dispatcher.cfg();
+        let mut presence = None;
+        let mut routed: Vec<String> = Vec::with_capacity(notifiers.len());
+        for name in notifiers {
+            let chosen = match cfg.conditional.get(name) {
+                Some(rules) => presence
+                    .get_or_insert_with(|| crate::presence::Presence::now(&cfg))
+                    .choose(rules, calendars),
+                None => std::slice::from_ref(name),
+            };
+            for n in chosen {
+                if !routed.contains(n) {
+                    routed.push(n.clone());
+                }
+            }
+        }
+        routed
+    }
+}
+
+impl Sink for Delivery {
+    fn handle(&self, signal: &Signal) {
+        if let Signal::Acked { trigger, .. } = signal {
+            // whoever acknowledged has heard enough of the alarm
+            super::notifier::Ringtone::silence();
+            self.dispatcher.alerts.clear(trigger);
+            return;
+        }
+        let Signal::Matched {
+            trigger,
+            event,
+            notifiers,
+        } = signal
+        else {
+            return;
+        };
+        // only a match reaching none of its notifiers counts as suppressed
+        let mut sent = false;
+        let mut reason = None;
+        let now = chrono::Local::now().naive_local();
+        let cfg = self.dispatcher.cfg();
+        let calendars = self.calendars(&cfg);
+        let key = self.dedup.key(event);
+        let window = (cfg.trigger.iter())
+            .find(|t| !t.name.is_empty() && t.name == event.trigger)
+            .and_then(|t| t.dedup)
+            .map_or(self.dedup.window(), Duration::from_secs);
+        // a composite notifier goes on to each member, by the member's own settings
+        let members = self.route(notifiers, &calendars).into_iter();
+        let members: Vec<_> = members
+            .flat_map(|routed| {
+                let on_duty = calendars.on_duty(cfg.notifier.common(&routed), now);
+                let members = cfg.notifier.members(&routed);
+                members.into_iter().map(move |m| (m, on_duty))
+            })
+            .collect();
+        // taken as it matched, the game may show something else by the time it is sent
+        let windows = (members.iter())
+            .filter(|(_, on_duty)| *on_duty)
+            .filter_map(|(name, _)| cfg.notifier.common(name))
+            .map(|c| c.screenshot.as_str())
+            .filter(|w| !w.is_empty());
+        let shots = super::notifier::screenshot::capture_all(windows);
+        let shot;
+        let event = if shots.is_empty() {
+            event
+        } else {
+            let mut e = Event::clone(event);
+            e.screenshots = shots;
+            shot = Arc::new(e);
+            &shot
+        };
+        for (name, on_duty) in members {
+            let name = &name;
+            if !on_duty || !calendars.on_duty(cfg.notifier.common(name), now) {
+                log::debug!("Off duty: {name} {}", event.message);
+                reason.get_or_insert(Reason::Calendar);
+                continue;
+            }
+            if !window.is_zero() && self.dedup.is_duplicate(name, &key) {
+                log::debug!("Duplicate suppressed: {name} {}", event.message);
+                reason.get_or_insert(Reason::Dedup);
+                continue;
+            }
+            match self.dispatcher.dispatch(name, event) {
+                // one that did not go out is no duplicate to the next
+                Ok(()) => {
+                    sent = true;
+                    self.dedup.record(name, &key, window);
+                }
+                Err(r) => {
+                    reason.get_or_insert(r);
+                }
+            }
+        }
+        // only named triggers can be acknowledged
+        if sent && !event.trigger.is_empty() {
+            self.dispatcher
+                .alerts
+                .raise(event, std::time::Instant::now());
+        }
+        if let Some(r) = reason.filter(|_| !sent) {
+            self.dispatcher.publish(&Signal::Suppressed {
+                trigger,
+                reason: r,
+                message: &event.message,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lane_capacity() {
+        let mut lane = Lane::new(2, 1);
+        let event = Arc::new(Event::plain("abc"));
+        lane.pending.push_back(Arc::clone(&event));
+        assert!(!lane.is_full());
+        lane.pending.push_back(Arc::clone(&event));
+        assert!(lane.is_full());
+        let now = Instant::now();
+        assert_eq!(lane.take(now).unwrap().unwrap().message, "abc");
+        // one at a time
+        assert!(lane.take(now).unwrap().is_none());
+        lane.running = 0;
+        assert!(lane.take(now).unwrap().is_some());
+        assert_eq!(Lane::new(0, 0).capacity, Lane::DEFAULT_CAPACITY);
+        assert_eq!(Lane::new(0, 0).concurrency, 1);
+    }
+
+    #[test]
+    fn test_lane_merge() {
+        let mut lane = Lane::new(0, 1);
+        let now = Instant::now();
+        let mut event = Event::plain("boss");
+        event.trigger = "boss".to_owned();
+        lane.push(Arc::new(event.clone()), now);
+        assert_eq!(lane.take(now).unwrap().unwrap().repeats, 0);
+        // still sending the first one
+        for _ in 0..3 {
+            lane.push(Arc::new(event.clone()), now);
+        }
+        lane.push(Arc::new(Event::plain("other")), now);
+        // the same trigger saying something else is told on its own
+        let mut other = event.clone();
+        other.message = "boss 2".to_owned();
+        lane.push(Arc::new(other), now);
+        assert_eq!(lane.pending.len(), 3);
+        lane.running = 0;
+        let merged = lane.take(now).unwrap().unwrap();
+        assert_eq!(merged.repeats, 2);
+        assert_eq!(merged.message, "boss\n(重复 2 次)");
+        // a digest lists each
+        let mut lane = Lane::new(0, 1).with_digest(Duration::from_secs(1));
+        lane.push(Arc::new(event.clone()), now);
+        lane.push(Arc::new(event), now);
+        assert_eq!(lane.pending.len(), 2);
+    }
+
+    #[test]
+    fn test_room() {
+        let cfg = Config::load("config.toml").unwrap();
+        let mut event = Event::plain("BOSS 出现了");
+        // "Notice: " of the template
+        assert_eq!(room(&cfg, "dingtalk", &event, 50), 42);
+        assert_eq!(room(&cfg, "simple", &event, 50), 50);
+        event.hint = Some("法兰城 (12,34)".to_owned());
+        assert_eq!(room(&cfg, "dingtalk", &event, 50), 30);
+        assert_eq!(room(&cfg, "dingtalk", &event, 10), 1);
+    }
+
+    #[test]
+    fn test_call_timeout() {
+        struct Hung;
+        impl Notifiable for Hung {
+            fn notify(&self, _: &str) -> Result<bool, Error> {
+                super::super::notifier::block_on(async {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    Ok(true)
+                })
+            }
+        }
+        struct Ringing;
+        impl Notifiable for Ringing {
+            fn notify(&self, _: &str) -> Result<bool, Error> {
+                thread::sleep(Duration::from_millis(100));
+                Ok(true)
+            }
+        }
+        let event = Event::plain("abc");
+        let e = call(&Hung, &event, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(e.kind(), "timeout");
+        assert!(!e.is_transient());
+        assert!(call(&Hung, &event, Duration::from_secs(5)).unwrap());
+        // not sending over the network, played to its end
+        assert!(call(&Ringing, &event, Duration::from_millis(10)).unwrap());
+    }
+
+    #[test]
+    fn test_lane_digest() {
+        let now = Instant::now();
+        let window = Duration::from_secs(300);
+        let mut lane = Lane::new(0, 1).with_digest(window);
+        let mut first = Event::plain("摆摊 玄铁");
+        first.time = "12:00:00".to_owned();
+        lane.push(Arc::new(first), now);
+        let mut second = Event::plain("摆摊 秘银");
+        second.time = "12:01:00".to_owned();
+        second.trigger = "stall".to_owned();
+        second.priority = config::Priority::High;
+        lane.push(Arc::new(second), now + Duration::from_secs(60));
+        assert_eq!(
+            lane.take(now + Duration::from_secs(60)).err(),
+            Some(Duration::from_secs(240))
+        );
+        let digest = lane.take(now + window).unwrap().unwrap();
+        assert_eq!(
+            digest.message,
+            "2 条匹配:\n12:00:00 摆摊 玄铁\n12:01:00 摆摊 秘银"
+        );
+        assert_eq!(digest.priority, config::Priority::High);
+        assert_eq!(digest.trigger, "stall");
+        assert!(lane.pending.is_empty() && lane.since.is_none());
+    }
+
+    #[test]
+    fn test_lanes_in_turn() {
+        let mut lanes = Lanes::default();
+        for name in ["a", "b"] {
+            let mut lane = Lane::new(0, 1);
+            lane.pending.push_back(Arc::new(Event::plain(name)));
+            lane.pending.push_back(Arc::new(Event::plain(name)));
+            lanes.lanes.insert(name.to_owned(), lane);
+            lanes.order.push(name.to_owned());
+        }
+        let now = Instant::now();
+        assert_eq!(lanes.take(now).unwrap().0, "a");
+        assert_eq!(lanes.take(now).unwrap().0, "b");
+        // both are busy sending
+        assert_eq!(lanes.take(now).unwrap_err(), None);
+        lanes.done("b");
+        assert_eq!(lanes.take(now).unwrap().0, "b");
+        lanes.done("a");
+        assert_eq!(lanes.take(now).unwrap().0, "a");
+    }
+
+    #[test]
+    fn test_lane_rate() {
+        let now = Instant::now();
+        let event = Arc::new(Event::plain("abc"));
+        let mut lane = Lane::new(0, 3).with_rate(60, 1, Overflow::Queue);
+        for _ in 0..3 {
+            lane.pending.push_back(Arc::clone(&event));
+        }
+        assert!(lane.take(now).unwrap().is_some());
+        let wait = lane.take(now).unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+        assert!(lane.take(now + wait).unwrap().is_some());
+
+        let mut lane = Lane::new(0, 3).with_rate(60, 2, Overflow::Collapse);
+        lane.pending.push_back(Arc::clone(&event));
+        lane.pending.push_back(Arc::clone(&event));
+        // both within the burst
+        assert_eq!(lane.take(now).unwrap().unwrap().message, "abc");
+        assert_eq!(lane.take(now).unwrap().unwrap().message, "abc");
+        for _ in 0..3 {
+            lane.pending.push_back(Arc::clone(&event));
+        }
+        assert!(lane.take(now).is_err());
+        let later = now + Duration::from_secs(1);
+        let collapsed = lane.take(later).unwrap().unwrap();
+        assert_eq!(collapsed.message, "abc\n(另有 2 条匹配)");
+        assert!(lane.pending.is_empty());
+
+        let mut lane = Lane::new(0, 1).with_rate(60, 1, Overflow::Drop);
+        assert!(!lane.refuses(now));
+        assert!(lane.refuses(now));
+        lane.pending.push_back(event);
+        // took its token when dispatched
+        assert!(lane.take(now).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_lane_of_dingtalk() {
+        let cfg = Config::load("config.toml").unwrap();
+        let common = cfg.notifier.common("dingtalk");
+        assert_eq!(common.unwrap().rate, 20);
+        let mut lane = Lane::of(common);
+        // a minute's worth at once, then paced
+        assert_eq!(lane.bucket.as_mut().unwrap().available(Instant::now()), 20);
+    }
+
+    #[test]
+    fn test_dispatch_backpressure() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        cfg.dispatch.wait = 50;
+        let dispatcher = Dispatcher::new(Arc::new(cfg), None, &Arc::new(Bus::new()));
+        {
+            // a lane busy sending with a full queue
+            let mut lanes = dispatcher.pool.lanes.lock().unwrap();
+            let mut lane = Lane::new(1, 1);
+            lane.running = 1;
+            lane.pending.push_back(Arc::new(Event::plain("abc")));
+            lanes.lanes.insert("slow".to_owned(), lane);
+            lanes.order.push("slow".to_owned());
+        }
+        let event = Arc::new(Event::plain("def"));
+        let start = Instant::now();
+        assert_eq!(dispatcher.dispatch("slow", &event), Err(Reason::Queue));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(dispatcher.queues()[0].dropped, 1);
+
+        let pool = Arc::clone(&dispatcher.pool);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            pool.lanes
+                .lock()
+                .unwrap()
+                .lanes
+                .get_mut("slow")
+                .unwrap()
+                .pending
+                .clear();
+            pool.room.notify_all();
+        });
+        assert!(dispatcher.dispatch("slow", &event).is_ok());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_registry() {
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let registry = Registry::new(cfg);
+        let simple = registry.get("simple").unwrap();
+        assert!(Arc::ptr_eq(&simple, &registry.get("simple").unwrap()));
+        assert!(registry.get("nothing").is_err());
+        assert!(!registry.built.lock().unwrap().contains_key("nothing"));
+    }
+
+    #[test]
+    fn test_registry_slow_build() {
+        static RELEASE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let mut registry = Registry::new(cfg);
+        registry.build = |cfg, name| {
+            if name == "slow" {
+                while !RELEASE.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                return config::Notifier::find(cfg, "simple");
+            }
+            config::Notifier::find(cfg, name)
+        };
+        let rc = registry.clone();
+        let slow = thread::spawn(move || rc.get("slow").unwrap());
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        registry.get("simple").unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!slow.is_finished());
+        RELEASE.store(true, Ordering::Relaxed);
+        let built = slow.join().unwrap();
+        assert!(Arc::ptr_eq(&built, &registry.get("slow").unwrap()));
+    }
+
+    #[test]
+    fn test_dispatch_composite() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        cfg.notifier.composite.insert(
+            "both".to_owned(),
+            config::Composite {
+                notifier: vec!["simple".to_owned(), "console".to_owned()],
+                ..Default::default()
+            },
+        );
+        let dispatcher = Dispatcher::new(Arc::new(cfg), None, &Arc::new(Bus::new()));
+        dispatcher
+            .dispatch("both", &Arc::new(Event::plain("abc")))
+            .unwrap();
+        // each member on its own lane
+        let names: Vec<_> = dispatcher
+            .queues()
+            .into_iter()
+            .map(|q| q.notifier)
+            .collect();
+        assert_eq!(names, ["console", "simple"]);
+        assert!(dispatcher.send("both", &Event::plain("def")).unwrap());
+    }
+
+    #[test]
+    fn test_dispatch_lanes() {
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let dispatcher = Dispatcher::new(cfg, None, &Arc::new(Bus::new()));
+        dispatcher
+            .dispatch("simple", &Arc::new(Event::plain("abc")))
+            .unwrap();
+        for _ in 0..100 {
+            if dispatcher.queues()[0].pending == 0 && dispatcher.queues()[0].running == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let queues = dispatcher.queues();
+        assert_eq!(queues.len(), 1);
+        assert_eq!(queues[0].notifier, "simple");
+        assert_eq!(queues[0].pending, 0);
+    }
+
+    #[test]
+    fn test_dispatch_shutdown() {
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let dispatcher = Dispatcher::new(cfg, None, &Arc::new(Bus::new()));
+        let event = Arc::new(Event::plain("abc"));
+        dispatcher.dispatch("simple", &event).unwrap();
+        dispatcher.dispatch("simple", &event).unwrap();
+        // returns once the workers sent what was queued and left
+        dispatcher.shutdown();
+        let queues = dispatcher.queues();
+        assert_eq!((queues[0].pending, queues[0].running), (0, 0));
+        assert!(dispatcher.pool.workers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delivery() {
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let bus = Arc::new(Bus::new());
+        let dispatcher = Dispatcher::new(cfg, None, &bus);
+        let suppressed = Arc::new(crate::suppressed::Suppressed::new());
+        bus.subscribe(Arc::new(Delivery::new(
+            dispatcher.clone(),
+            Dedup::new(Duration::from_secs(60)),
+            Calendars::default(),
+        )));
+        bus.subscribe(Arc::clone(&suppressed) as Arc<dyn Sink>);
+        let event = Arc::new(Event::plain("挑战赛通道 即将刷新"));
+        let signal = Signal::Matched {
+            trigger: "maze",
+            event: &event,
+            notifiers: &["simple".to_owned()],
+        };
+        bus.publish(&signal);
+        assert_eq!(suppressed.take().1, 0);
+        bus.publish(&signal);
+        let (entries, count) = suppressed.take();
+        assert_eq!(count, 1);
+        assert_eq!(entries[0].reason, Reason::Dedup);
+        assert_eq!(entries[0].trigger, "maze");
+    }
+
+    #[test]
+    fn test_delivery_route() {
+        let mut cfg = Config::load("config.toml").unwrap();
+        let rule = |calendar: &str, notifier: &str| crate::config::Condition {
+            calendar: calendar.to_owned(),
+            notifier: vec![notifier.to_owned()],
+            ..Default::default()
+        };
+        cfg.conditional.insert(
+            "alert".to_owned(),
+            vec![rule("", "simple"), rule("", "toast")],
+        );
+        let delivery = Delivery::new(
+            Dispatcher::new(Arc::new(cfg), None, &Arc::new(Bus::new())),
+            Dedup::new(Duration::from_secs(60)),
+            Calendars::default(),
+        );
+        let names = ["beep", "alert", "simple"].map(str::to_owned);
+        let calendars = Calendars::default();
+        assert_eq!(delivery.route(&names, &calendars), ["beep", "simple"]);
+    }
+}
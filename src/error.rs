@@ -0,0 +1,222 @@
+use std::io;
+use thiserror::Error;
+
+/// Errors of the crate, split by what went wrong so callers can decide to retry, store or give up.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Invalid configuration, e.g. a bad regex or an unknown notifier
+    #[error("Config error: {0}")]
+    Config(String),
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Malformed data such as JSON, an audio file or a history entry
+    #[error("Parse error: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Notifier(#[from] NotifierError),
+}
+
+/// Failures of delivering a notification.
+#[derive(Debug, Error)]
+pub enum NotifierError {
+    /// The remote answered but refused the message, resending will not help
+    #[error("Rejected with status {status}: {reason}")]
+    Rejected { status: u16, reason: String },
+    /// The remote could not be reached, the message may be sent later
+    #[error("Network unreachable: {0}")]
+    Unreachable(String),
+    /// A local device such as the audio output is missing
+    #[error("Device missing: {0}")]
+    DeviceMissing(String),
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+    /// The notifier did not finish in time and was left running
+    #[error("Timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    /// Held in the notifier's own queue, to be sent later
+    #[error("Deferred: {0}")]
+    Deferred(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Error {
+    /// Short name of the error kind, as reported by the status API.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Io(_) => "io",
+            Error::Parse(_) => "parse",
+            Error::Notifier(n) => match n {
+                NotifierError::Rejected { .. } => "rejected",
+                NotifierError::Unreachable(_) => "unreachable",
+                NotifierError::DeviceMissing(_) => "device",
+                NotifierError::Unsupported(_) => "unsupported",
+                NotifierError::Timeout(_) => "timeout",
+                NotifierError::Deferred(_) => "deferred",
+                NotifierError::Other(_) => "notifier",
+            },
+        }
+    }
+
+    /// Whether the error was caused by connectivity rather than the remote rejecting the message.
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Error::Notifier(NotifierError::Unreachable(_)))
+    }
+
+    /// Whether the notification was queued rather than sent, neither delivered nor failed yet.
+    pub fn is_deferred(&self) -> bool {
+        matches!(self, Error::Notifier(NotifierError::Deferred(_)))
+    }
+
+    /// Whether resending later may succeed: the remote was unreachable, failing itself or asking
+    /// to slow down.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Notifier(NotifierError::Rejected { status, .. }) => {
+                *status >= 500 || *status == 429
+            }
+            _ => self.is_offline(),
+        }
+    }
+
+    pub fn device(message: impl ToString) -> Self {
+        NotifierError::DeviceMissing(message.to_string()).into()
+    }
+
+    pub fn notifier(message: impl ToString) -> Self {
+        NotifierError::Other(message.to_string()).into()
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_connect() || e.is_timeout() {
+            NotifierError::Unreachable(e.to_string()).into()
+        } else if let Some(status) = e.status() {
+            NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: e.to_string(),
+            }
+            .into()
+        } else if e.is_decode() {
+            Error::Parse(e.to_string())
+        } else if e.is_builder() {
+            Error::Config(e.to_string())
+        } else {
+            Error::notifier(e)
+        }
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        match e.status() {
+            // 5xx, resending will not help
+            Some(code) if e.is_permanent() => NotifierError::Rejected {
+                status: code.into(),
+                reason: e.to_string(),
+            }
+            .into(),
+            _ if e.is_client() => Error::Config(e.to_string()),
+            // connection, TLS and 4xx errors, the server may take it later
+            _ => NotifierError::Unreachable(e.to_string()).into(),
+        }
+    }
+}
+
+impl From<tokio_xmpp::Error> for Error {
+    fn from(e: tokio_xmpp::Error) -> Self {
+        match e {
+            tokio_xmpp::Error::Auth(_) | tokio_xmpp::Error::JidParse(_) => {
+                Error::Config(e.to_string())
+            }
+            _ => NotifierError::Unreachable(e.to_string()).into(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode;
+        match e.sqlite_error_code() {
+            // another process holds the database, it may be free later
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+                NotifierError::Unreachable(e.to_string()).into()
+            }
+            Some(ErrorCode::CannotOpen | ErrorCode::ReadOnly | ErrorCode::PermissionDenied) => {
+                Error::Config(e.to_string())
+            }
+            _ => Error::notifier(e),
+        }
+    }
+}
+
+impl From<serialport::Error> for Error {
+    fn from(e: serialport::Error) -> Self {
+        match e.kind() {
+            serialport::ErrorKind::NoDevice => Error::device(e),
+            serialport::ErrorKind::InvalidInput => Error::Config(e.to_string()),
+            serialport::ErrorKind::Io(kind) => Error::Io(io::Error::new(kind, e.description)),
+            serialport::ErrorKind::Unknown => Error::notifier(e),
+        }
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let e: Error = NotifierError::Unreachable("timeout".to_owned()).into();
+        assert!(e.is_offline());
+        assert_eq!(e.kind(), "unreachable");
+        let e: Error = NotifierError::Rejected {
+            status: 403,
+            reason: "Forbidden".to_owned(),
+        }
+        .into();
+        assert!(!e.is_offline());
+        assert!(!e.is_transient());
+        assert_eq!(e.to_string(), "Rejected with status 403: Forbidden");
+        let e: Error = NotifierError::Rejected {
+            status: 502,
+            reason: "Bad Gateway".to_owned(),
+        }
+        .into();
+        assert!(e.is_transient());
+        assert_eq!(Error::device("no output").kind(), "device");
+        let e: Error = toml::from_str::<toml::Value>("a =").unwrap_err().into();
+        assert_eq!(e.kind(), "config");
+    }
+
+    #[test]
+    fn test_error_reqwest() {
+        // nothing listens on port 1
+        let e: Error = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(reqwest::Client::new().get("http://127.0.0.1:1/").send())
+            .unwrap_err()
+            .into();
+        assert!(e.is_offline());
+    }
+}
@@ -0,0 +1,132 @@
+use super::chat::coord::Coord;
+use super::chat::record::{Channel, Record};
+use super::config::{Mention, Priority, Trigger};
+use super::notifier::screenshot::Png;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A matched chat record with everything a notifier may want to send on.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub time: String,
+    pub channel: Channel,
+    pub sender: Option<String>,
+    pub raw: String,
+    pub trigger: String,
+    pub priority: Priority,
+    pub captures: Vec<String>,
+    pub message: String,
+    pub coord: Option<Coord>,
+    /// Map or teleport hint rendered from the trigger's `map` template
+    pub hint: Option<String>,
+    /// Names of the game clients that saw the record
+    pub clients: Vec<String>,
+    /// Tags of the trigger, e.g. emoji short codes for ntfy
+    pub tags: Vec<String>,
+    /// Times the trigger fired again while this was waiting to be sent
+    #[serde(default)]
+    pub repeats: u32,
+    /// The message in other languages, for notifiers set to one
+    #[serde(skip)]
+    pub localized: HashMap<String, String>,
+    /// Screenshots taken when matched, by window title, for notifiers attaching one
+    #[serde(skip)]
+    pub screenshots: HashMap<String, Png>,
+}
+
+impl Event {
+    pub fn new(record: &Record, trigger: &Trigger, captures: Vec<String>, message: String) -> Self {
+        let coord = record.coord();
+        let hint = coord
+            .filter(|_| !trigger.map.is_empty())
+            .map(|c| c.fill(&trigger.map));
+        Self {
+            time: record.fmt_time(),
+            channel: record.get_channel().clone(),
+            sender: record.sender().map(|v| v.to_owned()),
+            raw: record.msg().to_owned(),
+            trigger: trigger.name.clone(),
+            priority: trigger.priority,
+            captures,
+            message,
+            coord,
+            hint,
+            clients: Vec::new(),
+            tags: trigger.tags.clone(),
+            repeats: 0,
+            localized: HashMap::new(),
+            screenshots: HashMap::new(),
+        }
+    }
+
+    /// An event carrying only a message, for notifications not caused by a chat record.
+    pub fn plain(message: &str) -> Self {
+        Self {
+            time: chrono::Local::now().format("%H:%M:%S").to_string(),
+            channel: Channel::Common,
+            sender: None,
+            raw: message.to_owned(),
+            trigger: String::new(),
+            priority: Priority::Normal,
+            captures: Vec::new(),
+            message: message.to_owned(),
+            coord: None,
+            hint: None,
+            clients: Vec::new(),
+            tags: Vec::new(),
+            repeats: 0,
+            localized: HashMap::new(),
+            screenshots: HashMap::new(),
+        }
+    }
+
+    /// People in the mention table who sent the record or were captured by the trigger.
+    pub fn mentions<'a>(&self, table: &'a [Mention]) -> Vec<&'a Mention> {
+        table
+            .iter()
+            .filter(|m| {
+                self.sender.as_deref() == Some(m.name.as_str())
+                    || self.captures.iter().skip(1).any(|c| c == &m.name)
+            })
+            .collect()
+    }
+
+    /// The message followed by the map hint, if any.
+    pub fn with_hint(&self) -> String {
+        match &self.hint {
+            Some(h) => format!("{}\n{}", self.message, h),
+            None => self.message.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let record = Record::from("21:40:12丂[世界]盛明兰oO: 半山来个合格车头").unwrap();
+        let trigger = Trigger::new(r#"半山(\w+)"#);
+        let captures = trigger.try_match(record.msg()).unwrap();
+        let message = trigger.format(&captures);
+        let event = Event::new(&record, &trigger, captures, message);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["time"], "21:40:12");
+        assert_eq!(json["channel"], "world");
+        assert_eq!(json["sender"], "盛明兰oO");
+        assert_eq!(json["captures"][1], "来个合格车头");
+        assert!(json["coord"].is_null());
+    }
+
+    #[test]
+    fn test_event_hint() {
+        let record = Record::from("21:40:12丂[地图]盛明兰oO: 王在(123,456)").unwrap();
+        let mut trigger = Trigger::new(r#"王在"#);
+        trigger.map = "传送: {x},{y}".to_owned();
+        let captures = trigger.try_match(record.msg()).unwrap();
+        let event = Event::new(&record, &trigger, captures, "王出现了".to_owned());
+        assert_eq!(event.coord, Some(Coord { x: 123, y: 456 }));
+        assert_eq!(event.with_hint(), "王出现了\n传送: 123,456");
+    }
+}
@@ -0,0 +1,264 @@
+use super::config;
+use chrono::NaiveTime;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A time range of the day, wrapping over midnight when `end` is before `start`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Period {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Period {
+    /// Parses `HH:MM-HH:MM`, the end may be `24:00` for the end of the day.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (start, end) = text.split_once('-')?;
+        let parse = |v: &str| match v.trim() {
+            // the leap second representation, after any other time of the day
+            "24:00" => NaiveTime::from_hms_milli_opt(23, 59, 59, 1999),
+            v => NaiveTime::parse_from_str(v, "%H:%M").ok(),
+        };
+        Some(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        self.starts_by(time) || self.carries_over(time)
+    }
+
+    /// Whether `time` is in the part of the period on the day it starts.
+    pub fn starts_by(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start
+        }
+    }
+
+    /// Whether `time` is in the part after midnight of a period crossing it.
+    pub fn carries_over(&self, time: NaiveTime) -> bool {
+        self.start > self.end && time < self.end
+    }
+}
+
+struct State {
+    enabled: bool,
+    cooldown: Duration,
+    schedule: Vec<Period>,
+    last: Option<Instant>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub name: String,
+    pub enabled: bool,
+    /// Seconds left before the group may fire again
+    pub cooldown: u64,
+}
+
+/// Runtime state of trigger groups, enabled flags can be toggled while running.
+pub struct Groups {
+    states: Mutex<HashMap<String, State>>,
+}
+
+impl Groups {
+    pub fn new(groups: &HashMap<String, config::Group>) -> Result<Self, String> {
+        let mut states = HashMap::new();
+        for (name, g) in groups {
+            let schedule = g
+                .schedule
+                .iter()
+                .map(|v| Period::parse(v).ok_or_else(|| format!("Invalid schedule: {name} {v}")))
+                .collect::<Result<_, _>>()?;
+            states.insert(
+                name.clone(),
+                State {
+                    enabled: g.enabled,
+                    cooldown: Duration::from_secs(g.cooldown),
+                    schedule,
+                    last: None,
+                },
+            );
+        }
+        Ok(Self {
+            states: Mutex::new(states),
+        })
+    }
+
+    /// Takes the groups of a reloaded config, their cooldowns going on from the last firings.
+    pub fn update(&self, groups: &HashMap<String, config::Group>) -> Result<(), String> {
+        let fresh = Groups::new(groups)?.states.into_inner().unwrap();
+        let mut states = self.states.lock().unwrap();
+        let old = std::mem::replace(&mut *states, fresh);
+        for (name, s) in old {
+            if let Some(state) = states.get_mut(&name) {
+                state.last = s.last;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a trigger of the group may fire at `time` of the day, records the firing if so.
+    /// Triggers without a group or with an unknown one always fire.
+    pub fn allow(&self, group: &str, time: NaiveTime, now: Instant) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let Some(state) = states.get_mut(group) else {
+            return true;
+        };
+        if !state.enabled {
+            return false;
+        }
+        if !state.schedule.is_empty() && !state.schedule.iter().any(|p| p.contains(time)) {
+            return false;
+        }
+        if state
+            .last
+            .is_some_and(|t| now.duration_since(t) < state.cooldown)
+        {
+            return false;
+        }
+        state.last = Some(now);
+        true
+    }
+
+    /// Unix time each group last fired, by `now` and `unix` both being the current time.
+    pub fn fired(&self, now: Instant, unix: i64) -> BTreeMap<String, i64> {
+        let states = self.states.lock().unwrap();
+        states
+            .iter()
+            .filter_map(|(name, s)| {
+                let ago = now.duration_since(s.last?).as_secs() as i64;
+                Some((name.clone(), unix - ago))
+            })
+            .collect()
+    }
+
+    /// Restores the last firings saved by [`Groups::fired`], so cooldowns go on after a restart.
+    pub fn restore(&self, fired: &BTreeMap<String, i64>, now: Instant, unix: i64) {
+        let mut states = self.states.lock().unwrap();
+        for (name, t) in fired {
+            if let Some(s) = states.get_mut(name) {
+                let ago = Duration::from_secs((unix - t).max(0) as u64);
+                s.last = now.checked_sub(ago);
+            }
+        }
+    }
+
+    /// Enables or disables a group, returns `false` if there is no such group.
+    pub fn set_enabled(&self, group: &str, enabled: bool) -> bool {
+        match self.states.lock().unwrap().get_mut(group) {
+            Some(s) => {
+                s.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Enables a disabled group or disables an enabled one, returns whether it is now enabled,
+    /// `None` if there is no such group.
+    pub fn toggle(&self, group: &str) -> Option<bool> {
+        let mut states = self.states.lock().unwrap();
+        let s = states.get_mut(group)?;
+        s.enabled = !s.enabled;
+        Some(s.enabled)
+    }
+
+    pub fn list(&self) -> Vec<Status> {
+        let states = self.states.lock().unwrap();
+        let mut list: Vec<_> = states
+            .iter()
+            .map(|(name, s)| Status {
+                name: name.clone(),
+                enabled: s.enabled,
+                cooldown: s
+                    .last
+                    .map_or(0, |t| s.cooldown.saturating_sub(t.elapsed()).as_secs()),
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_period() {
+        let p = Period::parse("20:00-23:30").unwrap();
+        assert!(p.contains(time(21, 0)));
+        assert!(!p.contains(time(23, 30)));
+        let p = Period::parse("22:00 - 02:00").unwrap();
+        assert!(p.contains(time(23, 0)));
+        assert!(p.contains(time(1, 0)));
+        assert!(!p.contains(time(12, 0)));
+        assert!(Period::parse("22:00").is_none());
+        let p = Period::parse("18:00-24:00").unwrap();
+        assert!(p.contains(NaiveTime::from_hms_opt(23, 59, 59).unwrap()));
+        assert!(!p.contains(time(0, 0)));
+    }
+
+    #[test]
+    fn test_groups() {
+        let groups = HashMap::from([(
+            "boss".to_owned(),
+            config::Group {
+                enabled: true,
+                notifier: Vec::new(),
+                cooldown: 60,
+                schedule: vec!["20:00-23:00".to_owned()],
+                hotkey: String::new(),
+            },
+        )]);
+        let groups = Groups::new(&groups).unwrap();
+        let now = Instant::now();
+        assert!(groups.allow("", time(1, 0), now));
+        assert!(!groups.allow("boss", time(1, 0), now));
+        assert!(groups.allow("boss", time(21, 0), now));
+        assert!(!groups.allow("boss", time(21, 0), now + Duration::from_secs(30)));
+        assert!(groups.allow("boss", time(21, 0), now + Duration::from_secs(60)));
+
+        assert!(groups.set_enabled("boss", false));
+        assert!(!groups.allow("boss", time(21, 0), now + Duration::from_secs(600)));
+        assert!(!groups.list()[0].enabled);
+        assert!(!groups.set_enabled("other", false));
+        assert_eq!(groups.toggle("boss"), Some(true));
+        assert_eq!(groups.toggle("boss"), Some(false));
+        assert_eq!(groups.toggle("other"), None);
+
+        let fired = groups.fired(now + Duration::from_secs(70), 10_000);
+        assert_eq!(fired["boss"], 10_000 - 10);
+        let mut edited = HashMap::from([("boss".to_owned(), config::Group::default())]);
+        edited.get_mut("boss").unwrap().cooldown = 60;
+        groups.update(&edited).unwrap();
+        // enabled as the edit says, still cooling down from the last firing
+        assert!(groups.list()[0].enabled);
+        assert!(!groups.allow("boss", time(1, 0), now + Duration::from_secs(90)));
+        assert!(groups.allow("boss", time(1, 0), now + Duration::from_secs(120)));
+        edited.get_mut("boss").unwrap().schedule = vec!["x".to_owned()];
+        assert!(groups.update(&edited).is_err());
+        let groups = Groups::new(&HashMap::from([(
+            "boss".to_owned(),
+            config::Group {
+                cooldown: 60,
+                ..Default::default()
+            },
+        )]))
+        .unwrap();
+        let later = Instant::now();
+        groups.restore(&fired, later, 10_030);
+        assert!(!groups.allow("boss", time(21, 0), later));
+        assert!(groups.allow("boss", time(21, 0), later + Duration::from_secs(20)));
+    }
+}
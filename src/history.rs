@@ -0,0 +1,371 @@
+use super::bus::{Signal, Sink};
+use super::error::Error;
+use super::event::Event;
+use super::stats::Ack;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// An acknowledgment line, `delay` in seconds after the first alert.
+#[derive(Debug, Serialize, Deserialize)]
+struct Acked {
+    ack: String,
+    delay: u64,
+}
+
+/// Matched events kept on disk, one per line as `{unix time}\t{event}`, along with the
+/// acknowledgments as `{unix time}\t{"ack": trigger, "delay": seconds}`.
+///
+/// With a key the event JSON is encrypted with AES-256-GCM and stored as base64 of nonce and
+/// ciphertext. The AES key is derived from the passphrase with PBKDF2-HMAC-SHA256, its rounds and
+/// random salt kept in the header line `pbkdf2\t{rounds}\t{salt}` of the file. The time stays plain
+/// so old lines can be purged without the key.
+pub struct History {
+    path: PathBuf,
+    cipher: Option<Aes256Gcm>,
+    /// Days to keep, 0 for ever
+    days: u64,
+    lock: Arc<Mutex<()>>,
+    /// Lines of the sink, appended on a thread of its own to not hold up the pipeline
+    writer: Sender<String>,
+}
+
+impl History {
+    const NONCE_LEN: usize = 12;
+    const SALT_LEN: usize = 16;
+    /// PBKDF2 rounds of a new file, as OWASP recommends for HMAC-SHA256
+    #[cfg(not(test))]
+    const ROUNDS: u32 = 600_000;
+    /// Far too slow unoptimized, the rounds being read from the header any count works
+    #[cfg(test)]
+    const ROUNDS: u32 = 1_000;
+    const HEADER: &'static str = "pbkdf2";
+
+    /// Reads the salt of the file at `path`, writing a header with a new one first if it has none.
+    pub fn new(path: PathBuf, key: &str, days: u64) -> Result<Self, Error> {
+        let cipher = if key.is_empty() {
+            None
+        } else {
+            let (rounds, salt) = History::salt(&path)?;
+            let mut derived = [0_u8; 32];
+            pbkdf2::pbkdf2_hmac::<Sha256>(key.as_bytes(), &salt, rounds, &mut derived);
+            Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived)))
+        };
+        let lock = Arc::new(Mutex::new(()));
+        let (writer, lines) = mpsc::channel::<String>();
+        let (wp, wl) = (path.clone(), Arc::clone(&lock));
+        thread::spawn(move || {
+            for line in lines {
+                if let Err(e) = History::write(&wp, &wl, &line) {
+                    log::error!("History error: {e}");
+                }
+            }
+        });
+        Ok(Self {
+            path,
+            cipher,
+            days,
+            lock,
+            writer,
+        })
+    }
+
+    /// The rounds and salt from the header of the file, a new header prepended when missing.
+    fn salt(path: &Path) -> Result<(u32, Vec<u8>), Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let first = text.lines().next().unwrap_or_default();
+        if let Some(header) = History::header(first) {
+            return header;
+        }
+        let mut salt = vec![0_u8; History::SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let header = format!(
+            "{}\t{}\t{}\n",
+            History::HEADER,
+            History::ROUNDS,
+            BASE64.encode(&salt)
+        );
+        fs::write(path, header + &text)?;
+        Ok((History::ROUNDS, salt))
+    }
+
+    fn header(line: &str) -> Option<Result<(u32, Vec<u8>), Error>> {
+        let mut parts = line.split('\t');
+        if parts.next() != Some(History::HEADER) {
+            return None;
+        }
+        let invalid = || Error::Parse(format!("Invalid history header: {line}"));
+        let rounds = parts.next().and_then(|r| r.parse().ok());
+        let salt = parts.next().and_then(|s| BASE64.decode(s).ok());
+        Some(rounds.zip(salt).ok_or_else(invalid))
+    }
+
+    fn seal(&self, text: &str) -> Result<String, Error> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(text.to_owned());
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut data = nonce.to_vec();
+        data.extend(
+            cipher
+                .encrypt(&nonce, text.as_bytes())
+                .map_err(|_| Error::Parse("Encrypt failed".to_owned()))?,
+        );
+        Ok(BASE64.encode(data))
+    }
+
+    fn open(&self, text: &str) -> Result<String, Error> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(text.to_owned());
+        };
+        let data = BASE64
+            .decode(text)
+            .map_err(|e| Error::Parse(e.to_string()))?;
+        if data.len() < History::NONCE_LEN {
+            return Err(Error::Parse("History entry too short".to_owned()));
+        }
+        let (nonce, sealed) = data.split_at(History::NONCE_LEN);
+        let plain = cipher
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| Error::Parse("Decrypt failed, wrong key?".to_owned()))?;
+        String::from_utf8(plain).map_err(|e| Error::Parse(e.to_string()))
+    }
+
+    pub fn record(&self, event: &Event) -> Result<(), Error> {
+        self.append(&serde_json::to_string(event)?)
+    }
+
+    pub fn record_ack(&self, trigger: &str, delay: Duration) -> Result<(), Error> {
+        self.append(&History::ack(trigger, delay)?)
+    }
+
+    fn ack(trigger: &str, delay: Duration) -> Result<String, Error> {
+        let acked = Acked {
+            ack: trigger.to_owned(),
+            delay: delay.as_secs(),
+        };
+        Ok(serde_json::to_string(&acked)?)
+    }
+
+    /// The line of the file holding `text`, encrypted if there is a key.
+    fn line(&self, text: &str) -> Result<String, Error> {
+        Ok(format!(
+            "{}\t{}",
+            Local::now().timestamp(),
+            self.seal(text)?
+        ))
+    }
+
+    fn append(&self, text: &str) -> Result<(), Error> {
+        History::write(&self.path, &self.lock, &self.line(text)?)
+    }
+
+    fn write(path: &Path, lock: &Mutex<()>, line: &str) -> Result<(), Error> {
+        let _guard = lock.lock().unwrap();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+
+    /// Matches and acknowledgments by trigger name, from what is kept.
+    pub fn acks(&self) -> Result<BTreeMap<String, Ack>, Error> {
+        let mut acks: BTreeMap<String, Ack> = BTreeMap::new();
+        for text in self.read()? {
+            if let Ok(a) = serde_json::from_str::<Acked>(&text) {
+                acks.entry(a.ack)
+                    .or_default()
+                    .add(Duration::from_secs(a.delay));
+                continue;
+            }
+            let value: serde_json::Value = serde_json::from_str(&text)?;
+            match value["trigger"].as_str() {
+                Some(t) if !t.is_empty() => acks.entry(t.to_owned()).or_default().matches += 1,
+                _ => {}
+            }
+        }
+        Ok(acks)
+    }
+
+    /// The recorded entries as JSON, oldest first.
+    pub fn read(&self) -> Result<Vec<String>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        self.lines()?
+            .iter()
+            .filter(|l| History::header(l).is_none())
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(_, v)| self.open(v))
+            .collect()
+    }
+
+    fn lines(&self) -> io::Result<Vec<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(t) => Ok(t
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| l.to_owned())
+                .collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Drops the lines older than the retention, returns how many were dropped.
+    pub fn purge(&self, now: i64) -> io::Result<usize> {
+        if self.days == 0 {
+            return Ok(0);
+        }
+        let since = now - (self.days * 24 * 3600) as i64;
+        let _guard = self.lock.lock().unwrap();
+        let lines = self.lines()?;
+        let kept: Vec<_> = lines
+            .iter()
+            .filter(|l| {
+                History::header(l).is_some()
+                    || l.split_once('\t')
+                        .and_then(|(t, _)| t.parse::<i64>().ok())
+                        .is_some_and(|t| t >= since)
+            })
+            .collect();
+        let purged = lines.len() - kept.len();
+        if purged > 0 {
+            let mut text = String::new();
+            for l in kept {
+                text.push_str(l);
+                text.push('\n');
+            }
+            fs::write(&self.path, text)?;
+        }
+        Ok(purged)
+    }
+}
+
+impl Sink for History {
+    fn handle(&self, signal: &Signal) {
+        let text = match signal {
+            Signal::Matched { event, .. } => {
+                serde_json::to_string(event.as_ref()).map_err(Error::from)
+            }
+            Signal::Acked { trigger, delay } => History::ack(trigger, *delay),
+            _ => return,
+        };
+        match text.and_then(|t| self.line(&t)) {
+            Ok(line) => {
+                let _ = self.writer.send(line);
+            }
+            Err(e) => log::error!("History error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_history_encrypted() {
+        let path = path("cgaid_history_encrypted.log");
+        let history = History::new(path.clone(), "secret", 0).unwrap();
+        history.record(&Event::plain("悄悄话")).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("悄悄话"));
+        assert!(text.starts_with("pbkdf2\t1000\t"));
+        let events = history.read().unwrap();
+        assert!(events[0].contains("悄悄话"));
+        // the salt is read back, not made anew
+        let again = History::new(path.clone(), "secret", 0).unwrap();
+        assert_eq!(again.read().unwrap(), events);
+        assert!(History::new(path, "wrong", 0).unwrap().read().is_err());
+    }
+
+    #[test]
+    fn test_history_acks() {
+        let path = path("cgaid_history_acks.log");
+        let history = History::new(path, "secret", 0).unwrap();
+        let mut trigger = crate::config::Trigger::new("离开了队伍");
+        trigger.name = "leave".to_owned();
+        let event =
+            crate::notifier::testutil::matched("12:00:02丂画眉鸟离开了队伍。", &trigger, "掉线了");
+        history.record(&event).unwrap();
+        history.record(&event).unwrap();
+        history.record(&Event::plain("abc")).unwrap();
+        history
+            .record_ack("leave", Duration::from_secs(90))
+            .unwrap();
+        let acks = history.acks().unwrap();
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks["leave"].matches, 2);
+        assert_eq!(acks["leave"].acked, 1);
+        assert_eq!(acks["leave"].average, Some(90));
+    }
+
+    #[test]
+    fn test_history_sink() {
+        let path = path("cgaid_history_sink.log");
+        let history = History::new(path, "", 0).unwrap();
+        let event = Arc::new(Event::plain("BOSS 出现了"));
+        history.handle(&Signal::Matched {
+            trigger: "boss",
+            event: &event,
+            notifiers: &[],
+        });
+        history.handle(&Signal::Acked {
+            trigger: "boss",
+            delay: Duration::from_secs(30),
+        });
+        // written by the writer thread
+        for _ in 0..100 {
+            if history.read().unwrap().len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let entries = history.read().unwrap();
+        assert!(entries[0].contains("BOSS 出现了"));
+        assert!(entries[1].contains(r#""delay":30"#));
+    }
+
+    #[test]
+    fn test_history_purge() {
+        let path = path("cgaid_history_purge.log");
+        let history = History::new(path.clone(), "", 1).unwrap();
+        history.record(&Event::plain("abc")).unwrap();
+        let now = Local::now().timestamp();
+        fs::write(
+            &path,
+            format!(
+                "{}\told\n{}",
+                now - 2 * 24 * 3600,
+                fs::read_to_string(&path).unwrap()
+            ),
+        )
+        .unwrap();
+        assert_eq!(history.purge(now).unwrap(), 1);
+        let events = history.read().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].contains("abc"));
+        assert_eq!(history.purge(now).unwrap(), 0);
+    }
+}
@@ -0,0 +1,97 @@
+//! A global hotkey, pressed anywhere, even in the game's full screen window.
+
+use super::error::Error;
+
+/// Modifier flags, as taken by `RegisterHotKey`.
+const ALT: u32 = 0x1;
+const CONTROL: u32 = 0x2;
+const SHIFT: u32 = 0x4;
+const WIN: u32 = 0x8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hotkey {
+    pub modifiers: u32,
+    /// Virtual key code
+    pub key: u32,
+}
+
+impl Hotkey {
+    /// Parses a combination like `Ctrl+Alt+A`, the key being a letter, a digit, `F1` to `F24`,
+    /// `Space` or `Pause`.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let invalid = || Error::Config(format!("Invalid hotkey: {text}"));
+        let mut modifiers = 0;
+        let mut key = None;
+        for part in text.split('+').map(|p| p.trim().to_uppercase()) {
+            match part.as_str() {
+                "CTRL" | "CONTROL" => modifiers |= CONTROL,
+                "ALT" => modifiers |= ALT,
+                "SHIFT" => modifiers |= SHIFT,
+                "WIN" => modifiers |= WIN,
+                _ if key.is_some() => return Err(invalid()),
+                "SPACE" => key = Some(0x20),
+                "PAUSE" => key = Some(0x13),
+                k if k.len() == 1 && k.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                    key = Some(k.as_bytes()[0] as u32)
+                }
+                k => match k.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+                    Some(n @ 1..=24) => key = Some(0x70 + n - 1),
+                    _ => return Err(invalid()),
+                },
+            }
+        }
+        Ok(Self {
+            modifiers,
+            key: key.ok_or_else(invalid)?,
+        })
+    }
+
+    /// Calls `f` on a thread of its own each time the hotkey is pressed.
+    #[cfg(windows)]
+    pub fn listen<F: Fn() + Send + 'static>(self, f: F) {
+        use windows_sys::Win32::UI::Input::KeyboardAndMouse::{RegisterHotKey, MOD_NOREPEAT};
+        use windows_sys::Win32::UI::WindowsAndMessaging::{GetMessageW, MSG, WM_HOTKEY};
+
+        std::thread::spawn(move || unsafe {
+            // registered on the thread, whose message queue then gets WM_HOTKEY
+            let null = std::ptr::null_mut();
+            if RegisterHotKey(null, 1, self.modifiers | MOD_NOREPEAT, self.key) == 0 {
+                log::error!("Hotkey error: {}", std::io::Error::last_os_error());
+                return;
+            }
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, null, 0, 0) > 0 {
+                if msg.message == WM_HOTKEY {
+                    f();
+                }
+            }
+        });
+    }
+
+    /// Hotkeys are only registered on Windows.
+    #[cfg(not(windows))]
+    pub fn listen<F: Fn() + Send + 'static>(self, _f: F) {
+        log::warn!("Hotkeys are only supported on Windows");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Hotkey::parse("Ctrl+Alt+A").unwrap(),
+            Hotkey {
+                modifiers: CONTROL | ALT,
+                key: 'A' as u32
+            }
+        );
+        assert_eq!(Hotkey::parse("shift + f12").unwrap().key, 0x7B);
+        assert_eq!(Hotkey::parse("Pause").unwrap().modifiers, 0);
+        assert!(Hotkey::parse("Ctrl+A+B").is_err());
+        assert!(Hotkey::parse("Ctrl+Alt").is_err());
+        assert!(Hotkey::parse("Ctrl+F25").is_err());
+    }
+}
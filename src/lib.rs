@@ -0,0 +1,58 @@
+//! CrossGate chat log watching and notification.
+//!
+//! The `cgaid` binary drives everything from `config.toml`, while [`stream::RecordStream`] lets
+//! other programs consume the parsed chat directly.
+use error::Error;
+
+pub mod alert;
+pub mod api;
+pub mod bus;
+pub mod calendar;
+pub mod chat;
+pub mod config;
+pub mod crash;
+pub mod deliveries;
+pub mod dispatcher;
+pub mod error;
+pub mod event;
+pub mod group;
+pub mod history;
+pub mod hotkey;
+pub mod merge;
+pub mod mute;
+pub mod notifier;
+pub mod presence;
+pub mod price;
+pub mod profile;
+pub mod reload;
+pub mod scheduler;
+pub mod secret;
+pub mod setup;
+pub mod simulate;
+pub mod state;
+pub mod stats;
+pub mod stream;
+pub mod subscription;
+pub mod suppressed;
+pub mod system;
+pub mod template;
+pub mod title;
+pub mod vars;
+pub mod watchdog;
+pub mod watcher;
+use event::Event;
+
+/// A notification backend.
+///
+/// Matches are dispatched through [`Notifiable::notify_event`] with the whole [`Event`], so
+/// backends storing or publishing structured data (trigger, channel, time, captures, raw line,
+/// priority) override it instead of parsing the message; `notify` is the plain text path for
+/// tests and the setup wizard.
+pub trait Notifiable: Send + Sync {
+    fn notify(&self, message: &str) -> Result<bool, Error>;
+
+    /// Notifies with the full matched event, by default only its formatted message is sent.
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.notify(&event.message)
+    }
+}
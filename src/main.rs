@@ -1,94 +1,396 @@
-use encoding_rs_io::DecodeReaderBytesBuilder;
+use chrono::Local;
 use notify::{Config as NC, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use regex::Regex;
 use simplelog::{ConfigBuilder, SimpleLogger};
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::error::Error;
-use std::fs::{self, File};
-use std::io::{self, BufRead, Seek};
-use std::path::{Path, PathBuf};
-use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
-use std::{cmp, env};
 
-mod chat;
-mod config;
-mod notifier;
-use chat::record::Record;
-use config::Config as CC;
-
-pub trait Notifiable {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>>;
-}
+use cgaid::bus::{Bus, Signal, State};
+use cgaid::calendar::Calendars;
+use cgaid::chat::enrich::{Enriched, Pipeline};
+use cgaid::chat::infer::Inferrer;
+use cgaid::chat::record::Record;
+use cgaid::chat::trade::Parser;
+use cgaid::config::{self, Config as CC};
+use cgaid::deliveries::Deliveries;
+use cgaid::dispatcher::{Delivery, Dispatcher, Registry};
+use cgaid::event::Event;
+use cgaid::group::Groups;
+use cgaid::history::History;
+use cgaid::hotkey::Hotkey;
+use cgaid::merge::Merger;
+use cgaid::mute::Mutes;
+use cgaid::notifier::dedup::Dedup;
+use cgaid::notifier::outbox::Outbox;
+use cgaid::notifier::telegram::{Command, Listener};
+use cgaid::notifier::tray;
+use cgaid::price::Prices;
+use cgaid::profile::{Buffered, Profile, Report};
+use cgaid::reload::{Current, Recent, Reload};
+use cgaid::scheduler::{self, Scheduler};
+use cgaid::state::{Reminder, TriggerState};
+use cgaid::stats::Stats;
+use cgaid::subscription::Subscriptions;
+use cgaid::suppressed::{Reason, Suppressed};
+use cgaid::template;
+use cgaid::vars::Vars;
+use cgaid::watchdog::Watchdog;
+use cgaid::watcher::{ChatLog, Pacer};
+use cgaid::{api, crash, system, title};
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("simulate") => return simulate(&args[1..]),
+        Some("setup") => return setup(),
+        Some("status") => return status(),
+        _ => {}
+    }
+
     let mut lcb = ConfigBuilder::new();
     let _ = lcb.set_time_offset_to_local();
     SimpleLogger::init(log::LevelFilter::Info, lcb.build())?;
 
-    let work_dir = env::current_dir()?;
+    let work_dir = std::env::current_dir()?;
     log::info!("Work dir: {}", work_dir.display());
 
-    let cfg = CC::load(work_dir.join("config.toml"))?;
+    let cfg_path = work_dir.join("config.toml");
+    let cfg = CC::load(&cfg_path)?;
     log::debug!("Config: {cfg:?}");
+    cgaid::notifier::set_proxy(&cfg.proxy)?;
 
-    let game_dir: &String = &cfg.game.path;
-    log::info!("Game root: {game_dir}");
-
-    let game_path = Path::new(game_dir);
-    let log_dir = game_path.join("Log");
-    if !log_dir.exists() {
-        log::info!("Log dir not exists: {}", log_dir.display());
-        fs::create_dir_all(&log_dir)?;
+    log::info!("Game root: {}", cfg.game.path);
+    let mut logs = vec![ChatLog::new(&cfg.game.name, &cfg.game.path)?];
+    for client in &cfg.game.clients {
+        log::info!("Game client {}: {}", client.name, client.path);
+        logs.push(ChatLog::new(&client.name, &client.path)?);
+    }
+    let prices = Arc::new(Prices::new());
+    if !cfg.trade.mail.is_empty() || !cfg.trade.auction.is_empty() {
+        let parser = Arc::new(Parser::new(&cfg.trade)?.with_prices(Arc::clone(&prices)));
+        let games: Vec<_> = std::iter::once((&cfg.game.name, &cfg.game.path))
+            .chain(cfg.game.clients.iter().map(|c| (&c.name, &c.path)))
+            .collect();
+        for prefix in [&cfg.trade.mail, &cfg.trade.auction] {
+            if prefix.is_empty() {
+                continue;
+            }
+            for (name, path) in &games {
+                logs.push(ChatLog::trade(name, path, prefix, Arc::clone(&parser))?);
+            }
+        }
     }
 
+    logs = logs
+        .into_iter()
+        .map(|l| l.with_shadow(cfg.game.shadow))
+        .collect();
+
     let (tx, rx) = channel();
-    let mut watcher =
-        RecommendedWatcher::new(tx, NC::default().with_poll_interval(Duration::from_secs(1)))?;
+    let mut dirs: BTreeSet<_> = logs.iter().map(|l| l.log_dir.clone()).collect();
+    if cfg.reload.watch {
+        dirs.insert(work_dir.clone());
+    }
+    let mut _watcher = watch(&tx, &dirs, Pacer::FAST)?;
+
+    let empty = PathBuf::new();
+    let bus = Arc::new(Bus::new());
+    let stats = Arc::new(Stats::new());
+    bus.subscribe(Arc::clone(&stats) as _);
+    let outbox = if cfg.outbox.path.is_empty() {
+        None
+    } else {
+        Some(Arc::new(
+            Outbox::new(work_dir.join(&cfg.outbox.path), cfg.outbox.format.clone())
+                .with_attempts(cfg.outbox.attempts),
+        ))
+    };
+    let current = Arc::new(Current::new(Arc::new(cfg)));
+    let ac = current.get();
+    let registry = Registry::shared(Arc::clone(&current));
+    registry.build_all();
+    if let Some(o) = &outbox {
+        let oc = Arc::clone(o);
+        let rc = registry.clone();
+        let interval = Duration::from_secs(ac.outbox.interval.max(1));
+        thread::spawn(move || loop {
+            match oc.flush(|name, event| rc.get(name).and_then(|o| o.notify_event(event))) {
+                Ok(0) => {}
+                Ok(n) => log::info!("Outbox flushed: {n}"),
+                Err(e) => log::error!("Outbox error: {e}"),
+            }
+            thread::sleep(interval);
+        });
+    }
+    if !ac.history.path.is_empty() {
+        let h = Arc::new(History::new(
+            work_dir.join(&ac.history.path),
+            &ac.history.key,
+            ac.history.days,
+        )?);
+        let hc = Arc::clone(&h);
+        thread::spawn(move || loop {
+            match hc.purge(Local::now().timestamp()) {
+                Ok(0) => {}
+                Ok(n) => log::info!("History purged: {n}"),
+                Err(e) => log::error!("History error: {e}"),
+            }
+            thread::sleep(Duration::from_secs(3600));
+        });
+        match h.acks() {
+            Ok(acks) => stats.load_acks(acks),
+            Err(e) => log::error!("History error: {e}"),
+        }
+        bus.subscribe(h);
+    }
+    if !ac.deliveries.path.is_empty() {
+        bus.subscribe(Arc::new(Deliveries::new(
+            work_dir.join(&ac.deliveries.path),
+            ac.deliveries.size * 1024,
+        )));
+    }
+    let subscriptions = Arc::new(Subscriptions::new());
+    let groups = Arc::new(Groups::new(&ac.group)?);
+    let state = Arc::new(TriggerState::load(
+        Some(&ac.state.path)
+            .filter(|p| !p.is_empty())
+            .map(|p| work_dir.join(p)),
+    )?);
+    prices.restore(state.prices());
+    groups.restore(
+        &state.groups(),
+        std::time::Instant::now(),
+        Local::now().timestamp(),
+    );
+    let dispatcher = Dispatcher::new(Arc::clone(&ac), outbox, &bus).with_registry(registry);
+    bus.subscribe(Arc::new(Delivery::new(
+        dispatcher.clone(),
+        Dedup::new(Duration::from_secs(ac.dedup.window)).with_key(ac.dedup.key),
+        Calendars::new(&ac.calendar)?,
+    )));
+    let mutes = Arc::new(Mutes::new());
+    // both read their settings from the running config, doing nothing without notifiers
+    bus.subscribe(Arc::new(Watchdog::new(dispatcher.clone())));
+    let recent = Arc::new(Recent::new(Duration::from_secs(ac.reload.minutes * 60)));
+    let reload = Reload::new(Arc::clone(&current), cfg_path, Arc::clone(&recent));
+    let profile = Arc::new(Profile::new());
+    if !ac.api.listen.is_empty() {
+        api::Api::new(
+            Arc::clone(&subscriptions),
+            Arc::clone(&stats),
+            Arc::clone(&groups),
+            dispatcher.clone(),
+            Arc::clone(&mutes),
+            reload.clone(),
+            Arc::clone(&bus),
+        )
+        .with_state(Arc::clone(&state))
+        .with_prices(Arc::clone(&prices))
+        .with_profile(Arc::clone(&profile))
+        .with_token(&ac.api.token)
+        .start(&ac.api.listen)?;
+    }
+    if !ac.hotkey.ack.is_empty() {
+        let (sc, mc, bc) = (Arc::clone(&stats), Arc::clone(&mutes), Arc::clone(&bus));
+        let dc = dispatcher.clone();
+        let minutes = ac.hotkey.minutes;
+        Hotkey::parse(&ac.hotkey.ack)?.listen(move || {
+            let Some(alert) = dc.alerts().latest() else {
+                log::info!("Hotkey: nothing to acknowledge");
+                return;
+            };
+            if minutes > 0 {
+                mc.mute(&alert.trigger, Some(Duration::from_secs(minutes * 60)));
+            }
+            api::ack(&sc, &bc, &alert.trigger);
+        });
+    }
+    for (name, g) in ac.group.iter().filter(|(_, g)| !g.hotkey.is_empty()) {
+        let (gc, name) = (Arc::clone(&groups), name.clone());
+        Hotkey::parse(&g.hotkey)?.listen(move || match gc.toggle(&name) {
+            Some(true) => log::info!("Hotkey: group {name} enabled"),
+            Some(false) => log::info!("Hotkey: group {name} disabled"),
+            None => log::info!("Hotkey: no group {name}"),
+        });
+    }
+    let tc = &ac.notifier.telegram;
+    if tc.buttons && !tc.token.is_empty() {
+        let (sc, mc, bc) = (Arc::clone(&stats), Arc::clone(&mutes), Arc::clone(&bus));
+        Listener::new(tc.api.clone(), tc.token.clone(), tc.chat_id.clone()).start(move |command| {
+            match command {
+                Command::Ack(trigger) => api::ack(&sc, &bc, trigger),
+                Command::Mute(trigger, minutes) => {
+                    mc.mute(trigger, Some(Duration::from_secs(minutes * 60)));
+                    api::ack(&sc, &bc, trigger);
+                    true
+                }
+            }
+        });
+    }
+    {
+        let s = Arc::new(Suppressed::new());
+        let sc = Arc::clone(&s);
+        let cc = Arc::clone(&current);
+        let dc = dispatcher.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(
+                cc.get().suppressed.interval.max(1) * 60,
+            ));
+            let (entries, count) = sc.take();
+            let cs = &cc.get().suppressed;
+            if cs.notifier.is_empty() {
+                continue;
+            }
+            if let Some(text) = Suppressed::summary(&cs.format, &entries, count, cs.list) {
+                let event = Arc::new(Event::plain(&text));
+                for name in &cs.notifier {
+                    let _ = dc.dispatch(name, &event);
+                }
+            }
+        });
+        bus.subscribe(s);
+    }
+    let infer = inferrer(&ac)?;
+    let merge = if logs.len() > 1 && ac.game.merge > 0 {
+        Some(Duration::from_secs(ac.game.merge))
+    } else {
+        None
+    };
+    let ctx = Arc::new(Context {
+        cfg: current,
+        bus,
+        dispatcher,
+        subscriptions,
+        groups,
+        mutes,
+        recent,
+        infer: RwLock::new(infer),
+        enrich: RwLock::new(Pipeline::new(&ac.enrich)?),
+        vars: Vars::with_values(state.vars()),
+        state,
+        prices,
+        scheduler: Scheduler::new(),
+        stats,
+        profile,
+        merger: Merger::new(),
+        merge,
+    });
+    let (sc, pc) = (Arc::clone(&ctx.state), Arc::clone(&ctx.prices));
+    // matches only change the state in memory, written at most this often and on exit
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+        sc.save_prices(pc.prices());
+        sc.flush();
+    });
+    let now = Local::now().timestamp();
+    for reminder in ctx.state.reminders() {
+        if reminder.at > now {
+            schedule(&ctx, reminder);
+        } else {
+            log::info!("Reminder missed while stopped: {}", reminder.event.message);
+            ctx.state.remind(&reminder.event.trigger, reminder.at);
+        }
+    }
 
-    watcher.watch(&log_dir, RecursiveMode::NonRecursive)?;
+    if ac.notifier.tray.enabled {
+        if let Err(e) = tray::start(&ac.notifier.tray.title) {
+            log::error!("Tray icon error: {e}");
+        }
+    }
 
-    let chat_file_re = Regex::new(r"^chat_\d{6}\.txt$")?;
-    let chat_file_filter =
-        |p: &PathBuf| chat_file_re.is_match(p.file_name().and_then(|v| v.to_str()).unwrap_or(""));
-    let mut chat_file = find_file(&log_dir, chat_file_filter)?;
+    if !ac.crash.process.is_empty() {
+        crash::CrashMonitor::new(&ac.crash)?.start(Arc::clone(&ctx.stats), ctx.dispatcher.clone());
+    }
 
-    let mut offset = 0_u64;
-    if let Some(f) = &chat_file {
-        log::info!("Chat file found: {f}");
-        let (_, p) = read(Path::new(f), 0)?;
-        offset = p;
+    if !ac.title.filter.is_empty() {
+        let cc = Arc::clone(&ctx);
+        let clients = vec![ac.game.name.clone()];
+        title::TitleWatcher::new(&ac.title).start(move |record| {
+            process(&cc, &record, &clients);
+        });
     }
 
-    let empty = PathBuf::new();
-    let ac = Arc::new(cfg);
-    let mut last_record = None;
-    for r in rx {
+    let sc = tx.clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        if rt.block_on(tokio::signal::ctrl_c()).is_ok() {
+            log::info!("Stopping...");
+            let _ = sc.send(Watched::Stop);
+        }
+    });
+
+    let mut pacer = Pacer::new(Some(Duration::from_secs(ac.game.idle)).filter(|d| !d.is_zero()));
+    let mut timeout = Pacer::FAST;
+    loop {
+        ctx.stats.beat();
+        let t = pacer.timeout(std::time::Instant::now());
+        if t != timeout {
+            timeout = t;
+            // the new one watches before the old one stops, no change is missed in between
+            match watch(&tx, &dirs, t) {
+                Ok(w) => _watcher = w,
+                Err(e) => log::error!("Watcher restart error: {e}"),
+            }
+            let state = if t == Pacer::SLOW {
+                State::Idle
+            } else {
+                State::Active
+            };
+            ctx.bus.publish(&Signal::Watcher(state));
+        }
+        let now = std::time::Instant::now();
+        for log in logs.iter_mut() {
+            if let Some(result) = log.retry(now) {
+                read_lines(&ctx, &mut pacer, log, result);
+            }
+        }
+        // locked logs are tried again in time, without holding up the others
+        let wait = logs
+            .iter()
+            .filter_map(|l| l.retry_in(now))
+            .fold(t, Duration::min);
+        let r = match rx.recv_timeout(wait) {
+            Ok(Watched::Fs(r)) => r,
+            Ok(Watched::Stop) => break,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                log::error!("File watcher stopped");
+                ctx.bus.publish(&Signal::Fault {
+                    source: "文件监视",
+                    detail: "监视线程已退出",
+                    fatal: true,
+                });
+                break;
+            }
+        };
         match r {
             Ok(event) => {
                 // println!("{:?} {:?}", event, &chat_file);
+                if ac.reload.watch
+                    && matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+                    && event.paths.iter().any(|p| p == reload.path())
+                {
+                    // after the editor is done writing, several events of one save apply once
+                    let (cc, rc) = (Arc::clone(&ctx), reload.clone());
+                    ctx.scheduler
+                        .schedule(Duration::from_millis(500), move || apply_config(&cc, &rc));
+                    continue;
+                }
                 match event.kind {
                     EventKind::Modify(_) => {
-                        let path = event.paths.iter().next().unwrap_or(&empty);
-                        if let Some(f) = &chat_file {
-                            if path != Path::new(f) {
-                                chat_file = find_file(&log_dir, chat_file_filter)?;
-                                log::info!("Chat file changed: {chat_file:?}");
-                            }
-                        } else {
-                            chat_file = find_file(&log_dir, chat_file_filter)?;
-                            log::info!("Chat file changed: {chat_file:?}");
-                        }
-
-                        if let Some(f) = &chat_file {
-                            let (lines, p) = read(Path::new(f), offset)?;
-                            log::debug!("{} -> {}", offset, p);
-                            offset = p;
-                            last_record = try_notify(Arc::clone(&ac), last_record, lines);
-                        } else {
-                            log::info!("Chat file not found");
+                        let path = event.paths.first().unwrap_or(&empty);
+                        for log in logs.iter_mut().filter(|l| l.contains(path)) {
+                            let result = log.on_modify(path);
+                            read_lines(&ctx, &mut pacer, log, result);
                         }
                     }
                     _ => {
@@ -96,89 +398,476 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }
                 }
             }
-            Err(error) => log::error!("Error: {error:?}"),
+            Err(error) => {
+                log::error!("Error: {error:?}");
+                ctx.bus.publish(&Signal::Fault {
+                    source: "文件监视",
+                    detail: &error.to_string(),
+                    fatal: false,
+                });
+            }
         }
     }
 
+    ctx.bus.publish(&Signal::Watcher(State::Stopped));
+    ctx.dispatcher.shutdown();
+    ctx.state.save_prices(ctx.prices.prices());
+    ctx.state.flush();
+    tray::stop();
+    let summary = ctx.stats.summary();
+    log::info!("Session summary:\n{summary}");
+    let event = Event::plain(&summary);
+    for name in &ac.summary.notifier {
+        let _ = ctx.dispatcher.send(name, &event);
+    }
     Ok(())
 }
 
-fn find_file<P, F>(root: P, filter: F) -> io::Result<Option<String>>
-where
-    P: AsRef<Path>,
-    F: Fn(&PathBuf) -> bool,
-{
-    let mut entries: Vec<PathBuf> = fs::read_dir(root)?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.path())
-        .filter(filter)
-        .collect::<Vec<_>>();
-    entries.sort_by_key(|f| cmp::Reverse(f.file_name().unwrap().to_owned()));
-    if entries.is_empty() {
-        Ok(None)
+/// What the watch loop receives.
+enum Watched {
+    Fs(notify::Result<notify::Event>),
+    /// Ctrl+C, waking the loop at once whatever its pace
+    Stop,
+}
+
+/// Watches `dirs`, polling every `interval` where the platform has to poll.
+fn watch(
+    tx: &Sender<Watched>,
+    dirs: &BTreeSet<PathBuf>,
+    interval: Duration,
+) -> notify::Result<RecommendedWatcher> {
+    let tx = tx.clone();
+    let mut watcher = RecommendedWatcher::new(
+        move |r| {
+            let _ = tx.send(Watched::Fs(r));
+        },
+        NC::default().with_poll_interval(interval),
+    )?;
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+/// `cgaid simulate --pack <triggers.toml> --log <chat.txt> [--expect <expected.json>]`
+///
+/// Prints the matches as JSON, or with `--expect` the differences, exiting with 1 if there are any.
+fn simulate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let arg = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let usage =
+        "Usage: cgaid simulate --pack <triggers.toml> --log <chat.txt> [--expect <expected.json>]";
+    let (Some(pack), Some(log)) = (arg("--pack"), arg("--log")) else {
+        return Err(usage.into());
+    };
+    let matches = cgaid::simulate::run(&std::fs::read_to_string(pack)?, &std::fs::read(log)?)?;
+    let Some(expect) = arg("--expect") else {
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+        return Ok(());
+    };
+    let expected: Vec<cgaid::simulate::Match> =
+        serde_json::from_str(&std::fs::read_to_string(expect)?)?;
+    let lines = cgaid::simulate::diff(&expected, &matches);
+    if lines.is_empty() {
+        println!("OK: {} matches", matches.len());
+        return Ok(());
+    }
+    for l in &lines {
+        println!("{l}");
+    }
+    println!("FAILED: {} differences", lines.len());
+    std::process::exit(1);
+}
+
+/// `cgaid setup`
+///
+/// Asks for the game dir, audio device and webhook, then writes `config.toml` in the work dir,
+/// keeping the previous one as `config.toml.bak`.
+fn setup() -> Result<(), Box<dyn Error>> {
+    let path = std::env::current_dir()?.join("config.toml");
+    let template = if path.exists() {
+        std::fs::read_to_string(&path)?
     } else {
-        Ok(entries[0].to_str().map(|v| v.to_owned()))
+        include_str!("../config.toml").to_owned()
+    };
+    let candidates = cgaid::setup::candidates(&cgaid::setup::default_roots());
+    let devices = cgaid::setup::output_devices();
+    let text = cgaid::setup::run(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout(),
+        &template,
+        &candidates,
+        &devices,
+    )?;
+    if path.exists() {
+        std::fs::copy(&path, path.with_extension("toml.bak"))?;
     }
+    std::fs::write(&path, text)?;
+    println!("已保存: {}", path.display());
+    Ok(())
 }
 
-fn read(path: &Path, offset: u64) -> io::Result<(Vec<String>, u64)> {
-    // log::info!("Reading file: {path:?}");
-    let mut f = File::open(path)?;
-    f.seek(io::SeekFrom::Start(offset))?;
-    let reader = io::BufReader::new(
-        DecodeReaderBytesBuilder::new()
-            .encoding(Some(encoding_rs::GB18030))
-            .build(&f),
-    );
+/// `cgaid status`
+///
+/// Prints the throughput, regex time, buffers and queues of the running instance, read from its
+/// control API.
+fn status() -> Result<(), Box<dyn Error>> {
+    let cfg = CC::load(std::env::current_dir()?.join("config.toml"))?;
+    if cfg.api.listen.is_empty() {
+        return Err("Api disabled, set listen of [api] in config.toml".into());
+    }
+    let address = cfg.api.listen.replace("0.0.0.0", "127.0.0.1");
+    let mut stream = TcpStream::connect(&address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write!(
+        stream,
+        "GET /status HTTP/1.1\r\nHost: {address}\r\nAuthorization: Bearer {}\r\nConnection: close\r\n\r\n",
+        cfg.api.token
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, b)| b);
+    let report: Report = serde_json::from_str(body)?;
+    println!("{}", report.text());
+    Ok(())
+}
+
+/// Shared state of the notify pipeline.
+struct Context {
+    /// The running config, replaced when the file is edited
+    cfg: Arc<Current>,
+    /// Records, matches and results go out here, to the stats, history and notifiers
+    bus: Arc<Bus>,
+    dispatcher: Dispatcher,
+    subscriptions: Arc<Subscriptions>,
+    groups: Arc<Groups>,
+    mutes: Arc<Mutes>,
+    /// Lines read lately, to preview config edits against
+    recent: Arc<Recent>,
+    /// Channel guessing for records tagged unlike the game does, `None` when disabled
+    infer: RwLock<Option<Inferrer>>,
+    /// Steps of the enrich profile in use, `None` when none is chosen
+    enrich: RwLock<Option<Pipeline>>,
+    vars: Vars,
+    state: Arc<TriggerState>,
+    /// Prices of the items sold in the auction log
+    prices: Arc<Prices>,
+    scheduler: Scheduler,
+    stats: Arc<Stats>,
+    /// Parse and match timings for `cgaid status`
+    profile: Arc<Profile>,
+    merger: Merger,
+    /// Merge window for broadcasts seen by several clients, `None` when watching only one
+    merge: Option<Duration>,
+}
 
-    let mut lines = Vec::new();
-    for line in reader.lines().map(|v| v.unwrap()) {
-        lines.push(line);
+/// Applies the edited config file, keeping the running config when the new one is invalid.
+fn apply_config(ctx: &Context, reload: &Reload) {
+    match reload.apply() {
+        Ok(None) => {}
+        Ok(Some(applied)) => {
+            let cfg = ctx.cfg.get();
+            ctx.dispatcher.reload();
+            if let Err(e) = ctx.groups.update(&cfg.group) {
+                log::error!("Group error, keeping the previous groups: {e}");
+            }
+            match inferrer(&cfg) {
+                Ok(infer) => *ctx.infer.write().unwrap() = infer,
+                Err(e) => log::error!("Infer error, keeping the previous one: {e}"),
+            }
+            match Pipeline::new(&cfg.enrich) {
+                Ok(enrich) => *ctx.enrich.write().unwrap() = enrich,
+                Err(e) => log::error!("Enrich error, keeping the previous one: {e}"),
+            }
+            log::info!("Config reloaded: {} triggers", cfg.trigger.len());
+            if !applied.restart.is_empty() {
+                log::warn!(
+                    "Restart to apply the edits of: {}",
+                    applied.restart.join(", ")
+                );
+            }
+        }
+        Err(e) => {
+            log::error!("Config reload error, keeping the running config: {e}");
+            ctx.bus.publish(&Signal::Fault {
+                source: "重新加载配置",
+                detail: &e.to_string(),
+                fatal: false,
+            });
+        }
+    }
+}
+
+/// The channel guessing of `cfg`, `None` when disabled.
+fn inferrer(cfg: &CC) -> Result<Option<Inferrer>, cgaid::error::Error> {
+    if cfg.infer.enabled {
+        Inferrer::new(&cfg.infer).map(Some)
+    } else {
+        Ok(None)
     }
-    let p = f.stream_position()?;
-    // println!("Read: {offset} -> {p}");
-    Ok((lines, p))
 }
 
-fn try_notify(cfg: Arc<CC>, last: Option<Record>, lines: Vec<String>) -> Option<Record> {
-    let records: BTreeSet<_> = lines.iter().filter_map(|v| Record::from(v)).collect();
+/// Notifies the new lines read from `log`, or reports why they could not be read.
+fn read_lines(
+    ctx: &Arc<Context>,
+    pacer: &mut Pacer,
+    log: &mut ChatLog,
+    result: std::io::Result<Vec<String>>,
+) {
+    let lines = match result {
+        Ok(lines) => lines,
+        Err(e) => {
+            log::error!("Read {} error: {e}", log.name);
+            ctx.bus.publish(&Signal::Fault {
+                source: "读取聊天记录",
+                detail: &e.to_string(),
+                fatal: false,
+            });
+            return;
+        }
+    };
+    if !lines.is_empty() {
+        pacer.touch(std::time::Instant::now());
+    }
+    let n = lines.len();
+    log.last = try_notify(ctx, &log.name, log.last.take(), lines);
+    ctx.profile.end_batch(n);
+    let (recent_lines, recent_bytes) = ctx.recent.usage();
+    ctx.profile.set_buffered(Buffered {
+        recent_lines,
+        recent_bytes,
+        merging: ctx.merger.len(),
+        scheduled: ctx.scheduler.len(),
+    });
+}
+
+fn try_notify(
+    ctx: &Arc<Context>,
+    client: &str,
+    last: Option<Record>,
+    lines: Vec<String>,
+) -> Option<Record> {
+    if !lines.is_empty() {
+        ctx.bus.publish(&Signal::Lines {
+            client,
+            count: lines.len(),
+        });
+    }
+    let records: BTreeSet<_> = lines
+        .iter()
+        .filter_map(|v| Record::from(v))
+        .map(|r| match &*ctx.infer.read().unwrap() {
+            Some(i) => i.apply(r),
+            None => r,
+        })
+        .collect();
     if records.is_empty() {
         return last;
     }
-    // log::info!("{:?}", records.len());
-    let triggers = cfg.as_ref().clone().trigger;
+    ctx.recent.push(&lines, std::time::Instant::now());
     for record in &records {
         if last.as_ref().is_some_and(|r| r == record) {
             continue;
         }
+        ctx.bus.publish(&Signal::Record { client, record });
         // println!("{:?}", record);
-        let msg = record.msg();
-        for trigger in &triggers {
-            if !trigger.accept(record.get_channel()) {
+        let Some(window) = ctx.merge else {
+            process(ctx, record, &[client.to_owned()]);
+            continue;
+        };
+        match ctx.merger.add(record, client) {
+            None => process(ctx, record, &[client.to_owned()]),
+            Some(true) => {
+                let cc = Arc::clone(ctx);
+                let rc = record.clone();
+                ctx.scheduler.schedule(window, move || {
+                    let clients = cc.merger.take(&rc);
+                    process(&cc, &rc, &clients);
+                });
+            }
+            Some(false) => log::debug!("Merged: {client} {record}"),
+        }
+    }
+    records.last().cloned()
+}
+
+/// Matches a record seen by `clients` against all triggers and dispatches the notifications.
+fn process(ctx: &Context, record: &Record, clients: &[String]) {
+    let enriched = match &*ctx.enrich.read().unwrap() {
+        Some(p) => match p.run(record.clone()) {
+            Some(e) => e,
+            None => {
+                log::debug!("Ignored: {record}");
+                return;
+            }
+        },
+        None => Enriched::from(record.clone()),
+    };
+    let record = &enriched.record;
+    let cfg = ctx.cfg.get();
+    let mut triggers = cfg.triggers();
+    triggers.extend(ctx.subscriptions.triggers());
+    let msg = record.msg();
+    let mut times = Vec::with_capacity(triggers.len());
+    for trigger in &triggers {
+        if !trigger.accept(record.get_channel()) {
+            continue;
+        }
+        if trigger.name == config::Me::TRIGGER && record.sender().is_some_and(|s| cfg.me.is_me(s)) {
+            continue;
+        }
+        if trigger.name == config::Afk::TRIGGER && !cfg.afk.is_away(system::idle_time()) {
+            continue;
+        }
+        let mut nc = trigger.clone();
+        nc.priority = enriched.priority.unwrap_or(nc.priority);
+        nc.notifier = cfg.notifiers(&nc, record.get_channel());
+        nc.tags.extend(enriched.tags.iter().cloned());
+        let label = if trigger.name.is_empty() {
+            &trigger.regex
+        } else {
+            &trigger.name
+        };
+        let start = std::time::Instant::now();
+        let matched = nc.try_match(msg);
+        times.push((label.as_str(), start.elapsed()));
+        if let Some(matched) = matched {
+            let suppress = |reason, message: &str| {
+                ctx.bus.publish(&Signal::Suppressed {
+                    trigger: label,
+                    reason,
+                    message,
+                });
+            };
+            if ctx.mutes.is_muted(&nc.name, std::time::Instant::now()) {
+                log::debug!("Trigger {} muted: {msg}", nc.name);
+                suppress(Reason::Muted, msg);
                 continue;
             }
-            let nc = trigger.clone();
-            if let Some(matched) = nc.try_match(msg) {
-                let message = nc.format(&matched).replace("{time}", &record.fmt_time());
-                log::debug!("Matched: {message}");
-                for name in nc.notifier {
-                    let cc = Arc::clone(&cfg);
-                    let mc = message.clone();
-                    thread::spawn(move || {
-                        match config::Notifier::find(cc.as_ref(), &name).and_then(|o| o.notify(&mc))
-                        {
-                            Ok(b) => {
-                                log::debug!("{name} notified: {b}");
-                            }
-                            Err(e) => {
-                                log::error!("Notify error: {e}");
-                            }
-                        }
-                    });
+            let now = Local::now().timestamp();
+            if !ctx.state.allow(label, &nc, now) {
+                log::debug!("Trigger {label} cooling down: {msg}");
+                suppress(Reason::Cooldown, msg);
+                continue;
+            }
+            if !ctx
+                .groups
+                .allow(&nc.group, record.get_time(), std::time::Instant::now())
+            {
+                log::debug!("Group {} inactive: {msg}", nc.group);
+                suppress(Reason::Group, msg);
+                continue;
+            }
+            let count = ctx.state.fire(label, now).to_string();
+            let mut values = template::captures(&matched);
+            values.insert("time", &record.fmt_time());
+            values.insert("count", &count);
+            values.insert("client", &clients.join(", "));
+            ctx.vars.insert(&mut values);
+            if let Some(c) = record.coord() {
+                c.insert(&mut values);
+            }
+            let render = |template: &str| {
+                let mut message = template::render(template, &values);
+                if clients.len() > 1 {
+                    message = format!("{message} ({})", clients.join(", "));
                 }
+                message
+            };
+            let message = render(nc.format.text());
+            let localized: HashMap<_, _> = nc
+                .format
+                .langs()
+                .iter()
+                .map(|(lang, t)| (lang.clone(), render(t)))
+                .collect();
+            // set after formatting so the message can still show the previous values
+            for (name, template) in &nc.store {
+                ctx.vars
+                    .set(name, config::Trigger::render(template, &matched));
+            }
+            ctx.state.save(
+                ctx.vars.values(),
+                ctx.groups.fired(std::time::Instant::now(), now),
+            );
+            log::debug!("Matched: {message}");
+            let mut event = Event::new(record, &nc, matched, message.clone());
+            event.clients = clients.to_vec();
+            event.localized = localized;
+            let event = Arc::new(event);
+            ctx.bus.publish(&Signal::Matched {
+                trigger: label,
+                event: &event,
+                notifiers: &nc.notifier,
+            });
+            if nc.deadline.is_some() {
+                schedule_reminder(ctx, &nc, &event);
             }
         }
     }
-    records.last().cloned()
+    ctx.profile.add_record(&times);
+}
+
+/// Schedules a follow-up notification `remind` minutes before the deadline captured by the trigger.
+fn schedule_reminder(ctx: &Context, trigger: &config::Trigger, event: &Event) {
+    let Some(text) = trigger.deadline.and_then(|i| event.captures.get(i)) else {
+        return;
+    };
+    let now = Local::now().naive_local();
+    let Some(deadline) = scheduler::parse_deadline(text, now) else {
+        log::error!("Invalid deadline: {text}");
+        return;
+    };
+    let at = deadline - chrono::Duration::minutes(trigger.remind as i64);
+    let Some(delay) = (at - now).to_std().ok().filter(|d| !d.is_zero()) else {
+        log::debug!("Deadline too close to remind: {deadline}");
+        return;
+    };
+    let template = if trigger.remind_format.is_empty() {
+        &trigger.format
+    } else {
+        &trigger.remind_format
+    };
+    let mut reminder = event.clone();
+    let mut values = template::captures(&event.captures);
+    values.insert("time", &event.time);
+    values.insert("deadline", &deadline.format("%H:%M:%S").to_string());
+    reminder.message = template::render(template.text(), &values);
+    let localized = (template.langs().iter())
+        .map(|(lang, t)| (lang.clone(), template::render(t, &values)))
+        .collect();
+    log::info!(
+        "Reminder at {}: {}",
+        at.format("%H:%M:%S"),
+        reminder.message
+    );
+
+    let reminder = Reminder {
+        at: Local::now().timestamp() + delay.as_secs() as i64,
+        notifier: trigger.notifier.clone(),
+        event: reminder,
+        localized,
+    };
+    // kept in the state until sent, so a restart in between still sends it
+    ctx.state.add_reminder(reminder.clone());
+    schedule(ctx, reminder);
+}
+
+/// Sends the reminder at its time and forgets it then.
+fn schedule(ctx: &Context, reminder: Reminder) {
+    let delay = Duration::from_secs((reminder.at - Local::now().timestamp()).max(0) as u64);
+    let dispatcher = ctx.dispatcher.clone();
+    let state = Arc::clone(&ctx.state);
+    ctx.scheduler.schedule(delay, move || {
+        let mut event = reminder.event;
+        event.localized = reminder.localized.into_iter().collect();
+        let event = Arc::new(event);
+        for name in &reminder.notifier {
+            let _ = dispatcher.dispatch(name, &event);
+        }
+        state.remind(&event.trigger, reminder.at);
+    });
 }
@@ -0,0 +1,82 @@
+use super::chat::record::{Channel, Record};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Merges the same world/region broadcast seen by several game clients.
+#[derive(Default)]
+pub struct Merger {
+    pending: Mutex<HashMap<(Channel, String), Vec<String>>>,
+}
+
+impl Merger {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(record: &Record) -> Option<(Channel, String)> {
+        match record.get_channel() {
+            Channel::World | Channel::Region => {
+                Some((record.get_channel().clone(), record.msg().to_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Registers that `client` saw the record. Returns `None` if the record can not be merged,
+    /// `Some(true)` if it is the first sighting and should be flushed after the merge window,
+    /// `Some(false)` if it joined a pending sighting.
+    pub fn add(&self, record: &Record, client: &str) -> Option<bool> {
+        let key = Merger::key(record)?;
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&key) {
+            Some(clients) => {
+                if !clients.iter().any(|c| c == client) {
+                    clients.push(client.to_owned());
+                }
+                Some(false)
+            }
+            None => {
+                pending.insert(key, vec![client.to_owned()]);
+                Some(true)
+            }
+        }
+    }
+
+    /// Ends the merge window of the record and returns the clients that saw it.
+    pub fn take(&self, record: &Record) -> Vec<String> {
+        Merger::key(record)
+            .and_then(|k| self.pending.lock().unwrap().remove(&k))
+            .unwrap_or_default()
+    }
+
+    /// Broadcasts still in their merge window.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merger() {
+        let merger = Merger::new();
+        let a = Record::from("21:40:12丂[世界]盛明兰oO: 半山来个合格车头").unwrap();
+        let b = Record::from("21:40:13丂[世界]盛明兰oO: 半山来个合格车头").unwrap();
+        let c = Record::from("21:40:13丂队伍已经解散了。").unwrap();
+        assert_eq!(merger.add(&a, "大号"), Some(true));
+        assert_eq!(merger.add(&b, "小号"), Some(false));
+        assert_eq!(merger.add(&b, "小号"), Some(false));
+        assert_eq!(merger.add(&c, "小号"), None);
+        assert_eq!(merger.take(&a), vec!["大号", "小号"]);
+        assert!(merger.take(&a).is_empty());
+        assert_eq!(merger.add(&b, "小号"), Some(true));
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize)]
+pub struct MuteStatus {
+    pub trigger: String,
+    /// Seconds left, `None` until unmuted
+    pub remaining: Option<u64>,
+}
+
+/// Triggers muted from the control API, for a while or until unmuted.
+#[derive(Default)]
+pub struct Mutes {
+    until: Mutex<HashMap<String, Option<Instant>>>,
+}
+
+impl Mutes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mutes a trigger by name, for `duration` or until unmuted when `None`.
+    pub fn mute(&self, trigger: &str, duration: Option<Duration>) {
+        log::info!("Trigger muted: {trigger} {duration:?}");
+        self.until
+            .lock()
+            .unwrap()
+            // a duration past the clock's range mutes until unmuted
+            .insert(
+                trigger.to_owned(),
+                duration.and_then(|d| Instant::now().checked_add(d)),
+            );
+    }
+
+    pub fn unmute(&self, trigger: &str) -> bool {
+        self.until.lock().unwrap().remove(trigger).is_some()
+    }
+
+    pub fn is_muted(&self, trigger: &str, now: Instant) -> bool {
+        let mut until = self.until.lock().unwrap();
+        match until.get(trigger) {
+            Some(Some(t)) if *t <= now => {
+                until.remove(trigger);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<MuteStatus> {
+        let now = Instant::now();
+        let mut until = self.until.lock().unwrap();
+        until.retain(|_, t| t.is_none_or(|t| t > now));
+        let mut list: Vec<_> = until
+            .iter()
+            .map(|(name, t)| MuteStatus {
+                trigger: name.clone(),
+                remaining: t.map(|t| (t - now).as_secs()),
+            })
+            .collect();
+        list.sort_by(|a, b| a.trigger.cmp(&b.trigger));
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutes() {
+        let mutes = Mutes::new();
+        let now = Instant::now();
+        mutes.mute("maze", Some(Duration::from_secs(600)));
+        mutes.mute("leave", None);
+        assert!(mutes.is_muted("maze", now));
+        assert!(mutes.is_muted("leave", now + Duration::from_secs(3600)));
+        assert!(!mutes.is_muted("card", now));
+        assert_eq!(mutes.list().len(), 2);
+        assert!(!mutes.is_muted("maze", now + Duration::from_secs(601)));
+        assert_eq!(mutes.list().len(), 1);
+        assert!(mutes.unmute("leave"));
+        assert!(!mutes.unmute("leave"));
+    }
+}
@@ -0,0 +1,129 @@
+use super::super::error::Error;
+use super::super::Notifiable;
+use std::thread;
+use std::time::Duration;
+
+/// Sound played by `MessageBeep`, i.e. the one of the Windows sound scheme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sound {
+    Default,
+    Info,
+    Warning,
+    Error,
+    Question,
+    /// The plain beep of the speaker, without the sound scheme
+    Simple,
+}
+
+impl Sound {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "" | "default" => Ok(Sound::Default),
+            "info" => Ok(Sound::Info),
+            "warning" => Ok(Sound::Warning),
+            "error" => Ok(Sound::Error),
+            "question" => Ok(Sound::Question),
+            "simple" => Ok(Sound::Simple),
+            _ => Err(Error::Config(format!(
+                "Unknown beep sound {name}, expect default, info, warning, error, question or simple"
+            ))),
+        }
+    }
+
+    #[cfg(windows)]
+    fn style(&self) -> u32 {
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            MB_ICONASTERISK, MB_ICONEXCLAMATION, MB_ICONHAND, MB_ICONQUESTION, MB_OK,
+        };
+        match self {
+            Sound::Default => MB_OK,
+            Sound::Info => MB_ICONASTERISK,
+            Sound::Warning => MB_ICONEXCLAMATION,
+            Sound::Error => MB_ICONHAND,
+            Sound::Question => MB_ICONQUESTION,
+            Sound::Simple => u32::MAX,
+        }
+    }
+}
+
+/// Beeps without an audio library, for machines where the ringtone finds no output device.
+pub struct Beep {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    sound: Sound,
+    /// Tone of the speaker in Hz, the sound is used when 0
+    #[cfg_attr(not(windows), allow(dead_code))]
+    frequency: u32,
+    duration: Duration,
+    count: u32,
+}
+
+impl Beep {
+    /// Pause between the beeps
+    const GAP: Duration = Duration::from_millis(300);
+
+    pub fn new(sound: Sound, frequency: u32, duration: Duration, count: u32) -> Self {
+        Self {
+            sound,
+            frequency,
+            duration,
+            count: count.max(1),
+        }
+    }
+
+    /// Returns once the beep is over, `MessageBeep` only starts the sound.
+    #[cfg(windows)]
+    fn beep(&self) -> Result<(), Error> {
+        use windows_sys::Win32::System::Diagnostics::Debug::{Beep, MessageBeep};
+
+        let ok = if self.frequency > 0 {
+            unsafe { Beep(self.frequency, self.duration.as_millis() as u32) }
+        } else {
+            let ok = unsafe { MessageBeep(self.sound.style()) };
+            thread::sleep(self.duration);
+            ok
+        };
+        if ok == 0 {
+            return Err(Error::device(std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// The terminal bell, the terminal decides how it sounds.
+    #[cfg(not(windows))]
+    fn beep(&self) -> Result<(), Error> {
+        use std::io::Write;
+
+        let mut stdout = std::io::stdout();
+        stdout.write_all(b"\x07")?;
+        stdout.flush()?;
+        thread::sleep(self.duration);
+        Ok(())
+    }
+}
+
+impl Notifiable for Beep {
+    fn notify(&self, _message: &str) -> Result<bool, Error> {
+        for i in 0..self.count {
+            if i > 0 {
+                thread::sleep(Beep::GAP);
+            }
+            self.beep()?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beep() {
+        assert_eq!(Sound::parse("").unwrap(), Sound::Default);
+        assert_eq!(Sound::parse("warning").unwrap(), Sound::Warning);
+        assert_eq!(Sound::parse("loud").unwrap_err().kind(), "config");
+        let beep = Beep::new(Sound::Simple, 0, Duration::from_millis(10), 0);
+        assert_eq!(beep.count, 1);
+        assert!(beep.notify("abc").unwrap());
+    }
+}
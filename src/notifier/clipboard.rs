@@ -0,0 +1,60 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use std::sync::Mutex;
+
+/// Kept for the whole run, on X11 and Wayland the copied text is gone once its owner is dropped.
+static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+
+/// Copies the match to the clipboard, ready to paste into the game chat.
+pub struct Clipboard {
+    /// `{0}`, `{1}` ... for the captures, e.g. `/w {1} ` to whisper the player
+    template: String,
+}
+
+impl Clipboard {
+    pub fn new(template: String) -> Self {
+        Self { template }
+    }
+
+    fn text(&self, event: &Event) -> String {
+        template::render(&self.template, &template::context(event))
+    }
+}
+
+impl Notifiable for Clipboard {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let mut clipboard = CLIPBOARD.lock().unwrap();
+        let clipboard = match clipboard.as_mut() {
+            Some(c) => c,
+            None => clipboard.insert(arboard::Clipboard::new().map_err(Error::device)?),
+        };
+        clipboard
+            .set_text(self.text(event))
+            .map_err(Error::notifier)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Trigger;
+    use crate::notifier::testutil::matched;
+
+    #[test]
+    fn test_clipboard_text() {
+        let mut trigger = Trigger::new(r#"收(\w+)"#);
+        trigger.name = "buy".to_owned();
+        let event = matched("21:40:12丂[世界]画眉鸟: 收玄铁", &trigger, "有人收玄铁");
+        let clipboard = Clipboard::new("/w {sender} 有{1}".to_owned());
+        assert_eq!(clipboard.text(&event), "/w 画眉鸟 有玄铁");
+        let clipboard = Clipboard::new("{message}".to_owned());
+        assert_eq!(clipboard.text(&event), "有人收玄铁");
+    }
+}
@@ -0,0 +1,100 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+
+/// Several notifiers under one name, so triggers can refer to them together. The dispatcher
+/// sends to each member on its own queue instead, this one is for a composite within a fallback.
+pub struct Composite {
+    children: Vec<(String, Box<dyn Notifiable>)>,
+}
+
+impl Composite {
+    pub fn new(children: Vec<(String, Box<dyn Notifiable>)>) -> Self {
+        Self { children }
+    }
+
+    /// Sends through every child, a failing one does not stop the others. Succeeds if any child
+    /// did, otherwise fails with the first error.
+    fn each<F>(&self, send: F) -> Result<bool, Error>
+    where
+        F: Fn(&dyn Notifiable) -> Result<bool, Error>,
+    {
+        let mut sent = false;
+        let mut first = None;
+        for (name, child) in &self.children {
+            match send(child.as_ref()) {
+                Ok(b) => {
+                    log::debug!("{name} notified: {b}");
+                    sent |= b;
+                }
+                Err(e) => {
+                    log::error!("{name} notify error: {e}");
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        match first {
+            Some(e) if !sent => Err(e),
+            _ => Ok(sent),
+        }
+    }
+}
+
+impl Notifiable for Composite {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.each(|n| n.notify(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.each(|n| n.notify_event(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NotifierError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Fake {
+        result: fn() -> Result<bool, Error>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Notifiable for Fake {
+        fn notify(&self, _: &str) -> Result<bool, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            (self.result)()
+        }
+    }
+
+    fn composite(results: &[fn() -> Result<bool, Error>]) -> (Composite, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let children = results
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let child: Box<dyn Notifiable> = Box::new(Fake {
+                    result: *r,
+                    calls: Arc::clone(&calls),
+                });
+                (i.to_string(), child)
+            })
+            .collect();
+        (Composite::new(children), calls)
+    }
+
+    #[test]
+    fn test_composite() {
+        let fail = || Err(NotifierError::Unsupported("fake".to_owned()).into());
+        let (c, calls) = composite(&[fail, || Ok(true), || Ok(false)]);
+        assert!(c.notify("abc").unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let (c, _) = composite(&[|| Ok(false), fail]);
+        assert!(c.notify("abc").is_err());
+        let (c, _) = composite(&[|| Ok(false)]);
+        assert!(!c.notify("abc").unwrap());
+    }
+}
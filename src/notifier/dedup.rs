@@ -0,0 +1,143 @@
+use super::super::config::DedupKey;
+use super::super::event::Event;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Suppresses identical messages sent to the same notifier within a time window,
+/// regardless of which trigger produced them.
+pub struct Dedup {
+    /// Window and key, replaced when the config is reloaded
+    settings: Mutex<(Duration, DedupKey)>,
+    /// Until when each notifier and key stays suppressed
+    sent: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            settings: Mutex::new((window, DedupKey::default())),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_key(mut self, by: DedupKey) -> Self {
+        self.settings.get_mut().unwrap().1 = by;
+        self
+    }
+
+    /// Takes the settings of a reloaded config, what was sent lately is still kept.
+    pub fn configure(&self, window: Duration, by: DedupKey) {
+        *self.settings.lock().unwrap() = (window, by);
+    }
+
+    pub fn window(&self) -> Duration {
+        self.settings.lock().unwrap().0
+    }
+
+    /// What the event is compared by, case and runs of whitespace ignored.
+    pub fn key(&self, event: &Event) -> String {
+        let by = self.settings.lock().unwrap().1;
+        let text = match by {
+            DedupKey::Message => event.message.clone(),
+            DedupKey::Line => event.raw.clone(),
+            DedupKey::Captures => {
+                let groups = event.captures.get(1..).filter(|g| !g.is_empty());
+                let groups = groups.unwrap_or(&event.captures);
+                format!("{}\u{1f}{}", event.trigger, groups.join("\u{1f}"))
+            }
+        };
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    /// Returns `true` if the message should be sent, and remembers it.
+    pub fn check(&self, notifier: &str, message: &str) -> bool {
+        self.check_within(notifier, message, self.window())
+    }
+
+    /// Like [`Dedup::check`], remembering the message for `window` instead.
+    pub fn check_within(&self, notifier: &str, key: &str, window: Duration) -> bool {
+        if window.is_zero() || self.is_duplicate(notifier, key) {
+            return window.is_zero();
+        }
+        self.record(notifier, key, window);
+        true
+    }
+
+    /// Whether the message was sent to the notifier lately.
+    pub fn is_duplicate(&self, notifier: &str, key: &str) -> bool {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        sent.retain(|_, until| now < *until);
+        sent.contains_key(&(notifier.to_owned(), key.to_owned()))
+    }
+
+    /// Remembers the message sent to the notifier for `window`.
+    pub fn record(&self, notifier: &str, key: &str, window: Duration) {
+        if window.is_zero() {
+            return;
+        }
+        let until = Instant::now() + window;
+        (self.sent.lock().unwrap()).insert((notifier.to_owned(), key.to_owned()), until);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup() {
+        let dedup = Dedup::new(Duration::from_millis(200));
+        assert!(dedup.check("dingtalk", "挑战赛通道 即将刷新"));
+        assert!(!dedup.check("dingtalk", "挑战赛通道 即将刷新"));
+        assert!(dedup.check("ringtone", "挑战赛通道 即将刷新"));
+        assert!(dedup.check("dingtalk", "队长掉线了"));
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(dedup.check("dingtalk", "挑战赛通道 即将刷新"));
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let mut first = Event::plain("12:00:00 招募  队友 刷迷宫");
+        first.raw = "招募  队友 刷迷宫".to_owned();
+        first.trigger = "recruit".to_owned();
+        first.captures = vec!["招募  队友 刷迷宫".to_owned(), "刷迷宫".to_owned()];
+        let mut second = first.clone();
+        second.message = "12:00:10 招募 队友 刷迷宫".to_owned();
+        second.raw = "招募 队友 刷迷宫".to_owned();
+        let dedup = Dedup::new(Duration::from_secs(60));
+        assert_ne!(dedup.key(&first), dedup.key(&second));
+        let dedup = dedup.with_key(DedupKey::Line);
+        assert_eq!(dedup.key(&first), dedup.key(&second));
+        let dedup = dedup.with_key(DedupKey::Captures);
+        assert_eq!(dedup.key(&first), "recruit\u{1f}刷迷宫");
+        assert!(dedup.check_within("dingtalk", "abc", Duration::from_secs(60)));
+        assert!(dedup.check_within("dingtalk", "abc", Duration::ZERO));
+        assert!(!dedup.check("dingtalk", "abc"));
+        dedup.configure(Duration::ZERO, DedupKey::Message);
+        assert!(dedup.check("dingtalk", "abc"));
+    }
+
+    #[test]
+    fn test_dedup_disabled() {
+        let dedup = Dedup::new(Duration::ZERO);
+        assert!(dedup.check("dingtalk", "Hello"));
+        assert!(dedup.check("dingtalk", "Hello"));
+    }
+
+    #[test]
+    fn test_dedup_record() {
+        let dedup = Dedup::new(Duration::from_secs(60));
+        let window = Duration::from_secs(60);
+        assert!(!dedup.is_duplicate("dingtalk", "Hello"));
+        assert!(!dedup.is_duplicate("dingtalk", "Hello"));
+        dedup.record("dingtalk", "Hello", window);
+        assert!(dedup.is_duplicate("dingtalk", "Hello"));
+        assert!(!dedup.is_duplicate("ringtone", "Hello"));
+        dedup.record("ringtone", "Hello", Duration::ZERO);
+        assert!(!dedup.is_duplicate("ringtone", "Hello"));
+    }
+}
@@ -0,0 +1,105 @@
+use super::super::config::Priority;
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+
+/// Desktop notification over DBus on Linux and BSD, for games played under Wine.
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+pub struct Desktop {
+    title: String,
+    /// Icon name of the theme or path of an image, empty for none
+    icon: String,
+    /// Seconds before normal and low priority notifications close, 0 for the server default
+    timeout: u64,
+    /// Titles events with the name of their trigger
+    named: bool,
+}
+
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+impl Desktop {
+    pub fn new(title: String, icon: String, timeout: u64, named: bool) -> Self {
+        Self {
+            title,
+            icon,
+            timeout,
+            named,
+        }
+    }
+
+    fn title<'a>(&'a self, trigger: &'a str) -> &'a str {
+        if self.named && !trigger.is_empty() {
+            trigger
+        } else {
+            &self.title
+        }
+    }
+
+    /// The urgency of the notification spec for a priority, critical ones stay until closed.
+    fn urgency(priority: Priority) -> &'static str {
+        match priority {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "critical",
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn show(&self, text: &str, trigger: &str, priority: Priority) -> Result<bool, Error> {
+        use notify_rust::{Notification, Timeout, Urgency};
+
+        let mut notification = Notification::new();
+        notification
+            .appname("cgaid")
+            .summary(self.title(trigger))
+            .body(text)
+            .urgency(match Desktop::urgency(priority) {
+                "low" => Urgency::Low,
+                "critical" => Urgency::Critical,
+                _ => Urgency::Normal,
+            });
+        if !self.icon.is_empty() {
+            notification.icon(&self.icon);
+        }
+        if priority == Priority::High {
+            notification.timeout(Timeout::Never);
+        } else if self.timeout > 0 {
+            notification.timeout(Timeout::Milliseconds(self.timeout as u32 * 1000));
+        }
+        notification.show().map_err(Error::notifier)?;
+        Ok(true)
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn show(&self, _text: &str, _trigger: &str, _priority: Priority) -> Result<bool, Error> {
+        Err(super::super::error::NotifierError::Unsupported(
+            "Desktop notification is only supported on Linux".to_owned(),
+        )
+        .into())
+    }
+}
+
+impl Notifiable for Desktop {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.show(message, "", Priority::Normal)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.show(&event.with_hint(), &event.trigger, event.priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_look() {
+        let desktop = Desktop::new("cgaid".to_owned(), String::new(), 0, true);
+        assert_eq!(desktop.title("迷宫"), "迷宫");
+        assert_eq!(desktop.title(""), "cgaid");
+        assert_eq!(Desktop::urgency(Priority::High), "critical");
+        assert_eq!(Desktop::urgency(Priority::Low), "low");
+        let desktop = Desktop::new("cgaid".to_owned(), String::new(), 0, false);
+        assert_eq!(desktop.title("迷宫"), "cgaid");
+    }
+}
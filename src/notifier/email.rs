@@ -0,0 +1,226 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::SmtpTransport;
+use lettre::{Message, Transport};
+use std::time::Duration;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Security {
+    /// TLS from the start, usually port 465
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually port 587
+    StartTls,
+    /// No encryption, only for servers on the local network, usually port 25
+    None,
+}
+
+impl std::str::FromStr for Security {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tls" | "" => Ok(Security::Tls),
+            "starttls" => Ok(Security::StartTls),
+            "none" => Ok(Security::None),
+            _ => Err(Error::Config(format!("Unknown email security {s}"))),
+        }
+    }
+}
+
+/// Sends mails through an SMTP server.
+pub struct Email {
+    host: String,
+    /// 0 for the usual port of the security
+    port: u16,
+    security: Security,
+    username: String,
+    password: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: String,
+    template: String,
+}
+
+impl Email {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        host: String,
+        port: u16,
+        security: Security,
+        username: String,
+        password: String,
+        from: &str,
+        to: &[String],
+        subject: String,
+        template: String,
+    ) -> Result<Self, Error> {
+        let mailbox = |m: &str| {
+            m.parse::<Mailbox>()
+                .map_err(|e| Error::Config(format!("Invalid email address {m}: {e}")))
+        };
+        if to.is_empty() {
+            return Err(Error::Config("No email recipient".to_owned()));
+        }
+        Ok(Self {
+            host,
+            port,
+            security,
+            username,
+            password,
+            from: mailbox(from)?,
+            to: to.iter().map(|m| mailbox(m)).collect::<Result<_, _>>()?,
+            subject,
+            template,
+        })
+    }
+
+    fn message(&self, event: &Event) -> Result<Message, Error> {
+        let values = template::context(event);
+        let fill = |text: &str| template::render(text, &values);
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(fill(&self.subject))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        builder
+            .body(fill(&self.template))
+            .map_err(|e| Error::notifier(e.to_string()))
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, Error> {
+        let mut builder = match self.security {
+            Security::Tls => SmtpTransport::relay(&self.host),
+            Security::StartTls => SmtpTransport::starttls_relay(&self.host),
+            Security::None => Ok(SmtpTransport::builder_dangerous(&self.host)),
+        }?
+        .timeout(Some(Email::TIMEOUT));
+        if self.port > 0 {
+            builder = builder.port(self.port);
+        }
+        if !self.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                self.username.clone(),
+                self.password.clone(),
+            ));
+        }
+        Ok(builder.build())
+    }
+}
+
+impl Notifiable for Email {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let response = self.transport()?.send(&self.message(event)?)?;
+        if !response.is_positive() {
+            return Err(NotifierError::Rejected {
+                status: response.code().into(),
+                reason: response.message().collect::<Vec<_>>().join(" "),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+
+    /// Accepts one mail as a plain SMTP server, returning its data.
+    fn serve_once() -> (u16, std::thread::JoinHandle<String>) {
+        let (listener, _) = crate::notifier::testutil::listen();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 OK queued\r\n").unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                } else {
+                    let reply: &[u8] = match &line[..4] {
+                        "EHLO" => b"250 localhost\r\n",
+                        "DATA" => {
+                            in_data = true;
+                            b"354 Go ahead\r\n"
+                        }
+                        "QUIT" => {
+                            writer.write_all(b"221 Bye\r\n").unwrap();
+                            break;
+                        }
+                        _ => b"250 OK\r\n",
+                    };
+                    writer.write_all(reply).unwrap();
+                }
+                line.clear();
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn test_email() {
+        let (port, handle) = serve_once();
+        let email = Email::new(
+            "127.0.0.1".to_owned(),
+            port,
+            Security::None,
+            String::new(),
+            String::new(),
+            "cgaid <cgaid@example.com>",
+            &["me@example.com".to_owned()],
+            "[cgaid] {trigger}".to_owned(),
+            "Notice: {message}".to_owned(),
+        )
+        .unwrap();
+        let mut event = Event::plain("点卡剩余 2 小时");
+        event.trigger = "card".to_owned();
+        assert!(email.notify_event(&event).unwrap());
+        let data = handle.join().unwrap();
+        assert!(data.contains("From: cgaid <cgaid@example.com>"));
+        assert!(data.contains("To: me@example.com"));
+        assert!(data.contains("Subject: [cgaid] card"));
+
+        assert!("starttls".parse::<Security>().unwrap() == Security::StartTls);
+        assert!("ssl".parse::<Security>().is_err());
+        let bad = |from: &str, to: &[String]| {
+            Email::new(
+                String::new(),
+                0,
+                Security::Tls,
+                String::new(),
+                String::new(),
+                from,
+                to,
+                String::new(),
+                String::new(),
+            )
+        };
+        assert!(bad("not an address", &["me@example.com".to_owned()]).is_err());
+        assert!(bad("cgaid@example.com", &[]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+
+/// Notifiers tried in order until one sends, e.g. DingTalk, then the ringtone when offline.
+pub struct Fallback {
+    chain: Vec<(String, Box<dyn Notifiable>)>,
+}
+
+impl Fallback {
+    pub fn new(chain: Vec<(String, Box<dyn Notifiable>)>) -> Self {
+        Self { chain }
+    }
+
+    /// Moves on to the next notifier when one fails, declines or is over its rate, which then
+    /// drops the message rather than queueing it. Fails with the first error if none sends.
+    fn each<F>(&self, send: F) -> Result<bool, Error>
+    where
+        F: Fn(&dyn Notifiable) -> Result<bool, Error>,
+    {
+        let mut first = None;
+        for (name, notifier) in &self.chain {
+            match super::unqueued(|| send(notifier.as_ref())) {
+                Ok(true) => {
+                    log::debug!("{name} notified");
+                    return Ok(true);
+                }
+                Ok(false) => log::warn!("{name} not sent, falling back"),
+                Err(e) => {
+                    log::error!("{name} notify error, falling back: {e}");
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        match first {
+            Some(e) => Err(e),
+            None => Ok(false),
+        }
+    }
+}
+
+impl Notifiable for Fallback {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.each(|n| n.notify(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.each(|n| n.notify_event(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NotifierError;
+    use std::sync::{Arc, Mutex};
+
+    type Outcome = fn() -> Result<bool, Error>;
+
+    struct Fake {
+        name: &'static str,
+        result: Outcome,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Notifiable for Fake {
+        fn notify(&self, _: &str) -> Result<bool, Error> {
+            self.calls.lock().unwrap().push(self.name);
+            (self.result)()
+        }
+    }
+
+    fn fallback(chain: &[(&'static str, Outcome)]) -> (Fallback, Arc<Mutex<Vec<&'static str>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let chain = chain
+            .iter()
+            .map(|(name, result)| {
+                let notifier: Box<dyn Notifiable> = Box::new(Fake {
+                    name,
+                    result: *result,
+                    calls: Arc::clone(&calls),
+                });
+                (name.to_string(), notifier)
+            })
+            .collect();
+        (Fallback::new(chain), calls)
+    }
+
+    #[test]
+    fn test_fallback() {
+        let offline = || Err(NotifierError::Unsupported("offline".to_owned()).into());
+        let (f, calls) = fallback(&[
+            ("dingtalk", offline),
+            ("ringtone", || Ok(false)),
+            ("invoke", || Ok(true)),
+            ("never", || Ok(true)),
+        ]);
+        assert!(f.notify("abc").unwrap());
+        assert_eq!(*calls.lock().unwrap(), ["dingtalk", "ringtone", "invoke"]);
+
+        let (f, _) = fallback(&[("ringtone", || Ok(false)), ("dingtalk", offline)]);
+        assert_eq!(f.notify("abc").unwrap_err().kind(), "unsupported");
+        let (f, _) = fallback(&[("ringtone", || Ok(false))]);
+        assert!(!f.notify("abc").unwrap());
+    }
+
+    #[test]
+    fn test_fallback_rate_limited() {
+        let reply = crate::notifier::testutil::response("200 OK", r#"{"errcode":0}"#);
+        let (url, handle) = crate::notifier::testutil::serve_once(reply);
+        let dingtalk =
+            super::super::webhook::DingTalk::new(url, "{message}".to_owned(), 1, false, Vec::new());
+        let (ringtone, calls) = fallback(&[("ringtone", || Ok(true))]);
+        let mut chain: Vec<(String, Box<dyn Notifiable>)> =
+            vec![("dingtalk".to_owned(), Box::new(dingtalk))];
+        chain.extend(ringtone.chain);
+        let f = Fallback::new(chain);
+        assert!(f.notify("abc").unwrap());
+        assert!(calls.lock().unwrap().is_empty());
+        // over the rate: not queued for later but passed on
+        assert!(f.notify("def").unwrap());
+        assert_eq!(*calls.lock().unwrap(), ["ringtone"]);
+        assert!(handle.join().unwrap().contains("abc"));
+    }
+}
@@ -0,0 +1,132 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use chrono::Local;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Serializes appends and rotations of every file log, notifiers run on several threads.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Appends matches to a file, one per line, rotated by size.
+pub struct FileLog {
+    path: PathBuf,
+    template: String,
+    /// Bytes the file may reach before it is rotated, 0 to never rotate
+    max_size: u64,
+    /// Rotated files kept as `<path>.1` (newest) to `<path>.<keep>`
+    keep: usize,
+}
+
+impl FileLog {
+    pub fn new(path: PathBuf, template: String, max_size: u64, keep: usize) -> Self {
+        Self {
+            path,
+            template,
+            max_size,
+            keep,
+        }
+    }
+
+    fn render(&self, event: &Event) -> String {
+        let mut values = template::context(event);
+        values.insert("date", &Local::now().format("%Y-%m-%d").to_string());
+        values.insert("channel", &event.channel.to_string());
+        let line = template::render(&self.template, &values);
+        // one match per line whatever the chat holds
+        line.replace(['\r', '\n'], " ")
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        name.into()
+    }
+
+    /// Shifts `<path>.n` to `<path>.n+1`, dropping the oldest, then the file to `<path>.1`.
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep == 0 {
+            return remove(&self.path);
+        }
+        remove(&self.rotated(self.keep))?;
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    fn append(&self, line: &str) -> io::Result<()> {
+        let _guard = LOCK.lock().unwrap();
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let size = fs::metadata(&self.path).map_or(0, |m| m.len());
+        if self.max_size > 0 && size > 0 && size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{line}")
+    }
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+impl Notifiable for FileLog {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.append(&self.render(event))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filelog() {
+        let dir = std::env::temp_dir().join("cgaid_test_filelog");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("matches.log");
+        let log = FileLog::new(
+            path.clone(),
+            "{time} [{channel}] {message}".to_owned(),
+            40,
+            2,
+        );
+        let mut event = Event::plain("收玄铁\n高价");
+        event.time = "12:00:01".to_owned();
+        assert!(log.notify_event(&event).unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "12:00:01 [普通] 收玄铁 高价\n"
+        );
+
+        for _ in 0..3 {
+            log.notify_event(&event).unwrap();
+        }
+        // each line is over half the limit, so every one started a new file
+        assert!(log.rotated(1).exists());
+        assert!(log.rotated(2).exists());
+        assert!(!log.rotated(3).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+use super::super::error::Error;
+#[cfg(windows)]
+use super::super::system;
+use super::super::Notifiable;
+
+/// Flashes the game windows and their taskbar buttons, a silent alert while playing in another
+/// window.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct FlashWindow {
+    /// Text the titles of the game windows contain
+    window: String,
+    /// Times to flash, 0 to flash until the window comes to the foreground
+    count: u32,
+    /// Flashes only the taskbar button, not the caption
+    taskbar: bool,
+}
+
+impl FlashWindow {
+    pub fn new(window: String, count: u32, taskbar: bool) -> Self {
+        Self {
+            window,
+            count,
+            taskbar,
+        }
+    }
+
+    #[cfg(windows)]
+    fn flash(&self, hwnd: isize) {
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            FlashWindowEx, FLASHWINFO, FLASHW_ALL, FLASHW_TIMER, FLASHW_TIMERNOFG, FLASHW_TRAY,
+        };
+
+        let mut flags = if self.taskbar {
+            FLASHW_TRAY
+        } else {
+            FLASHW_ALL
+        };
+        flags |= if self.count == 0 {
+            FLASHW_TIMERNOFG
+        } else {
+            FLASHW_TIMER
+        };
+        let info = FLASHWINFO {
+            cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
+            hwnd: hwnd as _,
+            dwFlags: flags,
+            // FLASHW_TIMERNOFG stops earlier, once the window comes to the foreground
+            uCount: if self.count == 0 {
+                u32::MAX
+            } else {
+                self.count
+            },
+            dwTimeout: 0,
+        };
+        // returns the previous state of the window, not an error
+        unsafe { FlashWindowEx(&info) };
+    }
+}
+
+impl Notifiable for FlashWindow {
+    #[cfg(windows)]
+    fn notify(&self, _message: &str) -> Result<bool, Error> {
+        let windows = system::window_titles(&self.window);
+        if windows.is_empty() {
+            log::debug!("No window to flash: {}", self.window);
+            return Ok(false);
+        }
+        for (hwnd, _) in windows {
+            self.flash(hwnd);
+        }
+        Ok(true)
+    }
+
+    #[cfg(not(windows))]
+    fn notify(&self, _message: &str) -> Result<bool, Error> {
+        Err(super::super::error::NotifierError::Unsupported(
+            "Flashing windows is only supported on Windows".to_owned(),
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flash_window() {
+        let flash = FlashWindow::new("no such window 7b2c".to_owned(), 3, false);
+        match flash.notify("abc") {
+            Ok(flashed) => assert!(!flashed),
+            Err(e) => assert_eq!(e.kind(), "unsupported"),
+        }
+    }
+}
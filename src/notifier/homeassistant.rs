@@ -0,0 +1,147 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde_json::Value;
+
+/// Drives Home Assistant, by a webhook trigger or by calling a service through the REST API,
+/// https://developers.home-assistant.io/docs/api/rest/
+pub struct HomeAssistant {
+    /// Base address such as `http://homeassistant.local:8123`
+    url: String,
+    /// Webhook ID, the whole event is posted to it when set
+    webhook: String,
+    /// Long-lived access token for service calls
+    token: String,
+    /// Service to call as `domain.service`, e.g. `light.turn_on`
+    service: String,
+    /// Service data, `{message}`, `{trigger}` and `{time}` in its strings are filled
+    data: Value,
+}
+
+impl HomeAssistant {
+    pub fn new(url: String, webhook: String, token: String, service: String, data: Value) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            webhook,
+            token,
+            service,
+            data,
+        }
+    }
+
+    /// The service data with the event filled into every string.
+    fn fill(value: &Value, values: &template::Context) -> Value {
+        match value {
+            Value::String(s) => Value::String(template::render(s, values)),
+            Value::Array(a) => Value::Array(a.iter().map(|v| Self::fill(v, values)).collect()),
+            Value::Object(o) => Value::Object(
+                o.iter()
+                    .map(|(k, v)| (k.clone(), Self::fill(v, values)))
+                    .collect(),
+            ),
+            Value::Null => Value::Object(Default::default()),
+            v => v.clone(),
+        }
+    }
+
+    fn request(&self, event: &Event) -> Result<reqwest::RequestBuilder, Error> {
+        let client = super::client();
+        if !self.webhook.is_empty() {
+            return Ok(client
+                .post(format!("{}/api/webhook/{}", self.url, self.webhook))
+                .json(event));
+        }
+        let Some((domain, service)) = self.service.split_once('.') else {
+            return Err(Error::Config(format!(
+                "Invalid Home Assistant service {}, expect domain.service",
+                self.service
+            )));
+        };
+        Ok(client
+            .post(format!("{}/api/services/{domain}/{service}", self.url))
+            .bearer_auth(&self.token)
+            .json(&Self::fill(&self.data, &template::context(event))))
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        let response = self.request(event)?.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for HomeAssistant {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        super::block_on(self.send(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{self, response, serve_once};
+
+    #[test]
+    fn test_home_assistant() {
+        let (url, handle) = serve_once(response("200 OK", "[]"));
+        let data = serde_json::json!({
+            "entity_id": ["light.desk"],
+            "flash": "long",
+            "brightness": 255,
+            "message": "{trigger}: {message}",
+        });
+        let ha = HomeAssistant::new(
+            url,
+            String::new(),
+            "tk".to_owned(),
+            "light.turn_on".to_owned(),
+            data,
+        );
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        assert!(ha.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /api/services/light/turn_on "));
+        assert!(request.contains("authorization: Bearer tk"));
+        let body: Value = serde_json::from_str(testutil::body(&request)).unwrap();
+        assert_eq!(body["entity_id"][0], "light.desk");
+        assert_eq!(body["brightness"], 255);
+        assert_eq!(body["message"], "boss: BOSS 出现了");
+
+        let (url, handle) = serve_once(response("200 OK", "[]"));
+        let ha = HomeAssistant::new(
+            url,
+            "cgaid".to_owned(),
+            String::new(),
+            String::new(),
+            Value::Null,
+        );
+        assert!(ha.notify("abc").unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /api/webhook/cgaid "));
+        assert!(!request.contains("authorization"));
+        let body: Value = serde_json::from_str(testutil::body(&request)).unwrap();
+        assert_eq!(body["message"], "abc");
+
+        let ha = HomeAssistant::new(
+            String::new(),
+            String::new(),
+            String::new(),
+            "turn_on".to_owned(),
+            Value::Null,
+        );
+        assert_eq!(ha.notify("abc").unwrap_err().kind(), "config");
+    }
+}
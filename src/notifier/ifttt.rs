@@ -0,0 +1,145 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Triggers an IFTTT Webhooks applet, https://ifttt.com/maker_webhooks
+pub struct Ifttt {
+    api: String,
+    key: String,
+    /// Event name, `{trigger}` for the name of the matched trigger
+    event: String,
+    /// Templates of `value1` to `value3`, `{0}`, `{1}` ... for the captures
+    values: Vec<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Body {
+    value1: String,
+    value2: String,
+    value3: String,
+}
+
+impl Ifttt {
+    pub const DEFAULT_API: &'static str = "https://maker.ifttt.com";
+    const DEFAULT_EVENT: &'static str = "cgaid";
+
+    pub fn new(api: String, key: String, event: String, values: Vec<String>) -> Self {
+        let api = if api.is_empty() {
+            Ifttt::DEFAULT_API.to_owned()
+        } else {
+            api.trim_end_matches('/').to_owned()
+        };
+        Self {
+            api,
+            key,
+            event,
+            values,
+        }
+    }
+
+    fn url(&self, event: &Event) -> String {
+        let name = template::render(&self.event, &template::context(event));
+        let name = if name.is_empty() {
+            Ifttt::DEFAULT_EVENT
+        } else {
+            &name
+        };
+        let encode = |s: &str| {
+            percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
+        };
+        format!(
+            "{}/trigger/{}/with/key/{}",
+            self.api,
+            encode(name),
+            encode(&self.key)
+        )
+    }
+
+    fn body(&self, event: &Event) -> Body {
+        static UNMATCHED: OnceLock<Regex> = OnceLock::new();
+        let unmatched = UNMATCHED.get_or_init(|| Regex::new(r"\{\d+\}").unwrap());
+        let values = template::context(event);
+        let value = |i: usize| {
+            let Some(text) = self.values.get(i) else {
+                return String::new();
+            };
+            let text = template::render(text, &values);
+            // groups the regex does not have, or plain messages without any
+            unmatched.replace_all(&text, "").into_owned()
+        };
+        Body {
+            value1: value(0),
+            value2: value(1),
+            value3: value(2),
+        }
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        let response = super::client()
+            .post(self.url(event))
+            .json(&self.body(event))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Ifttt {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        super::block_on(self.send(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Trigger;
+    use crate::notifier::testutil::{body, matched, serve_once};
+
+    fn serve(status: &str) -> (String, std::thread::JoinHandle<String>) {
+        serve_once(format!(
+            "HTTP/1.1 {status}\r\nContent-Length: 11\r\n\r\nCongrats!!!"
+        ))
+    }
+
+    #[test]
+    fn test_ifttt() {
+        let (api, handle) = serve("200 OK");
+        let values = vec!["{message}".to_owned(), "{1}".to_owned(), "{2}".to_owned()];
+        let ifttt = Ifttt::new(api, "k3y".to_owned(), "cg_{trigger}".to_owned(), values);
+        let mut trigger = Trigger::new(r#"(\w+)离开了队伍。"#);
+        trigger.name = "leave".to_owned();
+        let event = matched("21:40:12丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        assert!(ifttt.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /trigger/cg%5Fleave/with/key/k3y "));
+        let body: serde_json::Value = serde_json::from_str(body(&request)).unwrap();
+        assert_eq!(body["value1"], "画眉鸟 掉线了");
+        assert_eq!(body["value2"], "画眉鸟");
+        assert_eq!(body["value3"], "");
+
+        let (api, _) = serve("401 Unauthorized");
+        let ifttt = Ifttt::new(api, "bad".to_owned(), "{trigger}".to_owned(), Vec::new());
+        assert!(ifttt
+            .url(&Event::plain("abc"))
+            .ends_with("/trigger/cgaid/with/key/bad"));
+        let e = ifttt.notify("abc").unwrap_err();
+        assert_eq!(e.kind(), "rejected");
+    }
+}
@@ -0,0 +1,171 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::Notifiable;
+
+/// Writes a line protocol point per match to InfluxDB, the measurement is the trigger name,
+/// https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/
+pub struct Influx {
+    /// Base address such as `http://localhost:8086`
+    url: String,
+    /// Bucket and organization of InfluxDB 2, written with the token
+    bucket: String,
+    org: String,
+    token: String,
+    /// Database of InfluxDB 1, used when there is no bucket
+    database: String,
+}
+
+impl Influx {
+    /// Measurement of events without a trigger name
+    const DEFAULT_MEASUREMENT: &'static str = "cgaid";
+
+    pub fn new(url: String, bucket: String, org: String, token: String, database: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            bucket,
+            org,
+            token,
+            database,
+        }
+    }
+
+    fn write_url(&self) -> String {
+        let encode = |s: &str| {
+            percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
+        };
+        if self.bucket.is_empty() {
+            format!(
+                "{}/write?db={}&precision=ns",
+                self.url,
+                encode(&self.database)
+            )
+        } else {
+            format!(
+                "{}/api/v2/write?org={}&bucket={}&precision=ns",
+                self.url,
+                encode(&self.org),
+                encode(&self.bucket)
+            )
+        }
+    }
+
+    /// `trigger,channel=..,sender=.. count=1i,message=".." <nanos>`
+    fn line(event: &Event, nanos: i64) -> String {
+        // measurements escape commas and spaces, tags equal signs as well
+        let escape = |s: &str, chars: &[char]| {
+            let mut escaped = String::with_capacity(s.len());
+            for c in s.chars() {
+                if c == '\\' || chars.contains(&c) {
+                    escaped.push('\\');
+                }
+                // line breaks end the point
+                escaped.push(if c == '\n' { ' ' } else { c });
+            }
+            escaped
+        };
+        let measurement = if event.trigger.is_empty() {
+            Influx::DEFAULT_MEASUREMENT
+        } else {
+            &event.trigger
+        };
+        let mut line = escape(measurement, &[',', ' ']);
+        line.push_str(",channel=");
+        line.push_str(&escape(event.channel.name(), &[',', '=', ' ']));
+        if let Some(sender) = event.sender.as_deref().filter(|s| !s.is_empty()) {
+            line.push_str(",sender=");
+            line.push_str(&escape(sender, &[',', '=', ' ']));
+        }
+        let message = event
+            .message
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', " ");
+        line.push_str(&format!(" count=1i,message=\"{message}\" {nanos}"));
+        line
+    }
+
+    async fn send(&self, line: String) -> Result<bool, Error> {
+        let mut request = super::client()
+            .post(self.write_url())
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(line);
+        if !self.token.is_empty() {
+            request = request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("Token {}", self.token),
+            );
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Influx {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        super::block_on(self.send(Influx::line(event, nanos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{body, response, serve_once};
+
+    #[test]
+    fn test_influx_line() {
+        let mut event = Event::plain("BOSS \"火龙\"\n出现了");
+        event.trigger = "boss, world".to_owned();
+        event.sender = Some("画眉 鸟=1".to_owned());
+        assert_eq!(
+            Influx::line(&event, 1700000000000000000),
+            format!(
+                "boss\\,\\ world,channel={},sender=画眉\\ 鸟\\=1 count=1i,message=\"BOSS \\\"火龙\\\" 出现了\" 1700000000000000000",
+                event.channel.name()
+            )
+        );
+        let event = Event::plain("abc");
+        assert!(Influx::line(&event, 1).starts_with("cgaid,channel="));
+
+        let v1 = Influx::new(
+            "http://localhost:8086/".to_owned(),
+            String::new(),
+            String::new(),
+            String::new(),
+            "cgaid".to_owned(),
+        );
+        assert_eq!(
+            v1.write_url(),
+            "http://localhost:8086/write?db=cgaid&precision=ns"
+        );
+    }
+
+    #[test]
+    fn test_influx() {
+        let (url, handle) = serve_once(response("204 No Content", ""));
+        let influx = Influx::new(
+            url,
+            "game".to_owned(),
+            "home".to_owned(),
+            "secret".to_owned(),
+            String::new(),
+        );
+        assert!(influx.notify("abc").unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /api/v2/write?org=home&bucket=game&precision=ns "));
+        assert!(request.contains("authorization: Token secret"));
+        assert!(body(&request).starts_with("cgaid,channel="));
+    }
+}
@@ -0,0 +1,474 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use super::style::Styles;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Sends matches to an IRC channel, staying connected and joined between notifications.
+pub struct Irc {
+    /// `host:port`, e.g. `irc.libera.chat:6697`
+    server: String,
+    tls: bool,
+    nick: String,
+    /// Server password, empty for none
+    password: String,
+    /// Channel such as `#cgaid`, or a nick for private messages
+    channel: String,
+    template: String,
+    styles: Option<Styles>,
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// How far a connection got, shared by its session thread and the senders.
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    Joining,
+    Joined,
+    /// The server refused the channel with the numeric reply and its text
+    Failed(u16, String),
+    Closed,
+}
+
+/// An open connection: the lines to send and its status.
+#[derive(Clone)]
+struct Link {
+    lines: Sender<String>,
+    status: Arc<(Mutex<Status>, Condvar)>,
+}
+
+impl Link {
+    /// The status once no longer joining, or still joining after `timeout`.
+    fn wait(&self, timeout: Duration) -> Status {
+        let (status, changed) = &*self.status;
+        let status = changed
+            .wait_timeout_while(status.lock().unwrap(), timeout, |s| *s == Status::Joining)
+            .unwrap()
+            .0;
+        status.clone()
+    }
+}
+
+/// Sets the status of a link, a failure stays.
+fn set(status: &(Mutex<Status>, Condvar), to: Status) {
+    let (status, changed) = status;
+    let mut s = status.lock().unwrap();
+    if !matches!(*s, Status::Failed(..)) {
+        *s = to;
+    }
+    changed.notify_all();
+}
+
+/// The open connections, by server, nick and channel.
+fn links() -> &'static Mutex<HashMap<String, Link>> {
+    static LINKS: OnceLock<Mutex<HashMap<String, Link>>> = OnceLock::new();
+    LINKS.get_or_init(Default::default)
+}
+
+impl Irc {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How often the connection thread looks for lines to send
+    const POLL: Duration = Duration::from_millis(200);
+    /// Bytes of text in one PRIVMSG, the whole line including the prefix added by the server
+    /// must stay below 512
+    const MAX_TEXT: usize = 400;
+
+    pub fn new(
+        server: String,
+        tls: bool,
+        nick: String,
+        password: String,
+        channel: String,
+        template: String,
+    ) -> Self {
+        Self {
+            server,
+            tls,
+            nick,
+            password,
+            channel,
+            template,
+            styles: None,
+        }
+    }
+
+    /// Colors messages by the priority of their events.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    fn key(&self) -> String {
+        format!("{}/{}/{}", self.server, self.nick, self.channel)
+    }
+
+    /// One PRIVMSG per line of the message, as IRC messages cannot contain line breaks.
+    fn lines(&self, message: &str) -> Vec<String> {
+        template::render(&self.template, &template::message(message))
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| format!("PRIVMSG {} :{}", self.channel, truncate(l, Irc::MAX_TEXT)))
+            .collect()
+    }
+
+    fn open(&self) -> Result<Box<dyn Stream>, Error> {
+        let unreachable = |e: &dyn std::fmt::Display| {
+            Error::from(NotifierError::Unreachable(format!("{}: {e}", self.server)))
+        };
+        let addr = self
+            .server
+            .to_socket_addrs()
+            .map_err(|e| unreachable(&e))?
+            .next()
+            .ok_or_else(|| Error::Config(format!("Invalid IRC server {}", self.server)))?;
+        let tcp =
+            TcpStream::connect_timeout(&addr, Irc::CONNECT_TIMEOUT).map_err(|e| unreachable(&e))?;
+        tcp.set_read_timeout(Some(Irc::CONNECT_TIMEOUT))?;
+        let socket = tcp.try_clone()?;
+        let mut stream: Box<dyn Stream> = if self.tls {
+            let host = self.server.rsplit_once(':').map_or("", |(h, _)| h);
+            let connector = native_tls::TlsConnector::new().map_err(Error::notifier)?;
+            Box::new(connector.connect(host, tcp).map_err(|e| unreachable(&e))?)
+        } else {
+            Box::new(tcp)
+        };
+        socket.set_read_timeout(Some(Irc::POLL))?;
+        if !self.password.is_empty() {
+            write!(stream, "PASS {}\r\n", self.password)?;
+        }
+        write!(
+            stream,
+            "NICK {}\r\nUSER {} 0 * :cgaid\r\n",
+            self.nick, self.nick
+        )?;
+        stream.flush()?;
+        Ok(stream)
+    }
+
+    /// The link of the connection, connecting without holding the other connections up.
+    fn link(&self) -> Result<Link, Error> {
+        let key = self.key();
+        if let Some(link) = links().lock().unwrap().get(&key) {
+            return Ok(link.clone());
+        }
+        let stream = self.open()?;
+        let (tx, rx) = mpsc::channel();
+        let status = Arc::new((Mutex::new(Status::Joining), Condvar::new()));
+        let link = Link {
+            lines: tx,
+            status: Arc::clone(&status),
+        };
+        // another send may have connected meanwhile, this connection then quits
+        let link = links().lock().unwrap().entry(key).or_insert(link).clone();
+        let (nick, channel) = (self.nick.clone(), self.channel.clone());
+        thread::spawn(move || {
+            let mut session = Session::new(stream, rx, nick, channel, Arc::clone(&status));
+            match session.run() {
+                Ok(()) => log::warn!("IRC connection closed by the server"),
+                Err(e) => log::error!("IRC connection error: {e}"),
+            }
+            set(&status, Status::Closed);
+        });
+        Ok(link)
+    }
+
+    /// Sends once the channel is joined, connecting unless connected. A refused channel fails
+    /// the send, as does a connection that ended, which the next send opens again.
+    fn send(&self, lines: Vec<String>) -> Result<(), Error> {
+        let link = self.link()?;
+        let status = link.wait(Irc::CONNECT_TIMEOUT);
+        // fails when the connection thread has ended
+        if status == Status::Joined
+            && lines
+                .into_iter()
+                .try_for_each(|l| link.lines.send(l))
+                .is_ok()
+        {
+            return Ok(());
+        }
+        if status != Status::Joining {
+            let mut links = links().lock().unwrap();
+            if links
+                .get(&self.key())
+                .is_some_and(|l| Arc::ptr_eq(&l.status, &link.status))
+            {
+                links.remove(&self.key());
+            }
+        }
+        Err(match status {
+            Status::Failed(status, reason) => NotifierError::Rejected { status, reason },
+            Status::Joining => {
+                NotifierError::Unreachable(format!("{}: {} not joined", self.server, self.channel))
+            }
+            _ => NotifierError::Unreachable(format!("{}: connection lost", self.server)),
+        }
+        .into())
+    }
+}
+
+/// Cuts the text to at most `max` bytes on a char boundary.
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// A registered connection, answering pings and sending the queued lines once joined.
+struct Session {
+    reader: BufReader<Box<dyn Stream>>,
+    outgoing: Receiver<String>,
+    nick: String,
+    channel: String,
+    joined: bool,
+    status: Arc<(Mutex<Status>, Condvar)>,
+    queue: VecDeque<String>,
+}
+
+impl Session {
+    fn new(
+        stream: Box<dyn Stream>,
+        outgoing: Receiver<String>,
+        nick: String,
+        channel: String,
+        status: Arc<(Mutex<Status>, Condvar)>,
+    ) -> Self {
+        Self {
+            reader: BufReader::new(stream),
+            outgoing,
+            nick,
+            channel,
+            joined: false,
+            status,
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn join(&mut self) {
+        self.joined = true;
+        set(&self.status, Status::Joined);
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        let stream = self.reader.get_mut();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()
+    }
+
+    /// Runs until the server closes the connection, the connection stays open for the next
+    /// notifications otherwise.
+    fn run(&mut self) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            // a timeout keeps what was read so far in `line`
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Ok(()),
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line).trim_end().to_owned();
+                    line.clear();
+                    if !self.handle(&text)? {
+                        return Ok(());
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            }
+            loop {
+                match self.outgoing.try_recv() {
+                    Ok(l) => self.queue.push_back(l),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.write("QUIT :bye")?;
+                        return Ok(());
+                    }
+                }
+            }
+            while self.joined {
+                let Some(l) = self.queue.pop_front() else {
+                    break;
+                };
+                self.write(&l)?;
+            }
+        }
+    }
+
+    /// Handles a line from the server, false when the server ends the connection.
+    fn handle(&mut self, line: &str) -> std::io::Result<bool> {
+        let (prefix, rest) = match line.strip_prefix(':') {
+            Some(l) => l.split_once(' ').unwrap_or((l, "")),
+            None => ("", line),
+        };
+        let (command, params) = rest.split_once(' ').unwrap_or((rest, ""));
+        match command {
+            "PING" => self.write(&format!("PONG {params}"))?,
+            // welcome, registered
+            "001" if self.channel.starts_with(['#', '&']) => {
+                self.write(&format!("JOIN {}", self.channel))?
+            }
+            "001" => self.join(),
+            // nick in use
+            "433" => {
+                self.nick.push('_');
+                self.write(&format!("NICK {}", self.nick))?;
+            }
+            "JOIN" if prefix.split('!').next() == Some(self.nick.as_str()) => {
+                log::info!("IRC joined {}", self.channel);
+                self.join();
+            }
+            // no such channel, full, invite only, banned or wrong key
+            "403" | "471" | "473" | "474" | "475" => {
+                let reason = format!("cannot join {}: {params}", self.channel);
+                set(
+                    &self.status,
+                    Status::Failed(command.parse().unwrap_or_default(), reason.clone()),
+                );
+                return Err(std::io::Error::other(reason));
+            }
+            "ERROR" => {
+                log::warn!("IRC server error: {params}");
+                return Ok(false);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Irc {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        let lines = self.lines(message);
+        if lines.is_empty() {
+            return Ok(false);
+        }
+        self.send(lines)?;
+        Ok(true)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let text = event.with_hint();
+        match self.styles.as_ref().map(|s| s.look(event.priority)) {
+            Some(l) => self.notify(&l.irc(&event.trigger, &text)),
+            None => self.notify(&text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::listen;
+
+    #[test]
+    fn test_irc() {
+        let (listener, server) = listen();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut read = || {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end().to_owned()
+            };
+            let mut received = vec![read(), read()];
+            writer
+                .write_all(b":srv 433 * cgaid :Nickname is already in use\r\n")
+                .unwrap();
+            received.push(read());
+            writer.write_all(b":srv 001 cgaid_ :Welcome\r\n").unwrap();
+            received.push(read());
+            writer.write_all(b"PING :srv\r\n").unwrap();
+            received.push(read());
+            writer.write_all(b":cgaid_!u@h JOIN #cgaid\r\n").unwrap();
+            received.push(read());
+            received.push(read());
+            received.push(read());
+            writer.write_all(b"ERROR :Closing link\r\n").unwrap();
+            received
+        });
+
+        let irc = Irc::new(
+            server,
+            false,
+            "cgaid".to_owned(),
+            String::new(),
+            "#cgaid".to_owned(),
+            "[cgaid] {message}".to_owned(),
+        );
+        assert!(irc.notify("BOSS 出现了\n在 3 线").unwrap());
+        assert!(irc.notify("again").unwrap());
+        assert_eq!(
+            handle.join().unwrap(),
+            [
+                "NICK cgaid",
+                "USER cgaid 0 * :cgaid",
+                "NICK cgaid_",
+                "JOIN #cgaid",
+                "PONG :srv",
+                "PRIVMSG #cgaid :[cgaid] BOSS 出现了",
+                "PRIVMSG #cgaid :在 3 线",
+                "PRIVMSG #cgaid :[cgaid] again",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_irc_banned() {
+        let (listener, server) = listen();
+        let handle = thread::spawn(move || {
+            // a refused join ends the connection, the next send connects again
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                for _ in 0..2 {
+                    reader.read_line(&mut line).unwrap();
+                }
+                writer.write_all(b":srv 001 cgaid :Welcome\r\n").unwrap();
+                reader.read_line(&mut line).unwrap();
+                writer
+                    .write_all(b":srv 474 cgaid #cgaid :Cannot join channel (+b)\r\n")
+                    .unwrap();
+            }
+        });
+
+        let irc = Irc::new(
+            server,
+            false,
+            "cgaid".to_owned(),
+            String::new(),
+            "#cgaid".to_owned(),
+            "{message}".to_owned(),
+        );
+        for _ in 0..2 {
+            let e = irc.notify("BOSS 出现了").unwrap_err();
+            assert_eq!(e.kind(), "rejected");
+            assert!(e.to_string().contains("Cannot join channel"));
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("abc", 5), "abc");
+        assert_eq!(truncate("出现了", 4), "出");
+    }
+}
@@ -0,0 +1,154 @@
+//! Notifiers in dynamic libraries, such as `plugins/wechat.dll` (`libwechat.so` elsewhere)
+//! sending as the notifier `wechat`, built with the `dylib` feature.
+//!
+//! A library exports, with the C ABI:
+//!
+//! ```c
+//! struct CgaidNotifier {
+//!     uint32_t abi; /* 1 */
+//!     void *state;
+//!     /* 1 sent, 0 skipped, -1 failed, -2 failed for now and worth resending,
+//!        a failure may write a message of at most error_len bytes to error */
+//!     int32_t (*notify)(void *state, const char *event_json, char *error, size_t error_len);
+//!     void (*destroy)(void *state);
+//! };
+//!
+//! /* settings_json is the library's table under [notifier.library], NULL when failing */
+//! const struct CgaidNotifier *create_notifier(const char *settings_json);
+//! ```
+
+use super::super::config;
+use super::super::error::Error;
+use super::super::Notifiable;
+
+/// The notifier in the library of the name.
+#[cfg(feature = "dylib")]
+pub fn build(lc: &config::Library, name: &str) -> Result<Box<dyn Notifiable>, Error> {
+    let settings = lc
+        .settings
+        .get(name)
+        .map_or(Ok(serde_json::Value::Null), serde_json::to_value)?;
+    Ok(Box::new(dylib::Library::load(&lc.file(name), &settings)?))
+}
+
+/// The notifier in the library of the name.
+#[cfg(not(feature = "dylib"))]
+pub fn build(lc: &config::Library, name: &str) -> Result<Box<dyn Notifiable>, Error> {
+    Err(super::super::error::NotifierError::Unsupported(format!(
+        "{} needs a build with the dylib feature",
+        lc.file(name).display()
+    ))
+    .into())
+}
+
+#[cfg(feature = "dylib")]
+mod dylib {
+    use super::super::super::error::{Error, NotifierError};
+    use super::super::super::event::Event;
+    use super::super::super::Notifiable;
+    use std::ffi::{c_char, c_void, CStr, CString};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    #[repr(C)]
+    struct Vtable {
+        abi: u32,
+        state: *mut c_void,
+        notify: unsafe extern "C" fn(*mut c_void, *const c_char, *mut c_char, usize) -> i32,
+        destroy: unsafe extern "C" fn(*mut c_void),
+    }
+
+    type Create = unsafe extern "C" fn(*const c_char) -> *const Vtable;
+
+    pub struct Library {
+        vtable: *const Vtable,
+        /// Calls are made one at a time, the library need not be thread safe
+        lock: Mutex<()>,
+        /// Unloaded last, after the notifier is destroyed
+        _lib: libloading::Library,
+    }
+
+    // the state is only touched under the lock
+    unsafe impl Send for Library {}
+    unsafe impl Sync for Library {}
+
+    impl Library {
+        const ABI: u32 = 1;
+        const ERROR_LEN: usize = 512;
+
+        pub fn load(path: &Path, settings: &serde_json::Value) -> Result<Self, Error> {
+            let failed = |e: &dyn std::fmt::Display| {
+                Error::Config(format!("Failed to load {}: {e}", path.display()))
+            };
+            let settings = CString::new(settings.to_string()).map_err(|e| failed(&e))?;
+            unsafe {
+                let lib = libloading::Library::new(path).map_err(|e| failed(&e))?;
+                let create: libloading::Symbol<Create> =
+                    lib.get(b"create_notifier\0").map_err(|e| failed(&e))?;
+                let vtable = create(settings.as_ptr());
+                if vtable.is_null() {
+                    return Err(failed(&"create_notifier failed"));
+                }
+                if (*vtable).abi != Self::ABI {
+                    return Err(failed(&format!("unsupported ABI {}", (*vtable).abi)));
+                }
+                log::info!("Notifier library loaded: {}", path.display());
+                Ok(Self {
+                    vtable,
+                    lock: Mutex::new(()),
+                    _lib: lib,
+                })
+            }
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            unsafe { ((*self.vtable).destroy)((*self.vtable).state) }
+        }
+    }
+
+    impl Notifiable for Library {
+        fn notify(&self, message: &str) -> Result<bool, Error> {
+            self.notify_event(&Event::plain(message))
+        }
+
+        fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+            let json = CString::new(serde_json::to_string(event)?)
+                .map_err(|e| Error::notifier(e.to_string()))?;
+            let mut error = vec![0 as c_char; Self::ERROR_LEN];
+            let _guard = self.lock.lock().unwrap();
+            let code = unsafe {
+                let vtable = &*self.vtable;
+                (vtable.notify)(
+                    vtable.state,
+                    json.as_ptr(),
+                    error.as_mut_ptr(),
+                    error.len() - 1,
+                )
+            };
+            let message = || {
+                let text = unsafe { CStr::from_ptr(error.as_ptr()) };
+                text.to_string_lossy().into_owned()
+            };
+            match code {
+                1.. => Ok(true),
+                0 => Ok(false),
+                -2 => Err(NotifierError::Unreachable(message()).into()),
+                _ => Err(Error::notifier(message())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_missing() {
+        let lc = config::Library::default();
+        let e = build(&lc, "cgaid_missing").err().unwrap();
+        assert!(matches!(e.kind(), "config" | "unsupported"));
+    }
+}
@@ -0,0 +1,138 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Ships matches to Grafana Loki, https://grafana.com/docs/loki/latest/reference/loki-http-api/#ingest-logs
+pub struct Loki {
+    /// Base address such as `http://localhost:3100`
+    url: String,
+    /// Labels of every stream besides `trigger` and `channel`, `job` is `cgaid` unless set
+    labels: BTreeMap<String, String>,
+    /// Log line, `{message}` and `{raw}` are filled
+    template: String,
+    /// Basic auth, e.g. the instance ID and an API token for Grafana Cloud
+    user: String,
+    password: String,
+    /// `X-Scope-OrgID` of a multi-tenant Loki, empty for none
+    tenant: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Push {
+    streams: Vec<Stream>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stream {
+    stream: BTreeMap<String, String>,
+    /// Nanoseconds since the epoch as a string, and the line
+    values: Vec<[String; 2]>,
+}
+
+impl Loki {
+    pub fn new(
+        url: String,
+        mut labels: BTreeMap<String, String>,
+        template: String,
+        user: String,
+        password: String,
+        tenant: String,
+    ) -> Self {
+        labels
+            .entry("job".to_owned())
+            .or_insert_with(|| "cgaid".to_owned());
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            labels,
+            template,
+            user,
+            password,
+            tenant,
+        }
+    }
+
+    fn push(&self, event: &Event, nanos: i64) -> Push {
+        let mut stream = self.labels.clone();
+        stream.insert("trigger".to_owned(), event.trigger.clone());
+        stream.insert("channel".to_owned(), event.channel.name().to_owned());
+        let line = template::render(&self.template, &template::context(event));
+        Push {
+            streams: vec![Stream {
+                stream,
+                values: vec![[nanos.to_string(), line]],
+            }],
+        }
+    }
+
+    async fn send(&self, push: &Push) -> Result<bool, Error> {
+        let mut request = super::client()
+            .post(format!("{}/loki/api/v1/push", self.url))
+            .json(push);
+        if !self.user.is_empty() {
+            request = request.basic_auth(&self.user, Some(&self.password));
+        }
+        if !self.tenant.is_empty() {
+            request = request.header("X-Scope-OrgID", &self.tenant);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Loki {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        super::block_on(self.send(&self.push(event, nanos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{body, response, serve_once};
+
+    #[test]
+    fn test_loki() {
+        let (url, handle) = serve_once(response("204 No Content", ""));
+        let labels = BTreeMap::from([("host".to_owned(), "pc1".to_owned())]);
+        let loki = Loki::new(
+            url,
+            labels,
+            "{message} | {raw}".to_owned(),
+            "123".to_owned(),
+            "token".to_owned(),
+            "guild".to_owned(),
+        );
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        event.raw = "[系统] BOSS 出现了".to_owned();
+        assert!(loki.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /loki/api/v1/push "));
+        assert!(request.contains("x-scope-orgid: guild"));
+        assert!(request.to_lowercase().contains("authorization: basic"));
+        let body: serde_json::Value = serde_json::from_str(body(&request)).unwrap();
+        let stream = &body["streams"][0];
+        assert_eq!(stream["stream"]["job"], "cgaid");
+        assert_eq!(stream["stream"]["host"], "pc1");
+        assert_eq!(stream["stream"]["trigger"], "boss");
+        assert_eq!(stream["stream"]["channel"], event.channel.name());
+        assert_eq!(stream["values"][0][1], "BOSS 出现了 | [系统] BOSS 出现了");
+        assert!(stream["values"][0][0].as_str().unwrap().len() >= 19);
+    }
+}
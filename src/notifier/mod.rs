@@ -1,11 +1,151 @@
+use super::config;
+use super::error::{Error, NotifierError};
+use super::event::Event;
+use super::template;
 use colored::{Color, Colorize};
 use cpal::traits::{DeviceTrait, HostTrait};
 use rodio::{Decoder, OutputStream, Sink};
-use std::error::Error;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::fs::File;
+use std::future::Future;
 use std::io::{BufReader, Cursor, Read, Seek};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use unicode_width::UnicodeWidthStr;
+pub mod beep;
+pub mod clipboard;
+pub mod composite;
+pub mod dedup;
+pub mod desktop;
+pub mod email;
+pub mod fallback;
+pub mod filelog;
+pub mod flash;
+pub mod homeassistant;
+pub mod ifttt;
+pub mod influx;
+pub mod irc;
+pub mod library;
+pub mod loki;
+pub mod nats;
+pub mod ntfy;
+pub mod outbox;
+pub mod overlay;
+pub mod plugin;
+pub mod power;
+pub mod pushgateway;
+pub mod pushover;
+pub mod pushplus;
+pub mod ratelimit;
+pub mod sandbox;
+pub mod screenshot;
+pub mod serial;
+pub mod sms;
+pub mod socket;
+pub mod sqlite;
+pub mod style;
+pub mod telegram;
+pub mod toast;
+pub mod tray;
+pub mod truncate;
 pub mod webhook;
+pub mod xmpp;
 
+/// The runtime the notifiers send on, started by the first one, rather than one per send.
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("notifier")
+            .enable_all()
+            .build()
+            .expect("Failed to start the notifier runtime")
+    })
+}
+
+thread_local! {
+    /// Time the async sends of the current notification may take, set by [`within`]
+    static TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+    /// Set by [`unqueued`]
+    static UNQUEUED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with the async sends it makes given up after `timeout`. Notifiers not sending over
+/// the network, e.g. a ringtone playing to its end, are not limited.
+pub fn within<R>(timeout: Duration, f: impl FnOnce() -> R) -> R {
+    let previous = TIMEOUT.replace(Some(timeout));
+    let result = f();
+    TIMEOUT.set(previous);
+    result
+}
+
+/// Runs `f` with notifiers sending right away or not at all, instead of holding a message over
+/// their rate in their own queue, so that a fallback can move on to the next one.
+pub fn unqueued<R>(f: impl FnOnce() -> R) -> R {
+    let previous = UNQUEUED.replace(true);
+    let result = f();
+    UNQUEUED.set(previous);
+    result
+}
+
+/// Whether a notifier may hold a message in its own queue, see [`unqueued`].
+pub fn may_queue() -> bool {
+    !UNQUEUED.get()
+}
+
+/// Runs an async send on the shared runtime, blocking the notifier's worker thread until done,
+/// or until the timeout set by [`within`]. Must not be called from a task of the runtime itself.
+pub fn block_on<T, E, F>(future: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<NotifierError>,
+{
+    match TIMEOUT.get() {
+        Some(timeout) => runtime().block_on(async {
+            tokio::time::timeout(timeout, future)
+                .await
+                .unwrap_or_else(|_| Err(NotifierError::Timeout(timeout).into()))
+        }),
+        None => runtime().block_on(future),
+    }
+}
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Sends the notifiers' HTTP requests through `proxy`, must be called before the first one.
+pub fn set_proxy(proxy: &config::Proxy) -> Result<(), Error> {
+    CLIENT
+        .set(build_client(proxy)?)
+        .map_err(|_| Error::Config("Proxy set after the HTTP client was used".to_owned()))
+}
+
+fn build_client(proxy: &config::Proxy) -> Result<reqwest::Client, Error> {
+    let mut builder = reqwest::Client::builder();
+    if proxy.disabled {
+        builder = builder.no_proxy();
+    } else if !proxy.url.is_empty() {
+        let mut p = reqwest::Proxy::all(&proxy.url)
+            .map_err(|e| Error::Config(format!("Invalid proxy {}: {e}", proxy.url)))?;
+        if !proxy.username.is_empty() {
+            p = p.basic_auth(&proxy.username, &proxy.password);
+        }
+        builder = builder.proxy(p);
+    }
+    builder
+        .build()
+        .map_err(|e| Error::Config(format!("Invalid proxy {}: {e}", proxy.url)))
+}
+
+/// The HTTP client shared by the notifiers, through the system proxy unless one was set.
+pub fn client() -> reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new).clone()
+}
+
+#[derive(Default)]
 pub struct Simple {}
 
 impl Simple {
@@ -15,7 +155,7 @@ impl Simple {
 }
 
 impl super::Notifiable for Simple {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>> {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
         log::info!("{message}",);
         Ok(true)
     }
@@ -23,40 +163,104 @@ impl super::Notifiable for Simple {
 
 pub struct Console {
     color: Option<Color>,
+    /// Colors by priority or channel name
+    colors: HashMap<String, Color>,
     format: String,
+    align: usize,
     by_log: bool,
+    mirror: Option<Arc<socket::Hub>>,
 }
 impl Console {
-    pub fn new(color: String, format: String, by_log: bool) -> Self {
+    pub fn new(
+        color: String,
+        colors: &HashMap<String, String>,
+        format: String,
+        align: usize,
+        by_log: bool,
+        mirror: Option<Arc<socket::Hub>>,
+    ) -> Self {
         let color = color.parse().ok();
+        let colors = colors
+            .iter()
+            .filter_map(|(k, v)| v.parse().ok().map(|c| (k.to_lowercase(), c)))
+            .collect();
         Self {
             color,
+            colors,
             format,
+            align,
             by_log,
+            mirror,
         }
     }
-}
 
-impl super::Notifiable for Console {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>> {
-        let msg = self.format.replace("{message}", message);
-        let cm = if let Some(c) = self.color {
+    /// Pads `text` with spaces to the display width of the column.
+    fn pad(&self, text: &str) -> String {
+        let width = text.width();
+        if width < self.align {
+            format!("{text}{}", " ".repeat(self.align - width))
+        } else {
+            text.to_owned()
+        }
+    }
+
+    fn render(&self, message: &str, time: &str, channel: &str, sender: &str) -> String {
+        let mut values = template::message(message);
+        values.insert("time", time);
+        values.insert("channel", channel);
+        values.insert("sender", &self.pad(sender));
+        template::render(&self.format, &values)
+    }
+
+    /// Priority color first, then channel color, then the default one.
+    fn color_of(&self, event: &Event) -> Option<Color> {
+        self.colors
+            .get(event.priority.name())
+            .or_else(|| self.colors.get(event.channel.name()))
+            .copied()
+            .or(self.color)
+    }
+
+    fn print(&self, msg: String, color: Option<Color>) {
+        let cm = if let Some(c) = color {
             msg.color(c).to_string()
         } else {
             msg
         };
+        if let Some(m) = &self.mirror {
+            m.broadcast(&cm);
+        }
         if self.by_log {
             log::info!("{cm}");
         } else {
             println!("{cm}");
         }
+    }
+}
+
+impl super::Notifiable for Console {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        let time = chrono::Local::now().format("%H:%M:%S").to_string();
+        self.print(self.render(message, &time, "", ""), self.color);
+        Ok(true)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let msg = self.render(
+            &event.message,
+            &event.time,
+            &event.channel.to_string(),
+            event.sender.as_deref().unwrap_or_default(),
+        );
+        self.print(msg, self.color_of(event));
         Ok(true)
     }
 }
 
 struct Player {
     sink: Sink,
-    _stream: OutputStream,
+    /// Dropped with the player to close the output stream on its thread
+    _stream: mpsc::Sender<()>,
 }
 
 pub struct Ringtone {
@@ -64,7 +268,39 @@ pub struct Ringtone {
     player: Option<Player>,
 }
 
+/// Bumped to stop the ringtones playing, e.g. when an alert is acknowledged.
+static SILENCED: AtomicU64 = AtomicU64::new(0);
+
 impl Player {
+    /// Opens the device on a thread of its own, where the output stream, which cannot be sent
+    /// to other threads, stays open until the player is dropped.
+    fn open(device: cpal::Device) -> Result<Self, Error> {
+        let (tx, rx) = mpsc::channel();
+        let (keep, closed) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let opened = OutputStream::try_from_device(&device)
+                .map_err(Error::device)
+                .and_then(|(stream, handle)| {
+                    Ok((stream, Sink::try_new(&handle).map_err(Error::device)?))
+                });
+            match opened {
+                Ok((_stream, sink)) => {
+                    let _ = tx.send(Ok(sink));
+                    // returns once the sender is dropped
+                    let _ = closed.recv();
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                }
+            }
+        });
+        let sink = rx.recv().map_err(Error::device)??;
+        Ok(Self {
+            sink,
+            _stream: keep,
+        })
+    }
+
     fn stop(&self) {
         self.sink.stop();
     }
@@ -74,23 +310,32 @@ impl Player {
     {
         self.sink.append(source);
     }
+    /// Waits for the end, or stops early once [`Ringtone::silence`] is called.
     fn wait_end(&self) {
-        self.sink.sleep_until_end();
+        let silenced = SILENCED.load(Ordering::SeqCst);
+        while !self.sink.empty() {
+            if SILENCED.load(Ordering::SeqCst) != silenced {
+                log::info!("Ringtone silenced");
+                self.sink.stop();
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
     }
 }
 
 impl Ringtone {
-    pub fn new(path: String, device_name: String) -> Result<Self, Box<dyn Error>> {
+    pub fn new(path: String, device_name: String) -> Result<Self, Error> {
         let device = Ringtone::find_device(&device_name)?;
-        let mut player = None;
-        if let Some(d) = device {
-            let (_stream, handle) = OutputStream::try_from_device(&d)?;
-            let sink = Sink::try_new(&handle)?;
-            player = Some(Player { sink, _stream });
-        }
+        let player = device.map(Player::open).transpose()?;
         Ok(Self { path, player })
     }
 
+    /// Stops all ringtones playing now, the ones started later play as usual.
+    pub fn silence() {
+        SILENCED.fetch_add(1, Ordering::SeqCst);
+    }
+
     #[allow(dead_code)]
     pub fn stop(&self) {
         if let Some(player) = &self.player {
@@ -98,15 +343,15 @@ impl Ringtone {
         }
     }
 
-    fn find_device(name: &String) -> Result<Option<cpal::Device>, Box<dyn Error>> {
+    fn find_device(name: &str) -> Result<Option<cpal::Device>, Error> {
         let host = cpal::default_host();
         if name.is_empty() {
             return Ok(host.default_output_device());
         }
-        let devices = host.output_devices()?;
+        let devices = host.output_devices().map_err(Error::device)?;
         let mut device = None;
         for d in devices {
-            let name = d.name()?;
+            let name = d.name().map_err(Error::device)?;
             // println!("Device: {}", name);
             if name.contains(name.as_str()) {
                 device = Some(d);
@@ -114,27 +359,31 @@ impl Ringtone {
         }
         Ok(device)
     }
-}
 
-trait ReadSeek: Read + Seek {}
-impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+    fn decode<R>(data: R) -> Result<Decoder<R>, Error>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        Decoder::new(data).map_err(|e| Error::Parse(e.to_string()))
+    }
+}
 
 impl super::Notifiable for Ringtone {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>> {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
         log::info!("Ringtone notify: {}", message);
         if let Some(player) = &self.player {
             player.stop();
             let path = &self.path;
             if path.is_empty() {
                 let data = include_bytes!("demo.mp3");
-                player.play(Decoder::new(Cursor::new(data.as_ref()))?);
+                player.play(Ringtone::decode(Cursor::new(data.as_ref()))?);
             } else {
-                player.play(Decoder::new(BufReader::new(File::open(path)?))?);
+                player.play(Ringtone::decode(BufReader::new(File::open(path)?))?);
             };
             player.wait_end();
             Ok(true)
         } else {
-            Err("Device not found".into())
+            Err(NotifierError::DeviceMissing("Device not found".to_owned()).into())
         }
     }
 }
@@ -143,6 +392,8 @@ pub struct Invoke {
     path: String,
     args: Vec<String>,
     workdir: String,
+    /// Limits of the sandbox the command runs in, `None` to run it as is
+    sandbox: Option<sandbox::Limits>,
 }
 
 impl Invoke {
@@ -151,12 +402,19 @@ impl Invoke {
             path,
             args,
             workdir,
+            sandbox: None,
         }
     }
+
+    /// Runs the command in a sandbox, so that whatever it starts is killed when it exits.
+    pub fn with_sandbox(mut self, limits: sandbox::Limits) -> Self {
+        self.sandbox = Some(limits);
+        self
+    }
 }
 
 impl super::Notifiable for Invoke {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>> {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
         let mut command = std::process::Command::new(&self.path);
         let dir = if self.workdir.is_empty() {
             std::env::current_dir()?
@@ -165,14 +423,109 @@ impl super::Notifiable for Invoke {
         };
         command.current_dir(dir);
         for arg in &self.args {
-            command.arg(arg.replace("{message}", message));
+            command.arg(template::render(arg, &template::message(message)));
         }
-        let output = command.output()?;
+        let output = match self.sandbox {
+            Some(limits) => sandbox::run(command, limits)?,
+            None => command.output()?,
+        };
         log::info!("Invoke result: {output:?}");
         Ok(output.status.success())
     }
 }
 
+#[cfg(test)]
+pub(crate) mod testutil {
+    //! Local servers and matched events shared by the notifier tests.
+
+    use crate::chat::record::Record;
+    use crate::config::Trigger;
+    use crate::event::Event;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+
+    /// A listener on a free local port with its `host:port`.
+    pub fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    /// An HTTP response with a JSON body.
+    pub fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Reads one HTTP request up to the end of the body given by its `Content-Length`.
+    pub fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length: ")
+                            .map(|v| v.parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    /// Answers a request per connection with the next of `responses`,
+    /// returning `http://host:port` and the requests received.
+    pub fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let (listener, addr) = listen();
+        let handle = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let request = read_request(&mut stream);
+                    stream.write_all(response.as_bytes()).unwrap();
+                    request
+                })
+                .collect()
+        });
+        (format!("http://{addr}"), handle)
+    }
+
+    /// Answers a single request with `response`.
+    pub fn serve_once(response: String) -> (String, JoinHandle<String>) {
+        let (url, handle) = serve(vec![response]);
+        let handle = thread::spawn(move || handle.join().unwrap().remove(0));
+        (url, handle)
+    }
+
+    /// The body of a request read by [`read_request`].
+    pub fn body(request: &str) -> &str {
+        request.split_once("\r\n\r\n").unwrap().1
+    }
+
+    /// The event of `trigger` matching the chat `line`, notified as `message`.
+    pub fn matched(line: &str, trigger: &Trigger, message: &str) -> Event {
+        let record = Record::from(line).unwrap();
+        let captures = trigger.try_match(record.msg()).unwrap();
+        Event::new(&record, trigger, captures, message.to_owned())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -219,12 +572,53 @@ mod tests {
         ];
         for color in colors {
             let name = color.to_owned();
-            let console = Console::new(name.clone(), "{message}".to_owned(), false);
+            let console = Console::new(
+                name.clone(),
+                &HashMap::new(),
+                "{message}".to_owned(),
+                0,
+                false,
+                None,
+            );
             let ret = console.notify(&name).unwrap();
             assert!(ret);
         }
     }
 
+    #[test]
+    fn test_console_event() {
+        let colors = HashMap::from([
+            ("world".to_owned(), "cyan".to_owned()),
+            ("high".to_owned(), "red".to_owned()),
+        ]);
+        let console = Console::new(
+            String::new(),
+            &colors,
+            "{time} [{channel}] {sender}| {message}".to_owned(),
+            8,
+            false,
+            None,
+        );
+        let line = "21:40:12丂[世界]盛明兰: 半山来个车头";
+        let mut trigger = crate::config::Trigger::new("半山");
+        let event = testutil::matched(line, &trigger, "车头");
+        assert_eq!(
+            console.render(
+                &event.message,
+                &event.time,
+                &event.channel.to_string(),
+                "盛明兰"
+            ),
+            "21:40:12 [世界] 盛明兰  | 车头"
+        );
+        assert_eq!(console.color_of(&event), Some(Color::Cyan));
+        assert!(console.notify_event(&event).unwrap());
+
+        trigger.priority = crate::config::Priority::High;
+        let event = testutil::matched(line, &trigger, "车头");
+        assert_eq!(console.color_of(&event), Some(Color::Red));
+    }
+
     #[test]
     fn test_music_mp3() {
         let (_stream, handle) = rodio::OutputStream::try_default().unwrap();
@@ -280,4 +674,58 @@ mod tests {
         let ret = invoke.notify("Hello, World!").unwrap();
         assert!(ret);
     }
+
+    #[test]
+    fn test_shared_runtime() {
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    block_on(async move {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok::<_, Error>(i)
+                    })
+                })
+            })
+            .collect();
+        let sum: i32 = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().unwrap())
+            .sum();
+        assert_eq!(sum, 6);
+        assert!(std::ptr::eq(runtime(), runtime()));
+    }
+
+    #[test]
+    fn test_block_on_within() {
+        let slow = || {
+            block_on(async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, Error>(true)
+            })
+        };
+        let e = within(Duration::from_millis(20), slow).unwrap_err();
+        assert_eq!(e.kind(), "timeout");
+        // only for the sends made within
+        assert!(slow().unwrap());
+    }
+
+    #[test]
+    fn test_proxy() {
+        let mut proxy = config::Proxy {
+            url: "socks5://127.0.0.1:1".to_owned(),
+            username: "user".to_owned(),
+            password: "p@ss".to_owned(),
+            ..Default::default()
+        };
+        let client = build_client(&proxy).unwrap();
+        let e: Error = runtime()
+            .block_on(client.get("http://example.com/").send())
+            .unwrap_err()
+            .into();
+        assert!(e.is_offline());
+        proxy.url = "127.0.0.1 1080".to_owned();
+        assert_eq!(build_client(&proxy).unwrap_err().kind(), "config");
+        proxy.disabled = true;
+        assert!(build_client(&proxy).is_ok());
+    }
 }
@@ -0,0 +1,346 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Publishes events as JSON to a NATS subject, https://docs.nats.io/reference/reference-protocols/nats-protocol
+///
+/// The payload is the event as described by `assets/event.schema.json`.
+pub struct Nats {
+    /// `host:port`, e.g. `localhost:4222`
+    server: String,
+    /// Subject, `{trigger}` and `{channel}` are filled, e.g. `cgaid.{channel}.{trigger}`
+    subject: String,
+    user: String,
+    password: String,
+    token: String,
+    /// Reconnects after a failed publish, with a doubling pause
+    retries: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct Connect<'a> {
+    verbose: bool,
+    pedantic: bool,
+    name: &'static str,
+    lang: &'static str,
+    version: &'static str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    user: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pass: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    auth_token: &'a str,
+}
+
+/// A connection that has been accepted by the server.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(data)?;
+        Ok(self.writer.flush()?)
+    }
+
+    /// Pings and waits for the pong, so everything written before has been processed.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.write(b"PING\r\n")?;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(NotifierError::Unreachable("NATS connection closed".to_owned()).into());
+            }
+            let line = line.trim_end();
+            match line {
+                "PONG" => return Ok(()),
+                "PING" => self.write(b"PONG\r\n")?,
+                _ if line.starts_with("-ERR") => {
+                    return Err(NotifierError::Rejected {
+                        status: 0,
+                        reason: line.trim_start_matches("-ERR").trim().to_owned(),
+                    }
+                    .into())
+                }
+                // +OK, INFO updates
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Open connections by server and credentials, see [`Nats::key`].
+fn connections() -> &'static Mutex<HashMap<String, Connection>> {
+    static CONNECTIONS: OnceLock<Mutex<HashMap<String, Connection>>> = OnceLock::new();
+    CONNECTIONS.get_or_init(Default::default)
+}
+
+impl Nats {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// Pause before the first retry
+    const RETRY: Duration = Duration::from_millis(500);
+
+    pub fn new(
+        server: String,
+        subject: String,
+        user: String,
+        password: String,
+        token: String,
+        retries: u32,
+    ) -> Self {
+        Self {
+            server,
+            subject,
+            user,
+            password,
+            token,
+            retries,
+        }
+    }
+
+    /// Subject tokens are separated by dots and may not contain spaces.
+    fn subject(&self, event: &Event) -> String {
+        let token = |s: &str| {
+            let t: String = s
+                .chars()
+                .map(|c| {
+                    if c == '.' || c.is_whitespace() {
+                        '_'
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            if t.is_empty() {
+                "_".to_owned()
+            } else {
+                t
+            }
+        };
+        let mut values = template::Context::new();
+        values.insert("trigger", &token(&event.trigger));
+        values.insert("channel", &token(event.channel.name()));
+        template::render(&self.subject, &values)
+    }
+
+    fn connect(&self) -> Result<Connection, Error> {
+        let unreachable = |e: std::io::Error| {
+            Error::from(NotifierError::Unreachable(format!("{}: {e}", self.server)))
+        };
+        let addr = self
+            .server
+            .to_socket_addrs()
+            .map_err(unreachable)?
+            .next()
+            .ok_or_else(|| Error::Config(format!("Invalid NATS server {}", self.server)))?;
+        let stream = TcpStream::connect_timeout(&addr, Nats::TIMEOUT).map_err(unreachable)?;
+        stream.set_read_timeout(Some(Nats::TIMEOUT))?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let mut info = String::new();
+        connection.reader.read_line(&mut info)?;
+        if !info.starts_with("INFO ") {
+            return Err(Error::notifier(format!(
+                "Not a NATS server: {}",
+                info.trim()
+            )));
+        }
+        let connect = Connect {
+            verbose: false,
+            pedantic: false,
+            name: "cgaid",
+            lang: "rust",
+            version: env!("CARGO_PKG_VERSION"),
+            user: &self.user,
+            pass: &self.password,
+            auth_token: &self.token,
+        };
+        let line = format!("CONNECT {}\r\n", serde_json::to_string(&connect)?);
+        connection.write(line.as_bytes())?;
+        // an authorization error arrives instead of the pong
+        connection.flush()?;
+        log::info!("NATS connected: {}", self.server);
+        Ok(connection)
+    }
+
+    /// Instances with other credentials publish as another user, so on their own connection.
+    fn key(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.server, self.user, self.password, self.token
+        )
+    }
+
+    fn publish(&self, subject: &str, payload: &[u8]) -> Result<(), Error> {
+        let key = self.key();
+        let mut connections = connections().lock().unwrap();
+        if !connections.contains_key(&key) {
+            let connection = self.connect()?;
+            connections.insert(key.clone(), connection);
+        }
+        let connection = connections.get_mut(&key).unwrap();
+        let mut message = format!("PUB {subject} {}\r\n", payload.len()).into_bytes();
+        message.extend_from_slice(payload);
+        message.extend_from_slice(b"\r\n");
+        let published = connection.write(&message).and_then(|_| connection.flush());
+        if published.is_err() {
+            // reconnected on the next try
+            connections.remove(&key);
+        }
+        published
+    }
+}
+
+impl Notifiable for Nats {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let subject = self.subject(event);
+        let payload = serde_json::to_vec(event)?;
+        let mut pause = Nats::RETRY;
+        for attempt in 0..=self.retries {
+            match self.publish(&subject, &payload) {
+                Ok(()) => return Ok(true),
+                // the server will not take it after reconnecting either
+                Err(e) if e.kind() == "rejected" || attempt == self.retries => return Err(e),
+                Err(e) => {
+                    log::warn!("NATS publish error, retrying in {pause:?}: {e}");
+                    thread::sleep(pause);
+                    pause *= 2;
+                }
+            }
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves one client per connection, dropping the first after the INFO line when `drop_first`.
+    fn serve(connections: usize, drop_first: bool) -> (String, thread::JoinHandle<Vec<String>>) {
+        let (listener, server) = crate::notifier::testutil::listen();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            for i in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                writer
+                    .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+                    .unwrap();
+                if drop_first && i == 0 {
+                    continue;
+                }
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                let mut denied = false;
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let l = line.trim_end().to_owned();
+                    line.clear();
+                    if l == "PING" && denied {
+                        writer
+                            .write_all(b"-ERR 'Authorization Violation'\r\n")
+                            .unwrap();
+                        break;
+                    } else if l == "PING" {
+                        writer.write_all(b"PONG\r\n").unwrap();
+                    } else if l.starts_with("CONNECT") {
+                        denied = l.contains("bad");
+                    } else if l.starts_with("PUB") {
+                        let mut payload = String::new();
+                        reader.read_line(&mut payload).unwrap();
+                        received.push(format!("{l}\n{}", payload.trim_end()));
+                    }
+                }
+            }
+            received
+        });
+        (server, handle)
+    }
+
+    #[test]
+    fn test_nats() {
+        let (server, handle) = serve(2, true);
+        let nats = Nats::new(
+            server,
+            "cgaid.{channel}.{trigger}".to_owned(),
+            String::new(),
+            String::new(),
+            "secret".to_owned(),
+            2,
+        );
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss. world".to_owned();
+        assert!(nats.notify_event(&event).unwrap());
+        connections().lock().unwrap().clear();
+        let received = handle.join().unwrap();
+        assert_eq!(received.len(), 1);
+        let (head, payload) = received[0].split_once('\n').unwrap();
+        let subject = format!("cgaid.{}.boss__world", event.channel.name());
+        assert_eq!(head, format!("PUB {subject} {}", payload.len()));
+        let json: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(json["message"], "BOSS 出现了");
+    }
+
+    #[test]
+    fn test_nats_rejected() {
+        let (server, _) = serve(1, false);
+        let nats = Nats::new(
+            server,
+            "cgaid".to_owned(),
+            "bad".to_owned(),
+            "bad".to_owned(),
+            String::new(),
+            3,
+        );
+        assert_eq!(nats.notify("abc").unwrap_err().kind(), "rejected");
+    }
+
+    #[test]
+    fn test_nats_key() {
+        let nats = |user: &str, token: &str| {
+            Nats::new(
+                "localhost:4222".to_owned(),
+                "cgaid".to_owned(),
+                user.to_owned(),
+                String::new(),
+                token.to_owned(),
+                0,
+            )
+        };
+        assert_eq!(nats("a", "").key(), nats("a", "").key());
+        assert_ne!(nats("a", "").key(), nats("b", "").key());
+        assert_ne!(nats("", "x").key(), nats("", "y").key());
+    }
+
+    #[test]
+    fn test_event_schema() {
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../../assets/event.schema.json")).unwrap();
+        let event = serde_json::to_value(Event::plain("abc")).unwrap();
+        let mut keys: Vec<_> = event.as_object().unwrap().keys().collect();
+        let mut required: Vec<_> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|k| k.as_str().unwrap())
+            .collect();
+        keys.sort();
+        required.sort();
+        assert_eq!(keys, required);
+    }
+}
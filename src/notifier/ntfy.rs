@@ -0,0 +1,143 @@
+use super::super::config::Priority;
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::Serialize;
+
+/// Publishes to an ntfy topic, on ntfy.sh or a self-hosted server, https://docs.ntfy.sh/publish/
+pub struct Ntfy {
+    server: String,
+    topic: String,
+    /// Access token of protected topics, empty for public ones
+    token: String,
+    title: String,
+    template: String,
+    /// Tags or emoji short codes added to every message
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Body<'a> {
+    topic: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    title: &'a str,
+    priority: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<&'a str>,
+}
+
+impl Ntfy {
+    pub const DEFAULT_SERVER: &'static str = "https://ntfy.sh";
+
+    pub fn new(
+        server: String,
+        topic: String,
+        token: String,
+        title: String,
+        template: String,
+        tags: Vec<String>,
+    ) -> Self {
+        let server = if server.is_empty() {
+            Ntfy::DEFAULT_SERVER.to_owned()
+        } else {
+            server.trim_end_matches('/').to_owned()
+        };
+        Self {
+            server,
+            topic,
+            token,
+            title,
+            template,
+            tags,
+        }
+    }
+
+    /// ntfy priority from 1 (min) to 5 (max).
+    fn priority(priority: Priority) -> u8 {
+        match priority {
+            Priority::Low => 2,
+            Priority::Normal => 3,
+            Priority::High => 5,
+        }
+    }
+
+    fn body<'a>(&'a self, event: &'a Event) -> Body<'a> {
+        let mut tags: Vec<_> = self.tags.iter().map(|t| t.as_str()).collect();
+        for t in &event.tags {
+            if !tags.contains(&t.as_str()) {
+                tags.push(t);
+            }
+        }
+        Body {
+            topic: &self.topic,
+            message: template::render(&self.template, &template::context(event)),
+            title: &self.title,
+            priority: Ntfy::priority(event.priority),
+            tags,
+        }
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        // JSON to the server root, so titles need no header encoding
+        let mut request = super::client().post(&self.server).json(&self.body(event));
+        if !self.token.is_empty() {
+            request = request.bearer_auth(&self.token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Ntfy {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        super::block_on(self.send(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{body, matched, response, serve_once};
+
+    #[test]
+    fn test_ntfy() {
+        let (server, handle) = serve_once(response("200 OK", "{}"));
+        let ntfy = Ntfy::new(
+            server,
+            "cgaid".to_owned(),
+            "tk_abc".to_owned(),
+            "魔力宝贝".to_owned(),
+            "Notice: {message}".to_owned(),
+            vec!["video_game".to_owned()],
+        );
+        let mut trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        trigger.priority = Priority::High;
+        trigger.tags = vec!["warning".to_owned(), "video_game".to_owned()];
+        let event = matched("12:00:02丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        assert!(ntfy.notify_event(&event).unwrap());
+
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST / "));
+        assert!(request.contains("authorization: Bearer tk_abc"));
+        let body: serde_json::Value = serde_json::from_str(body(&request)).unwrap();
+        assert_eq!(body["topic"], "cgaid");
+        assert_eq!(body["message"], "Notice: 画眉鸟 掉线了");
+        assert_eq!(body["title"], "魔力宝贝");
+        assert_eq!(body["priority"], 5);
+        assert_eq!(body["tags"], serde_json::json!(["video_game", "warning"]));
+    }
+}
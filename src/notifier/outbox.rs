@@ -0,0 +1,228 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::template;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A notification that could not be delivered because the network or the remote was down.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub notifier: String,
+    pub time: String,
+    pub message: String,
+    /// The whole event, absent in entries stored by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+    /// Resends failed so far
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Persists undelivered notifications to disk, one JSON entry per line, so they can be re-sent
+/// once the network returns, on the next start at the latest.
+pub struct Outbox {
+    path: PathBuf,
+    format: String,
+    /// Resends before an entry is dropped, 0 for no limit
+    attempts: u32,
+    lock: Mutex<()>,
+}
+
+impl Outbox {
+    const TIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+
+    pub fn new(path: PathBuf, format: String) -> Self {
+        Self {
+            path,
+            format,
+            attempts: 0,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn push(&self, notifier: &str, event: &Event) -> io::Result<()> {
+        let entry = Entry {
+            notifier: notifier.to_owned(),
+            time: Local::now().format(Outbox::TIME_FORMAT).to_string(),
+            message: event.message.clone(),
+            event: Some(event.clone()),
+            attempts: 0,
+        };
+        let _guard = self.lock.lock().unwrap();
+        self.append(&[entry])
+    }
+
+    fn append(&self, entries: &[Entry]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for entry in entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    }
+
+    fn take(&self) -> io::Result<Vec<Entry>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let entries = text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| match serde_json::from_str(l) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    log::error!("Outbox entry invalid: {e}");
+                    None
+                }
+            })
+            .collect();
+        fs::remove_file(&self.path)?;
+        Ok(entries)
+    }
+
+    /// Re-sends every stored entry through `send`, noting the original time in the message.
+    /// Entries still failing for a passing reason are written back, until they run out of
+    /// attempts. Returns the number delivered.
+    pub fn flush<F>(&self, send: F) -> io::Result<usize>
+    where
+        F: Fn(&str, &Event) -> Result<bool, Error>,
+    {
+        let _guard = self.lock.lock().unwrap();
+        let entries = self.take()?;
+        let mut failed = Vec::new();
+        let mut sent = 0;
+        for (i, entry) in entries.iter().enumerate() {
+            let mut event = entry
+                .event
+                .clone()
+                .unwrap_or_else(|| Event::plain(&entry.message));
+            let mut values = template::message(&entry.message);
+            values.insert("time", &entry.time);
+            event.message = template::render(&self.format, &values);
+            match send(&entry.notifier, &event) {
+                Ok(_) => sent += 1,
+                Err(e) if e.is_offline() => {
+                    log::debug!("Outbox resend failed: {e}");
+                    // still offline, keep the rest for the next round
+                    failed.extend_from_slice(&entries[i..]);
+                    failed[0].attempts += 1;
+                    break;
+                }
+                Err(e) if e.is_transient() => {
+                    log::debug!("Outbox resend failed: {e}");
+                    failed.push(Entry {
+                        attempts: entry.attempts + 1,
+                        ..entry.clone()
+                    });
+                }
+                Err(e) => log::error!("Outbox entry dropped: {e} {entry:?}"),
+            }
+        }
+        if self.attempts > 0 {
+            failed.retain(|e| {
+                let keep = e.attempts < self.attempts;
+                if !keep {
+                    log::error!("Outbox entry out of attempts, dropped: {e:?}");
+                }
+                keep
+            });
+        }
+        if !failed.is_empty() {
+            self.append(&failed)?;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox(name: &str) -> Outbox {
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        Outbox::new(path, "[{time}] {message}".to_owned())
+    }
+
+    #[test]
+    fn test_outbox_flush() {
+        let outbox = outbox("cgaid_outbox_flush.jsonl");
+        let mut event = Event::plain("挑战赛通道 即将刷新");
+        event.trigger = "maze".to_owned();
+        outbox.push("dingtalk", &event).unwrap();
+        outbox
+            .push("dingtalk", &Event::plain("队长掉线了"))
+            .unwrap();
+
+        let sent = std::cell::RefCell::new(Vec::new());
+        let n = outbox
+            .flush(|name, event| {
+                sent.borrow_mut().push((name.to_owned(), event.clone()));
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(n, 2);
+        let sent = sent.into_inner();
+        assert_eq!(sent[0].0, "dingtalk");
+        assert_eq!(sent[0].1.trigger, "maze");
+        assert!(sent[0].1.message.starts_with('['));
+        assert!(sent[1].1.message.ends_with("] 队长掉线了"));
+        assert!(outbox.take().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_outbox_transient() {
+        let outbox = outbox("cgaid_outbox_transient.jsonl").with_attempts(2);
+        // stored by an older version
+        outbox
+            .append(&[serde_json::from_str(
+                r#"{"notifier":"http","time":"2024-01-01 00:00:00","message":"Hello"}"#,
+            )
+            .unwrap()])
+            .unwrap();
+        let unavailable = |_: &str, _: &Event| -> Result<bool, Error> {
+            Err(crate::error::NotifierError::Rejected {
+                status: 503,
+                reason: "Service Unavailable".to_owned(),
+            }
+            .into())
+        };
+        assert_eq!(outbox.flush(unavailable).unwrap(), 0);
+        let kept = outbox.take().unwrap();
+        assert_eq!(kept[0].attempts, 1);
+        outbox.append(&kept).unwrap();
+        assert_eq!(outbox.flush(unavailable).unwrap(), 0);
+        // out of attempts
+        assert!(outbox.take().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_outbox_drop_rejected() {
+        let outbox = outbox("cgaid_outbox_drop.jsonl");
+        outbox.push("dingtalk", &Event::plain("Hello")).unwrap();
+        let n = outbox
+            .flush(|_, _| {
+                Err(crate::error::NotifierError::Rejected {
+                    status: 400,
+                    reason: "rejected".to_owned(),
+                }
+                .into())
+            })
+            .unwrap();
+        assert_eq!(n, 0);
+        assert!(outbox.take().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+//! A small translucent window above the game showing the last match for a while. It never takes
+//! the focus and clicks go through it to the game.
+use super::super::config::Priority;
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+use std::time::Duration;
+
+/// Screen corner the overlay sits in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "top-left" => Ok(Corner::TopLeft),
+            "" | "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(Error::Config(format!(
+                "Unknown overlay corner {name}, expect top-left, top-right, bottom-left or bottom-right"
+            ))),
+        }
+    }
+
+    /// Top left of a `width` x `height` window in this corner of the work area, given as left,
+    /// top, right and bottom.
+    pub fn place(&self, area: (i32, i32, i32, i32), width: i32, height: i32) -> (i32, i32) {
+        let (left, top, right, bottom) = area;
+        let x = match self {
+            Corner::TopLeft | Corner::BottomLeft => left + MARGIN,
+            Corner::TopRight | Corner::BottomRight => right - MARGIN - width,
+        };
+        let y = match self {
+            Corner::TopLeft | Corner::TopRight => top + MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => bottom - MARGIN - height,
+        };
+        (x, y)
+    }
+}
+
+/// Pixels between the overlay and the edges of the screen.
+const MARGIN: i32 = 16;
+
+/// What the overlay shows next, with the settings of the notifier showing it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(not(windows), allow(dead_code))]
+struct Notice {
+    text: String,
+    urgent: bool,
+    corner: Corner,
+    duration: Duration,
+    /// 0 for invisible to 255 for opaque
+    alpha: u8,
+    /// Font height in pixels
+    size: i32,
+    /// Maximum width in pixels, longer lines wrap
+    width: i32,
+}
+
+#[cfg(windows)]
+static NOTICE: std::sync::Mutex<Option<Notice>> = std::sync::Mutex::new(None);
+
+/// Shows the last match in a corner of the screen for some seconds.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct Overlay {
+    corner: Corner,
+    duration: Duration,
+    /// Opacity in percent
+    opacity: u8,
+    size: u32,
+    width: u32,
+}
+
+impl Overlay {
+    pub fn new(corner: Corner, duration: Duration, opacity: u8, size: u32, width: u32) -> Self {
+        Self {
+            corner,
+            duration,
+            opacity: opacity.clamp(10, 100),
+            size,
+            width,
+        }
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn notice(&self, event: &Event) -> Notice {
+        let text = if event.trigger.is_empty() {
+            event.with_hint()
+        } else {
+            format!("[{}] {}", event.trigger, event.with_hint())
+        };
+        Notice {
+            text,
+            urgent: event.priority == Priority::High,
+            corner: self.corner,
+            duration: self.duration,
+            alpha: (self.opacity as u32 * 255 / 100) as u8,
+            size: self.size.max(8) as i32,
+            width: self.width.max(100) as i32,
+        }
+    }
+}
+
+impl Notifiable for Overlay {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    #[cfg(windows)]
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        *NOTICE.lock().unwrap() = Some(self.notice(event));
+        win::show()?;
+        Ok(true)
+    }
+
+    #[cfg(not(windows))]
+    fn notify_event(&self, _event: &Event) -> Result<bool, Error> {
+        Err(super::super::error::NotifierError::Unsupported(
+            "Overlay is only supported on Windows".to_owned(),
+        )
+        .into())
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use super::{Error, Notice, NOTICE};
+    use std::sync::OnceLock;
+    use windows_sys::Win32::Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM};
+    use windows_sys::Win32::Graphics::Gdi::{
+        BeginPaint, CreateFontW, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect,
+        GetDC, InvalidateRect, ReleaseDC, SelectObject, SetBkMode, SetTextColor, DEFAULT_CHARSET,
+        DT_CALCRECT, DT_NOPREFIX, DT_WORDBREAK, FW_BOLD, HDC, PAINTSTRUCT, TRANSPARENT,
+    };
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, KillTimer, PostMessageW,
+        RegisterClassW, SetLayeredWindowAttributes, SetTimer, SetWindowPos, ShowWindow,
+        SystemParametersInfoW, TranslateMessage, HWND_TOPMOST, LWA_ALPHA, MSG, SPI_GETWORKAREA,
+        SWP_NOACTIVATE, SW_HIDE, SW_SHOWNOACTIVATE, WM_APP, WM_PAINT, WM_TIMER, WNDCLASSW,
+        WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT,
+        WS_POPUP,
+    };
+
+    /// A new notice to show
+    const WM_NOTICE: u32 = WM_APP + 1;
+    const TIMER_HIDE: usize = 1;
+    /// Pixels between the text and the edges of the window
+    const PADDING: i32 = 12;
+
+    /// The overlay window, created by the first notice.
+    static WINDOW: OnceLock<Result<usize, String>> = OnceLock::new();
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> COLORREF {
+        r as u32 | (g as u32) << 8 | (b as u32) << 16
+    }
+
+    fn start() -> Result<usize, String> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || unsafe {
+            let instance = GetModuleHandleW(std::ptr::null());
+            let class: Vec<u16> = "cgaid_overlay\0".encode_utf16().collect();
+            let mut wc: WNDCLASSW = std::mem::zeroed();
+            wc.lpfnWndProc = Some(proc);
+            wc.hInstance = instance;
+            wc.lpszClassName = class.as_ptr();
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                // click-through, above the game and not in the taskbar
+                WS_EX_LAYERED
+                    | WS_EX_TRANSPARENT
+                    | WS_EX_TOPMOST
+                    | WS_EX_TOOLWINDOW
+                    | WS_EX_NOACTIVATE,
+                class.as_ptr(),
+                class.as_ptr(),
+                WS_POPUP,
+                0,
+                0,
+                0,
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                instance,
+                std::ptr::null(),
+            );
+            if hwnd.is_null() {
+                let _ = tx.send(Err(std::io::Error::last_os_error().to_string()));
+                return;
+            }
+            let _ = tx.send(Ok(hwnd as usize));
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        });
+        rx.recv().map_err(|e| e.to_string())?
+    }
+
+    /// Wakes the overlay thread to show the notice.
+    pub fn show() -> Result<(), Error> {
+        let hwnd = WINDOW.get_or_init(start).clone().map_err(Error::notifier)? as HWND;
+        if unsafe { PostMessageW(hwnd, WM_NOTICE, 0, 0) } == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    unsafe fn font(notice: &Notice) -> *mut core::ffi::c_void {
+        let face: Vec<u16> = "Microsoft YaHei\0".encode_utf16().collect();
+        CreateFontW(
+            -notice.size,
+            0,
+            0,
+            0,
+            FW_BOLD as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET as u32,
+            0,
+            0,
+            0,
+            0,
+            face.as_ptr(),
+        )
+    }
+
+    /// Draws, or with `DT_CALCRECT` measures, the text of the notice.
+    unsafe fn text(dc: HDC, notice: &Notice, rect: &mut RECT, flags: u32) {
+        let font = font(notice);
+        let old = SelectObject(dc, font);
+        let text = wide(&notice.text);
+        DrawTextW(
+            dc,
+            text.as_ptr(),
+            text.len() as i32,
+            rect,
+            DT_WORDBREAK | DT_NOPREFIX | flags,
+        );
+        SelectObject(dc, old);
+        DeleteObject(font);
+    }
+
+    /// Sizes the window to the text and puts it in its corner for the duration of the notice.
+    unsafe fn place(hwnd: HWND) {
+        let Some(notice) = NOTICE.lock().unwrap().clone() else {
+            return;
+        };
+        let dc = GetDC(hwnd);
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: notice.width - 2 * PADDING,
+            bottom: 0,
+        };
+        text(dc, &notice, &mut rect, DT_CALCRECT);
+        ReleaseDC(hwnd, dc);
+        let (width, height) = (rect.right + 2 * PADDING, rect.bottom + 2 * PADDING);
+        let mut area: RECT = std::mem::zeroed();
+        SystemParametersInfoW(SPI_GETWORKAREA, 0, &mut area as *mut RECT as _, 0);
+        let (x, y) = notice.corner.place(
+            (area.left, area.top, area.right, area.bottom),
+            width,
+            height,
+        );
+        SetLayeredWindowAttributes(hwnd, 0, notice.alpha, LWA_ALPHA);
+        SetWindowPos(hwnd, HWND_TOPMOST, x, y, width, height, SWP_NOACTIVATE);
+        ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+        InvalidateRect(hwnd, std::ptr::null(), 1);
+        // a new notice restarts the timer
+        SetTimer(
+            hwnd,
+            TIMER_HIDE,
+            notice.duration.as_millis().max(1) as u32,
+            None,
+        );
+    }
+
+    unsafe fn paint(hwnd: HWND) {
+        let mut ps: PAINTSTRUCT = std::mem::zeroed();
+        let dc = BeginPaint(hwnd, &mut ps);
+        if let Some(notice) = NOTICE.lock().unwrap().as_ref() {
+            let background = CreateSolidBrush(if notice.urgent {
+                rgb(120, 0, 0)
+            } else {
+                rgb(20, 20, 20)
+            });
+            FillRect(dc, &ps.rcPaint, background);
+            DeleteObject(background);
+            SetBkMode(dc, TRANSPARENT as i32);
+            SetTextColor(dc, rgb(255, 255, 255));
+            let mut rect = RECT {
+                left: PADDING,
+                top: PADDING,
+                right: notice.width - PADDING,
+                bottom: ps.rcPaint.bottom - PADDING,
+            };
+            text(dc, notice, &mut rect, 0);
+        }
+        EndPaint(hwnd, &ps);
+    }
+
+    unsafe extern "system" fn proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_NOTICE => place(hwnd),
+            WM_PAINT => paint(hwnd),
+            WM_TIMER if wparam == TIMER_HIDE => {
+                KillTimer(hwnd, TIMER_HIDE);
+                ShowWindow(hwnd, SW_HIDE);
+            }
+            _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay() {
+        assert!(Corner::parse("middle").is_err());
+        let area = (0, 0, 1920, 1040);
+        assert_eq!(Corner::TopLeft.place(area, 300, 60), (16, 16));
+        assert_eq!(
+            Corner::parse("bottom-right").unwrap().place(area, 300, 60),
+            (1604, 964)
+        );
+
+        let overlay = Overlay::new(Corner::TopRight, Duration::from_secs(5), 0, 0, 0);
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        event.priority = Priority::High;
+        let notice = overlay.notice(&event);
+        assert_eq!(notice.text, "[boss] BOSS 出现了");
+        assert!(notice.urgent);
+        assert_eq!(notice.alpha, 25);
+        assert_eq!((notice.size, notice.width), (8, 100));
+        match overlay.notify("abc") {
+            Ok(shown) => assert!(shown),
+            Err(e) => assert_eq!(e.kind(), "unsupported"),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::Notifiable;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Hands events to a long running external program, e.g. a Python script, one JSON per line
+/// each way.
+///
+/// Each request is `{"id": 1, "event": {...}}` on the program's stdin, answered on its stdout by
+/// `{"id": 1}` when sent, `{"id": 1, "sent": false}` when skipped, or `{"id": 1, "error": "...",
+/// "retry": true}` when failed, `retry` telling whether it is worth resending later. What the
+/// program writes to stderr is logged.
+pub struct Plugin {
+    path: String,
+    args: Vec<String>,
+    workdir: String,
+    /// Time to wait for an answer, the program is restarted after
+    timeout: Duration,
+    /// Started on the first event and again once it exits
+    process: Mutex<Option<Process>>,
+    next: AtomicU64,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of stdout, read on a thread of their own so that waiting can time out
+    lines: mpsc::Receiver<String>,
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    event: &'a Event,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    sent: Option<bool>,
+    error: Option<String>,
+    #[serde(default)]
+    retry: bool,
+}
+
+impl Plugin {
+    pub fn new(path: String, args: Vec<String>, workdir: String, timeout: Duration) -> Self {
+        Self {
+            path,
+            args,
+            workdir,
+            timeout,
+            process: Mutex::new(None),
+            next: AtomicU64::new(1),
+        }
+    }
+
+    fn start(&self) -> Result<Process, Error> {
+        let mut command = Command::new(&self.path);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if !self.workdir.is_empty() {
+            command.current_dir(&self.workdir);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            // no console window popping up over the game
+            command.creation_flags(windows_sys::Win32::System::Threading::CREATE_NO_WINDOW);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|e| Error::device(format!("Failed to start plugin {}: {e}", self.path)))?;
+        log::info!("Plugin started: {} ({})", self.path, child.id());
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let name = self.path.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                log::info!("Plugin {name}: {line}");
+            }
+        });
+        Ok(Process {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    /// Writes the request and waits for its answer, skipping the late answers of earlier ones.
+    fn exchange(&self, process: &mut Process, id: u64, line: &str) -> Result<Response, Error> {
+        writeln!(process.stdin, "{line}")?;
+        process.stdin.flush()?;
+        loop {
+            let text = match process.lines.recv_timeout(self.timeout) {
+                Ok(t) => t,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(NotifierError::Timeout(self.timeout).into())
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(Error::notifier(format!("Plugin {} exited", self.path)))
+                }
+            };
+            match serde_json::from_str::<Response>(&text) {
+                Ok(r) if r.id == id => return Ok(r),
+                Ok(_) => continue,
+                Err(_) => log::warn!("Plugin {}: {text}", self.path),
+            }
+        }
+    }
+}
+
+impl Notifiable for Plugin {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let line = serde_json::to_string(&Request { id, event })?;
+        let mut process = self.process.lock().unwrap();
+        if process.is_none() {
+            *process = Some(self.start()?);
+        }
+        let response = match self.exchange(process.as_mut().unwrap(), id, &line) {
+            Ok(r) => r,
+            Err(e) => {
+                // hung or gone, restarted for the next event
+                *process = None;
+                return Err(e);
+            }
+        };
+        match response.error {
+            Some(e) if response.retry => Err(NotifierError::Unreachable(e).into()),
+            Some(e) => Err(Error::notifier(e)),
+            None => Ok(response.sent.unwrap_or(true)),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn plugin(script: &str, timeout: Duration) -> Plugin {
+        Plugin::new(
+            "sh".to_owned(),
+            vec!["-c".to_owned(), script.to_owned()],
+            String::new(),
+            timeout,
+        )
+    }
+
+    #[test]
+    fn test_plugin() {
+        // answers by the id of each request, failing the messages containing "fail"
+        let script = r#"while read -r line; do
+            id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+            case "$line" in
+                *fail*) echo "{\"id\":$id,\"error\":\"quota\",\"retry\":true}" ;;
+                *skip*) echo "not json"; echo "{\"id\":$id,\"sent\":false}" ;;
+                *) echo "{\"id\":$id}" ;;
+            esac
+        done"#;
+        let plugin = plugin(script, Duration::from_secs(5));
+        assert!(plugin.notify("abc").unwrap());
+        assert!(!plugin.notify("skip").unwrap());
+        let e = plugin.notify("fail").unwrap_err();
+        assert!(e.is_transient());
+        assert!(plugin.notify("abc").unwrap());
+    }
+
+    #[test]
+    fn test_plugin_restart() {
+        let hung = plugin("read -r line; sleep 5", Duration::from_millis(200));
+        assert_eq!(hung.notify("abc").unwrap_err().kind(), "timeout");
+        assert!(hung.process.lock().unwrap().is_none());
+        let exited = plugin("exit 0", Duration::from_secs(5));
+        assert!(exited.notify("abc").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use super::super::error::Error;
+use super::super::Notifiable;
+use std::net::UdpSocket;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the power notifier does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Sends a wake-on-LAN magic packet for the MAC address to the broadcast address
+    Wake {
+        mac: [u8; 6],
+        target: String,
+    },
+    Sleep,
+    Shutdown {
+        force: bool,
+    },
+}
+
+impl Action {
+    /// Parses the action name, `mac` and `target` are only used by `wake`.
+    pub fn parse(name: &str, mac: &str, target: &str, force: bool) -> Result<Self, Error> {
+        match name {
+            "wake" => Ok(Action::Wake {
+                mac: parse_mac(mac)?,
+                target: if target.is_empty() {
+                    "255.255.255.255:9".to_owned()
+                } else {
+                    target.to_owned()
+                },
+            }),
+            "sleep" => Ok(Action::Sleep),
+            "shutdown" => Ok(Action::Shutdown { force }),
+            _ => Err(Error::Config(format!(
+                "Unknown power action {name}, expect wake, sleep or shutdown"
+            ))),
+        }
+    }
+
+    /// The command putting this machine to sleep or shutting it down.
+    fn command(&self) -> Option<(&'static str, Vec<&'static str>)> {
+        match self {
+            Action::Wake { .. } => None,
+            Action::Sleep if cfg!(windows) => Some((
+                "rundll32.exe",
+                vec!["powrprof.dll,SetSuspendState", "0,1,0"],
+            )),
+            Action::Sleep => Some(("systemctl", vec!["suspend"])),
+            Action::Shutdown { force } if cfg!(windows) => {
+                let mut args = vec!["/s", "/t", "0"];
+                if *force {
+                    args.push("/f");
+                }
+                Some(("shutdown", args))
+            }
+            Action::Shutdown { force: true } => Some(("systemctl", vec!["poweroff", "--force"])),
+            Action::Shutdown { force: false } => Some(("systemctl", vec!["poweroff"])),
+        }
+    }
+}
+
+fn parse_mac(mac: &str) -> Result<[u8; 6], Error> {
+    let invalid = || Error::Config(format!("Invalid MAC address {mac}"));
+    let parts: Vec<_> = mac.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(invalid());
+    }
+    let mut bytes = [0; 6];
+    for (b, p) in bytes.iter_mut().zip(parts) {
+        *b = u8::from_str_radix(p, 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+/// Six 0xFF then the MAC address 16 times.
+fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+/// A sleep or shutdown waiting for its delay, it can be cancelled until then.
+struct Pending {
+    id: u64,
+    at: Instant,
+}
+
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+/// Cancels the pending sleep or shutdown, returns the seconds it had left.
+pub fn cancel() -> Option<u64> {
+    let pending = PENDING.lock().unwrap().take()?;
+    let left = pending.at.saturating_duration_since(Instant::now());
+    log::warn!("Power action cancelled, {}s left", left.as_secs());
+    Some(left.as_secs())
+}
+
+/// Wakes another machine, or puts this one to sleep or shuts it down after a delay during which
+/// it can be cancelled with `POST /power/cancel`.
+pub struct Power {
+    action: Action,
+    delay: Duration,
+    /// Only logs what would be done
+    dry_run: bool,
+}
+
+impl Power {
+    pub fn new(action: Action, delay: Duration, dry_run: bool) -> Self {
+        Self {
+            action,
+            delay,
+            dry_run,
+        }
+    }
+
+    fn wake(&self, mac: &[u8; 6], target: &str) -> Result<bool, Error> {
+        if self.dry_run {
+            log::info!("Power dry run: wake {mac:02x?} via {target}");
+            return Ok(true);
+        }
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        socket.send_to(&magic_packet(mac), target)?;
+        Ok(true)
+    }
+
+    /// Runs the command after the delay unless cancelled, a second one while pending is ignored.
+    fn schedule(&self) -> Result<bool, Error> {
+        let Some((program, args)) = self.action.command() else {
+            return Ok(false);
+        };
+        let id = {
+            let mut pending = PENDING.lock().unwrap();
+            if pending.is_some() {
+                log::info!("Power action already pending: {:?}", self.action);
+                return Ok(false);
+            }
+            let id = next_id();
+            *pending = Some(Pending {
+                id,
+                at: Instant::now() + self.delay,
+            });
+            id
+        };
+        log::warn!(
+            "{:?} in {}s, cancel with POST /power/cancel",
+            self.action,
+            self.delay.as_secs()
+        );
+        let delay = self.delay;
+        let dry_run = self.dry_run;
+        thread::spawn(move || {
+            thread::sleep(delay);
+            {
+                let mut pending = PENDING.lock().unwrap();
+                if pending.as_ref().map(|p| p.id) != Some(id) {
+                    return;
+                }
+                *pending = None;
+            }
+            if dry_run {
+                log::info!("Power dry run: {program} {}", args.join(" "));
+                return;
+            }
+            if let Err(e) = Command::new(program).args(&args).status() {
+                log::error!("Power action error: {program} {e}");
+            }
+        });
+        Ok(true)
+    }
+}
+
+/// Tells the pending actions apart, so a cancelled one does not run when another is scheduled.
+fn next_id() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Notifiable for Power {
+    fn notify(&self, _message: &str) -> Result<bool, Error> {
+        match &self.action {
+            Action::Wake { mac, target } => self.wake(mac, target),
+            _ => self.schedule(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wake() {
+        assert!(parse_mac("00-11-22-33-44").is_err());
+        assert!(parse_mac("00:11:22:33:44:gg").is_err());
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+        let action = Action::parse("wake", "00:11:22:aa:BB:cc", &target, false).unwrap();
+        let power = Power::new(action, Duration::ZERO, false);
+        assert!(power.notify("abc").unwrap());
+        let mut buf = [0; 256];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(n, 102);
+        assert_eq!(&buf[..6], &[0xff; 6]);
+        assert_eq!(&buf[96..102], &[0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]);
+    }
+
+    #[test]
+    fn test_power_cancel() {
+        assert!(Action::parse("reboot", "", "", false).is_err());
+        let action = Action::parse("shutdown", "", "", false).unwrap();
+        assert!(action.command().is_some());
+        let power = Power::new(action, Duration::from_secs(60), true);
+        assert!(power.notify("abc").unwrap());
+        // only one pending at a time
+        assert!(!power.notify("abc").unwrap());
+        assert!(cancel().unwrap() > 50);
+        assert!(cancel().is_none());
+    }
+}
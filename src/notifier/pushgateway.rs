@@ -0,0 +1,125 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::Notifiable;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Counts matches by trigger and channel, and pushes the counters to a Prometheus Pushgateway,
+/// https://github.com/prometheus/pushgateway
+pub struct Pushgateway {
+    /// Base address such as `http://localhost:9091`
+    url: String,
+    job: String,
+    /// Grouping label to tell several clients apart, empty for none
+    instance: String,
+}
+
+/// Matches since start, keyed by trigger and channel.
+fn counters() -> &'static Mutex<BTreeMap<(String, String), u64>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<(String, String), u64>>> = OnceLock::new();
+    COUNTERS.get_or_init(Default::default)
+}
+
+impl Pushgateway {
+    const DEFAULT_JOB: &'static str = "cgaid";
+
+    pub fn new(url: String, job: String, instance: String) -> Self {
+        let job = if job.is_empty() {
+            Pushgateway::DEFAULT_JOB.to_owned()
+        } else {
+            job
+        };
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            job,
+            instance,
+        }
+    }
+
+    fn push_url(&self) -> String {
+        let encode = |s: &str| {
+            percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
+        };
+        let mut url = format!("{}/metrics/job/{}", self.url, encode(&self.job));
+        if !self.instance.is_empty() {
+            url.push_str(&format!("/instance/{}", encode(&self.instance)));
+        }
+        url
+    }
+
+    /// Counts the event, returns all counters in the text exposition format.
+    fn count(event: &Event) -> String {
+        let label = |v: &str| {
+            v.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        };
+        let mut counters = counters().lock().unwrap();
+        *counters
+            .entry((event.trigger.clone(), event.channel.name().to_owned()))
+            .or_default() += 1;
+        let mut text = String::from("# TYPE cgaid_matches_total counter\n");
+        for ((trigger, channel), n) in counters.iter() {
+            text.push_str(&format!(
+                "cgaid_matches_total{{trigger=\"{}\",channel=\"{}\"}} {n}\n",
+                label(trigger),
+                label(channel)
+            ));
+        }
+        text
+    }
+
+    async fn send(&self, body: String) -> Result<bool, Error> {
+        // POST replaces only the metric pushed, other metrics of the group are kept
+        let response = super::client()
+            .post(self.push_url())
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Pushgateway {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let body = Pushgateway::count(event);
+        super::block_on(self.send(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{body, response, serve_once};
+
+    #[test]
+    fn test_pushgateway() {
+        let (url, handle) = serve_once(response("200 OK", ""));
+        let mut event = Event::plain("挑战赛通道开启");
+        event.trigger = "pushgateway\"test".to_owned();
+        Pushgateway::count(&event);
+        let gateway = Pushgateway::new(url, String::new(), "pc 1".to_owned());
+        assert!(gateway.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /metrics/job/cgaid/instance/pc%201 "));
+        let body = body(&request);
+        assert!(body.starts_with("# TYPE cgaid_matches_total counter\n"));
+        assert!(body.contains(&format!(
+            "cgaid_matches_total{{trigger=\"pushgateway\\\"test\",channel=\"{}\"}} 2\n",
+            event.channel.name()
+        )));
+    }
+}
@@ -0,0 +1,228 @@
+use super::super::config::Priority;
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::{Deserialize, Serialize};
+
+/// Sends through Pushover, https://pushover.net/api
+pub struct Pushover {
+    api: String,
+    token: String,
+    user: String,
+    title: String,
+    template: String,
+    /// Devices of the user to send to, all when empty
+    device: String,
+    /// Sends high priority events as emergencies, repeated until acknowledged
+    emergency: bool,
+    /// Seconds between the repeats of an emergency, at least 30
+    retry: u64,
+    /// Seconds an emergency is repeated for at most, up to 10800
+    expire: u64,
+    /// Title of the game window to attach a screenshot of, empty for none
+    screenshot: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Body<'a> {
+    token: &'a str,
+    user: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    title: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    device: &'a str,
+    priority: i8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expire: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    status: i32,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+impl Pushover {
+    pub const DEFAULT_API: &'static str = "https://api.pushover.net/1/messages.json";
+    const EMERGENCY: i8 = 2;
+    const SCREENSHOT_NAME: &'static str = "screenshot.png";
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        api: String,
+        token: String,
+        user: String,
+        title: String,
+        template: String,
+        device: String,
+        emergency: bool,
+        retry: u64,
+        expire: u64,
+    ) -> Self {
+        let api = if api.is_empty() {
+            Pushover::DEFAULT_API.to_owned()
+        } else {
+            api
+        };
+        Self {
+            api,
+            token,
+            user,
+            title,
+            template,
+            device,
+            emergency,
+            retry: retry.max(30),
+            expire: expire.clamp(1, 10800),
+            screenshot: String::new(),
+        }
+    }
+
+    /// Attaches the screenshot of the game window whose title contains `window`, taken when the
+    /// event matched.
+    pub fn with_screenshot(mut self, window: String) -> Self {
+        self.screenshot = window;
+        self
+    }
+
+    /// Pushover priority from -2 (silent) to 2 (emergency).
+    fn priority(&self, priority: Priority) -> i8 {
+        match priority {
+            Priority::Low => -1,
+            Priority::Normal => 0,
+            Priority::High if self.emergency => Pushover::EMERGENCY,
+            Priority::High => 1,
+        }
+    }
+
+    fn body<'a>(&'a self, event: &Event) -> Body<'a> {
+        let priority = self.priority(event.priority);
+        let emergency = priority == Pushover::EMERGENCY;
+        Body {
+            token: &self.token,
+            user: &self.user,
+            message: template::render(&self.template, &template::context(event)),
+            title: &self.title,
+            device: &self.device,
+            priority,
+            retry: emergency.then_some(self.retry),
+            expire: emergency.then_some(self.expire),
+        }
+    }
+
+    async fn send(&self, event: &Event, screenshot: Option<Vec<u8>>) -> Result<bool, Error> {
+        let request = super::client().post(&self.api);
+        let request = match screenshot {
+            Some(png) => {
+                // attachments are only taken in forms, with the fields of the body as text
+                let body = serde_json::to_value(self.body(event))?;
+                let mut form = reqwest::multipart::Form::new();
+                for (name, value) in body.as_object().into_iter().flatten() {
+                    let text = value.as_str().map_or(value.to_string(), str::to_owned);
+                    form = form.text(name.clone(), text);
+                }
+                let file = reqwest::multipart::Part::bytes(png)
+                    .file_name(Pushover::SCREENSHOT_NAME)
+                    .mime_str("image/png")?;
+                request.multipart(form.part("attachment", file))
+            }
+            None => request.json(&self.body(event)),
+        };
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let reply: Reply = response.json().await?;
+        if reply.status != 1 {
+            return Err(NotifierError::Rejected {
+                status,
+                reason: reply.errors.join(", "),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Pushover {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let screenshot = event
+            .screenshots
+            .get(&self.screenshot)
+            .map(|png| png.0.to_vec());
+        super::block_on(self.send(event, screenshot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{response, serve_once};
+
+    fn pushover(emergency: bool) -> Pushover {
+        Pushover::new(
+            String::new(),
+            "app".to_owned(),
+            "user".to_owned(),
+            "cgaid".to_owned(),
+            "Notice: {message}".to_owned(),
+            String::new(),
+            emergency,
+            10,
+            86400,
+        )
+    }
+
+    #[test]
+    fn test_pushover() {
+        let mut event = Event::plain("BOSS 出现了");
+        let body = serde_json::to_value(pushover(true).body(&event)).unwrap();
+        assert_eq!(body["message"], "Notice: BOSS 出现了");
+        assert_eq!(body["priority"], 0);
+        assert!(body.get("retry").is_none());
+        assert!(body.get("device").is_none());
+
+        event.priority = Priority::High;
+        let body = serde_json::to_value(pushover(true).body(&event)).unwrap();
+        assert_eq!(body["priority"], 2);
+        assert_eq!(body["retry"], 30);
+        assert_eq!(body["expire"], 10800);
+        let body = serde_json::to_value(pushover(false).body(&event)).unwrap();
+        assert_eq!(body["priority"], 1);
+        assert!(body.get("expire").is_none());
+    }
+
+    #[test]
+    fn test_pushover_screenshot() {
+        let (url, handle) = serve_once(response("200 OK", r#"{"status":1}"#));
+        let mut pushover = pushover(false).with_screenshot("魔力宝贝".to_owned());
+        pushover.api = format!("{url}/1/messages.json");
+        let mut event = Event::plain("BOSS 出现了");
+        let png = super::super::screenshot::Png(std::sync::Arc::new(b"PNG fake".to_vec()));
+        event.screenshots.insert("魔力宝贝".to_owned(), png);
+        assert!(pushover.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.contains("multipart/form-data; boundary="));
+        assert!(request.contains("name=\"message\"\r\n\r\nNotice: BOSS 出现了"));
+        assert!(request.contains("name=\"priority\"\r\n\r\n0"));
+        assert!(request.contains("name=\"attachment\"; filename=\"screenshot.png\""));
+    }
+
+    #[test]
+    fn test_pushover_rejected() {
+        let reply = r#"{"user":"invalid","errors":["user identifier is invalid"],"status":0}"#;
+        let (url, _) = serve_once(response("400 Bad Request", reply));
+        let mut pushover = pushover(false);
+        pushover.api = format!("{url}/1/messages.json");
+        let e = pushover.notify("abc").unwrap_err();
+        assert_eq!(e.kind(), "rejected");
+        assert!(e.to_string().contains("user identifier is invalid"));
+    }
+}
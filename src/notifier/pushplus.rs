@@ -0,0 +1,158 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::{Deserialize, Serialize};
+
+/// Pushes to WeChat through PushPlus, https://www.pushplus.plus/doc/
+pub struct PushPlus {
+    api: String,
+    token: String,
+    /// Group code for pushes to every subscriber of a group, empty to push to the token owner
+    topic: String,
+    title: String,
+    template: String,
+    /// Content type, `txt`, `html` or `markdown`
+    format: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Body<'a> {
+    token: &'a str,
+    title: &'a str,
+    content: String,
+    template: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    topic: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    code: i32,
+    #[serde(default)]
+    msg: String,
+}
+
+impl PushPlus {
+    pub const DEFAULT_API: &'static str = "https://www.pushplus.plus/send";
+    const SUCCESS: i32 = 200;
+
+    pub fn new(
+        api: String,
+        token: String,
+        topic: String,
+        title: String,
+        template: String,
+        format: String,
+    ) -> Self {
+        let api = if api.is_empty() {
+            PushPlus::DEFAULT_API.to_owned()
+        } else {
+            api
+        };
+        let format = if format.is_empty() {
+            "txt".to_owned()
+        } else {
+            format
+        };
+        Self {
+            api,
+            token,
+            topic,
+            title,
+            template,
+            format,
+        }
+    }
+
+    fn body<'a>(&'a self, event: &Event) -> Body<'a> {
+        Body {
+            token: &self.token,
+            title: &self.title,
+            content: template::render(&self.template, &template::context(event)),
+            template: &self.format,
+            topic: &self.topic,
+        }
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        let response = super::client()
+            .post(&self.api)
+            .json(&self.body(event))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        // errors such as a wrong token come with 200 and a code of their own
+        let reply: Reply = response.json().await?;
+        if reply.code != PushPlus::SUCCESS {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: format!("{} {}", reply.code, reply.msg),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for PushPlus {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        super::block_on(self.send(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{body, response, serve_once};
+
+    fn serve(reply: &str) -> (String, std::thread::JoinHandle<String>) {
+        let (url, handle) = serve_once(response("200 OK", reply));
+        (format!("{url}/send"), handle)
+    }
+
+    #[test]
+    fn test_pushplus() {
+        let (api, handle) = serve(r#"{"code":200,"msg":"请求成功","data":"abc"}"#);
+        let pushplus = PushPlus::new(
+            api,
+            "tk".to_owned(),
+            "guild".to_owned(),
+            "cgaid".to_owned(),
+            "Notice: {message}".to_owned(),
+            String::new(),
+        );
+        assert!(pushplus.notify("画眉鸟 掉线了").unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /send "));
+        let body: serde_json::Value = serde_json::from_str(body(&request)).unwrap();
+        assert_eq!(body["token"], "tk");
+        assert_eq!(body["topic"], "guild");
+        assert_eq!(body["content"], "Notice: 画眉鸟 掉线了");
+        assert_eq!(body["template"], "txt");
+
+        let (api, _) = serve(r#"{"code":903,"msg":"无效的用户token","data":null}"#);
+        let pushplus = PushPlus::new(
+            api,
+            "bad".to_owned(),
+            String::new(),
+            "cgaid".to_owned(),
+            "{message}".to_owned(),
+            "markdown".to_owned(),
+        );
+        let e = pushplus.notify("abc").unwrap_err();
+        assert_eq!(e.kind(), "rejected");
+        assert!(e.to_string().contains("无效的用户token"));
+    }
+}
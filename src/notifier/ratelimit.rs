@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Sliding window limiter allowing at most `limit` sends per `period`.
+pub struct Window {
+    limit: usize,
+    period: Duration,
+    sent: VecDeque<Instant>,
+    blocked: Option<Instant>,
+}
+
+impl Window {
+    pub fn new(limit: usize, period: Duration) -> Self {
+        Self {
+            limit,
+            period,
+            sent: VecDeque::new(),
+            blocked: None,
+        }
+    }
+
+    /// Takes a slot, or returns how long to wait for the next one.
+    pub fn acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.blocked {
+            if now < until {
+                return Err(until - now);
+            }
+            self.blocked = None;
+        }
+        while self
+            .sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.period)
+        {
+            self.sent.pop_front();
+        }
+        if self.limit == 0 || self.sent.len() < self.limit {
+            self.sent.push_back(now);
+            Ok(())
+        } else {
+            Err(self.sent[0] + self.period - now)
+        }
+    }
+
+    /// Refuses all sends for `wait`, e.g. after the server asked to slow down.
+    pub fn block(&mut self, now: Instant, wait: Duration) {
+        self.blocked = Some(now + wait);
+    }
+}
+
+/// Token bucket refilled with `rate` tokens per minute up to `burst`, one token per send.
+pub struct Bucket {
+    /// Tokens per second
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    /// A full bucket, a `burst` of 0 holds one minute's worth.
+    pub fn new(rate: u32, burst: u32, now: Instant) -> Self {
+        let burst = if burst == 0 { rate } else { burst }.max(1) as f64;
+        Self {
+            rate: rate as f64 / 60.0,
+            burst,
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    /// Whole tokens in the bucket, none taken.
+    pub fn available(&mut self, now: Instant) -> usize {
+        self.refill(now);
+        self.tokens as usize
+    }
+
+    /// Takes a token, or returns how long to wait for the next one.
+    pub fn acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut b = Bucket::new(20, 2, now);
+        assert!(b.acquire(now).is_ok());
+        assert!(b.acquire(now).is_ok());
+        // one token every 3 seconds
+        let wait = b.acquire(now).unwrap_err();
+        assert_eq!(wait.as_secs_f64().round(), 3.0);
+        assert!(b.acquire(now + Duration::from_secs(3)).is_ok());
+        assert!(b.acquire(now + Duration::from_secs(4)).is_err());
+        assert_eq!(b.available(now + Duration::from_secs(7)), 1);
+        // refills up to the burst only
+        let later = now + Duration::from_secs(600);
+        assert!(b.acquire(later).is_ok());
+        assert!(b.acquire(later).is_ok());
+        assert!(b.acquire(later).is_err());
+        assert_eq!(Bucket::new(20, 0, now).burst, 20.0);
+    }
+
+    #[test]
+    fn test_window() {
+        let now = Instant::now();
+        let mut w = Window::new(2, Duration::from_secs(60));
+        assert!(w.acquire(now).is_ok());
+        assert!(w.acquire(now + Duration::from_secs(10)).is_ok());
+        assert_eq!(
+            w.acquire(now + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(w.acquire(now + Duration::from_secs(60)).is_ok());
+        w.block(now + Duration::from_secs(60), Duration::from_secs(30));
+        assert_eq!(
+            w.acquire(now + Duration::from_secs(80)),
+            Err(Duration::from_secs(10))
+        );
+        assert!(w.acquire(now + Duration::from_secs(90)).is_ok());
+    }
+
+    #[test]
+    fn test_window_unlimited() {
+        let now = Instant::now();
+        let mut w = Window::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(w.acquire(now).is_ok());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Runs commands so that nothing they start outlives them, optionally capped in memory and CPU.
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Limits of a sandboxed command and everything it starts, `0` for unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Memory of each process, in MB
+    pub memory: u64,
+    /// Share of the whole CPU in percent. Only capped on Windows, elsewhere the processes get
+    /// the lowest scheduling priority instead.
+    pub cpu: u8,
+    /// Seconds the command may run before it is killed with everything it started
+    pub timeout: u64,
+}
+
+/// Waits at most `timeout` seconds for the command, `None` when it is still running.
+fn wait(child: &mut Child, timeout: u64) -> io::Result<Option<ExitStatus>> {
+    if timeout == 0 {
+        return child.wait().map(Some);
+    }
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Waits for the command itself, then kills whatever it left running, the command too once it
+/// runs out of time. Its output is read aside, since the processes left over may hold on to
+/// the pipes.
+fn finish(mut child: Child, timeout: u64, kill: impl FnOnce()) -> io::Result<Output> {
+    fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    }
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = wait(&mut child, timeout);
+    kill();
+    let Some(status) = status? else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Command killed after {timeout}s"),
+        ));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn spawn(command: &mut Command) -> io::Result<Child> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+}
+
+/// A handle closed when dropped. Closing the last handle of a job kills the processes still in
+/// it.
+#[cfg(windows)]
+struct Handle(windows_sys::Win32::Foundation::HANDLE);
+
+#[cfg(windows)]
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+/// Resumes the threads of a process started suspended.
+#[cfg(windows)]
+fn resume(pid: u32) -> io::Result<()> {
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    let snapshot = Handle(snapshot);
+    let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    let mut more = unsafe { Thread32First(snapshot.0, &mut entry) } != 0;
+    while more {
+        if entry.th32OwnerProcessID == pid {
+            let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID) };
+            if thread.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let thread = Handle(thread);
+            if unsafe { ResumeThread(thread.0) } == u32::MAX {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        more = unsafe { Thread32Next(snapshot.0, &mut entry) } != 0;
+    }
+    Ok(())
+}
+
+/// Runs the command to completion, then kills whatever it left running.
+#[cfg(windows)]
+pub fn run(mut command: Command, limits: Limits) -> io::Result<Output> {
+    use std::os::windows::io::AsRawHandle;
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectCpuRateControlInformation,
+        JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_CPU_RATE_CONTROL_INFORMATION, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_CPU_RATE_CONTROL_ENABLE, JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+    };
+    use windows_sys::Win32::System::Threading::CREATE_SUSPENDED;
+
+    let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+    if job.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let job = Handle(job);
+    unsafe {
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        if limits.memory > 0 {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+            info.ProcessMemoryLimit = (limits.memory * 1024 * 1024) as usize;
+        }
+        if SetInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            &info as *const _ as *const _,
+            std::mem::size_of_val(&info) as u32,
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        if limits.cpu > 0 {
+            let mut rate: JOBOBJECT_CPU_RATE_CONTROL_INFORMATION = std::mem::zeroed();
+            rate.ControlFlags =
+                JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP;
+            // in hundredths of a percent
+            rate.Anonymous.CpuRate = limits.cpu.min(100) as u32 * 100;
+            if SetInformationJobObject(
+                job.0,
+                JobObjectCpuRateControlInformation,
+                &rate as *const _ as *const _,
+                std::mem::size_of_val(&rate) as u32,
+            ) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    // suspended until it is in the job, so nothing it starts can get away
+    command.creation_flags(CREATE_SUSPENDED);
+    let mut child = spawn(&mut command)?;
+    let started =
+        if unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            resume(child.id())
+        };
+    if let Err(e) = started {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+    finish(child, limits.timeout, move || drop(job))
+}
+
+/// Runs the command to completion, then kills whatever it left running.
+#[cfg(unix)]
+pub fn run(mut command: Command, limits: Limits) -> io::Result<Output> {
+    use std::os::unix::process::CommandExt;
+
+    // a group of its own, so the children can be found and killed together
+    command.process_group(0);
+    if limits.memory > 0 || limits.cpu > 0 {
+        unsafe {
+            command.pre_exec(move || {
+                if limits.memory > 0 {
+                    let bytes = (limits.memory * 1024 * 1024) as libc::rlim_t;
+                    let limit = libc::rlimit {
+                        rlim_cur: bytes,
+                        rlim_max: bytes,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if limits.cpu > 0 && libc::setpriority(libc::PRIO_PROCESS, 0, 19) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    let child = spawn(&mut command)?;
+    let group = child.id() as libc::pid_t;
+    finish(child, limits.timeout, || unsafe {
+        libc::killpg(group, libc::SIGKILL);
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sandbox() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & echo $!"]);
+        let output = run(command, Limits::default()).unwrap();
+        assert!(output.status.success());
+        let pid: libc::pid_t = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .unwrap();
+        let alive = || {
+            std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|s| !s.contains(") Z "))
+        };
+        for _ in 0..50 {
+            if !alive() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!alive());
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo ok"]);
+        let limits = Limits {
+            memory: 256,
+            cpu: 50,
+            timeout: 10,
+        };
+        let output = run(command, limits).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "ok");
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30"]);
+        let limits = Limits {
+            timeout: 1,
+            ..Limits::default()
+        };
+        let start = std::time::Instant::now();
+        let e = run(command, limits).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,175 @@
+use super::super::error::Error;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A PNG taken when an event matched, shared by the notifiers sending it.
+#[derive(Clone, PartialEq)]
+pub struct Png(pub Arc<Vec<u8>>);
+
+impl fmt::Debug for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Png({} bytes)", self.0.len())
+    }
+}
+
+/// A PNG of a window, kept for the notifiers sending the same match.
+struct Shot {
+    at: Instant,
+    window: String,
+    png: Vec<u8>,
+}
+
+static LAST: Mutex<Option<Shot>> = Mutex::new(None);
+
+/// Notifiers of one match within this time share one screenshot.
+const REUSE: Duration = Duration::from_secs(3);
+
+/// PNG of the first visible window whose title contains `window`, `None` when there is no such
+/// window or it is minimized.
+pub fn capture(window: &str) -> Result<Option<Vec<u8>>, Error> {
+    let mut last = LAST.lock().unwrap();
+    if let Some(s) = last
+        .as_ref()
+        .filter(|s| s.window == window && s.at.elapsed() < REUSE)
+    {
+        return Ok(Some(s.png.clone()));
+    }
+    let Some((width, height, bgra)) = grab(window)? else {
+        return Ok(None);
+    };
+    let png = encode(width, height, &bgra)?;
+    *last = Some(Shot {
+        at: Instant::now(),
+        window: window.to_owned(),
+        png: png.clone(),
+    });
+    Ok(Some(png))
+}
+
+/// Screenshots of the windows by title, leaving out the ones failing or not shown.
+pub fn capture_all<'a>(windows: impl IntoIterator<Item = &'a str>) -> HashMap<String, Png> {
+    let mut shots = HashMap::new();
+    for window in windows {
+        if shots.contains_key(window) {
+            continue;
+        }
+        match capture(window) {
+            Ok(Some(png)) => {
+                shots.insert(window.to_owned(), Png(Arc::new(png)));
+            }
+            Ok(None) => log::debug!("No window to capture: {window}"),
+            Err(e) => log::warn!("Screenshot error: {e}"),
+        }
+    }
+    shots
+}
+
+/// Width, height and top-down BGRA pixels of the client area of the window.
+#[cfg(windows)]
+fn grab(window: &str) -> Result<Option<(u32, u32, Vec<u8>)>, Error> {
+    use windows_sys::Win32::Foundation::{HWND, RECT};
+    use windows_sys::Win32::Graphics::Gdi::{
+        CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits,
+        ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+    };
+    use windows_sys::Win32::Storage::Xps::{PrintWindow, PW_CLIENTONLY};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetClientRect, IsIconic};
+    /// Also captures windows drawn with DirectX, Windows 8.1 and later
+    const PW_RENDERFULLCONTENT: u32 = 2;
+
+    let Some((hwnd, _)) = super::super::system::window_titles(window)
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+    let hwnd = hwnd as HWND;
+    unsafe {
+        let mut rect: RECT = std::mem::zeroed();
+        if IsIconic(hwnd) != 0 || GetClientRect(hwnd, &mut rect) == 0 {
+            return Ok(None);
+        }
+        let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+        if width <= 0 || height <= 0 {
+            return Ok(None);
+        }
+        let dc = GetDC(hwnd);
+        let mem = CreateCompatibleDC(dc);
+        let bitmap = CreateCompatibleBitmap(dc, width, height);
+        let old = SelectObject(mem, bitmap);
+        let printed = PrintWindow(hwnd, mem, PW_CLIENTONLY | PW_RENDERFULLCONTENT);
+        let mut info: BITMAPINFO = std::mem::zeroed();
+        info.bmiHeader.biSize = std::mem::size_of::<BITMAPINFOHEADER>() as u32;
+        info.bmiHeader.biWidth = width;
+        // negative for top-down rows
+        info.bmiHeader.biHeight = -height;
+        info.bmiHeader.biPlanes = 1;
+        info.bmiHeader.biBitCount = 32;
+        info.bmiHeader.biCompression = BI_RGB;
+        let mut bgra = vec![0u8; width as usize * height as usize * 4];
+        let lines = GetDIBits(
+            mem,
+            bitmap,
+            0,
+            height as u32,
+            bgra.as_mut_ptr() as _,
+            &mut info,
+            DIB_RGB_COLORS,
+        );
+        SelectObject(mem, old);
+        DeleteObject(bitmap);
+        DeleteDC(mem);
+        ReleaseDC(hwnd, dc);
+        if printed == 0 || lines == 0 {
+            return Err(Error::device(format!("Failed to capture window {window}")));
+        }
+        Ok(Some((width as u32, height as u32, bgra)))
+    }
+}
+
+#[cfg(not(windows))]
+fn grab(_window: &str) -> Result<Option<(u32, u32, Vec<u8>)>, Error> {
+    Err(super::super::error::NotifierError::Unsupported(
+        "Screenshots are only supported on Windows".to_owned(),
+    )
+    .into())
+}
+
+fn encode(width: u32, height: u32, bgra: &[u8]) -> Result<Vec<u8>, Error> {
+    let rgb: Vec<u8> = bgra
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0]])
+        .collect();
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(Error::notifier)?;
+    writer.write_image_data(&rgb).map_err(Error::notifier)?;
+    writer.finish().map_err(Error::notifier)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        // blue, green, red, white in BGRA
+        let bgra = [255, 0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0, 255, 255, 255, 0];
+        let png = encode(2, 2, &bgra).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(
+            &buf[..12],
+            &[0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255]
+        );
+        assert!(encode(2, 2, &bgra[..8]).is_err());
+    }
+}
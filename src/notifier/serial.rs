@@ -0,0 +1,132 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serialport::SerialPort;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Writes to a serial port, e.g. for an Arduino driving a buzzer or a light tower.
+pub struct Serial {
+    /// Port name such as `COM3` or `/dev/ttyUSB0`
+    port: String,
+    baud: u32,
+    /// Line to write, `{trigger}`, `{priority}`, `{channel}` and `{message}` are filled
+    text: String,
+    /// Raw bytes written instead of the text when not empty
+    bytes: Vec<u8>,
+}
+
+/// Ports kept open, as opening a port resets many boards.
+fn ports() -> &'static Mutex<HashMap<String, Box<dyn SerialPort>>> {
+    static PORTS: OnceLock<Mutex<HashMap<String, Box<dyn SerialPort>>>> = OnceLock::new();
+    PORTS.get_or_init(Default::default)
+}
+
+impl Serial {
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// `hex` is the bytes as hex digits, spaces allowed, e.g. `A5 01 FF`.
+    pub fn new(port: String, baud: u32, text: String, hex: &str) -> Result<Self, Error> {
+        Ok(Self {
+            port,
+            baud,
+            text,
+            bytes: Serial::parse_hex(hex)?,
+        })
+    }
+
+    fn parse_hex(hex: &str) -> Result<Vec<u8>, Error> {
+        let digits: String = hex.split_whitespace().collect();
+        let invalid = || Error::Config(format!("Invalid serial bytes {hex}"));
+        if !digits.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| {
+                digits
+                    .get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect()
+    }
+
+    fn payload(&self, event: &Event) -> Vec<u8> {
+        if !self.bytes.is_empty() {
+            return self.bytes.clone();
+        }
+        template::render(&self.text, &template::context(event)).into_bytes()
+    }
+
+    fn write(&self, payload: &[u8]) -> Result<(), Error> {
+        let mut ports = ports().lock().unwrap();
+        if !ports.contains_key(&self.port) {
+            let port = serialport::new(&self.port, self.baud)
+                .timeout(Serial::TIMEOUT)
+                // DTR resets boards such as the Arduino Uno
+                .dtr_on_open(false)
+                .open()?;
+            ports.insert(self.port.clone(), port);
+        }
+        let port = ports.get_mut(&self.port).unwrap();
+        let written = port.write_all(payload).and_then(|_| port.flush());
+        if written.is_err() {
+            // unplugged, reopened on the next notification
+            ports.remove(&self.port);
+        }
+        Ok(written?)
+    }
+}
+
+impl Notifiable for Serial {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.write(&self.payload(event))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_payload() {
+        assert_eq!(
+            Serial::parse_hex("A5 01ff").unwrap(),
+            vec![0xa5, 0x01, 0xff]
+        );
+        assert!(Serial::parse_hex("").unwrap().is_empty());
+        assert!(Serial::parse_hex("A5 1").is_err());
+        assert!(Serial::parse_hex("zz").is_err());
+
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        let serial = Serial::new(
+            "COM9".to_owned(),
+            9600,
+            "{priority} {trigger}\n".to_owned(),
+            "",
+        )
+        .unwrap();
+        assert_eq!(serial.payload(&event), b"normal boss\n");
+        let serial = Serial::new(String::new(), 9600, String::new(), "01 02").unwrap();
+        assert_eq!(serial.payload(&event), vec![1, 2]);
+
+        let serial = Serial::new(
+            "/dev/no-such-port-cgaid".to_owned(),
+            9600,
+            "x".to_owned(),
+            "",
+        )
+        .unwrap();
+        assert!(serial.notify("abc").is_err());
+    }
+}
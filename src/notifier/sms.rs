@@ -0,0 +1,151 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use serde::Deserialize;
+
+/// Sends text messages through Twilio, https://www.twilio.com/docs/messaging/api/message-resource
+pub struct Sms {
+    api: String,
+    /// Account SID, also the user of the basic auth
+    sid: String,
+    token: String,
+    /// Twilio number or messaging service SID sending the message
+    from: String,
+    /// Numbers in E.164 format, e.g. `+8613800000000`
+    to: Vec<String>,
+    template: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct Reply {
+    #[serde(default)]
+    code: i64,
+    #[serde(default)]
+    message: String,
+}
+
+impl Sms {
+    pub const DEFAULT_API: &'static str = "https://api.twilio.com";
+
+    pub fn new(
+        api: String,
+        sid: String,
+        token: String,
+        from: String,
+        to: Vec<String>,
+        template: String,
+    ) -> Self {
+        let api = if api.is_empty() {
+            Sms::DEFAULT_API.to_owned()
+        } else {
+            api.trim_end_matches('/').to_owned()
+        };
+        Self {
+            api,
+            sid,
+            token,
+            from,
+            to,
+            template,
+        }
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            self.api, self.sid
+        )
+    }
+
+    fn form<'a>(&'a self, to: &'a str, body: &'a str) -> [(&'static str, &'a str); 3] {
+        // a messaging service picks the sender itself
+        let from = if self.from.starts_with("MG") {
+            "MessagingServiceSid"
+        } else {
+            "From"
+        };
+        [("To", to), (from, &self.from), ("Body", body)]
+    }
+
+    async fn send(&self, message: &str) -> Result<bool, Error> {
+        let client = super::client();
+        let body = template::render(&self.template, &template::message(message));
+        let mut first = None;
+        // one message per number, a failing number does not stop the others
+        for to in &self.to {
+            let response = client
+                .post(self.url())
+                .basic_auth(&self.sid, Some(&self.token))
+                .form(&self.form(to, &body))
+                .send()
+                .await?;
+            let status = response.status();
+            if status.is_success() {
+                continue;
+            }
+            let reply: Reply = response.json().await.unwrap_or_default();
+            log::error!("Twilio rejected {to}: {} {}", reply.code, reply.message);
+            first.get_or_insert(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: format!("{} {}", reply.code, reply.message),
+            });
+        }
+        match first {
+            Some(e) => Err(e.into()),
+            None => Ok(true),
+        }
+    }
+}
+
+impl Notifiable for Sms {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        super::block_on(self.send(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.notify(&event.with_hint())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{self, response};
+
+    /// Answers each request with the next status, returns the raw requests.
+    fn serve(statuses: Vec<&str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let reply = r#"{"code":21211,"message":"Invalid 'To' Phone Number","status":400}"#;
+        testutil::serve(statuses.into_iter().map(|s| response(s, reply)).collect())
+    }
+
+    #[test]
+    fn test_sms() {
+        let (api, handle) = serve(vec!["201 Created", "400 Bad Request"]);
+        let sms = Sms::new(
+            api,
+            "AC123".to_owned(),
+            "secret".to_owned(),
+            "+15005550006".to_owned(),
+            vec!["+8613800000000".to_owned(), "+86138".to_owned()],
+            "cgaid: {message}".to_owned(),
+        );
+        let e = sms.notify("BOSS 出现了").unwrap_err();
+        assert_eq!(e.kind(), "rejected");
+        assert!(e.to_string().contains("21211"));
+        let requests = handle.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("POST /2010-04-01/Accounts/AC123/Messages.json "));
+        // AC123:secret
+        assert!(requests[0].contains("authorization: Basic QUMxMjM6c2VjcmV0"));
+        let body = testutil::body(&requests[0]);
+        let form: Vec<(String, String)> = reqwest::Url::parse(&format!("http://localhost/?{body}"))
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert!(form.contains(&("To".to_owned(), "+8613800000000".to_owned())));
+        assert!(form.contains(&("From".to_owned(), "+15005550006".to_owned())));
+        assert!(form.contains(&("Body".to_owned(), "cgaid: BOSS 出现了".to_owned())));
+    }
+}
@@ -0,0 +1,114 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// Lines written to every connected client, e.g. `nc 127.0.0.1 7879` in a second terminal.
+pub struct Hub {
+    clients: Mutex<Vec<Arc<TcpStream>>>,
+}
+
+impl Hub {
+    /// The hub listening on `listen`, bound on first use and shared afterwards.
+    pub fn get(listen: &str) -> io::Result<Arc<Hub>> {
+        static HUBS: OnceLock<Mutex<HashMap<String, Arc<Hub>>>> = OnceLock::new();
+        let mut hubs = HUBS.get_or_init(Default::default).lock().unwrap();
+        if let Some(h) = hubs.get(listen) {
+            return Ok(Arc::clone(h));
+        }
+        let listener = TcpListener::bind(listen)?;
+        log::info!("Socket listening on {}", listener.local_addr()?);
+        let hub = Arc::new(Hub {
+            clients: Mutex::new(Vec::new()),
+        });
+        let hc = Arc::clone(&hub);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(s) => {
+                        log::debug!("Socket client: {:?}", s.peer_addr());
+                        hc.clients.lock().unwrap().push(Arc::new(s));
+                    }
+                    Err(e) => log::error!("Socket accept error: {e}"),
+                }
+            }
+        });
+        hubs.insert(listen.to_owned(), Arc::clone(&hub));
+        Ok(hub)
+    }
+
+    /// Writes a line to all clients, dropping the disconnected ones. Returns the number reached.
+    pub fn broadcast(&self, line: &str) -> usize {
+        // written without the lock, a slow client does not hold up accepting others
+        let clients = self.clients.lock().unwrap().clone();
+        let line = format!("{line}\r\n");
+        let failed: Vec<_> = clients
+            .iter()
+            .filter(|c| c.as_ref().write_all(line.as_bytes()).is_err())
+            .collect();
+        if !failed.is_empty() {
+            let mut clients = self.clients.lock().unwrap();
+            clients.retain(|c| !failed.iter().any(|f| Arc::ptr_eq(c, f)));
+        }
+        clients.len() - failed.len()
+    }
+}
+
+pub struct Socket {
+    hub: Arc<Hub>,
+    format: String,
+}
+
+impl Socket {
+    pub fn new(listen: &str, format: String) -> io::Result<Self> {
+        Ok(Self {
+            hub: Hub::get(listen)?,
+            format,
+        })
+    }
+}
+
+impl Notifiable for Socket {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        let n = self
+            .hub
+            .broadcast(&template::render(&self.format, &template::message(message)));
+        log::debug!("Socket sent to {n} clients");
+        Ok(true)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.notify(&event.with_hint())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::time::Duration;
+
+    #[test]
+    fn test_socket() {
+        let (listener, addr) = crate::notifier::testutil::listen();
+        drop(listener);
+        let socket = Socket::new(&addr, "> {message}".to_owned()).unwrap();
+        let client = TcpStream::connect(&addr).unwrap();
+        // wait for the client to be accepted
+        for _ in 0..50 {
+            if !socket.hub.clients.lock().unwrap().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        socket.notify("abc").unwrap();
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "> abc\r\n");
+    }
+}
@@ -0,0 +1,120 @@
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+use chrono::Local;
+use rusqlite::{params, Connection};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Inserts every match into a SQLite database, for counting events afterwards, e.g.
+/// `SELECT trigger, count(*) FROM matches GROUP BY trigger`.
+pub struct Sqlite {
+    path: PathBuf,
+    /// Opened with the schema created on the first insert, kept for the ones after
+    conn: Mutex<Option<Connection>>,
+}
+
+impl Sqlite {
+    /// How long to wait for another writer, such as a query tool holding the database.
+    const BUSY: Duration = Duration::from_secs(5);
+    const SCHEMA: &'static str = "CREATE TABLE IF NOT EXISTS matches (
+        id INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        time TEXT NOT NULL,
+        channel TEXT NOT NULL,
+        sender TEXT,
+        trigger TEXT NOT NULL,
+        priority TEXT NOT NULL,
+        captures TEXT NOT NULL,
+        message TEXT NOT NULL,
+        raw TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS matches_trigger ON matches (trigger, at);";
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            conn: Mutex::new(None),
+        }
+    }
+
+    fn open(&self) -> Result<Connection, Error> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(Sqlite::BUSY)?;
+        conn.execute_batch(Sqlite::SCHEMA)?;
+        Ok(conn)
+    }
+
+    fn insert(&self, event: &Event) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let conn = match conn.take() {
+            Some(c) => conn.insert(c),
+            None => conn.insert(self.open()?),
+        };
+        conn.execute(
+            "INSERT INTO matches (at, time, channel, sender, trigger, priority, captures, message, raw)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                Local::now().timestamp(),
+                event.time,
+                event.channel.name(),
+                event.sender,
+                event.trigger,
+                event.priority.name(),
+                serde_json::to_string(&event.captures)?,
+                event.with_hint(),
+                event.raw,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl Notifiable for Sqlite {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.insert(event)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::matched;
+
+    #[test]
+    fn test_sqlite() {
+        let path = std::env::temp_dir().join("cgaid_test_matches.db");
+        let _ = std::fs::remove_file(&path);
+        let sqlite = Sqlite::new(path.clone());
+        let mut trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        trigger.name = "leave".to_owned();
+        let event = matched("21:40:12丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        assert!(sqlite.notify_event(&event).unwrap());
+        assert!(sqlite.notify("abc").unwrap());
+
+        let conn = Connection::open(&path).unwrap();
+        let (count, captures, raw): (i64, String, String) = conn
+            .query_row(
+                "SELECT count(*), captures, raw FROM matches WHERE trigger = 'leave'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(captures, r#"["画眉鸟离开了队伍。","画眉鸟"]"#);
+        assert_eq!(raw, "画眉鸟离开了队伍。");
+        let total: i64 = conn
+            .query_row("SELECT count(*) FROM matches", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+        drop(conn);
+        drop(sqlite);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! How priorities look in chat platforms, shared so urgency reads the same everywhere.
+use super::super::config::{self, Priority};
+use super::super::error::Error;
+
+/// Header colors Feishu cards accept, by name with their approximate RGB.
+const FEISHU_COLORS: [(&str, u32); 12] = [
+    ("blue", 0x3370ff),
+    ("wathet", 0x4dc1f9),
+    ("turquoise", 0x14c0b5),
+    ("green", 0x34c724),
+    ("yellow", 0xffc60a),
+    ("orange", 0xff8800),
+    ("red", 0xf54a45),
+    ("carmine", 0xd83a84),
+    ("violet", 0xbe49d5),
+    ("purple", 0x7f3bf5),
+    ("indigo", 0x4954e6),
+    ("grey", 0x8f959e),
+];
+
+/// The 16 mIRC colors IRC clients agree on, by number.
+const IRC_COLORS: [(u8, u32); 16] = [
+    (0, 0xffffff),
+    (1, 0x000000),
+    (2, 0x00007f),
+    (3, 0x009300),
+    (4, 0xff0000),
+    (5, 0x7f0000),
+    (6, 0x9c009c),
+    (7, 0xfc7f00),
+    (8, 0xffff00),
+    (9, 0x00fc00),
+    (10, 0x009393),
+    (11, 0x00ffff),
+    (12, 0x0000fc),
+    (13, 0xff00ff),
+    (14, 0x7f7f7f),
+    (15, 0xd2d2d2),
+];
+
+/// The name of the palette color closest to the RGB one.
+fn closest<T: Copy>(palette: &[(T, u32)], color: u32) -> T {
+    let rgb = |c: u32| [(c >> 16) as i32, (c >> 8 & 0xff) as i32, (c & 0xff) as i32];
+    let target = rgb(color);
+    palette
+        .iter()
+        .min_by_key(|(_, c)| {
+            rgb(*c)
+                .iter()
+                .zip(target)
+                .map(|(a, b)| (a - b).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+/// The styling of one priority.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Look {
+    /// RGB
+    pub color: u32,
+    pub emoji: String,
+    /// Whether plain messages start with the trigger name in bold, where the platform can
+    pub bold: bool,
+}
+
+impl Look {
+    /// The look of the config, with the given color when it has none.
+    fn new(cfg: &config::Look, color: u32) -> Result<Self, Error> {
+        let hex = cfg.color.trim_start_matches('#');
+        let color = if hex.is_empty() {
+            color
+        } else {
+            u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| Error::Config(format!("Invalid style color {}", cfg.color)))?
+        };
+        Ok(Self {
+            color,
+            emoji: cfg.emoji.clone(),
+            bold: cfg.bold,
+        })
+    }
+
+    /// The text behind the emoji, if any.
+    pub fn prefix(&self, text: &str) -> String {
+        if self.emoji.is_empty() {
+            text.to_owned()
+        } else {
+            format!("{} {text}", self.emoji)
+        }
+    }
+
+    /// The title on its own line when the look asks for it, marked up by `bold`.
+    fn titled(&self, title: &str, text: &str, bold: impl Fn(&str) -> String) -> String {
+        if self.bold && !title.is_empty() {
+            format!("{}\n{text}", self.prefix(&bold(title)))
+        } else {
+            self.prefix(text)
+        }
+    }
+
+    /// A plain Markdown message, the title on its own bold line when the look asks for it.
+    pub fn markdown(&self, title: &str, text: &str) -> String {
+        self.titled(title, text, |t| format!("**{t}**"))
+    }
+
+    /// A message of a platform without markup, the title on its own line when the look asks
+    /// for it.
+    pub fn plain(&self, title: &str, text: &str) -> String {
+        self.titled(title, text, str::to_owned)
+    }
+
+    /// An XMPP message, bold by the message styling most clients show (XEP-0393).
+    pub fn xmpp(&self, title: &str, text: &str) -> String {
+        self.titled(title, text, |t| format!("*{t}*"))
+    }
+
+    /// An IRC message in the mIRC color closest to the RGB one, the title bold. Only the first
+    /// line is colored, as each line goes as a message of its own.
+    pub fn irc(&self, title: &str, text: &str) -> String {
+        let color = closest(&IRC_COLORS, self.color);
+        let text = self.titled(title, text, |t| format!("\x02{t}\x02"));
+        format!("\x03{color:02}{text}")
+    }
+
+    /// The Feishu card color closest to the RGB one.
+    pub fn feishu_color(&self) -> &'static str {
+        closest(&FEISHU_COLORS, self.color)
+    }
+}
+
+/// The looks of all priorities.
+#[derive(Debug, Clone)]
+pub struct Styles {
+    low: Look,
+    normal: Look,
+    high: Look,
+}
+
+impl Styles {
+    /// The styles to use, `None` when styling is off.
+    pub fn new(cfg: &config::Style) -> Result<Option<Self>, Error> {
+        if !cfg.enabled {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            low: Look::new(&cfg.low, 0x95a5a6)?,
+            normal: Look::new(&cfg.normal, 0x3498db)?,
+            high: Look::new(&cfg.high, 0xe74c3c)?,
+        }))
+    }
+
+    pub fn look(&self, priority: Priority) -> &Look {
+        match priority {
+            Priority::Low => &self.low,
+            Priority::Normal => &self.normal,
+            Priority::High => &self.high,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styles() {
+        assert!(Styles::new(&config::Style::default()).unwrap().is_none());
+        let cfg = config::Style {
+            enabled: true,
+            ..Default::default()
+        };
+        let styles = Styles::new(&cfg).unwrap().unwrap();
+        let high = styles.look(Priority::High);
+        assert_eq!(high.color, 0xe74c3c);
+        assert_eq!(high.feishu_color(), "red");
+        assert_eq!(styles.look(Priority::Normal).feishu_color(), "blue");
+        assert_eq!(styles.look(Priority::Low).feishu_color(), "grey");
+        assert_eq!(
+            high.markdown("boss", "BOSS 出现了"),
+            "🚨 **boss**\nBOSS 出现了"
+        );
+        assert_eq!(high.markdown("", "BOSS 出现了"), "🚨 BOSS 出现了");
+        assert_eq!(styles.look(Priority::Low).markdown("boss", "abc"), "abc");
+        assert_eq!(high.plain("boss", "BOSS 出现了"), "🚨 boss\nBOSS 出现了");
+        assert_eq!(high.xmpp("boss", "BOSS 出现了"), "🚨 *boss*\nBOSS 出现了");
+        assert_eq!(
+            high.irc("boss", "BOSS 出现了"),
+            "\x0307🚨 \x02boss\x02\nBOSS 出现了"
+        );
+        assert_eq!(styles.look(Priority::Low).irc("boss", "abc"), "\x0314abc");
+
+        let cfg: config::Style = toml::from_str(
+            r#"
+            enabled = true
+            [high]
+            emoji = "🔥"
+            "#,
+        )
+        .unwrap();
+        let styles = Styles::new(&cfg).unwrap().unwrap();
+        assert_eq!(styles.look(Priority::High).color, 0xe74c3c);
+        assert_eq!(styles.look(Priority::High).prefix("abc"), "🔥 abc");
+        assert_eq!(styles.look(Priority::Normal).emoji, "🔔");
+
+        let mut bad = cfg.clone();
+        bad.low.color = "#12345".to_owned();
+        assert!(Styles::new(&bad).is_err());
+    }
+}
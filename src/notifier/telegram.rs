@@ -0,0 +1,643 @@
+use super::super::config::Mention;
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use super::style::Styles;
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+/// Sends through a Telegram bot, https://core.telegram.org/bots/api#sendmessage
+pub struct Telegram {
+    /// Bot API address, the official one unless a proxy or self-hosted server is used
+    api: String,
+    token: String,
+    chat_id: String,
+    template: String,
+    mentions: Vec<Mention>,
+    /// Minutes the mute button mutes the trigger for, no buttons when 0
+    mute: u64,
+    styles: Option<Styles>,
+    /// Title of the game window to attach a screenshot of, empty for none
+    screenshot: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Body<'a> {
+    chat_id: &'a str,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<Entity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<Markup>,
+}
+
+/// Formatting of a part of the text, by offset and length in UTF-16 code units.
+#[derive(Debug, Serialize, PartialEq)]
+struct Entity {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    offset: usize,
+    length: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Markup {
+    inline_keyboard: Vec<Vec<Button>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Button {
+    text: String,
+    callback_data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    ok: bool,
+    #[serde(default)]
+    description: String,
+}
+
+/// What a button under a message asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Ack(String),
+    /// Mutes the trigger for the minutes
+    Mute(String, u64),
+}
+
+impl Command {
+    /// Bytes Telegram allows in the data of a button
+    const MAX_DATA: usize = 64;
+
+    fn data(&self) -> String {
+        match self {
+            Command::Ack(trigger) => format!("ack:{trigger}"),
+            Command::Mute(trigger, minutes) => format!("mute:{minutes}:{trigger}"),
+        }
+    }
+
+    fn parse(data: &str) -> Option<Self> {
+        match data.split_once(':')? {
+            ("ack", trigger) => Some(Command::Ack(trigger.to_owned())),
+            ("mute", rest) => {
+                let (minutes, trigger) = rest.split_once(':')?;
+                Some(Command::Mute(trigger.to_owned(), minutes.parse().ok()?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Shown to whoever pressed the button.
+    fn done(&self) -> String {
+        match self {
+            Command::Ack(trigger) => format!("已确认 {trigger}"),
+            Command::Mute(trigger, minutes) => format!("已静音 {trigger} {minutes} 分钟"),
+        }
+    }
+}
+
+impl Telegram {
+    pub const DEFAULT_API: &'static str = "https://api.telegram.org";
+    /// Characters Telegram allows in the caption of a photo
+    const MAX_CAPTION: usize = 1024;
+    const SCREENSHOT_NAME: &'static str = "screenshot.png";
+
+    pub fn new(
+        api: String,
+        token: String,
+        chat_id: String,
+        template: String,
+        mentions: Vec<Mention>,
+    ) -> Self {
+        let api = if api.is_empty() {
+            Telegram::DEFAULT_API.to_owned()
+        } else {
+            api.trim_end_matches('/').to_owned()
+        };
+        Self {
+            api,
+            token,
+            chat_id,
+            template,
+            mentions,
+            mute: 0,
+            styles: None,
+            screenshot: String::new(),
+        }
+    }
+
+    /// Attaches the screenshot of the game window whose title contains `window`, taken when the
+    /// event matched.
+    pub fn with_screenshot(mut self, window: String) -> Self {
+        self.screenshot = window;
+        self
+    }
+
+    /// Styles messages by the priority of their events.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// Adds acknowledge and mute buttons under the messages of triggers, handled by a
+    /// [`Listener`].
+    pub fn with_buttons(mut self, mute: u64) -> Self {
+        self.mute = mute;
+        self
+    }
+
+    fn markup(&self, trigger: &str) -> Option<Markup> {
+        if self.mute == 0 || trigger.is_empty() {
+            return None;
+        }
+        let commands = [
+            (Command::Ack(trigger.to_owned()), "✅ 确认".to_owned()),
+            (
+                Command::Mute(trigger.to_owned(), self.mute),
+                format!("🔕 静音 {} 分钟", self.mute),
+            ),
+        ];
+        if commands
+            .iter()
+            .any(|(c, _)| c.data().len() > Command::MAX_DATA)
+        {
+            log::warn!("Trigger name too long for Telegram buttons: {trigger}");
+            return None;
+        }
+        let buttons = commands
+            .into_iter()
+            .map(|(c, text)| Button {
+                text,
+                callback_data: c.data(),
+            })
+            .collect();
+        Some(Markup {
+            inline_keyboard: vec![buttons],
+        })
+    }
+
+    fn text(&self, message: &str, at: &[String]) -> String {
+        let mut text = template::render(&self.template, &template::message(message));
+        for a in at {
+            text.push_str(&format!(" @{a}"));
+        }
+        text
+    }
+
+    /// The text of the event in the look of its priority, with the title bold by an entity
+    /// rather than markup, which would need the text escaped.
+    fn styled(&self, event: &Event, at: &[String]) -> (String, Vec<Entity>) {
+        let Some(look) = self.styles.as_ref().map(|s| s.look(event.priority)) else {
+            return (self.text(&event.with_hint(), at), Vec::new());
+        };
+        let text = self.text(&look.plain(&event.trigger, &event.with_hint()), at);
+        let utf16 = |s: &str| s.encode_utf16().count();
+        let title = format!("{}\n", look.prefix(&event.trigger));
+        let entities = (look.bold && !event.trigger.is_empty())
+            .then(|| text.find(&title))
+            .flatten()
+            .map(|i| Entity {
+                kind: "bold",
+                offset: utf16(&text[..i]) + utf16(&look.prefix("")),
+                length: utf16(&event.trigger),
+            });
+        (text, entities.into_iter().collect())
+    }
+
+    fn request(&self, method: &str) -> reqwest::RequestBuilder {
+        super::client().post(format!("{}/bot{}/{method}", self.api, self.token))
+    }
+
+    /// A `sendPhoto` form of the screenshot, captioned with the message when given.
+    fn photo(&self, png: Vec<u8>, caption: Option<Body>) -> Result<Form, Error> {
+        let file = Part::bytes(png)
+            .file_name(Telegram::SCREENSHOT_NAME)
+            .mime_str("image/png")?;
+        let mut form = Form::new()
+            .text("chat_id", self.chat_id.clone())
+            .part("photo", file);
+        if let Some(body) = caption {
+            form = form.text("caption", body.text);
+            if !body.entities.is_empty() {
+                form = form.text("caption_entities", serde_json::to_string(&body.entities)?);
+            }
+            if let Some(markup) = body.reply_markup {
+                form = form.text("reply_markup", serde_json::to_string(&markup)?);
+            }
+        }
+        Ok(form)
+    }
+
+    async fn send(
+        &self,
+        text: String,
+        entities: Vec<Entity>,
+        trigger: &str,
+        screenshot: Option<Vec<u8>>,
+    ) -> Result<bool, Error> {
+        let body = Body {
+            chat_id: &self.chat_id,
+            text,
+            entities,
+            reply_markup: self.markup(trigger),
+        };
+        let Some(png) = screenshot else {
+            return Telegram::call(self.request("sendMessage").json(&body)).await;
+        };
+        if body.text.chars().count() > Telegram::MAX_CAPTION {
+            // too long for a caption, the photo follows the message
+            Telegram::call(self.request("sendMessage").json(&body)).await?;
+            let form = self.photo(png, None)?;
+            return Telegram::call(self.request("sendPhoto").multipart(form)).await;
+        }
+        let form = self.photo(png, Some(body))?;
+        Telegram::call(self.request("sendPhoto").multipart(form)).await
+    }
+
+    async fn call(request: reqwest::RequestBuilder) -> Result<bool, Error> {
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let reply: Reply = response.json().await?;
+        if !reply.ok {
+            return Err(NotifierError::Rejected {
+                status,
+                reason: reply.description,
+            }
+            .into());
+        }
+        Ok(true)
+    }
+
+    fn post(
+        &self,
+        text: String,
+        entities: Vec<Entity>,
+        trigger: &str,
+        screenshot: Option<Vec<u8>>,
+    ) -> Result<bool, Error> {
+        super::block_on(self.send(text, entities, trigger, screenshot))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    ok: bool,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    callback_query: Option<Callback>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Callback {
+    id: String,
+    #[serde(default)]
+    data: String,
+    message: Option<CallbackMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackMessage {
+    chat: Chat,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+    #[serde(default)]
+    username: String,
+}
+
+impl Chat {
+    /// Whether this is the chat configured by id or by `@username`.
+    fn is(&self, chat_id: &str) -> bool {
+        self.id.to_string() == chat_id
+            || chat_id
+                .strip_prefix('@')
+                .is_some_and(|u| u.eq_ignore_ascii_case(&self.username))
+    }
+}
+
+/// Long-polls the bot for presses of the buttons added by [`Telegram::with_buttons`]. Only one
+/// poller per bot is allowed by Telegram.
+pub struct Listener {
+    api: String,
+    token: String,
+    /// Presses in other chats are ignored
+    chat_id: String,
+}
+
+impl Listener {
+    /// Seconds a poll waits for updates
+    const POLL: u64 = 30;
+    const RETRY: Duration = Duration::from_secs(10);
+
+    pub fn new(api: String, token: String, chat_id: String) -> Self {
+        let api = if api.is_empty() {
+            Telegram::DEFAULT_API.to_owned()
+        } else {
+            api.trim_end_matches('/').to_owned()
+        };
+        Self {
+            api,
+            token,
+            chat_id,
+        }
+    }
+
+    /// Polls on a thread of its own, `handle` returns whether the command did anything.
+    pub fn start<F>(self, handle: F)
+    where
+        F: Fn(&Command) -> bool + Send + 'static,
+    {
+        thread::spawn(move || {
+            let mut offset = 0;
+            loop {
+                match super::block_on(self.poll(offset, &handle)) {
+                    Ok(next) => offset = next,
+                    Err(e) => {
+                        log::error!("Telegram listener error: {e}");
+                        thread::sleep(Listener::RETRY);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Handles the presses after `offset`, returns the offset of the next poll.
+    async fn poll<F>(&self, offset: i64, handle: &F) -> Result<i64, Error>
+    where
+        F: Fn(&Command) -> bool,
+    {
+        let client = super::client();
+        let url = format!("{}/bot{}/getUpdates", self.api, self.token);
+        let query = serde_json::json!({
+            "offset": offset,
+            "timeout": Listener::POLL,
+            "allowed_updates": ["callback_query"],
+        });
+        let response = client
+            .post(url)
+            .json(&query)
+            .timeout(Duration::from_secs(Listener::POLL + 10))
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        let updates: Updates = response.json().await?;
+        if !updates.ok {
+            return Err(NotifierError::Rejected {
+                status,
+                reason: updates.description,
+            }
+            .into());
+        }
+        let mut next = offset;
+        for u in updates.result {
+            next = next.max(u.update_id + 1);
+            let Some(callback) = u.callback_query else {
+                continue;
+            };
+            let text = match Command::parse(&callback.data) {
+                Some(_) if !callback.message.is_some_and(|m| m.chat.is(&self.chat_id)) => {
+                    log::warn!("Telegram button pressed in another chat: {}", callback.data);
+                    "不是通知的群组".to_owned()
+                }
+                Some(c) if handle(&c) => c.done(),
+                Some(_) => "没有需要确认的通知".to_owned(),
+                None => continue,
+            };
+            let url = format!("{}/bot{}/answerCallbackQuery", self.api, self.token);
+            let answer = serde_json::json!({ "callback_query_id": callback.id, "text": text });
+            if let Err(e) = client.post(url).json(&answer).send().await {
+                log::warn!("Telegram answer error: {e}");
+            }
+        }
+        Ok(next)
+    }
+}
+
+impl Notifiable for Telegram {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.post(self.text(message, &[]), Vec::new(), "", None)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let at: Vec<_> = event
+            .mentions(&self.mentions)
+            .iter()
+            .filter(|m| !m.telegram.is_empty())
+            .map(|m| m.telegram.trim_start_matches('@').to_owned())
+            .collect();
+        let (text, entities) = self.styled(event, &at);
+        let screenshot = event
+            .screenshots
+            .get(&self.screenshot)
+            .map(|png| png.0.to_vec());
+        self.post(text, entities, &event.trigger, screenshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::testutil::{self, matched};
+
+    /// Answers one request with `reply` and returns the request.
+    fn serve_once(reply: &str) -> (String, std::thread::JoinHandle<String>) {
+        testutil::serve_once(testutil::response("200 OK", reply))
+    }
+
+    /// Answers each request with the next reply, one connection each, returns the requests.
+    fn serve(replies: Vec<&str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        testutil::serve(
+            replies
+                .into_iter()
+                .map(|reply| testutil::response("200 OK", reply))
+                .collect(),
+        )
+    }
+
+    fn body(request: &str) -> serde_json::Value {
+        serde_json::from_str(testutil::body(request)).unwrap()
+    }
+
+    #[test]
+    fn test_telegram() {
+        let (api, handle) = serve_once(r#"{"ok":true,"result":{}}"#);
+        let mentions = vec![Mention {
+            name: "画眉鸟".to_owned(),
+            dingtalk: String::new(),
+            telegram: "@huameiniao".to_owned(),
+        }];
+        let telegram = Telegram::new(
+            api,
+            "123:abc".to_owned(),
+            "-100".to_owned(),
+            "Notice: {message}".to_owned(),
+            mentions,
+        );
+        let trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        let event = matched("12:00:02丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        assert!(telegram.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /bot123:abc/sendMessage "));
+        let body = body(&request);
+        assert_eq!(body["chat_id"], "-100");
+        assert_eq!(body["text"], "Notice: 画眉鸟 掉线了 @huameiniao");
+        assert!(body.get("reply_markup").is_none());
+        assert!(body.get("entities").is_none());
+    }
+
+    #[test]
+    fn test_telegram_styles() {
+        let style = crate::config::Style {
+            enabled: true,
+            ..Default::default()
+        };
+        let telegram = Telegram::new(
+            String::new(),
+            String::new(),
+            String::new(),
+            "Notice: {message}".to_owned(),
+            Vec::new(),
+        )
+        .with_styles(Styles::new(&style).unwrap());
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        event.priority = crate::config::Priority::High;
+        let (text, entities) = telegram.styled(&event, &[]);
+        assert_eq!(text, "Notice: 🚨 boss\nBOSS 出现了");
+        // the emoji is two UTF-16 code units
+        assert_eq!(
+            entities,
+            [Entity {
+                kind: "bold",
+                offset: 11,
+                length: 4
+            }]
+        );
+        event.priority = crate::config::Priority::Normal;
+        let (text, entities) = telegram.styled(&event, &[]);
+        assert_eq!(text, "Notice: 🔔 BOSS 出现了");
+        assert!(entities.is_empty());
+    }
+
+    #[test]
+    fn test_telegram_screenshot() {
+        let (api, handle) = serve_once(r#"{"ok":true,"result":{}}"#);
+        let telegram = Telegram::new(
+            api,
+            "123:abc".to_owned(),
+            "-100".to_owned(),
+            "{message}".to_owned(),
+            Vec::new(),
+        )
+        .with_buttons(60)
+        .with_screenshot("魔力宝贝".to_owned());
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        let png = super::super::screenshot::Png(std::sync::Arc::new(b"PNG fake".to_vec()));
+        event.screenshots.insert("魔力宝贝".to_owned(), png);
+        assert!(telegram.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /bot123:abc/sendPhoto "));
+        assert!(request.contains("multipart/form-data; boundary="));
+        assert!(request.contains("name=\"photo\"; filename=\"screenshot.png\""));
+        assert!(request.contains("BOSS 出现了"));
+        assert!(request.contains("name=\"reply_markup\""));
+    }
+
+    #[test]
+    fn test_telegram_buttons() {
+        let command = Command::Mute("迷宫".to_owned(), 60);
+        assert_eq!(Command::parse(&command.data()), Some(command));
+        assert_eq!(
+            Command::parse("ack:boss"),
+            Some(Command::Ack("boss".to_owned()))
+        );
+        assert_eq!(Command::parse("mute:x:boss"), None);
+        assert_eq!(Command::parse("other"), None);
+
+        let (api, handle) = serve(vec![r#"{"ok":true}"#, r#"{"ok":true}"#]);
+        let telegram = Telegram::new(
+            api,
+            "123:abc".to_owned(),
+            "-100".to_owned(),
+            "{message}".to_owned(),
+            Vec::new(),
+        )
+        .with_buttons(60);
+        let mut event = Event::plain("迷宫 即将刷新");
+        event.trigger = "迷宫".to_owned();
+        assert!(telegram.notify_event(&event).unwrap());
+        // nothing to acknowledge without a trigger name
+        assert!(telegram.notify("abc").unwrap());
+        let requests = handle.join().unwrap();
+        let buttons = &body(&requests[0])["reply_markup"]["inline_keyboard"][0];
+        assert_eq!(buttons[0]["callback_data"], "ack:迷宫");
+        assert_eq!(buttons[1]["callback_data"], "mute:60:迷宫");
+        assert!(body(&requests[1]).get("reply_markup").is_none());
+    }
+
+    #[test]
+    fn test_telegram_listener() {
+        let updates = r#"{"ok":true,"result":[
+            {"update_id":7,"callback_query":{"id":"a","data":"ack:boss","message":{"chat":{"id":-100}}}},
+            {"update_id":8,"callback_query":{"id":"b","data":"mute:30:maze","message":{"chat":{"id":-200}}}},
+            {"update_id":9,"message":{"text":"hi"}},
+            {"update_id":10,"callback_query":{"id":"c","data":"ack:maze","message":{"chat":{"id":-100}}}}
+        ]}"#;
+        let (api, handle) = serve(vec![updates, "{}", "{}", "{}"]);
+        let listener = Listener::new(api, "123:abc".to_owned(), "-100".to_owned());
+        let handled = std::sync::Mutex::new(Vec::new());
+        let next = super::super::block_on(listener.poll(5, &|c: &Command| {
+            handled.lock().unwrap().push(c.clone());
+            c == &Command::Ack("boss".to_owned())
+        }))
+        .unwrap();
+        assert_eq!(next, 11);
+        assert_eq!(
+            *handled.lock().unwrap(),
+            [
+                Command::Ack("boss".to_owned()),
+                Command::Ack("maze".to_owned())
+            ]
+        );
+        let requests = handle.join().unwrap();
+        assert!(requests[0].starts_with("POST /bot123:abc/getUpdates "));
+        assert_eq!(body(&requests[0])["offset"], 5);
+        let answers: Vec<_> = requests[1..]
+            .iter()
+            .map(|r| body(r)["text"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            answers,
+            ["已确认 boss", "不是通知的群组", "没有需要确认的通知"]
+        );
+    }
+
+    #[test]
+    fn test_telegram_rejected() {
+        let (api, _) = serve_once(r#"{"ok":false,"description":"Bad Request: chat not found"}"#);
+        let telegram = Telegram::new(
+            api,
+            "123:abc".to_owned(),
+            "1".to_owned(),
+            "{message}".to_owned(),
+            Vec::new(),
+        );
+        let e = telegram.notify("abc").unwrap_err();
+        assert_eq!(e.kind(), "rejected");
+        assert!(e.to_string().contains("chat not found"));
+    }
+}
@@ -0,0 +1,364 @@
+use super::super::config::Priority;
+use super::super::error::Error;
+use super::super::event::Event;
+use super::super::Notifiable;
+use reqwest::Url;
+
+/// Windows toast notification, with buttons calling the control API for matched events.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct Toast {
+    title: String,
+    /// Minutes the snooze button mutes the trigger
+    snooze: u64,
+    /// Control API address, no buttons when empty
+    api: String,
+    /// Token of the control API, empty for none
+    token: String,
+    /// Windows sound name such as `Default`, `Reminder` or `Alarm2`, `none` for silence
+    sound: String,
+    /// Scenario of high priority events, `reminder` or `alarm` stay on screen until dismissed
+    /// and show over borderless fullscreen games, `default` for a normal toast
+    scenario: String,
+    /// Titles events with the name of their trigger
+    named: bool,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl Toast {
+    pub fn new(
+        title: String,
+        snooze: u64,
+        api: String,
+        sound: String,
+        scenario: String,
+        named: bool,
+    ) -> Self {
+        Self {
+            title,
+            snooze,
+            api,
+            token: String::new(),
+            sound,
+            scenario,
+            named,
+        }
+    }
+
+    /// Passes the token of the control API along with the button actions.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = token.to_owned();
+        self
+    }
+
+    fn title<'a>(&'a self, trigger: &'a str) -> &'a str {
+        if self.named && !trigger.is_empty() {
+            trigger
+        } else {
+            &self.title
+        }
+    }
+
+    /// The scenario of an event. Alarms need a button to be shown as such, without the control
+    /// API they are reminders.
+    fn scenario(&self, priority: Priority, buttons: bool) -> &str {
+        match self.scenario.as_str() {
+            _ if priority != Priority::High => "default",
+            "alarm" if !buttons => "reminder",
+            s => s,
+        }
+    }
+
+    fn url(&self, segments: &[&str], query: Option<String>) -> Result<String, Error> {
+        let invalid = || Error::Config(format!("Invalid api address: {}", self.api));
+        let mut url = Url::parse(&format!("http://{}/", self.api)).map_err(|_| invalid())?;
+        // listening on all interfaces, reached on loopback
+        let loopback = match url.host_str() {
+            Some("0.0.0.0") => Some("127.0.0.1"),
+            Some("[::]") => Some("[::1]"),
+            _ => None,
+        };
+        if loopback.is_some() {
+            url.set_host(loopback).map_err(|_| invalid())?;
+        }
+        url.path_segments_mut()
+            .map_err(|_| invalid())?
+            .pop_if_empty()
+            .extend(segments);
+        url.set_query(query.as_deref());
+        if !self.token.is_empty() {
+            url.query_pairs_mut().append_pair("token", &self.token);
+        }
+        Ok(url.to_string())
+    }
+
+    /// Buttons as label and action, the action being `METHOD URL` of the control API,
+    /// or `OPEN URL` to open it in the browser.
+    fn buttons(&self, trigger: &str) -> Result<Vec<(String, String)>, Error> {
+        let mut list = Vec::new();
+        if self.api.is_empty() {
+            return Ok(list);
+        }
+        if !trigger.is_empty() {
+            let minutes = Some(format!("minutes={}", self.snooze));
+            list.push((
+                format!("{}分钟内不再提醒", self.snooze),
+                format!(
+                    "POST {}",
+                    self.url(&["triggers", trigger, "mute"], minutes)?
+                ),
+            ));
+            list.push((
+                "静音此监控".to_owned(),
+                format!("POST {}", self.url(&["triggers", trigger, "mute"], None)?),
+            ));
+        }
+        list.push((
+            "打开面板".to_owned(),
+            format!("OPEN {}", self.url(&[], None)?),
+        ));
+        Ok(list)
+    }
+
+    /// The `<audio>` element of the sound, none for the default one.
+    fn audio(&self) -> Result<String, Error> {
+        let looping = |s: &str, prefix: &str| {
+            s.strip_prefix(prefix)
+                .is_some_and(|n| n.is_empty() || matches!(n.parse(), Ok(2..=10)))
+        };
+        Ok(match self.sound.as_str() {
+            "" | "Default" => String::new(),
+            "none" => r#"<audio silent="true"/>"#.to_owned(),
+            s @ ("IM" | "Mail" | "Reminder" | "SMS") => {
+                format!(r#"<audio src="ms-winsoundevent:Notification.{s}"/>"#)
+            }
+            s if looping(s, "Alarm") || looping(s, "Call") => {
+                format!(r#"<audio src="ms-winsoundevent:Notification.Looping.{s}"/>"#)
+            }
+            s => return Err(Error::Config(format!("Unknown toast sound {s}"))),
+        })
+    }
+
+    /// The toast XML of a notification.
+    fn xml(&self, text: &str, trigger: &str, priority: Priority) -> Result<String, Error> {
+        let buttons = self.buttons(trigger)?;
+        let scenario = match self.scenario(priority, !buttons.is_empty()) {
+            s @ ("reminder" | "alarm") => format!(r#" scenario="{s}""#),
+            "default" | "" => String::new(),
+            s => return Err(Error::Config(format!("Unknown toast scenario {s}"))),
+        };
+        let actions: String = (buttons.iter())
+            .map(|(label, action)| {
+                format!(
+                    r#"<action content="{}" arguments="{}"/>"#,
+                    escape(label),
+                    escape(action)
+                )
+            })
+            .collect();
+        Ok(format!(
+            r#"<toast{scenario}><visual><binding template="ToastGeneric"><text>{}</text><text>{}</text></binding></visual>{}<actions>{actions}</actions></toast>"#,
+            escape(self.title(trigger)),
+            escape(text),
+            self.audio()?
+        ))
+    }
+
+    #[cfg(windows)]
+    fn show(&self, text: &str, trigger: &str, priority: Priority) -> Result<bool, Error> {
+        use windows::core::{IInspectable, Interface, Ref, HSTRING};
+        use windows::Data::Xml::Dom::XmlDocument;
+        use windows::Foundation::TypedEventHandler;
+        use windows::UI::Notifications::{
+            ToastActivatedEventArgs, ToastDismissalReason, ToastDismissedEventArgs,
+            ToastNotification, ToastNotificationManager,
+        };
+
+        let doc = XmlDocument::new().map_err(Error::notifier)?;
+        doc.LoadXml(&HSTRING::from(self.xml(text, trigger, priority)?))
+            .map_err(Error::notifier)?;
+        let toast = ToastNotification::CreateToastNotification(&doc).map_err(Error::notifier)?;
+        toast
+            .Activated(&TypedEventHandler::new(|_, args: Ref<IInspectable>| {
+                let action = (args.as_ref())
+                    .and_then(|a| a.cast::<ToastActivatedEventArgs>().ok())
+                    .and_then(|a| a.Arguments().ok())
+                    .filter(|a| !a.is_empty());
+                if let Some(a) = action.map(|a| a.to_string()) {
+                    if let Err(e) = act(&a) {
+                        log::error!("Toast action error: {a} {e}");
+                    }
+                }
+                Ok(())
+            }))
+            .map_err(Error::notifier)?;
+        toast
+            .Dismissed(&TypedEventHandler::new(
+                |sender: Ref<ToastNotification>, args: Ref<ToastDismissedEventArgs>| {
+                    let reason = args.as_ref().and_then(|a| a.Reason().ok());
+                    // timed out ones wait in the action center, their buttons still work
+                    if reason != Some(ToastDismissalReason::TimedOut) {
+                        if let Some(t) = sender.as_ref() {
+                            SHOWN.lock().unwrap().retain(|s| s != t);
+                        }
+                    }
+                    Ok(())
+                },
+            ))
+            .map_err(Error::notifier)?;
+        ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_ID))
+            .and_then(|n| n.Show(&toast))
+            .map_err(Error::notifier)?;
+        let mut shown = SHOWN.lock().unwrap();
+        if shown.len() >= SHOWN_MAX {
+            shown.pop_front();
+        }
+        shown.push_back(toast);
+        Ok(true)
+    }
+
+    #[cfg(not(windows))]
+    fn show(&self, _text: &str, _trigger: &str, _priority: Priority) -> Result<bool, Error> {
+        Err(super::super::error::NotifierError::Unsupported(
+            "Toast is only supported on Windows".to_owned(),
+        )
+        .into())
+    }
+}
+
+/// Apps may only show toasts under an id registered with Windows, PowerShell's is always there.
+#[cfg(windows)]
+const APP_ID: &str =
+    r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe";
+
+/// Toasts kept as many as the action center holds, as a click reaches the handler only while
+/// its toast is alive.
+#[cfg(windows)]
+static SHOWN: std::sync::Mutex<
+    std::collections::VecDeque<windows::UI::Notifications::ToastNotification>,
+> = std::sync::Mutex::new(std::collections::VecDeque::new());
+
+#[cfg(windows)]
+const SHOWN_MAX: usize = 20;
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Runs the action of a clicked button.
+#[cfg(windows)]
+fn act(action: &str) -> Result<(), Error> {
+    let invalid = || Error::Parse(format!("Invalid action: {action}"));
+    let (method, url) = action.split_once(' ').ok_or_else(invalid)?;
+    if method == "OPEN" {
+        std::process::Command::new("cmd")
+            .args(["/C", "start", "", url])
+            .spawn()?;
+        return Ok(());
+    }
+    let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(|_| invalid())?;
+    let request = super::client().request(method, url);
+    let response = super::block_on(async { Ok::<_, Error>(request.send().await?) })?;
+    log::info!("Toast action: {action} {}", response.status());
+    Ok(())
+}
+
+impl Notifiable for Toast {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.show(message, "", Priority::Normal)
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        self.show(&event.with_hint(), &event.trigger, event.priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(api: &str) -> Toast {
+        Toast::new(
+            "cgaid".to_owned(),
+            10,
+            api.to_owned(),
+            String::new(),
+            "alarm".to_owned(),
+            true,
+        )
+    }
+
+    #[test]
+    fn test_toast_buttons() {
+        let toast = sample("127.0.0.1:7878");
+        let buttons = toast.buttons("迷宫").unwrap();
+        assert_eq!(buttons.len(), 3);
+        assert_eq!(
+            buttons[0].1,
+            "POST http://127.0.0.1:7878/triggers/%E8%BF%B7%E5%AE%AB/mute?minutes=10"
+        );
+        assert_eq!(
+            buttons[1].1,
+            "POST http://127.0.0.1:7878/triggers/%E8%BF%B7%E5%AE%AB/mute"
+        );
+        assert_eq!(buttons[2].1, "OPEN http://127.0.0.1:7878/");
+        assert_eq!(toast.buttons("").unwrap().len(), 1);
+
+        let toast = sample("");
+        assert!(toast.buttons("迷宫").unwrap().is_empty());
+
+        let toast = sample("127.0.0.1:7878").with_token("s3cret");
+        let buttons = toast.buttons("迷宫").unwrap();
+        assert!(buttons[0].1.ends_with("/mute?minutes=10&token=s3cret"));
+        assert_eq!(buttons[2].1, "OPEN http://127.0.0.1:7878/?token=s3cret");
+
+        let buttons = sample("0.0.0.0:7878").buttons("").unwrap();
+        assert_eq!(buttons[0].1, "OPEN http://127.0.0.1:7878/");
+        let buttons = sample("[::]:7878").buttons("").unwrap();
+        assert_eq!(buttons[0].1, "OPEN http://[::1]:7878/");
+    }
+
+    #[test]
+    fn test_toast_xml() {
+        let xml = sample("127.0.0.1:7878")
+            .xml("<玄铁> & 盛明兰", "迷宫", Priority::High)
+            .unwrap();
+        assert!(xml.starts_with(r#"<toast scenario="alarm"><visual>"#));
+        assert!(xml.contains("<text>迷宫</text><text>&lt;玄铁&gt; &amp; 盛明兰</text>"));
+        assert!(xml.contains(r#"arguments="POST http://127.0.0.1:7878/triggers/"#));
+        assert!(!xml.contains("<audio"));
+
+        let mut toast = sample("");
+        toast.sound = "Alarm2".to_owned();
+        let xml = toast.xml("abc", "", Priority::Normal).unwrap();
+        assert!(xml.starts_with("<toast><visual>"));
+        assert!(xml.contains("Notification.Looping.Alarm2"));
+        toast.sound = "none".to_owned();
+        assert!(toast.audio().unwrap().contains("silent"));
+        toast.sound = "Alarm11".to_owned();
+        assert!(toast.audio().is_err());
+    }
+
+    #[test]
+    fn test_toast_look() {
+        let toast = sample("127.0.0.1:7878");
+        assert_eq!(toast.title("迷宫"), "迷宫");
+        assert_eq!(toast.title(""), "cgaid");
+        assert_eq!(toast.scenario(Priority::High, true), "alarm");
+        assert_eq!(toast.scenario(Priority::High, false), "reminder");
+        assert_eq!(toast.scenario(Priority::Normal, true), "default");
+        let toast = Toast::new(
+            "cgaid".to_owned(),
+            10,
+            String::new(),
+            "none".to_owned(),
+            "default".to_owned(),
+            false,
+        );
+        assert_eq!(toast.title("迷宫"), "cgaid");
+        assert_eq!(toast.scenario(Priority::High, false), "default");
+    }
+}
@@ -0,0 +1,373 @@
+//! Tray icon of the running instance, matches pop as balloon tips and are listed in its menu.
+use super::super::config::Priority;
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::Notifiable;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+
+/// Matches listed in the menu.
+const KEEP: usize = 10;
+/// Characters of a match shown in the menu.
+const WIDTH: usize = 40;
+
+/// A tip waiting to pop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tip {
+    pub title: String,
+    pub text: String,
+    pub urgent: bool,
+}
+
+/// Recent matches and how many are unread, shared by the notifier and the tray thread.
+#[derive(Debug, Default)]
+pub struct Inbox {
+    title: String,
+    /// Newest first
+    recent: VecDeque<String>,
+    unread: usize,
+    tip: Option<Tip>,
+}
+
+impl Inbox {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, event: &Event) {
+        let text = event.with_hint();
+        let line = format!("{} {text}", event.time);
+        self.recent
+            .push_front(match line.char_indices().nth(WIDTH) {
+                Some((i, _)) => format!("{}…", &line[..i]),
+                None => line,
+            });
+        self.recent.truncate(KEEP);
+        self.unread += 1;
+        // a newer tip replaces the one not shown yet, as the balloons do themselves
+        self.tip = Some(Tip {
+            title: if event.trigger.is_empty() {
+                self.title.clone()
+            } else {
+                event.trigger.clone()
+            },
+            text,
+            urgent: event.priority == Priority::High,
+        });
+    }
+
+    pub fn take_tip(&mut self) -> Option<Tip> {
+        self.tip.take()
+    }
+
+    /// The hover text of the icon, with the unread count as its badge.
+    pub fn tooltip(&self) -> String {
+        if self.unread == 0 {
+            self.title.clone()
+        } else {
+            format!("{} - {} 条未读", self.title, self.unread)
+        }
+    }
+
+    /// Marks all as read, returns the matches newest first.
+    pub fn read(&mut self) -> Vec<String> {
+        self.unread = 0;
+        self.recent.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.recent.clear();
+        self.unread = 0;
+    }
+}
+
+static INBOX: OnceLock<Mutex<Inbox>> = OnceLock::new();
+
+/// Shows the tray icon, once for the process. Matches of the `tray` notifier only show while
+/// it is there.
+pub fn start(title: &str) -> Result<(), Error> {
+    if INBOX.set(Mutex::new(Inbox::new(title))).is_err() {
+        return Ok(());
+    }
+    #[cfg(windows)]
+    return win::start();
+    #[cfg(not(windows))]
+    Err(NotifierError::Unsupported("Tray icon is only supported on Windows".to_owned()).into())
+}
+
+/// Removes the icon, which Windows would otherwise leave until the mouse passes over it.
+pub fn stop() {
+    #[cfg(windows)]
+    win::stop();
+}
+
+/// Balloon tips of the tray icon, see [`start`].
+pub struct Tray {}
+
+impl Tray {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for Tray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifiable for Tray {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let Some(inbox) = INBOX.get() else {
+            return Err(NotifierError::Unsupported("Tray icon not started".to_owned()).into());
+        };
+        inbox.lock().unwrap().push(event);
+        #[cfg(windows)]
+        return win::refresh();
+        #[cfg(not(windows))]
+        Ok(false)
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use super::{Error, INBOX};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::Shell::{
+        Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO, NIIF_WARNING,
+        NIM_ADD, NIM_DELETE, NIM_MODIFY, NIN_BALLOONUSERCLICK, NOTIFYICONDATAW,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
+        DispatchMessageW, GetCursorPos, GetMessageW, LoadIconW, PostMessageW, RegisterClassW,
+        SendMessageW, SetForegroundWindow, TrackPopupMenu, TranslateMessage, IDI_APPLICATION,
+        MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_APP,
+        WM_LBUTTONUP, WM_NULL, WM_RBUTTONUP, WNDCLASSW,
+    };
+
+    /// New matches to show
+    const WM_NOTICE: u32 = WM_APP + 1;
+    /// Mouse events of the icon
+    const WM_ICON: u32 = WM_APP + 2;
+    const WM_REMOVE: u32 = WM_APP + 3;
+    const ID_CLEAR: usize = 1;
+
+    static WINDOW: AtomicUsize = AtomicUsize::new(0);
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Copies as much of the text as fits, keeping the trailing zero.
+    fn fill<const N: usize>(dst: &mut [u16; N], text: &str) {
+        let src: Vec<_> = text.encode_utf16().take(N - 1).collect();
+        dst[..src.len()].copy_from_slice(&src);
+        dst[src.len()] = 0;
+    }
+
+    fn data(hwnd: HWND) -> NOTIFYICONDATAW {
+        let mut data: NOTIFYICONDATAW = unsafe { std::mem::zeroed() };
+        data.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = hwnd;
+        data.uID = 1;
+        data
+    }
+
+    pub fn start() -> Result<(), Error> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || unsafe {
+            let instance = GetModuleHandleW(std::ptr::null());
+            let class = wide("cgaid_tray");
+            let mut wc: WNDCLASSW = std::mem::zeroed();
+            wc.lpfnWndProc = Some(proc);
+            wc.hInstance = instance;
+            wc.lpszClassName = class.as_ptr();
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                class.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                instance,
+                std::ptr::null(),
+            );
+            if hwnd.is_null() {
+                let _ = tx.send(Err(std::io::Error::last_os_error()));
+                return;
+            }
+            let mut nid = data(hwnd);
+            nid.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+            nid.uCallbackMessage = WM_ICON;
+            nid.hIcon = LoadIconW(std::ptr::null_mut(), IDI_APPLICATION);
+            if let Some(inbox) = INBOX.get() {
+                fill(&mut nid.szTip, &inbox.lock().unwrap().tooltip());
+            }
+            if Shell_NotifyIconW(NIM_ADD, &nid) == 0 {
+                let _ = tx.send(Err(std::io::Error::last_os_error()));
+                return;
+            }
+            WINDOW.store(hwnd as usize, Ordering::SeqCst);
+            let _ = tx.send(Ok(()));
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        });
+        rx.recv()
+            .map_err(|e| Error::notifier(e.to_string()))?
+            .map_err(Error::from)
+    }
+
+    fn window() -> Option<HWND> {
+        Some(WINDOW.load(Ordering::SeqCst))
+            .filter(|w| *w != 0)
+            .map(|w| w as HWND)
+    }
+
+    /// Wakes the tray thread to pop the new tip.
+    pub fn refresh() -> Result<bool, Error> {
+        let Some(hwnd) = window() else {
+            return Ok(false);
+        };
+        if unsafe { PostMessageW(hwnd, WM_NOTICE, 0, 0) } == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(true)
+    }
+
+    pub fn stop() {
+        if let Some(hwnd) = window() {
+            unsafe { SendMessageW(hwnd, WM_REMOVE, 0, 0) };
+        }
+    }
+
+    /// Updates the hover text, popping the tip if there is one.
+    unsafe fn update(hwnd: HWND) {
+        let Some(inbox) = INBOX.get() else {
+            return;
+        };
+        let mut inbox = inbox.lock().unwrap();
+        let mut nid = data(hwnd);
+        nid.uFlags = NIF_TIP;
+        fill(&mut nid.szTip, &inbox.tooltip());
+        if let Some(tip) = inbox.take_tip() {
+            nid.uFlags |= NIF_INFO;
+            fill(&mut nid.szInfoTitle, &tip.title);
+            fill(&mut nid.szInfo, &tip.text);
+            nid.dwInfoFlags = if tip.urgent { NIIF_WARNING } else { NIIF_INFO };
+        }
+        Shell_NotifyIconW(NIM_MODIFY, &nid);
+    }
+
+    /// Lists the recent matches at the cursor, which marks them read.
+    unsafe fn menu(hwnd: HWND) {
+        let Some(inbox) = INBOX.get() else {
+            return;
+        };
+        let recent = inbox.lock().unwrap().read();
+        let menu = CreatePopupMenu();
+        if recent.is_empty() {
+            AppendMenuW(
+                menu,
+                MF_STRING | MF_GRAYED,
+                0,
+                wide("没有新的通知").as_ptr(),
+            );
+        }
+        for (i, line) in recent.iter().enumerate() {
+            AppendMenuW(menu, MF_STRING, ID_CLEAR + 1 + i, wide(line).as_ptr());
+        }
+        AppendMenuW(menu, MF_SEPARATOR, 0, std::ptr::null());
+        AppendMenuW(menu, MF_STRING, ID_CLEAR, wide("清空").as_ptr());
+        let mut point = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut point);
+        // without it the menu would not close when clicking elsewhere
+        SetForegroundWindow(hwnd);
+        let id = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_RIGHTBUTTON,
+            point.x,
+            point.y,
+            0,
+            hwnd,
+            std::ptr::null(),
+        );
+        PostMessageW(hwnd, WM_NULL, 0, 0);
+        DestroyMenu(menu);
+        if id as usize == ID_CLEAR {
+            inbox.lock().unwrap().clear();
+        }
+        update(hwnd);
+    }
+
+    unsafe extern "system" fn proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_NOTICE => update(hwnd),
+            WM_ICON => match (lparam & 0xffff) as u32 {
+                WM_LBUTTONUP | WM_RBUTTONUP | NIN_BALLOONUSERCLICK => menu(hwnd),
+                _ => {}
+            },
+            WM_REMOVE => {
+                Shell_NotifyIconW(NIM_DELETE, &data(hwnd));
+                WINDOW.store(0, Ordering::SeqCst);
+            }
+            _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tray_inbox() {
+        let mut inbox = Inbox::new("cgaid");
+        assert_eq!(inbox.tooltip(), "cgaid");
+        let mut event = Event::plain("画眉鸟 掉线了");
+        event.time = "21:40:12".to_owned();
+        event.trigger = "leave".to_owned();
+        event.priority = Priority::High;
+        inbox.push(&event);
+        inbox.push(&Event::plain(&"长".repeat(50)));
+        assert_eq!(inbox.tooltip(), "cgaid - 2 条未读");
+        let tip = inbox.take_tip().unwrap();
+        assert_eq!(tip.title, "cgaid");
+        assert!(!tip.urgent);
+        assert!(inbox.take_tip().is_none());
+
+        let recent = inbox.read();
+        assert_eq!(recent[1], "21:40:12 画眉鸟 掉线了");
+        assert!(recent[0].ends_with("长…"));
+        assert_eq!(recent[0].chars().count(), WIDTH + 1);
+        assert_eq!(inbox.tooltip(), "cgaid");
+        for _ in 0..KEEP {
+            inbox.push(&event);
+        }
+        assert_eq!(inbox.read().len(), KEEP);
+        inbox.clear();
+        assert!(inbox.read().is_empty());
+    }
+}
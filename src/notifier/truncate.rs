@@ -0,0 +1,102 @@
+/// Shortens `message` to at most `max` chars, `0` means unlimited.
+///
+/// Whitespace runs are collapsed first, then text outside of `keep` (usually the
+/// captured groups) is cut from the end backwards and replaced with `…`, so the
+/// important parts survive. Only if that is not enough the tail is cut.
+pub fn truncate(message: &str, max: usize, keep: &[String]) -> String {
+    if max == 0 || message.chars().count() <= max {
+        return message.to_owned();
+    }
+    let collapsed = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    let chars: Vec<char> = collapsed.chars().collect();
+    if chars.len() <= max {
+        return collapsed;
+    }
+
+    let mut protected = vec![false; chars.len()];
+    for k in keep.iter().filter(|k| !k.is_empty()) {
+        let k: Vec<char> = k.chars().collect();
+        let mut i = 0;
+        while i + k.len() <= chars.len() {
+            if chars[i..i + k.len()] == k[..] {
+                protected[i..i + k.len()].iter_mut().for_each(|p| *p = true);
+                i += k.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // (start, end) of unprotected runs, right to left
+    let mut runs = Vec::new();
+    let mut i = chars.len();
+    while i > 0 {
+        if protected[i - 1] {
+            i -= 1;
+            continue;
+        }
+        let end = i;
+        while i > 0 && !protected[i - 1] {
+            i -= 1;
+        }
+        runs.push((i, end));
+    }
+
+    let mut excess = chars.len() - max;
+    let mut cuts = Vec::new();
+    for (start, end) in runs {
+        let len = end - start;
+        if len < 2 || excess == 0 {
+            continue;
+        }
+        let k = len.min(excess + 1);
+        cuts.push((end - k, end));
+        excess -= k - 1;
+    }
+
+    let mut result = String::new();
+    let mut i = 0;
+    cuts.sort();
+    for (start, end) in cuts {
+        result.extend(&chars[i..start]);
+        result.push('…');
+        i = end;
+    }
+    result.extend(&chars[i..]);
+
+    if excess > 0 {
+        let mut cut: String = result.chars().take(max.saturating_sub(1)).collect();
+        cut.push('…');
+        return cut;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_short() {
+        assert_eq!(truncate("队长掉线了", 10, &[]), "队长掉线了");
+        assert_eq!(truncate("队长掉线了", 0, &[]), "队长掉线了");
+        assert_eq!(truncate("队长  掉线了", 6, &[]), "队长 掉线了");
+    }
+
+    #[test]
+    fn test_truncate_keep() {
+        let message = "12:00:01. [物品:挑战赛通道钥匙|12345|67890] 卖给了 盛明兰oO";
+        let keep = vec!["盛明兰oO".to_owned()];
+        let ret = truncate(message, 20, &keep);
+        assert_eq!(ret.chars().count(), 20);
+        assert!(ret.ends_with("盛明兰oO"));
+        assert!(ret.contains('…'));
+    }
+
+    #[test]
+    fn test_truncate_hard() {
+        let keep = vec!["挑战赛通道挑战赛通道".to_owned()];
+        let ret = truncate("挑战赛通道挑战赛通道", 5, &keep);
+        assert_eq!(ret, "挑战赛通…");
+    }
+}
@@ -1,67 +1,1311 @@
-use serde::Serialize;
-use tokio::runtime::Runtime;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
+use super::super::config::Mention;
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
 use super::super::Notifiable;
-use std::error::Error;
+use super::ratelimit::Window;
+use super::style::Styles;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 ///https://open.dingtalk.com/document/orgapp/custom-robot-access
+#[derive(Clone)]
 pub struct DingTalk {
     webhook: String,
     template: String,
+    /// Messages allowed per minute, 0 is unlimited
+    rate: usize,
+    /// Send queued messages as one
+    merge: bool,
+    mentions: Vec<Mention>,
+    msgtype: MsgType,
+    /// Title of markdown, link and actionCard messages, `{message}` for the first line
+    title: String,
+    /// Opened by link and actionCard messages
+    url: String,
+    /// Picture of link messages
+    picture: String,
+    /// Secret of the "加签" security setting, empty for unsigned
+    secret: String,
+    styles: Option<Styles>,
 }
+
+/// How the message renders in the DingTalk chat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MsgType {
+    Text,
+    Markdown,
+    Link,
+    ActionCard,
+}
+
+impl MsgType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "" | "text" => Some(MsgType::Text),
+            "markdown" => Some(MsgType::Markdown),
+            "link" => Some(MsgType::Link),
+            "actionCard" | "action_card" => Some(MsgType::ActionCard),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MsgType::Text => "text",
+            MsgType::Markdown => "markdown",
+            MsgType::Link => "link",
+            MsgType::ActionCard => "actionCard",
+        }
+    }
+
+    /// Needs `url` to be sent.
+    pub fn needs_url(self) -> bool {
+        matches!(self, MsgType::Link | MsgType::ActionCard)
+    }
+}
+
 #[derive(Debug, Serialize)]
-struct Content {
+struct Text {
     content: String,
 }
 #[derive(Debug, Serialize)]
+struct Markdown {
+    title: String,
+    text: String,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Link {
+    title: String,
+    text: String,
+    message_url: String,
+    pic_url: String,
+}
+#[derive(Debug, Serialize)]
+struct ActionCard {
+    title: String,
+    text: String,
+    #[serde(rename = "singleTitle")]
+    single_title: String,
+    #[serde(rename = "singleURL")]
+    single_url: String,
+}
+/// Serialized under the key named by `msgtype`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Content {
+    Text(Text),
+    Markdown(Markdown),
+    Link(Link),
+    ActionCard(ActionCard),
+}
+#[derive(Debug, Serialize)]
+struct At {
+    #[serde(rename = "atMobiles")]
+    at_mobiles: Vec<String>,
+    #[serde(rename = "isAtAll")]
+    is_at_all: bool,
+}
+#[derive(Debug, Serialize)]
 struct Body {
     msgtype: String,
-    text: Content,
+    #[serde(flatten)]
+    content: Content,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    at: Option<At>,
+}
+
+/// A message and the mobiles to @.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Message {
+    text: String,
+    at: Vec<String>,
+}
+
+impl Message {
+    fn merge(list: Vec<Message>) -> Self {
+        let mut at: Vec<String> = Vec::new();
+        for m in &list {
+            for a in &m.at {
+                if !at.contains(a) {
+                    at.push(a.clone());
+                }
+            }
+        }
+        Self {
+            text: list
+                .into_iter()
+                .map(|m| m.text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            at,
+        }
+    }
+}
+#[derive(Debug, Deserialize)]
+struct Reply {
+    errcode: i64,
+    #[serde(default)]
+    errmsg: String,
+}
+
+enum Sent {
+    Ok,
+    Rejected,
+    /// Asked to slow down for the duration
+    Throttled(Duration),
+}
+
+/// Messages waiting for the rate limit of one webhook.
+struct Queue {
+    window: Mutex<Window>,
+    pending: Mutex<VecDeque<Message>>,
+    running: AtomicBool,
 }
 
 impl DingTalk {
-    pub fn new(webhook: String, template: String) -> Self {
-        Self { webhook, template }
+    /// Wait after a throttling reply without Retry-After
+    const THROTTLE_WAIT: Duration = Duration::from_secs(60);
+    /// Error codes for sending too fast
+    const THROTTLE_CODES: [i64; 2] = [130101, 660026];
+    const DEFAULT_TITLE: &'static str = "{message}";
+    /// Button of actionCard messages
+    const BUTTON: &'static str = "查看详情";
+    /// Tries of a queued message that cannot be sent for the network
+    const RETRIES: u32 = 3;
+    /// Wait before trying a queued message again
+    const RETRY_WAIT: Duration = Duration::from_secs(30);
+
+    pub fn new(
+        webhook: String,
+        template: String,
+        rate: usize,
+        merge: bool,
+        mentions: Vec<Mention>,
+    ) -> Self {
+        Self {
+            webhook,
+            template,
+            rate,
+            merge,
+            mentions,
+            msgtype: MsgType::Text,
+            title: DingTalk::DEFAULT_TITLE.to_owned(),
+            url: String::new(),
+            picture: String::new(),
+            secret: String::new(),
+            styles: None,
+        }
     }
 
-    async fn send(&self, message: &str) -> bool {
-        let client = reqwest::Client::new();
-        let body = Body {
-            msgtype: "text".to_owned(),
-            text: Content {
-                content: self.template.replace("{message}", message),
-            },
+    /// Styles messages by the priority of their events, in Markdown for the msgtypes showing it.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// The message of the event in the look of its priority.
+    fn styled(&self, event: &Event) -> String {
+        let text = event.with_hint();
+        match self.styles.as_ref().map(|s| s.look(event.priority)) {
+            Some(l) if matches!(self.msgtype, MsgType::Markdown | MsgType::ActionCard) => {
+                l.markdown(&event.trigger, &text)
+            }
+            Some(l) => l.plain(&event.trigger, &text),
+            None => text,
+        }
+    }
+
+    /// Signs every request for robots with the "加签" security setting.
+    pub fn with_secret(mut self, secret: String) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    /// The webhook with `timestamp` and `sign` appended when signing, the signature is the
+    /// base64 of the HMAC-SHA256 keyed with the secret over `{timestamp}\n{secret}`.
+    fn url(&self, timestamp: i64) -> String {
+        if self.secret.is_empty() {
+            return self.webhook.clone();
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(format!("{timestamp}\n{}", self.secret).as_bytes());
+        let sign = BASE64.encode(mac.finalize().into_bytes());
+        let sep = if self.webhook.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{sep}timestamp={timestamp}&sign={}",
+            self.webhook,
+            percent_encoding::utf8_percent_encode(&sign, percent_encoding::NON_ALPHANUMERIC)
+        )
+    }
+
+    /// Sends as `msgtype` instead of plain text, an empty title shows the first line of the message.
+    pub fn with_msgtype(
+        mut self,
+        msgtype: MsgType,
+        title: String,
+        url: String,
+        picture: String,
+    ) -> Self {
+        self.msgtype = msgtype;
+        if !title.is_empty() {
+            self.title = title;
+        }
+        self.url = url;
+        self.picture = picture;
+        self
+    }
+
+    fn body(&self, message: &Message) -> Body {
+        let mut text = template::render(&self.template, &template::message(&message.text));
+        let title = template::render(
+            &self.title,
+            &template::message(message.text.lines().next().unwrap_or_default()),
+        );
+        if self.msgtype != MsgType::Link {
+            // the mobiles must appear in the text to be notified
+            for m in &message.at {
+                text.push_str(&format!(" @{m}"));
+            }
+        }
+        if matches!(self.msgtype, MsgType::Markdown | MsgType::ActionCard) {
+            // single line breaks are folded in markdown
+            text = text.replace('\n', "  \n");
+        }
+        let content = match self.msgtype {
+            MsgType::Text => Content::Text(Text { content: text }),
+            MsgType::Markdown => Content::Markdown(Markdown { title, text }),
+            MsgType::Link => Content::Link(Link {
+                title,
+                text,
+                message_url: self.url.clone(),
+                pic_url: self.picture.clone(),
+            }),
+            MsgType::ActionCard => Content::ActionCard(ActionCard {
+                title,
+                text,
+                single_title: DingTalk::BUTTON.to_owned(),
+                single_url: self.url.clone(),
+            }),
         };
-        let response = client.post(&self.webhook).json(&body).send().await;
-        match response {
+        Body {
+            msgtype: self.msgtype.name().to_owned(),
+            content,
+            at: if message.at.is_empty() || self.msgtype == MsgType::Link {
+                None
+            } else {
+                Some(At {
+                    at_mobiles: message.at.clone(),
+                    is_at_all: false,
+                })
+            },
+        }
+    }
+
+    async fn send(&self, message: &Message) -> reqwest::Result<Sent> {
+        let client = super::client();
+        let body = self.body(message);
+        let url = self.url(chrono::Local::now().timestamp_millis());
+        let response = client.post(url).json(&body).send().await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DingTalk::THROTTLE_WAIT);
+            return Ok(Sent::Throttled(wait));
+        }
+        if response.status().as_u16() != 200 {
+            return Ok(Sent::Rejected);
+        }
+        match response.json::<Reply>().await {
+            Ok(r) if r.errcode == 0 => Ok(Sent::Ok),
+            Ok(r) if DingTalk::THROTTLE_CODES.contains(&r.errcode) => {
+                Ok(Sent::Throttled(DingTalk::THROTTLE_WAIT))
+            }
             Ok(r) => {
-                return r.status().as_u16() == 200;
+                log::error!("DingTalk rejected: {} {}", r.errcode, r.errmsg);
+                Ok(Sent::Rejected)
+            }
+            Err(_) => Ok(Sent::Ok),
+        }
+    }
+
+    fn deliver(&self, message: &Message) -> Result<Sent, Error> {
+        super::block_on(async { Ok(self.send(message).await?) })
+    }
+
+    fn queue(&self) -> Arc<Queue> {
+        static QUEUES: OnceLock<Mutex<HashMap<String, Arc<Queue>>>> = OnceLock::new();
+        let mut queues = QUEUES.get_or_init(Default::default).lock().unwrap();
+        Arc::clone(queues.entry(self.webhook.clone()).or_insert_with(|| {
+            Arc::new(Queue {
+                window: Mutex::new(Window::new(self.rate, Duration::from_secs(60))),
+                pending: Mutex::new(VecDeque::new()),
+                running: AtomicBool::new(false),
+            })
+        }))
+    }
+
+    fn enqueue(&self, queue: Arc<Queue>, message: Message) {
+        log::info!("DingTalk queued: {}", message.text);
+        queue.pending.lock().unwrap().push_back(message);
+        if queue.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let dt = self.clone();
+        let mut failures = 0;
+        thread::spawn(move || loop {
+            let wait = queue.window.lock().unwrap().acquire(Instant::now());
+            if let Err(d) = wait {
+                thread::sleep(d);
+                continue;
+            }
+            let message = {
+                let mut pending = queue.pending.lock().unwrap();
+                if dt.merge && pending.len() > 1 {
+                    Some(Message::merge(pending.drain(..).collect()))
+                } else {
+                    pending.pop_front()
+                }
+            };
+            let Some(message) = message else {
+                queue.running.store(false, Ordering::SeqCst);
+                // a message may have been queued before the flag was cleared
+                if queue.pending.lock().unwrap().is_empty()
+                    || queue.running.swap(true, Ordering::SeqCst)
+                {
+                    break;
+                }
+                continue;
+            };
+            match dt.deliver(&message) {
+                Ok(Sent::Ok) => log::debug!("DingTalk queued sent"),
+                Ok(Sent::Rejected) => log::error!("DingTalk queued rejected: {}", message.text),
+                Ok(Sent::Throttled(d)) => {
+                    queue.window.lock().unwrap().block(Instant::now(), d);
+                    queue.pending.lock().unwrap().push_front(message);
+                }
+                Err(e) if e.is_transient() && failures + 1 < DingTalk::RETRIES => {
+                    failures += 1;
+                    log::warn!("DingTalk queued error, trying again: {e}");
+                    let now = Instant::now();
+                    queue
+                        .window
+                        .lock()
+                        .unwrap()
+                        .block(now, DingTalk::RETRY_WAIT);
+                    queue.pending.lock().unwrap().push_front(message);
+                    continue;
+                }
+                Err(e) => log::error!("DingTalk queued message dropped: {e}: {}", message.text),
+            }
+            failures = 0;
+        });
+    }
+
+    /// Sends the message, or queues it behind the rate limit and reports it deferred. Not sent
+    /// when over the rate where it may not queue.
+    fn post(&self, message: Message) -> Result<bool, Error> {
+        let deferred = || Err(NotifierError::Deferred("DingTalk rate limit".to_owned()).into());
+        let queues = super::may_queue();
+        if self.rate == 0 {
+            return Ok(matches!(self.deliver(&message)?, Sent::Ok));
+        }
+        let queue = self.queue();
+        let idle = queue.pending.lock().unwrap().is_empty();
+        if !idle
+            || queue
+                .window
+                .lock()
+                .unwrap()
+                .acquire(Instant::now())
+                .is_err()
+        {
+            if !queues {
+                log::warn!("DingTalk over the rate, not sent: {}", message.text);
+                return Ok(false);
             }
-            Err(e) => {
-                log::error!("DingTalk notify error: {}", e);
+            self.enqueue(queue, message);
+            return deferred();
+        }
+        match self.deliver(&message)? {
+            Sent::Ok => Ok(true),
+            Sent::Rejected => Ok(false),
+            Sent::Throttled(d) => {
+                queue.window.lock().unwrap().block(Instant::now(), d);
+                if !queues {
+                    return Ok(false);
+                }
+                self.enqueue(queue, message);
+                deferred()
             }
         }
-        false
     }
 }
 
 impl Notifiable for DingTalk {
-    fn notify(&self, message: &str) -> Result<bool, Box<dyn Error>> {
-        let future = self.send(message);
-        Ok(Runtime::new().unwrap().block_on(future))
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.post(Message {
+            text: message.to_owned(),
+            at: Vec::new(),
+        })
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let at = event
+            .mentions(&self.mentions)
+            .iter()
+            .filter(|m| !m.dingtalk.is_empty())
+            .map(|m| m.dingtalk.clone())
+            .collect();
+        self.post(Message {
+            text: self.styled(event),
+            at,
+        })
+    }
+}
+
+pub enum Payload {
+    /// The formatted message as plain text
+    Text,
+    /// The whole matched event as JSON
+    Json,
+    /// The template as the raw body, placeholders escaped for its content type
+    Template,
+}
+
+/// Signs request bodies so the receiver can verify the sender.
+///
+/// The signature is the hex HMAC-SHA256 of `{timestamp}.{body}`, the unix timestamp in seconds
+/// is sent in the `X-Cgaid-Timestamp` header.
+pub struct Signer {
+    secret: String,
+    header: String,
+}
+
+impl Signer {
+    pub const TIMESTAMP_HEADER: &'static str = "X-Cgaid-Timestamp";
+    const DEFAULT_HEADER: &'static str = "X-Cgaid-Signature";
+
+    pub fn new(secret: String, header: String) -> Self {
+        let header = if header.is_empty() {
+            Signer::DEFAULT_HEADER.to_owned()
+        } else {
+            header
+        };
+        Self { secret, header }
+    }
+
+    fn sign(&self, timestamp: i64, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(body);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Posts to any HTTP endpoint.
+pub struct Http {
+    url: String,
+    payload: Payload,
+    template: String,
+    signer: Option<Signer>,
+    method: reqwest::Method,
+    /// Sent as given, a `Content-Type` among them replaces the one of the payload
+    headers: Vec<(String, String)>,
+}
+
+impl Http {
+    pub fn new(url: String, payload: Payload, template: String, signer: Option<Signer>) -> Self {
+        Self {
+            url,
+            payload,
+            template,
+            signer,
+            method: reqwest::Method::POST,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Sends with another method and extra headers.
+    pub fn with_request(mut self, method: reqwest::Method, headers: Vec<(String, String)>) -> Self {
+        self.method = method;
+        self.headers = headers;
+        self
+    }
+
+    fn content_type(&self) -> &str {
+        let given = self
+            .headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| v.as_str());
+        match self.payload {
+            Payload::Text => given.unwrap_or("text/plain; charset=utf-8"),
+            Payload::Json | Payload::Template => given.unwrap_or("application/json"),
+        }
+    }
+
+    /// The template with the event filled in, each value escaped so a JSON or form body stays
+    /// valid whatever the chat says.
+    fn render(&self, event: &Event) -> String {
+        let content_type = self.content_type();
+        let escape = |value: &str| {
+            if content_type.starts_with("application/json") {
+                let quoted = serde_json::to_string(value).unwrap_or_default();
+                quoted[1..quoted.len() - 1].to_owned()
+            } else if content_type.starts_with("application/x-www-form-urlencoded") {
+                percent_encoding::utf8_percent_encode(value, percent_encoding::NON_ALPHANUMERIC)
+                    .to_string()
+            } else {
+                value.to_owned()
+            }
+        };
+        fn escape_all(value: &mut serde_json::Value, escape: &dyn Fn(&str) -> String) {
+            match value {
+                serde_json::Value::String(s) => *s = escape(s),
+                serde_json::Value::Array(a) => a.iter_mut().for_each(|v| escape_all(v, escape)),
+                serde_json::Value::Object(o) => o.values_mut().for_each(|v| escape_all(v, escape)),
+                _ => {}
+            }
+        }
+        let mut values = template::context(event).into_json();
+        escape_all(&mut values, &escape);
+        let values = template::Context::from_value(values).unwrap_or_default();
+        template::render(&self.template, &values)
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        let client = super::client();
+        let body = match self.payload {
+            Payload::Text => {
+                template::render(&self.template, &template::context(event)).into_bytes()
+            }
+            Payload::Json => serde_json::to_vec(event)?,
+            Payload::Template => self.render(event).into_bytes(),
+        };
+        let mut request = client
+            .request(self.method.clone(), &self.url)
+            .header(reqwest::header::CONTENT_TYPE, self.content_type());
+        for (k, v) in &self.headers {
+            if !k.eq_ignore_ascii_case("content-type") {
+                request = request.header(k, v);
+            }
+        }
+        if let Some(s) = &self.signer {
+            let timestamp = chrono::Local::now().timestamp();
+            request = request
+                .header(Signer::TIMESTAMP_HEADER, timestamp)
+                .header(&s.header, s.sign(timestamp, &body));
+        }
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(status.is_success())
+    }
+}
+
+impl Notifiable for Http {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let future = self.send(event);
+        super::block_on(future)
+    }
+}
+
+/// Posts to a Discord webhook, https://discord.com/developers/docs/resources/webhook#execute-webhook
+pub struct Discord {
+    webhook: String,
+    template: String,
+    /// Sends events as an embed titled with the trigger name instead of plain content
+    embed: bool,
+    /// Overrides the webhook's default name when not empty
+    username: String,
+    styles: Option<Styles>,
+    /// Title of the game window to attach a screenshot of, empty for none
+    screenshot: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Embed {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    title: String,
+    description: String,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<EmbedImage>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedImage {
+    url: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+struct DiscordBody {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    content: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    username: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embeds: Vec<Embed>,
+}
+
+impl Discord {
+    pub fn new(webhook: String, template: String, embed: bool, username: String) -> Self {
+        Self {
+            webhook,
+            template,
+            embed,
+            username,
+            styles: None,
+            screenshot: String::new(),
+        }
+    }
+
+    /// Styles messages by the priority of their events.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// Attaches the screenshot of the game window whose title contains `window`, taken when the
+    /// event matched.
+    pub fn with_screenshot(mut self, window: String) -> Self {
+        self.screenshot = window;
+        self
+    }
+
+    const SCREENSHOT_NAME: &'static str = "screenshot.png";
+
+    fn body(&self, event: &Event) -> DiscordBody {
+        let text = template::render(&self.template, &template::context(event));
+        let look = self.styles.as_ref().map(|s| s.look(event.priority));
+        if !self.embed {
+            return DiscordBody {
+                content: look.map_or(text.clone(), |l| l.markdown(&event.trigger, &text)),
+                username: self.username.clone(),
+                ..Default::default()
+            };
+        }
+        // the record only has the time of day, it is from today
+        let now = chrono::Local::now();
+        let timestamp = chrono::NaiveTime::parse_from_str(&event.time, "%H:%M:%S")
+            .ok()
+            .and_then(|t| now.with_time(t).single())
+            .unwrap_or(now);
+        DiscordBody {
+            username: self.username.clone(),
+            embeds: vec![Embed {
+                title: match look {
+                    Some(l) if !event.trigger.is_empty() => l.prefix(&event.trigger),
+                    _ => event.trigger.clone(),
+                },
+                description: text,
+                timestamp: timestamp.to_rfc3339(),
+                color: look.map(|l| l.color),
+                image: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    async fn send(&self, event: &Event, screenshot: Option<Vec<u8>>) -> Result<bool, Error> {
+        let mut body = self.body(event);
+        let request = super::client().post(&self.webhook);
+        let request = match screenshot {
+            Some(png) => {
+                // shown inside the embed instead of below it
+                if let Some(e) = body.embeds.first_mut() {
+                    e.image = Some(EmbedImage {
+                        url: format!("attachment://{}", Discord::SCREENSHOT_NAME),
+                    });
+                }
+                let file = reqwest::multipart::Part::bytes(png)
+                    .file_name(Discord::SCREENSHOT_NAME)
+                    .mime_str("image/png")?;
+                request.multipart(
+                    reqwest::multipart::Form::new()
+                        .text("payload_json", serde_json::to_string(&body)?)
+                        .part("files[0]", file),
+                )
+            }
+            None => request.json(&body),
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(NotifierError::Rejected {
+                status: status.as_u16(),
+                reason: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Discord {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        // none when the capture failed, which only loses the picture
+        let screenshot = event
+            .screenshots
+            .get(&self.screenshot)
+            .map(|png| png.0.to_vec());
+        super::block_on(self.send(event, screenshot))
+    }
+}
+
+/// Posts to a Feishu/Lark custom bot,
+/// https://open.feishu.cn/document/client-docs/bot-v3/add-custom-bot
+pub struct Feishu {
+    webhook: String,
+    /// Secret of the "签名校验" security setting, empty when not enabled
+    secret: String,
+    template: String,
+    /// Sends events as an interactive card titled with the trigger name instead of plain text
+    card: bool,
+    /// Color of the card header, such as blue, red or green
+    color: String,
+    /// Replace the color, with an emoji before the title, by the priority of the events
+    styles: Option<Styles>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeishuReply {
+    #[serde(default)]
+    code: i64,
+    #[serde(default)]
+    msg: String,
+}
+
+impl Feishu {
+    pub fn new(
+        webhook: String,
+        secret: String,
+        template: String,
+        card: bool,
+        color: String,
+    ) -> Self {
+        Self {
+            webhook,
+            secret,
+            template,
+            card,
+            color,
+            styles: None,
+        }
+    }
+
+    /// Styles messages by the priority of their events.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    /// Base64 of the HMAC-SHA256 keyed with `{timestamp}\n{secret}` over nothing, as Feishu
+    /// expects.
+    fn sign(&self, timestamp: i64) -> String {
+        let key = format!("{timestamp}\n{}", self.secret);
+        let mac =
+            Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+
+    fn body(&self, event: &Event, timestamp: i64) -> serde_json::Value {
+        let text = template::render(&self.template, &template::context(event));
+        let look = self.styles.as_ref().map(|s| s.look(event.priority));
+        let mut body = if self.card {
+            let title = if event.trigger.is_empty() {
+                "cgaid"
+            } else {
+                &event.trigger
+            };
+            let (title, color) = match look {
+                Some(l) => (l.prefix(title), l.feishu_color()),
+                None => (title.to_owned(), self.color.as_str()),
+            };
+            serde_json::json!({
+                "msg_type": "interactive",
+                "card": {
+                    "header": {
+                        "title": {"tag": "plain_text", "content": title},
+                        "template": color,
+                    },
+                    "elements": [
+                        {"tag": "div", "text": {"tag": "lark_md", "content": text}},
+                        {"tag": "note", "elements": [{"tag": "plain_text", "content": event.time}]},
+                    ],
+                },
+            })
+        } else {
+            let text = look.map_or(text.clone(), |l| l.prefix(&text));
+            serde_json::json!({"msg_type": "text", "content": {"text": text}})
+        };
+        if !self.secret.is_empty() {
+            body["timestamp"] = timestamp.to_string().into();
+            body["sign"] = self.sign(timestamp).into();
+        }
+        body
+    }
+
+    async fn send(&self, event: &Event) -> Result<bool, Error> {
+        let body = self.body(event, chrono::Local::now().timestamp());
+        let response = super::client()
+            .post(&self.webhook)
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        let reply: FeishuReply = response.json().await?;
+        if reply.code != 0 {
+            return Err(NotifierError::Rejected {
+                status,
+                reason: format!("{} {}", reply.code, reply.msg),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+impl Notifiable for Feishu {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        self.notify_event(&Event::plain(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        super::block_on(self.send(event))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::notifier::testutil;
 
     #[test]
     fn test_dingtalk() {
-        let dingtalk =
-            DingTalk::new("https://oapi.dingtalk.com/robot/send?access_token=XXXXXXXXXXXXXXXXXXXX".to_owned(),
-            "Notice: {message}".to_owned());
+        let dingtalk = DingTalk::new(
+            "https://oapi.dingtalk.com/robot/send?access_token=XXXXXXXXXXXXXXXXXXXX".to_owned(),
+            "Notice: {message}".to_owned(),
+            20,
+            false,
+            Vec::new(),
+        );
         let ret = dingtalk.notify("Hello, World!");
         assert!(ret.is_ok());
     }
+
+    #[test]
+    fn test_dingtalk_offline() {
+        let (listener, addr) = testutil::listen();
+        drop(listener);
+        let url = format!("http://{addr}/");
+        let dingtalk = DingTalk::new(url, "{message}".to_owned(), 20, false, Vec::new());
+        // unreachable, so the outbox keeps it for later
+        let e = dingtalk.notify("Hello, World!").unwrap_err();
+        assert!(e.is_offline());
+    }
+
+    #[test]
+    fn test_dingtalk_mention() {
+        let mentions = vec![Mention {
+            name: "画眉鸟".to_owned(),
+            dingtalk: "13800000000".to_owned(),
+            telegram: String::new(),
+        }];
+        let dingtalk = DingTalk::new(
+            String::new(),
+            "Notice: {message}".to_owned(),
+            0,
+            false,
+            mentions,
+        );
+        let trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        let event = testutil::matched("12:00:02丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        let at: Vec<_> = event
+            .mentions(&dingtalk.mentions)
+            .iter()
+            .map(|m| m.dingtalk.clone())
+            .collect();
+        let body = serde_json::to_value(dingtalk.body(&Message {
+            text: event.message.clone(),
+            at,
+        }))
+        .unwrap();
+        assert_eq!(
+            body["text"]["content"],
+            "Notice: 画眉鸟 掉线了 @13800000000"
+        );
+        assert_eq!(body["at"]["atMobiles"][0], "13800000000");
+    }
+
+    #[test]
+    fn test_dingtalk_queue() {
+        let (url, handle) = testutil::serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\n\r\n"
+                .to_owned(),
+            testutil::response("200 OK", r#"{"errcode":0,"errmsg":"ok"}"#),
+        ]);
+        let dingtalk = DingTalk::new(url, "{message}".to_owned(), 20, false, Vec::new());
+        // throttled, then queued until the wait is over
+        let e = dingtalk.notify("BOSS 出现了").unwrap_err();
+        assert_eq!(e.kind(), "deferred");
+        let requests = handle.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(testutil::body(&requests[1]).contains("BOSS 出现了"));
+    }
+
+    #[test]
+    fn test_dingtalk_sign() {
+        let dingtalk = DingTalk::new(
+            "https://oapi.dingtalk.com/robot/send?access_token=abc".to_owned(),
+            String::new(),
+            0,
+            false,
+            Vec::new(),
+        );
+        assert_eq!(dingtalk.url(1), dingtalk.webhook);
+        let dingtalk = dingtalk.with_secret("SECxyz".to_owned());
+        let url = dingtalk.url(1700000000000);
+        let (webhook, query) = url.split_once("&timestamp=").unwrap();
+        assert_eq!(webhook, dingtalk.webhook);
+        let (timestamp, sign) = query.split_once("&sign=").unwrap();
+        assert_eq!(timestamp, "1700000000000");
+        let sign = percent_encoding::percent_decode_str(sign)
+            .decode_utf8()
+            .unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"SECxyz").unwrap();
+        mac.update(b"1700000000000\nSECxyz");
+        mac.verify_slice(&BASE64.decode(sign.as_bytes()).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_dingtalk_styles() {
+        let style = crate::config::Style {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut event = Event::plain("BOSS 出现了");
+        event.trigger = "boss".to_owned();
+        event.priority = crate::config::Priority::High;
+        let dingtalk = DingTalk::new(String::new(), "{message}".to_owned(), 0, false, Vec::new());
+        assert_eq!(dingtalk.styled(&event), "BOSS 出现了");
+        let dingtalk = dingtalk.with_styles(Styles::new(&style).unwrap());
+        assert_eq!(dingtalk.styled(&event), "🚨 boss\nBOSS 出现了");
+        let dingtalk = dingtalk.with_msgtype(
+            MsgType::Markdown,
+            String::new(),
+            String::new(),
+            String::new(),
+        );
+        assert_eq!(dingtalk.styled(&event), "🚨 **boss**\nBOSS 出现了");
+    }
+
+    #[test]
+    fn test_dingtalk_msgtype() {
+        let message = Message {
+            text: "画眉鸟 掉线了\n盛明兰 掉线了".to_owned(),
+            at: vec!["13800000000".to_owned()],
+        };
+        let dingtalk = DingTalk::new(
+            String::new(),
+            "### {message}".to_owned(),
+            0,
+            false,
+            Vec::new(),
+        )
+        .with_msgtype(
+            MsgType::Markdown,
+            String::new(),
+            String::new(),
+            String::new(),
+        );
+        let body = serde_json::to_value(dingtalk.body(&message)).unwrap();
+        assert_eq!(body["msgtype"], "markdown");
+        assert_eq!(body["markdown"]["title"], "画眉鸟 掉线了");
+        assert_eq!(
+            body["markdown"]["text"],
+            "### 画眉鸟 掉线了  \n盛明兰 掉线了 @13800000000"
+        );
+        assert_eq!(body["at"]["atMobiles"][0], "13800000000");
+        assert!(body.get("text").is_none());
+
+        let dingtalk = DingTalk::new(String::new(), "{message}".to_owned(), 0, false, Vec::new())
+            .with_msgtype(
+                MsgType::Link,
+                "掉线".to_owned(),
+                "https://example.com".to_owned(),
+                String::new(),
+            );
+        let body = serde_json::to_value(dingtalk.body(&message)).unwrap();
+        assert_eq!(body["msgtype"], "link");
+        assert_eq!(body["link"]["title"], "掉线");
+        assert_eq!(body["link"]["messageUrl"], "https://example.com");
+        assert!(body.get("at").is_none());
+
+        let dingtalk = dingtalk.with_msgtype(
+            MsgType::parse("actionCard").unwrap(),
+            String::new(),
+            "https://example.com".to_owned(),
+            String::new(),
+        );
+        let body = serde_json::to_value(dingtalk.body(&message)).unwrap();
+        assert_eq!(body["msgtype"], "actionCard");
+        assert_eq!(body["actionCard"]["title"], "掉线");
+        assert_eq!(body["actionCard"]["singleURL"], "https://example.com");
+        assert_eq!(MsgType::parse("card"), None);
+    }
+
+    #[test]
+    fn test_message_merge() {
+        let m = Message::merge(vec![
+            Message {
+                text: "a".to_owned(),
+                at: vec!["1".to_owned()],
+            },
+            Message {
+                text: "b".to_owned(),
+                at: vec!["1".to_owned(), "2".to_owned()],
+            },
+        ]);
+        assert_eq!(m.text, "a\nb");
+        assert_eq!(m.at, vec!["1", "2"]);
+    }
+
+    /// Accepts one request on a local port and returns its raw text.
+    fn serve_once() -> (String, std::thread::JoinHandle<String>) {
+        let (url, handle) = testutil::serve_once(testutil::response("200 OK", ""));
+        (format!("{url}/hook"), handle)
+    }
+
+    #[test]
+    fn test_http_text() {
+        let (url, handle) = serve_once();
+        let http = Http::new(url, Payload::Text, "Notice: {message}".to_owned(), None);
+        assert!(http.notify("挑战赛通道 即将刷新").unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.ends_with("Notice: 挑战赛通道 即将刷新"));
+    }
+
+    #[test]
+    fn test_http_template() {
+        let (url, handle) = serve_once();
+        let http = Http::new(
+            url,
+            Payload::Template,
+            r#"{"text":"{message}","at":"{time}","room":"{channel}"}"#.to_owned(),
+            None,
+        )
+        .with_request(
+            reqwest::Method::PUT,
+            vec![("Authorization".to_owned(), "Bearer tk".to_owned())],
+        );
+        assert!(http.notify(r#"他说"快跑""#).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("PUT /hook "));
+        assert!(request.contains("authorization: Bearer tk"));
+        assert!(request.contains("content-type: application/json"));
+        let json: serde_json::Value = serde_json::from_str(testutil::body(&request)).unwrap();
+        assert_eq!(json["text"], r#"他说"快跑""#);
+        assert_eq!(json["room"], "common");
+
+        let http = Http::new(
+            String::new(),
+            Payload::Template,
+            "msg={message}&from={sender}".to_owned(),
+            None,
+        )
+        .with_request(
+            reqwest::Method::POST,
+            vec![(
+                "content-type".to_owned(),
+                "application/x-www-form-urlencoded".to_owned(),
+            )],
+        );
+        assert_eq!(http.render(&Event::plain("a&b c")), "msg=a%26b%20c&from=");
+    }
+
+    #[test]
+    fn test_http_json() {
+        let (url, handle) = serve_once();
+        let http = Http::new(url, Payload::Json, String::new(), None);
+        assert!(http.notify("队长掉线了").unwrap());
+        let request = handle.join().unwrap();
+        let body = testutil::body(&request);
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["message"], "队长掉线了");
+        assert_eq!(json["channel"], "common");
+    }
+
+    #[test]
+    fn test_discord() {
+        let (url, handle) = serve_once();
+        let discord = Discord::new(url, "{message}".to_owned(), false, "cgaid".to_owned());
+        assert!(discord.notify("队长掉线了").unwrap());
+        let request = handle.join().unwrap();
+        let body: serde_json::Value = serde_json::from_str(testutil::body(&request)).unwrap();
+        assert_eq!(body["content"], "队长掉线了");
+        assert_eq!(body["username"], "cgaid");
+        assert!(body.get("embeds").is_none());
+
+        let discord = Discord::new(
+            String::new(),
+            "Notice: {message}".to_owned(),
+            true,
+            String::new(),
+        );
+        let mut trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        trigger.name = "leave".to_owned();
+        let event = testutil::matched("21:40:12丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        let body = serde_json::to_value(discord.body(&event)).unwrap();
+        assert!(body.get("content").is_none());
+        assert_eq!(body["embeds"][0]["title"], "leave");
+        assert_eq!(body["embeds"][0]["description"], "Notice: 画眉鸟 掉线了");
+        assert!(body["embeds"][0]["timestamp"]
+            .as_str()
+            .unwrap()
+            .contains("T21:40:12"));
+        assert!(body["embeds"][0].get("color").is_none());
+
+        let style = crate::config::Style {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut event = event;
+        event.priority = crate::config::Priority::High;
+        let discord = discord.with_styles(Styles::new(&style).unwrap());
+        let body = serde_json::to_value(discord.body(&event)).unwrap();
+        assert_eq!(body["embeds"][0]["title"], "🚨 leave");
+        assert_eq!(body["embeds"][0]["color"], 0xe74c3c);
+        let discord = Discord::new(String::new(), "{message}".to_owned(), false, String::new())
+            .with_styles(Styles::new(&style).unwrap());
+        let body = serde_json::to_value(discord.body(&event)).unwrap();
+        assert_eq!(body["content"], "🚨 **leave**\n画眉鸟 掉线了");
+    }
+
+    #[test]
+    fn test_discord_screenshot() {
+        let (url, handle) = serve_once();
+        let discord = Discord::new(url, "{message}".to_owned(), true, String::new())
+            .with_screenshot("魔力宝贝".to_owned());
+        let mut event = Event::plain("画眉鸟 掉线了");
+        let png = super::super::screenshot::Png(Arc::new(b"PNG fake".to_vec()));
+        event.screenshots.insert("魔力宝贝".to_owned(), png);
+        assert!(discord.notify_event(&event).unwrap());
+        let request = handle.join().unwrap();
+        assert!(request.contains("multipart/form-data; boundary="));
+        assert!(request.contains("name=\"payload_json\""));
+        assert!(request.contains("\"url\":\"attachment://screenshot.png\""));
+        assert!(request.contains("name=\"files[0]\"; filename=\"screenshot.png\""));
+        assert!(request.contains("PNG fake"));
+    }
+
+    #[test]
+    fn test_feishu() {
+        let feishu = Feishu::new(
+            String::new(),
+            String::new(),
+            "Notice: {message}".to_owned(),
+            false,
+            String::new(),
+        );
+        let body = feishu.body(&Event::plain("队长掉线了"), 1700000000);
+        assert_eq!(body["msg_type"], "text");
+        assert_eq!(body["content"]["text"], "Notice: 队长掉线了");
+        assert!(body.get("sign").is_none());
+
+        let feishu = Feishu::new(
+            String::new(),
+            "secret".to_owned(),
+            "{message}".to_owned(),
+            true,
+            "red".to_owned(),
+        );
+        let mut trigger = crate::config::Trigger::new(r#"(\w+)离开了队伍。"#);
+        trigger.name = "leave".to_owned();
+        let event = testutil::matched("21:40:12丂画眉鸟离开了队伍。", &trigger, "画眉鸟 掉线了");
+        let body = feishu.body(&event, 1700000000);
+        assert_eq!(body["msg_type"], "interactive");
+        assert_eq!(body["card"]["header"]["title"]["content"], "leave");
+        assert_eq!(body["card"]["header"]["template"], "red");
+        assert_eq!(
+            body["card"]["elements"][0]["text"]["content"],
+            "画眉鸟 掉线了"
+        );
+        assert_eq!(body["timestamp"], "1700000000");
+        assert_eq!(body["sign"], "fiWS2+gh28DOydAv7hzONH/mDn9+b1Y4Y5ivXWXy8vA=");
+
+        let style = crate::config::Style {
+            enabled: true,
+            ..Default::default()
+        };
+        let feishu = feishu.with_styles(Styles::new(&style).unwrap());
+        let body = feishu.body(&event, 1700000000);
+        assert_eq!(body["card"]["header"]["title"]["content"], "🔔 leave");
+        assert_eq!(body["card"]["header"]["template"], "blue");
+        let mut event = event;
+        event.priority = crate::config::Priority::Low;
+        let body = feishu.body(&event, 1700000000);
+        assert_eq!(body["card"]["header"]["template"], "grey");
+    }
+
+    #[test]
+    fn test_http_signed() {
+        let (url, handle) = serve_once();
+        let signer = Signer::new("secret".to_owned(), String::new());
+        let http = Http::new(url, Payload::Text, "{message}".to_owned(), Some(signer));
+        assert!(http.notify("abc").unwrap());
+        let request = handle.join().unwrap();
+        let header = |name: &str| {
+            request
+                .lines()
+                .find_map(|l| l.strip_prefix(&format!("{}: ", name.to_lowercase())))
+                .unwrap()
+                .to_owned()
+        };
+        let timestamp: i64 = header(Signer::TIMESTAMP_HEADER).parse().unwrap();
+        let signer = Signer::new("secret".to_owned(), String::new());
+        assert_eq!(header("X-Cgaid-Signature"), signer.sign(timestamp, b"abc"));
+        assert_eq!(
+            signer.sign(1700000000, b"abc"),
+            "bac79868ba85cd66bf48432a71b8667a28af2222a6933283ee0df24af0c496d3"
+        );
+    }
 }
@@ -0,0 +1,153 @@
+use super::super::error::{Error, NotifierError};
+use super::super::event::Event;
+use super::super::template;
+use super::super::Notifiable;
+use super::style::Styles;
+use futures::StreamExt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_xmpp::jid::{BareJid, Jid};
+use tokio_xmpp::parsers::message::{Lang, Message};
+use tokio_xmpp::Client;
+
+/// Sends chat messages over XMPP (Jabber), logging in as its own account for each notification.
+pub struct Xmpp {
+    /// Account sending the messages, e.g. `cgaid@example.com`
+    jid: BareJid,
+    password: String,
+    /// Accounts receiving the messages
+    to: Vec<Jid>,
+    template: String,
+    styles: Option<Styles>,
+}
+
+impl Xmpp {
+    /// The client reconnects by itself, so a wrong password or an unreachable server only shows
+    /// as not coming online in time.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(
+        jid: &str,
+        password: String,
+        to: &[String],
+        template: String,
+    ) -> Result<Self, Error> {
+        let parse = |jid: &str| {
+            Jid::from_str(jid)
+                .map_err(|e| Error::Config(format!("Invalid XMPP address {jid}: {e}")))
+        };
+        let jid = parse(jid)?.to_bare();
+        let to = to.iter().map(|j| parse(j)).collect::<Result<Vec<_>, _>>()?;
+        if to.is_empty() {
+            return Err(Error::Config("No XMPP address to send to".to_owned()));
+        }
+        Ok(Self {
+            jid,
+            password,
+            to,
+            template,
+            styles: None,
+        })
+    }
+
+    /// Styles messages by the priority of their events.
+    pub fn with_styles(mut self, styles: Option<Styles>) -> Self {
+        self.styles = styles;
+        self
+    }
+
+    fn messages(&self, message: &str) -> Vec<Message> {
+        let body = template::render(&self.template, &template::message(message));
+        self.to
+            .iter()
+            .map(|to| Message::chat(to.clone()).with_body(Lang::default(), body.clone()))
+            .collect()
+    }
+
+    async fn send(&self, message: &str) -> Result<bool, Error> {
+        let mut client = Client::new(self.jid.clone(), self.password.clone());
+        let online = tokio::time::timeout(Xmpp::TIMEOUT, async {
+            while let Some(event) = client.next().await {
+                match event {
+                    tokio_xmpp::Event::Online { .. } => return Ok(()),
+                    tokio_xmpp::Event::Disconnected(e) => return Err(Error::from(e)),
+                    // stanzas queued for the account while offline
+                    tokio_xmpp::Event::Stanza(_) => {}
+                }
+            }
+            Err(NotifierError::Unreachable("XMPP stream closed".to_owned()).into())
+        })
+        .await;
+        match online {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                return Err(NotifierError::Unreachable(format!(
+                    "{} not online in {}s, check the server and password",
+                    self.jid,
+                    Xmpp::TIMEOUT.as_secs()
+                ))
+                .into())
+            }
+        }
+        for m in self.messages(message) {
+            client.send_stanza(m.into()).await?;
+        }
+        client.send_end().await?;
+        Ok(true)
+    }
+}
+
+impl Notifiable for Xmpp {
+    fn notify(&self, message: &str) -> Result<bool, Error> {
+        super::block_on(self.send(message))
+    }
+
+    fn notify_event(&self, event: &Event) -> Result<bool, Error> {
+        let text = event.with_hint();
+        match self.styles.as_ref().map(|s| s.look(event.priority)) {
+            Some(l) => self.notify(&l.xmpp(&event.trigger, &text)),
+            None => self.notify(&text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xmpp_messages() {
+        assert!(Xmpp::new(
+            "not a jid@",
+            String::new(),
+            &["a@b.c".to_owned()],
+            String::new()
+        )
+        .is_err());
+        assert!(Xmpp::new("a@b.c", String::new(), &[], String::new()).is_err());
+        let xmpp = Xmpp::new(
+            "cgaid@example.com/pc",
+            "secret".to_owned(),
+            &[
+                "me@example.com".to_owned(),
+                "phone@example.com/mobile".to_owned(),
+            ],
+            "cgaid: {message}".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(xmpp.jid.to_string(), "cgaid@example.com");
+        let messages = xmpp.messages("BOSS 出现了");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[1].to.as_ref().unwrap().to_string(),
+            "phone@example.com/mobile"
+        );
+        assert_eq!(
+            messages[0].bodies.get(""),
+            Some(&"cgaid: BOSS 出现了".to_owned())
+        );
+        let xml = String::from(&tokio_xmpp::minidom::Element::from(messages[0].clone()));
+        assert!(xml.contains("type='chat'"), "{xml}");
+    }
+}
@@ -0,0 +1,94 @@
+//! What the player is up to, for conditional notifiers choosing how to tell them: an overlay
+//! while playing, a toast while at the computer, the phone once away.
+
+use super::calendar::Calendars;
+use super::config::{Condition, Config};
+use super::system;
+use chrono::{Local, NaiveDateTime};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Presence {
+    /// Whether the game window is in the foreground
+    pub focused: bool,
+    /// Time since the last keyboard or mouse input, `None` when unknown
+    pub idle: Option<Duration>,
+    pub locked: bool,
+    pub at: NaiveDateTime,
+}
+
+impl Presence {
+    /// The state of the machine now.
+    pub fn now(cfg: &Config) -> Self {
+        let window = match cfg.presence.window.as_str() {
+            "" => cfg.title.filter.as_str(),
+            w => w,
+        };
+        Self {
+            focused: !window.is_empty()
+                && system::foreground_title().is_some_and(|t| t.contains(window)),
+            idle: system::idle_time(),
+            locked: system::is_locked(),
+            at: Local::now().naive_local(),
+        }
+    }
+
+    pub fn holds(&self, rule: &Condition, calendars: &Calendars) -> bool {
+        let away = self.locked || self.idle.is_some_and(|d| d.as_secs() >= rule.idle);
+        rule.focused.is_none_or(|f| f == self.focused)
+            && rule.locked.is_none_or(|l| l == self.locked)
+            && (rule.idle == 0 || away)
+            && (rule.calendar.is_empty() || calendars.is_on(&rule.calendar, self.at))
+    }
+
+    /// The notifiers of the first rule holding, none when no rule does.
+    pub fn choose<'a>(&self, rules: &'a [Condition], calendars: &Calendars) -> &'a [String] {
+        rules
+            .iter()
+            .find(|r| self.holds(r, calendars))
+            .map_or(&[], |r| &r.notifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(notifier: &str) -> Condition {
+        Condition {
+            notifier: vec![notifier.to_owned()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_presence_choose() {
+        let rules = vec![
+            Condition {
+                focused: Some(true),
+                ..rule("overlay")
+            },
+            Condition {
+                idle: 300,
+                ..rule("dingtalk")
+            },
+            rule("toast"),
+        ];
+        let calendars = Calendars::default();
+        let mut presence = Presence {
+            focused: true,
+            idle: Some(Duration::from_secs(1)),
+            locked: false,
+            at: Local::now().naive_local(),
+        };
+        assert_eq!(presence.choose(&rules, &calendars), ["overlay"]);
+        presence.focused = false;
+        assert_eq!(presence.choose(&rules, &calendars), ["toast"]);
+        presence.idle = Some(Duration::from_secs(600));
+        assert_eq!(presence.choose(&rules, &calendars), ["dingtalk"]);
+        presence.idle = None;
+        presence.locked = true;
+        assert_eq!(presence.choose(&rules, &calendars), ["dingtalk"]);
+        assert!(presence.choose(&rules[..1], &calendars).is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Prices items sold for in the auction log, kept across restarts in the trigger state.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// The sales of one item.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    /// Times sold
+    pub count: u64,
+    pub last: u64,
+    pub min: u64,
+    pub max: u64,
+    /// Sum of all the prices, for the average
+    pub total: u64,
+    /// Unix time of the last sale
+    pub at: i64,
+}
+
+impl Price {
+    pub fn average(&self) -> u64 {
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// One item in the list of [`Prices::list`].
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub item: String,
+    #[serde(flatten)]
+    pub price: Price,
+    pub average: u64,
+}
+
+/// Tracks the prices of sold items by name, fed by the parser of the auction log.
+#[derive(Default)]
+pub struct Prices {
+    items: Mutex<BTreeMap<String, Price>>,
+}
+
+impl Prices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the prices saved before a restart, the sales since then are kept.
+    pub fn restore(&self, saved: BTreeMap<String, Price>) {
+        let mut items = self.items.lock().unwrap();
+        for (item, price) in saved {
+            items.entry(item).or_insert(price);
+        }
+    }
+
+    /// Records a sale at unix time `now`.
+    pub fn sold(&self, item: &str, price: u64, now: i64) {
+        log::debug!("Sold {item} for {price}");
+        let mut items = self.items.lock().unwrap();
+        let p = items.entry(item.to_owned()).or_default();
+        p.min = if p.count == 0 {
+            price
+        } else {
+            p.min.min(price)
+        };
+        p.max = p.max.max(price);
+        p.count += 1;
+        p.last = price;
+        p.total = p.total.saturating_add(price);
+        p.at = now;
+    }
+
+    pub fn get(&self, item: &str) -> Option<Price> {
+        self.items.lock().unwrap().get(item).cloned()
+    }
+
+    /// All the prices, to save them.
+    pub fn prices(&self) -> BTreeMap<String, Price> {
+        self.items.lock().unwrap().clone()
+    }
+
+    pub fn list(&self) -> Vec<Status> {
+        self.items
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(item, p)| Status {
+                item: item.clone(),
+                price: p.clone(),
+                average: p.average(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prices() {
+        let prices = Prices::new();
+        prices.sold("玄铁", 300, 1000);
+        prices.sold("玄铁", 200, 2000);
+        prices.sold("玄铁", 400, 3000);
+        let p = prices.get("玄铁").unwrap();
+        assert_eq!(
+            (p.count, p.last, p.min, p.max, p.at),
+            (3, 400, 200, 400, 3000)
+        );
+        assert_eq!(p.average(), 300);
+        assert!(prices.get("秘银").is_none());
+
+        let restored = Prices::new();
+        restored.sold("秘银", 50, 4000);
+        restored.restore(prices.prices());
+        restored.restore(BTreeMap::from([("秘银".to_owned(), Price::default())]));
+        let list = restored.list();
+        assert_eq!(list.len(), 2);
+        // the sale since the restart is kept
+        assert_eq!(list[0].item, "玄铁");
+        assert_eq!(list[0].average, 300);
+        assert_eq!(list[1].price.last, 50);
+    }
+}
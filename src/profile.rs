@@ -0,0 +1,281 @@
+//! Self-instrumentation, showing where time and memory go when many triggers are configured.
+use super::dispatcher::QueueStatus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many of the slowest triggers a report lists.
+const SLOWEST: usize = 10;
+
+/// Records and tasks held in memory, waiting for something.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Buffered {
+    /// Lines kept for the reload preview
+    pub recent_lines: usize,
+    /// Their size in bytes
+    pub recent_bytes: usize,
+    /// Broadcasts waiting for the merge window of several clients
+    pub merging: usize,
+    /// Reminders and merge flushes scheduled
+    pub scheduled: usize,
+}
+
+#[derive(Debug, Default)]
+struct Timing {
+    /// Regex time of the batch being read
+    current: Duration,
+    total: Duration,
+    last_batch: Duration,
+    max_batch: Duration,
+    /// Matching calls and their time by trigger
+    triggers: HashMap<String, (u64, Duration)>,
+}
+
+/// Time spent matching one trigger.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriggerTime {
+    pub trigger: String,
+    pub calls: u64,
+    /// Total microseconds
+    pub micros: u64,
+}
+
+/// A snapshot of the instrumentation, served as `GET /status` and read by `cgaid status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    /// Seconds since start
+    pub uptime: u64,
+    pub lines: u64,
+    pub batches: u64,
+    /// Lines parsed per second since start
+    pub lines_per_sec: f64,
+    /// Microseconds of regex matching in total
+    pub regex_micros: u64,
+    pub last_batch_micros: u64,
+    pub max_batch_micros: u64,
+    /// Triggers taking the most matching time, slowest first
+    pub slowest: Vec<TriggerTime>,
+    pub buffered: Buffered,
+    pub queues: Vec<QueueStatus>,
+}
+
+/// Counters of the watch loop, cheap enough to stay on all the time.
+pub struct Profile {
+    start: Instant,
+    lines: AtomicU64,
+    batches: AtomicU64,
+    timing: Mutex<Timing>,
+    buffered: Mutex<Buffered>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            lines: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+            timing: Mutex::new(Timing::default()),
+            buffered: Mutex::new(Buffered::default()),
+        }
+    }
+
+    /// Adds the matching time of every trigger tried on one record.
+    pub fn add_record(&self, times: &[(&str, Duration)]) {
+        let mut timing = self.timing.lock().unwrap();
+        for (trigger, d) in times {
+            timing.current += *d;
+            let t = timing.triggers.entry((*trigger).to_owned()).or_default();
+            t.0 += 1;
+            t.1 += *d;
+        }
+    }
+
+    /// Ends a batch of lines read at once, the records matched since the last one count for it.
+    pub fn end_batch(&self, lines: usize) {
+        self.lines.fetch_add(lines as u64, Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        let mut timing = self.timing.lock().unwrap();
+        let current = std::mem::take(&mut timing.current);
+        timing.total += current;
+        timing.last_batch = current;
+        timing.max_batch = timing.max_batch.max(current);
+    }
+
+    pub fn set_buffered(&self, buffered: Buffered) {
+        *self.buffered.lock().unwrap() = buffered;
+    }
+
+    pub fn report(&self, queues: Vec<QueueStatus>) -> Report {
+        let uptime = self.start.elapsed();
+        let lines = self.lines.load(Ordering::Relaxed);
+        let timing = self.timing.lock().unwrap();
+        let mut slowest: Vec<_> = timing
+            .triggers
+            .iter()
+            .map(|(trigger, (calls, d))| TriggerTime {
+                trigger: trigger.clone(),
+                calls: *calls,
+                micros: d.as_micros() as u64,
+            })
+            .collect();
+        slowest.sort_by(|a, b| b.micros.cmp(&a.micros).then(a.trigger.cmp(&b.trigger)));
+        slowest.truncate(SLOWEST);
+        Report {
+            uptime: uptime.as_secs(),
+            lines,
+            batches: self.batches.load(Ordering::Relaxed),
+            lines_per_sec: lines as f64 / uptime.as_secs_f64().max(1.0),
+            regex_micros: timing.total.as_micros() as u64,
+            last_batch_micros: timing.last_batch.as_micros() as u64,
+            max_batch_micros: timing.max_batch.as_micros() as u64,
+            slowest,
+            buffered: self.buffered.lock().unwrap().clone(),
+            queues,
+        }
+    }
+}
+
+impl Report {
+    /// For reading in a terminal.
+    pub fn text(&self) -> String {
+        let b = &self.buffered;
+        let mut text = format!(
+            "运行: {}\n处理行数: {} ({:.2} 行/秒, {} 批)\n正则耗时: 共 {} 微秒, 上一批 {} 微秒, 最长一批 {} 微秒\n缓存: 最近记录 {} 行 {} 字节, 合并中 {}, 定时任务 {}",
+            super::stats::fmt_duration(Duration::from_secs(self.uptime)),
+            self.lines,
+            self.lines_per_sec,
+            self.batches,
+            self.regex_micros,
+            self.last_batch_micros,
+            self.max_batch_micros,
+            b.recent_lines,
+            b.recent_bytes,
+            b.merging,
+            b.scheduled
+        );
+        for t in &self.slowest {
+            text.push_str(&format!(
+                "\n耗时 {}: {} 微秒 / {} 次",
+                t.trigger, t.micros, t.calls
+            ));
+        }
+        for q in &self.queues {
+            text.push_str(&format!(
+                "\n队列 {}: 等待 {}, 发送中 {}, 丢弃 {}, 超时 {}",
+                q.notifier, q.pending, q.running, q.dropped, q.timeouts
+            ));
+        }
+        text
+    }
+
+    /// In the Prometheus text format, served as `GET /metrics`.
+    pub fn prometheus(&self) -> String {
+        let label = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut lines = vec![
+            format!("cgaid_uptime_seconds {}", self.uptime),
+            format!("cgaid_lines_total {}", self.lines),
+            format!("cgaid_batches_total {}", self.batches),
+            format!(
+                "cgaid_regex_seconds_total {}",
+                self.regex_micros as f64 / 1e6
+            ),
+            format!(
+                "cgaid_regex_last_batch_seconds {}",
+                self.last_batch_micros as f64 / 1e6
+            ),
+            format!("cgaid_recent_lines {}", self.buffered.recent_lines),
+            format!("cgaid_recent_bytes {}", self.buffered.recent_bytes),
+            format!("cgaid_merging {}", self.buffered.merging),
+            format!("cgaid_scheduled {}", self.buffered.scheduled),
+        ];
+        for t in &self.slowest {
+            lines.push(format!(
+                "cgaid_trigger_regex_seconds_total{{trigger=\"{}\"}} {}",
+                label(&t.trigger),
+                t.micros as f64 / 1e6
+            ));
+        }
+        for q in &self.queues {
+            let name = label(&q.notifier);
+            lines.push(format!(
+                "cgaid_queue_pending{{notifier=\"{name}\"}} {}",
+                q.pending
+            ));
+            lines.push(format!(
+                "cgaid_queue_running{{notifier=\"{name}\"}} {}",
+                q.running
+            ));
+            lines.push(format!(
+                "cgaid_queue_dropped_total{{notifier=\"{name}\"}} {}",
+                q.dropped
+            ));
+            lines.push(format!(
+                "cgaid_queue_timeouts_total{{notifier=\"{name}\"}} {}",
+                q.timeouts
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let profile = Profile::new();
+        profile.add_record(&[
+            ("maze", Duration::from_micros(30)),
+            ("boss", Duration::from_micros(10)),
+        ]);
+        profile.add_record(&[("maze", Duration::from_micros(20))]);
+        profile.end_batch(2);
+        profile.add_record(&[("boss", Duration::from_micros(5))]);
+        profile.end_batch(1);
+        profile.set_buffered(Buffered {
+            recent_lines: 3,
+            recent_bytes: 90,
+            merging: 1,
+            scheduled: 0,
+        });
+
+        let report = profile.report(vec![QueueStatus {
+            notifier: "dingtalk".to_owned(),
+            pending: 2,
+            running: 1,
+            dropped: 0,
+            timeouts: 1,
+        }]);
+        assert_eq!(report.lines, 3);
+        assert_eq!(report.batches, 2);
+        assert_eq!(report.regex_micros, 65);
+        assert_eq!(report.last_batch_micros, 5);
+        assert_eq!(report.max_batch_micros, 60);
+        assert_eq!(report.slowest[0].trigger, "maze");
+        assert_eq!(report.slowest[0].calls, 2);
+        assert_eq!(report.slowest[1].micros, 15);
+
+        let text = report.text();
+        assert!(text.contains("处理行数: 3"));
+        assert!(text.contains("耗时 maze: 50 微秒 / 2 次"));
+        assert!(text.contains("队列 dingtalk: 等待 2"));
+        let metrics = report.prometheus();
+        assert!(metrics.contains("cgaid_lines_total 3\n"));
+        assert!(metrics.contains("cgaid_trigger_regex_seconds_total{trigger=\"maze\"} 0.00005\n"));
+        assert!(metrics.contains("cgaid_queue_pending{notifier=\"dingtalk\"} 2\n"));
+        assert!(metrics.contains("cgaid_queue_timeouts_total{notifier=\"dingtalk\"} 1\n"));
+
+        let json = serde_json::to_string(&report).unwrap();
+        let back: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.buffered, report.buffered);
+    }
+}
@@ -0,0 +1,247 @@
+//! Replaces the running config when `config.toml` is edited, and checks an edit against recent
+//! chat before it is saved.
+use super::config::Config;
+use super::error::Error;
+use super::simulate::{self, Changes};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// The running config, replaced as a whole so a reader never sees half of an edit.
+pub struct Current {
+    cfg: RwLock<Arc<Config>>,
+}
+
+impl Current {
+    pub fn new(cfg: Arc<Config>) -> Self {
+        Self {
+            cfg: RwLock::new(cfg),
+        }
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.cfg.read().unwrap())
+    }
+
+    pub fn set(&self, cfg: Arc<Config>) {
+        *self.cfg.write().unwrap() = cfg;
+    }
+}
+
+/// Chat log lines read in the last minutes.
+pub struct Recent {
+    keep: Duration,
+    lines: Mutex<VecDeque<(Instant, String)>>,
+}
+
+impl Recent {
+    /// Upper bound of kept lines, whatever the duration.
+    const MAX_LINES: usize = 10000;
+
+    pub fn new(keep: Duration) -> Self {
+        Self {
+            keep,
+            lines: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, lines: &[String], now: Instant) {
+        if self.keep.is_zero() {
+            return;
+        }
+        let mut kept = self.lines.lock().unwrap();
+        kept.extend(lines.iter().map(|l| (now, l.clone())));
+        while kept
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > self.keep)
+            || kept.len() > Recent::MAX_LINES
+        {
+            kept.pop_front();
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, l)| l.clone())
+            .collect()
+    }
+
+    /// How many lines are kept and their size in bytes.
+    pub fn usage(&self) -> (usize, usize) {
+        let kept = self.lines.lock().unwrap();
+        (kept.len(), kept.iter().map(|(_, l)| l.len()).sum())
+    }
+}
+
+/// Sections read once at start, an edit of which takes effect after a restart. The others are
+/// read from the running config as they are used.
+const RESTART: [&str; 17] = [
+    "game",
+    "trade",
+    "proxy",
+    "api",
+    "hotkey",
+    "outbox",
+    "history",
+    "deliveries",
+    "state",
+    "crash",
+    "title",
+    "reload",
+    "dispatch.workers",
+    "notifier.telegram.buttons",
+    "notifier.telegram.token",
+    "notifier.telegram.chat_id",
+    "notifier.tray",
+];
+
+/// What replacing the running config did.
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// Edited sections that need a restart, see [`RESTART`]
+    pub restart: Vec<&'static str>,
+}
+
+/// Compares the config on disk with the running one, and replaces it.
+#[derive(Clone)]
+pub struct Reload {
+    current: Arc<Current>,
+    path: PathBuf,
+    recent: Arc<Recent>,
+    /// Text of the running config, as an editor saving once may change the file several times
+    applied: Arc<Mutex<String>>,
+}
+
+impl Reload {
+    pub fn new(current: Arc<Current>, path: PathBuf, recent: Arc<Recent>) -> Self {
+        let applied = fs::read_to_string(&path).unwrap_or_default();
+        Self {
+            current,
+            path,
+            recent,
+            applied: Arc::new(Mutex::new(applied)),
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Matches of the recent chat that the config on disk would add or remove.
+    pub fn preview(&self) -> Result<Changes, Error> {
+        let new = Config::load(&self.path)?;
+        Ok(simulate::compare(
+            &self.recent.lines(),
+            &self.current.get().triggers(),
+            &new.triggers(),
+        ))
+    }
+
+    /// Replaces the running config by the one on disk if that changed, `None` if it did not.
+    /// An invalid one, e.g. half written or naming a notifier that is not set up, leaves the
+    /// running one in place.
+    pub fn apply(&self) -> Result<Option<Applied>, Error> {
+        let text = fs::read_to_string(&self.path)?;
+        let mut applied = self.applied.lock().unwrap();
+        if *applied == text {
+            return Ok(None);
+        }
+        let cfg = Config::parse(&text)?;
+        let names = cfg.notifier_names();
+        if let Some(name) = names.iter().find(|n| !cfg.notifier.defines(n)) {
+            return Err(Error::Config(format!("Not found notifier {name}")));
+        }
+        let restart = changed(&applied, &text);
+        self.current.set(Arc::new(cfg));
+        *applied = text;
+        Ok(Some(Applied { restart }))
+    }
+}
+
+/// The sections of [`RESTART`] differing between two config texts.
+fn changed(old: &str, new: &str) -> Vec<&'static str> {
+    let parse = |text: &str| text.parse::<toml::Table>().unwrap_or_default();
+    let (old, new) = (parse(old), parse(new));
+    let get = |table: &toml::Table, path: &str| {
+        let mut keys = path.split('.');
+        let first = table.get(keys.next()?);
+        keys.try_fold(first?, |v, k| v.get(k)).cloned()
+    };
+    RESTART
+        .into_iter()
+        .filter(|path| get(&old, path) != get(&new, path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent() {
+        let recent = Recent::new(Duration::from_secs(60));
+        let now = Instant::now();
+        recent.push(&["a".to_owned(), "b".to_owned()], now);
+        recent.push(&["c".to_owned()], now + Duration::from_secs(30));
+        assert_eq!(recent.lines(), vec!["a", "b", "c"]);
+        recent.push(&["d".to_owned()], now + Duration::from_secs(61));
+        assert_eq!(recent.lines(), vec!["c", "d"]);
+
+        let recent = Recent::new(Duration::ZERO);
+        recent.push(&["a".to_owned()], now);
+        assert!(recent.lines().is_empty());
+    }
+
+    #[test]
+    fn test_reload_preview() {
+        let cfg = Arc::new(Config::load("config.toml").unwrap());
+        let recent = Arc::new(Recent::new(Duration::from_secs(60)));
+        recent.push(&["12:00:02丂画眉鸟离开了队伍。".to_owned()], Instant::now());
+        let current = Arc::new(Current::new(cfg));
+        let reload = Reload::new(current, PathBuf::from("config.toml"), recent);
+        let changes = reload.preview().unwrap();
+        assert_eq!(changes.lines, 1);
+        assert!(changes.added.is_empty() && changes.removed.is_empty());
+    }
+
+    #[test]
+    fn test_reload_apply() {
+        let path = std::env::temp_dir().join("cgaid_reload_apply.toml");
+        let base = fs::read_to_string("config.toml").unwrap();
+        let trigger = |regex: &str| {
+            format!(
+                "{base}\n[[trigger]]\nregex = \"{regex}\"\nformat = \"{{0}}\"\nchannel = \"*\"\n"
+            )
+        };
+        fs::write(&path, trigger("a")).unwrap();
+        let current = Arc::new(Current::new(Arc::new(Config::load(&path).unwrap())));
+        let reload = Reload::new(
+            Arc::clone(&current),
+            path.clone(),
+            Arc::new(Recent::new(Duration::ZERO)),
+        );
+        assert!(reload.apply().unwrap().is_none());
+        fs::write(&path, trigger("b")).unwrap();
+        assert!(reload.apply().unwrap().unwrap().restart.is_empty());
+        assert_eq!(current.get().trigger.last().unwrap().regex, "b");
+        // unknown notifiers are refused
+        let unknown = trigger("c") + "notifier = [\"nothing\"]\n";
+        fs::write(&path, unknown).unwrap();
+        assert_eq!(reload.apply().unwrap_err().kind(), "config");
+        assert_eq!(current.get().trigger.last().unwrap().regex, "b");
+        // applied, though read again only after a restart
+        let api = trigger("b").replacen("127.0.0.1:7878", "127.0.0.1:7879", 1);
+        fs::write(&path, api).unwrap();
+        assert_eq!(reload.apply().unwrap().unwrap().restart, ["api"]);
+        // a broken edit keeps the running config
+        fs::write(&path, trigger("(")).unwrap();
+        assert!(reload.apply().is_err());
+        assert_eq!(current.get().trigger.last().unwrap().regex, "b");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::{Duration as CD, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A hashed timer wheel with one-tick granularity, entries further away than
+/// one revolution wait for the required number of rounds.
+pub struct TimerWheel<T> {
+    slots: Vec<Vec<(u64, T)>>,
+    cursor: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: (0..size.max(1)).map(|_| Vec::new()).collect(),
+            cursor: 0,
+        }
+    }
+
+    /// Schedules `item` to be returned after `ticks` ticks, at least one.
+    pub fn insert(&mut self, ticks: u64, item: T) {
+        let size = self.slots.len() as u64;
+        let ticks = ticks.max(1);
+        let slot = (self.cursor as u64 + ticks) % size;
+        let rounds = (ticks - 1) / size;
+        self.slots[slot as usize].push((rounds, item));
+    }
+
+    /// Advances one tick and returns the due entries.
+    pub fn tick(&mut self) -> Vec<T> {
+        self.cursor = (self.cursor + 1) % self.slots.len();
+        let slot = std::mem::take(&mut self.slots[self.cursor]);
+        let mut due = Vec::new();
+        for (rounds, item) in slot {
+            if rounds == 0 {
+                due.push(item);
+            } else {
+                self.slots[self.cursor].push((rounds - 1, item));
+            }
+        }
+        due
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.iter().map(|s| s.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|s| s.is_empty())
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Runs tasks after a delay, driven by a timer wheel ticking every second.
+pub struct Scheduler {
+    wheel: Arc<Mutex<TimerWheel<Task>>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    const TICK: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        let wheel: Arc<Mutex<TimerWheel<Task>>> = Arc::new(Mutex::new(TimerWheel::new(3600)));
+        let wc = Arc::clone(&wheel);
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut ticks = 0_u32;
+            loop {
+                ticks += 1;
+                let next = start + Scheduler::TICK * ticks;
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+                let due = wc.lock().unwrap().tick();
+                for task in due {
+                    // a panicking task would stop every task scheduled after it
+                    if let Err(e) = panic::catch_unwind(AssertUnwindSafe(task)) {
+                        log::error!("Scheduled task panicked: {}", Scheduler::message(&*e));
+                    }
+                }
+            }
+        });
+        Self { wheel }
+    }
+
+    fn message(payload: &(dyn Any + Send)) -> &str {
+        payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown")
+    }
+
+    pub fn schedule<F>(&self, delay: Duration, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let ticks = delay.as_secs().max(1);
+        self.wheel.lock().unwrap().insert(ticks, Box::new(task));
+    }
+
+    /// Tasks waiting to run.
+    pub fn len(&self) -> usize {
+        self.wheel.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn duration_pattern() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:(\d+)天)?(?:(\d+)(?:小时|时))?(?:(\d+)(?:分钟|分))?(?:(\d+)秒)?$").unwrap()
+    })
+}
+
+/// Computes the deadline from a captured duration (`1小时1分26秒`, `30分钟`)
+/// or end time (`23:30`, `23:30:00`, `23点30分`), relative to `now`.
+pub fn parse_deadline(text: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Some(caps) = duration_pattern().captures(text) {
+        let mut seconds = 0;
+        let mut found = false;
+        for (i, unit) in [(1, 86400), (2, 3600), (3, 60), (4, 1)] {
+            if let Some(m) = caps.get(i) {
+                seconds += m.as_str().parse::<i64>().ok()? * unit;
+                found = true;
+            }
+        }
+        return if found {
+            Some(now + CD::seconds(seconds))
+        } else {
+            None
+        };
+    }
+    let time = ["%H:%M:%S", "%H:%M", "%H点%M分"]
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(text, f).ok())?;
+    let mut deadline = now.date().and_time(time);
+    if deadline <= now {
+        deadline += CD::days(1);
+    }
+    Some(deadline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(22, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_deadline_duration() {
+        let d = parse_deadline("1小时1分26秒", now()).unwrap();
+        assert_eq!(d - now(), CD::seconds(3686));
+        let d = parse_deadline("30分钟", now()).unwrap();
+        assert_eq!(d - now(), CD::minutes(30));
+        let d = parse_deadline("2天", now()).unwrap();
+        assert_eq!(d - now(), CD::days(2));
+        assert!(parse_deadline("", now()).is_none());
+        assert!(parse_deadline("挑战赛通道", now()).is_none());
+    }
+
+    #[test]
+    fn test_parse_deadline_time() {
+        let d = parse_deadline("23:30", now()).unwrap();
+        assert_eq!(d - now(), CD::minutes(90));
+        let d = parse_deadline("21:00:00", now()).unwrap();
+        assert_eq!(d - now(), CD::hours(23));
+        let d = parse_deadline("23点30分", now()).unwrap();
+        assert_eq!(d - now(), CD::minutes(90));
+    }
+
+    #[test]
+    fn test_timer_wheel() {
+        let mut wheel = TimerWheel::new(4);
+        wheel.insert(1, "a");
+        wheel.insert(3, "b");
+        wheel.insert(9, "c");
+        assert_eq!(wheel.len(), 3);
+        assert_eq!(wheel.tick(), vec!["a"]);
+        assert!(wheel.tick().is_empty());
+        assert_eq!(wheel.tick(), vec!["b"]);
+        for _ in 3..8 {
+            assert!(wheel.tick().is_empty());
+        }
+        assert_eq!(wheel.tick(), vec!["c"]);
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let scheduler = Scheduler::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        scheduler.schedule(Duration::from_secs(1), || panic!("broken reminder"));
+        scheduler.schedule(Duration::from_secs(1), move || tx.send(1).unwrap());
+        // still running after the task before panicked
+        assert_eq!(rx.recv_timeout(Duration::from_secs(3)).unwrap(), 1);
+    }
+}
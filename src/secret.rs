@@ -0,0 +1,144 @@
+//! Secrets kept out of the config file: `${NAME}` is replaced by the environment variable and a
+//! value of `keyring:TARGET` by the password of the credential saved under that name, in the
+//! fields holding secrets only.
+
+use super::error::Error;
+use super::system;
+
+const KEYRING: &str = "keyring:";
+
+/// Fields holding secrets, the webhook and API addresses included as they often carry tokens.
+/// Every value of a `headers` table is one.
+const FIELDS: [&str; 10] = [
+    "token", "secret", "password", "webhook", "url", "key", "user", "username", "sid", "headers",
+];
+
+/// The value with its secrets filled in.
+pub fn resolve(value: &str) -> Result<String, Error> {
+    if let Some(target) = value.strip_prefix(KEYRING) {
+        return system::credential(target)
+            .ok_or_else(|| Error::Config(format!("Credential {target} not found in the keyring")));
+    }
+    let mut text = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let name = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|n| is_name(n));
+        match name {
+            Some(name) => {
+                let var = std::env::var(name)
+                    .map_err(|_| Error::Config(format!("Environment variable {name} not set")))?;
+                text.push_str(&var);
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                text.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+/// Resolves the secret fields in `value`, in nested tables and arrays of tables too. Templates
+/// and other text keep a `${...}` of theirs as it is.
+pub fn resolve_all(value: &mut toml::Value) -> Result<(), Error> {
+    match value {
+        toml::Value::Array(list) => {
+            for v in list {
+                resolve_all(v)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (k, v) in table.iter_mut() {
+                if FIELDS.contains(&k.as_str()) {
+                    resolve_strings(v)?;
+                } else {
+                    resolve_all(v)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Resolves every string in the value of a secret field.
+fn resolve_strings(value: &mut toml::Value) -> Result<(), Error> {
+    match value {
+        toml::Value::String(s) => *s = resolve(s)?,
+        toml::Value::Array(list) => {
+            for v in list {
+                resolve_strings(v)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, v) in table.iter_mut() {
+                resolve_strings(v)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        std::env::set_var("CGAID_TEST_TOKEN", "abc123");
+        assert_eq!(
+            resolve("https://oapi.dingtalk.com/robot/send?access_token=${CGAID_TEST_TOKEN}")
+                .unwrap(),
+            "https://oapi.dingtalk.com/robot/send?access_token=abc123"
+        );
+        assert_eq!(
+            resolve("${CGAID_TEST_TOKEN}${CGAID_TEST_TOKEN}").unwrap(),
+            "abc123abc123"
+        );
+        assert_eq!(resolve("cost ${1} or ${x").unwrap(), "cost ${1} or ${x");
+        assert_eq!(resolve("plain").unwrap(), "plain");
+        assert_eq!(
+            resolve("${CGAID_TEST_MISSING}").unwrap_err().kind(),
+            "config"
+        );
+        assert!(resolve("keyring:cgaid-test-missing").is_err());
+
+        let mut value: toml::Value = toml::from_str(
+            "template = \"${CGAID_TEST_MISSING}\"\n\
+             [dingtalk]\n\
+             webhook = \"${CGAID_TEST_TOKEN}\"\n\
+             template = \"cost ${CGAID_TEST_TOKEN}\"\n\
+             [[instance]]\n\
+             headers = { Authorization = \"Bearer ${CGAID_TEST_TOKEN}\" }",
+        )
+        .unwrap();
+        resolve_all(&mut value).unwrap();
+        assert_eq!(value["dingtalk"]["webhook"].as_str(), Some("abc123"));
+        assert_eq!(
+            value["instance"][0]["headers"]["Authorization"].as_str(),
+            Some("Bearer abc123")
+        );
+        // other text is left alone, even when it names no variable set
+        assert_eq!(
+            value["dingtalk"]["template"].as_str(),
+            Some("cost ${CGAID_TEST_TOKEN}")
+        );
+        assert_eq!(value["template"].as_str(), Some("${CGAID_TEST_MISSING}"));
+    }
+}
@@ -0,0 +1,210 @@
+//! `cgaid setup`: asks for the few settings most first runs get wrong and writes `config.toml`.
+use super::config::Config;
+use super::error::Error;
+use super::notifier::webhook::DingTalk;
+use super::notifier::Ringtone;
+use super::Notifiable;
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Sets `key` of `[section]` in a TOML text to a string, keeping comments and everything else.
+/// `None` if the section has no such key.
+pub fn set(text: &str, section: &str, key: &str, value: &str) -> Option<String> {
+    let header = format!("[{section}]");
+    let mut in_section = false;
+    let mut done = false;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_section = trimmed == header;
+        } else if in_section && !done {
+            if let Some((k, _)) = trimmed.split_once('=') {
+                if k.trim() == key && !trimmed.starts_with('#') {
+                    lines.push(format!("{key} = {}", toml::Value::from(value)));
+                    done = true;
+                    continue;
+                }
+            }
+        }
+        lines.push(line.to_owned());
+    }
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    done.then(|| lines.join(eol) + eol)
+}
+
+/// Whether the dir looks like a game client, which writes its chat to `Log`.
+pub fn is_game_dir(path: &Path) -> bool {
+    path.join("Log").is_dir()
+}
+
+/// Game dirs among `roots` and their direct sub dirs.
+pub fn candidates(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for root in roots {
+        let children = std::fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir());
+        for p in std::iter::once(root.clone()).chain(children) {
+            if is_game_dir(&p) && !found.contains(&p) {
+                found.push(p);
+            }
+        }
+    }
+    found
+}
+
+/// Where the game is usually installed.
+pub fn default_roots() -> Vec<PathBuf> {
+    let mut roots: Vec<_> = std::env::current_dir().into_iter().collect();
+    if cfg!(windows) {
+        if let Ok(home) = std::env::var("USERPROFILE") {
+            roots.push(Path::new(&home).join("Documents\\Game\\CrossGate"));
+        }
+        for drive in ['C', 'D', 'E', 'F'] {
+            for dir in ["CrossGate", "Game\\CrossGate", "Games\\CrossGate"] {
+                roots.push(PathBuf::from(format!("{drive}:\\{dir}")));
+            }
+        }
+    }
+    roots
+}
+
+/// Names of the audio output devices.
+pub fn output_devices() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|list| list.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Asks through `input` and `output`, test-firing the chosen ringtone and webhook on request,
+/// and returns `template` with the answers set.
+pub fn run<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    template: &str,
+    candidates: &[PathBuf],
+    devices: &[String],
+) -> Result<String, Error> {
+    let mut ask = |question: &str| -> Result<String, Error> {
+        write!(output, "{question}")?;
+        output.flush()?;
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        Ok(line.trim().to_owned())
+    };
+    let update = |text: &str, section: &str, key: &str, value: &str| {
+        set(text, section, key, value)
+            .ok_or_else(|| Error::Config(format!("No {key} in [{section}]")))
+    };
+
+    let mut question = String::from("游戏目录");
+    for (i, c) in candidates.iter().enumerate() {
+        question.push_str(&format!("\n  {}. {}", i + 1, c.display()));
+    }
+    if !candidates.is_empty() {
+        question.push_str("\n输入序号或目录, 直接回车使用 1");
+    }
+    let answer = ask(&format!("{question}: "))?;
+    let path = match answer.parse::<usize>() {
+        Ok(i) if (1..=candidates.len()).contains(&i) => candidates[i - 1].clone(),
+        _ if answer.is_empty() && !candidates.is_empty() => candidates[0].clone(),
+        _ => PathBuf::from(&answer),
+    };
+    if !is_game_dir(&path) {
+        ask(&format!(
+            "{} 下没有 Log 目录, 游戏开始聊天后才会创建, 回车继续",
+            path.display()
+        ))?;
+    }
+    let mut text = update(template, "game", "path", &path.to_string_lossy())?;
+
+    let mut device = String::new();
+    if !devices.is_empty() {
+        let mut question = String::from("播放设备\n  0. 默认设备");
+        for (i, d) in devices.iter().enumerate() {
+            question.push_str(&format!("\n  {}. {d}", i + 1));
+        }
+        if let Ok(i @ 1..) =
+            ask(&format!("{question}\n输入序号, 直接回车使用默认设备: "))?.parse::<usize>()
+        {
+            if let Some(d) = devices.get(i - 1) {
+                device = d.clone();
+            }
+        }
+    }
+    text = update(&text, "notifier.ringtone", "device", &device)?;
+    if ask("试听铃声? (y/N): ")?.eq_ignore_ascii_case("y") {
+        let audio = Config::parse(&text)?.notifier.ringtone.audio;
+        match Ringtone::new(audio, device).and_then(|r| r.notify("测试铃声")) {
+            Ok(_) => ask("听到铃声了吗? 回车继续")?,
+            Err(e) => ask(&format!("播放失败: {e}, 回车继续"))?,
+        };
+    }
+
+    let webhook = ask("钉钉机器人 webhook, 直接回车跳过: ")?;
+    if !webhook.is_empty() {
+        text = update(&text, "notifier.dingtalk", "webhook", &webhook)?;
+        let secret = ask("加签密钥, 没有使用加签直接回车: ")?;
+        if !secret.is_empty() {
+            text = update(&text, "notifier.dingtalk", "secret", &secret)?;
+        }
+        if ask("发送测试消息? (y/N): ")?.eq_ignore_ascii_case("y") {
+            let template = Config::parse(&text)?.notifier.dingtalk.template;
+            let dingtalk =
+                DingTalk::new(webhook, template, 0, false, Vec::new()).with_secret(secret);
+            match dingtalk.notify("cgaid 测试消息") {
+                Ok(true) => ask("已发送, 回车继续")?,
+                Ok(false) => ask("被拒绝, 请检查机器人的关键词或安全设置, 回车继续")?,
+                Err(e) => ask(&format!("发送失败: {e}, 回车继续"))?,
+            };
+        }
+    }
+
+    Config::parse(&text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEMPLATE: &str = "[game]\n# 游戏根目录\npath = \"C:\\\\Game\"\n\n[notifier.ringtone]\naudio = \"\"\ndevice = \"\"\n";
+
+    #[test]
+    fn test_set() {
+        let text = set(TEMPLATE, "notifier.ringtone", "device", "扬声器").unwrap();
+        assert!(text.contains("device = \"扬声器\""));
+        assert!(text.contains("# 游戏根目录"));
+        let text = set(&text, "game", "path", "D:\\CrossGate").unwrap();
+        let value: toml::Value = toml::from_str(&text).unwrap();
+        assert_eq!(value["game"]["path"].as_str(), Some("D:\\CrossGate"));
+        assert!(set(TEMPLATE, "game", "device", "").is_none());
+    }
+
+    #[test]
+    fn test_setup_run() {
+        let game = std::env::temp_dir().join("cgaid_setup");
+        std::fs::create_dir_all(game.join("HuaiJiu").join("Log")).unwrap();
+        let found = candidates(std::slice::from_ref(&game));
+        assert_eq!(found, vec![game.join("HuaiJiu")]);
+
+        let template = std::fs::read_to_string("config.toml").unwrap();
+        let devices = vec!["扬声器".to_owned(), "耳机".to_owned()];
+        let mut input = Cursor::new("\n2\nn\nhttps://example.com/hook\nSECabc\nn\n");
+        let mut output = Vec::new();
+        let text = run(&mut input, &mut output, &template, &found, &devices).unwrap();
+        let cfg = Config::parse(&text).unwrap();
+        assert_eq!(cfg.game.path, game.join("HuaiJiu").to_string_lossy());
+        assert_eq!(cfg.notifier.ringtone.device, "耳机");
+        assert_eq!(cfg.notifier.dingtalk.webhook, "https://example.com/hook");
+        assert_eq!(cfg.notifier.dingtalk.secret, "SECabc");
+        assert!(String::from_utf8(output).unwrap().contains("1. "));
+    }
+}
@@ -0,0 +1,178 @@
+//! Runs a chat log through a trigger pack, for trigger packs shipped with expected matches.
+use super::config::{Pattern, Trigger};
+use super::error::Error;
+use super::event::Event;
+use super::stream::RecordStream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A trigger pack, any TOML with `[[trigger]]` tables such as `config.toml`.
+#[derive(Debug, Deserialize)]
+pub struct Pack {
+    #[serde(default)]
+    pub fragment: HashMap<String, Pattern>,
+    pub trigger: Vec<Trigger>,
+}
+
+/// One match of the simulation, as stored in the expected file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Match {
+    /// Trigger name, or its regex when unnamed
+    pub trigger: String,
+    pub time: String,
+    pub message: String,
+    pub captures: Vec<String>,
+}
+
+impl Match {
+    fn from(event: Event, trigger: &Trigger) -> Self {
+        Self {
+            trigger: if trigger.name.is_empty() {
+                trigger.regex.clone()
+            } else {
+                trigger.name.clone()
+            },
+            time: event.time,
+            message: event.message,
+            captures: event.captures,
+        }
+    }
+}
+
+/// Matches of the log, which is read as UTF-8 when valid and as GB18030 like the game writes otherwise.
+pub fn run(pack: &str, log: &[u8]) -> Result<Vec<Match>, Error> {
+    let mut pack: Pack = toml::from_str(pack)?;
+    Trigger::expand_all(&mut pack.trigger, &pack.fragment)?;
+    let text = match std::str::from_utf8(log) {
+        Ok(text) => text.into(),
+        Err(_) => encoding_rs::GB18030.decode(log).0,
+    };
+    let lines: Vec<_> = text.lines().collect();
+    Ok(replay(&lines, &pack.trigger))
+}
+
+/// Matches of already decoded chat log lines, ordered by time.
+pub fn replay<S: AsRef<str>>(lines: &[S], triggers: &[Trigger]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    // one trigger at a time to know which one matched
+    for trigger in triggers {
+        let events =
+            RecordStream::new(lines.iter().map(|l| l.as_ref())).matching(vec![trigger.clone()]);
+        matches.extend(events.map(|e| Match::from(e, trigger)));
+    }
+    // stable, so matches of the same line keep the trigger order
+    matches.sort_by(|a, b| a.time.cmp(&b.time));
+    matches
+}
+
+/// How the matches of the same lines change between two trigger sets.
+#[derive(Debug, Serialize, Default)]
+pub struct Changes {
+    /// Number of lines replayed
+    pub lines: usize,
+    /// Matches only the new triggers produce
+    pub added: Vec<Match>,
+    /// Matches only the old triggers produce
+    pub removed: Vec<Match>,
+}
+
+pub fn compare<S: AsRef<str>>(lines: &[S], old: &[Trigger], new: &[Trigger]) -> Changes {
+    let before = replay(lines, old);
+    let after = replay(lines, new);
+    Changes {
+        lines: lines.len(),
+        added: missing(&after, &before).into_iter().cloned().collect(),
+        removed: missing(&before, &after).into_iter().cloned().collect(),
+    }
+}
+
+/// Matches of `from` left over once each match of `of` took out one equal to it, so a line
+/// matching twice where it matched once before still shows.
+fn missing<'a>(from: &'a [Match], of: &[Match]) -> Vec<&'a Match> {
+    let mut left: Vec<_> = from.iter().map(Some).collect();
+    for m in of {
+        if let Some(slot) = left.iter_mut().find(|l| l.is_some_and(|l| l == m)) {
+            *slot = None;
+        }
+    }
+    left.into_iter().flatten().collect()
+}
+
+/// Differences between the expected and actual matches, empty when they agree.
+pub fn diff(expected: &[Match], actual: &[Match]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for e in missing(expected, actual) {
+        lines.push(format!(
+            "- {}",
+            serde_json::to_string(e).unwrap_or_default()
+        ));
+    }
+    for a in missing(actual, expected) {
+        lines.push(format!(
+            "+ {}",
+            serde_json::to_string(a).unwrap_or_default()
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACK: &str = r#"
+        [[trigger]]
+        name = "leave"
+        regex = "(\\w+)离开了队伍。"
+        format = "{time}. {1} 掉线了"
+        channel = "common"
+        notifier = []
+
+        [[trigger]]
+        regex = "玄铁"
+        format = "{0}"
+        channel = "world"
+        notifier = []
+    "#;
+
+    const LOG: &str = "12:00:01丂[世界]盛明兰oO: 收玄铁\n12:00:02丂画眉鸟离开了队伍。\n";
+
+    #[test]
+    fn test_simulate() {
+        let utf8 = run(PACK, LOG.as_bytes()).unwrap();
+        let gb = run(PACK, &encoding_rs::GB18030.encode(LOG).0).unwrap();
+        assert_eq!(utf8, gb);
+        assert_eq!(utf8.len(), 2);
+        assert_eq!(utf8[0].trigger, "玄铁");
+        assert_eq!(utf8[1].trigger, "leave");
+        assert_eq!(utf8[1].message, "12:00:02. 画眉鸟 掉线了");
+
+        let expected: Vec<Match> =
+            serde_json::from_str(&serde_json::to_string(&utf8).unwrap()).unwrap();
+        assert!(diff(&expected, &utf8).is_empty());
+        let lines = diff(&expected[1..], &utf8[..1]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("- ") && lines[0].contains("leave"));
+        assert!(lines[1].starts_with("+ ") && lines[1].contains("玄铁"));
+        // a match twice is not the same as once
+        let twice = [utf8.clone(), utf8[..1].to_vec()].concat();
+        let lines = diff(&expected, &twice);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("+ ") && lines[0].contains("玄铁"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = toml::from_str::<Pack>(PACK).unwrap().trigger;
+        let mut new = old.clone();
+        new[0].format = "{1} 掉线了".into();
+        new.remove(1);
+        let lines: Vec<_> = LOG.lines().collect();
+        let changes = compare(&lines, &old, &new);
+        assert_eq!(changes.lines, 2);
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].message, "画眉鸟 掉线了");
+        assert_eq!(changes.removed.len(), 2);
+        assert!(compare(&lines, &old, &old).added.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Trigger state kept across restarts, so one-shot triggers and cooldowns hold after them.
+use super::config::Trigger;
+use super::error::Error;
+use super::event::Event;
+use super::price::Price;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What is known of one trigger.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Times fired
+    pub count: u64,
+    /// Unix time of the last firing
+    pub last: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Saved {
+    /// By trigger label
+    pub triggers: BTreeMap<String, Entry>,
+    /// Variables stored from captures, read by the triggers after
+    pub vars: BTreeMap<String, String>,
+    /// Unix time a trigger of each group last fired
+    pub groups: BTreeMap<String, i64>,
+    /// Follow-ups of triggers before their deadlines, not sent yet
+    pub reminders: Vec<Reminder>,
+    /// Prices of the items sold in the auction log
+    pub prices: BTreeMap<String, Price>,
+}
+
+/// A follow-up notification a trigger scheduled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reminder {
+    /// Unix time to send at
+    pub at: i64,
+    pub notifier: Vec<String>,
+    pub event: Event,
+    /// The message in other languages, which the event does not save
+    #[serde(default)]
+    pub localized: BTreeMap<String, String>,
+}
+
+/// Counts and last firing of triggers, saved to a JSON file when one is set. Changes are only
+/// written with [`TriggerState::flush`], not on every match.
+pub struct TriggerState {
+    path: Option<PathBuf>,
+    /// With whether it changed since written
+    saved: Mutex<(Saved, bool)>,
+}
+
+impl TriggerState {
+    /// Loads the saved state, starting empty when there is none.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, Error> {
+        let saved = match &path {
+            Some(p) if p.exists() => serde_json::from_str(&std::fs::read_to_string(p)?)?,
+            _ => Saved::default(),
+        };
+        Ok(Self {
+            path,
+            saved: Mutex::new((saved, false)),
+        })
+    }
+
+    pub fn vars(&self) -> BTreeMap<String, String> {
+        self.saved.lock().unwrap().0.vars.clone()
+    }
+
+    pub fn groups(&self) -> BTreeMap<String, i64> {
+        self.saved.lock().unwrap().0.groups.clone()
+    }
+
+    pub fn prices(&self) -> BTreeMap<String, Price> {
+        self.saved.lock().unwrap().0.prices.clone()
+    }
+
+    /// Takes the prices of sold items, written with the next flush if they changed.
+    pub fn save_prices(&self, prices: BTreeMap<String, Price>) {
+        let mut saved = self.saved.lock().unwrap();
+        if saved.0.prices != prices {
+            saved.0.prices = prices;
+            saved.1 = true;
+        }
+    }
+
+    /// The reminders not sent yet.
+    pub fn reminders(&self) -> Vec<Reminder> {
+        self.saved.lock().unwrap().0.reminders.clone()
+    }
+
+    /// Keeps a reminder until [`TriggerState::remind`] is called for it.
+    pub fn add_reminder(&self, reminder: Reminder) {
+        let mut saved = self.saved.lock().unwrap();
+        saved.0.reminders.push(reminder);
+        saved.1 = true;
+    }
+
+    /// Forgets the reminder of the trigger due at `at`, once it is sent.
+    pub fn remind(&self, trigger: &str, at: i64) {
+        let mut saved = self.saved.lock().unwrap();
+        saved
+            .0
+            .reminders
+            .retain(|r| r.at != at || r.event.trigger != trigger);
+        saved.1 = true;
+    }
+
+    pub fn entry(&self, label: &str) -> Entry {
+        self.saved
+            .lock()
+            .unwrap()
+            .0
+            .triggers
+            .get(label)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether the trigger may fire at `now`, not if it fires only once and already did, or is
+    /// still cooling down.
+    pub fn allow(&self, label: &str, trigger: &Trigger, now: i64) -> bool {
+        let entry = self.entry(label);
+        if trigger.once && entry.count > 0 {
+            return false;
+        }
+        entry
+            .last
+            .is_none_or(|t| now - t >= trigger.cooldown as i64)
+    }
+
+    /// Records a firing, returns how many times the trigger fired so far.
+    pub fn fire(&self, label: &str, now: i64) -> u64 {
+        let mut saved = self.saved.lock().unwrap();
+        saved.1 = true;
+        let entry = saved.0.triggers.entry(label.to_owned()).or_default();
+        entry.count += 1;
+        entry.last = Some(now);
+        entry.count
+    }
+
+    /// Forgets a trigger, so a one-shot one fires again, written at once. Returns `false` if it
+    /// never fired.
+    pub fn reset(&self, label: &str) -> bool {
+        let removed = {
+            let mut saved = self.saved.lock().unwrap();
+            let removed = saved.0.triggers.remove(label).is_some();
+            saved.1 |= removed;
+            removed
+        };
+        if removed {
+            self.flush();
+        }
+        removed
+    }
+
+    /// Takes the variables and group firings, written with the next flush.
+    pub fn save(&self, vars: BTreeMap<String, String>, groups: BTreeMap<String, i64>) {
+        let mut saved = self.saved.lock().unwrap();
+        saved.0.vars = vars;
+        saved.0.groups = groups;
+        saved.1 = true;
+    }
+
+    /// Writes the state if it changed since it was last written.
+    pub fn flush(&self) {
+        let mut saved = self.saved.lock().unwrap();
+        let Some(path) = self.path.as_ref().filter(|_| saved.1) else {
+            return;
+        };
+        // written aside first, a crash while writing must not lose the old state
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_string_pretty(&saved.0)
+            .map_err(Error::from)
+            .and_then(|text| Ok(std::fs::write(&tmp, text)?))
+            .and_then(|_| Ok(std::fs::rename(&tmp, path)?));
+        match result {
+            Ok(()) => saved.1 = false,
+            Err(e) => log::error!("State save error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger_state() {
+        let path = std::env::temp_dir().join("cgaid_test_state.json");
+        let _ = std::fs::remove_file(&path);
+        let state = TriggerState::load(Some(path.clone())).unwrap();
+        let mut once = Trigger::new("BOSS");
+        once.once = true;
+        let mut cooling = Trigger::new("迷宫");
+        cooling.cooldown = 600;
+
+        assert!(state.allow("boss", &once, 1000));
+        assert_eq!(state.fire("boss", 1000), 1);
+        assert!(!state.allow("boss", &once, 99999));
+        assert!(state.allow("maze", &cooling, 1000));
+        state.fire("maze", 1000);
+        assert!(!state.allow("maze", &cooling, 1599));
+        assert!(state.allow("maze", &cooling, 1600));
+        state.save(
+            BTreeMap::from([("leader".to_owned(), "画眉鸟".to_owned())]),
+            BTreeMap::from([("boss".to_owned(), 1000)]),
+        );
+        let mut event = Event::plain("点卡将在 12:00:00 到期");
+        event.trigger = "card".to_owned();
+        for at in [2000, 3000] {
+            state.add_reminder(Reminder {
+                at,
+                notifier: vec!["dingtalk".to_owned()],
+                event: event.clone(),
+                localized: BTreeMap::from([("en".to_owned(), "Card expires".to_owned())]),
+            });
+        }
+        state.remind("card", 2000);
+        let prices = BTreeMap::from([("玄铁".to_owned(), Price::default())]);
+        state.save_prices(prices.clone());
+        // nothing is written until flushed
+        assert!(!path.exists());
+        state.flush();
+
+        let state = TriggerState::load(Some(path.clone())).unwrap();
+        assert!(!state.allow("boss", &once, 99999));
+        assert_eq!(
+            state.entry("maze"),
+            Entry {
+                count: 1,
+                last: Some(1000)
+            }
+        );
+        assert_eq!(state.vars()["leader"], "画眉鸟");
+        assert_eq!(state.groups()["boss"], 1000);
+        assert_eq!(state.prices(), prices);
+        let reminders = state.reminders();
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].at, 3000);
+        assert_eq!(reminders[0].event.message, "点卡将在 12:00:00 到期");
+        assert_eq!(reminders[0].localized["en"], "Card expires");
+        assert!(state.reset("boss"));
+        assert!(!state.reset("boss"));
+        let state = TriggerState::load(Some(path.clone())).unwrap();
+        assert!(state.allow("boss", &once, 99999));
+
+        std::fs::write(&path, "{").unwrap();
+        assert!(TriggerState::load(Some(path.clone())).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(TriggerState::load(None).unwrap().vars().is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+use super::bus::{Signal, Sink};
+use super::error::Error;
+use chrono::Local;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Counters collected while watching, reported on exit.
+pub struct Stats {
+    start: Instant,
+    lines: AtomicU64,
+    matches: Mutex<BTreeMap<String, u64>>,
+    sent: AtomicU64,
+    failed: AtomicU64,
+    last_line: Mutex<Option<(Instant, String)>>,
+    /// Last successful notification and its notifier
+    last_sent: Mutex<Option<(Instant, String)>>,
+    /// Last error of each notifier with its kind
+    errors: Mutex<BTreeMap<String, (&'static str, Instant, String)>>,
+    /// Last turn of the watch loop
+    heartbeat: Mutex<Instant>,
+    /// Acknowledgment by trigger name
+    acks: Mutex<BTreeMap<String, Ack>>,
+    /// Last fault of cgaid itself by source
+    faults: Mutex<BTreeMap<String, (Instant, String)>>,
+}
+
+/// How the alerts of a trigger were acknowledged.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Ack {
+    pub matches: u64,
+    pub acked: u64,
+    /// Average seconds from the first unacknowledged alert to its acknowledgment
+    pub average: Option<u64>,
+    /// Total seconds of the acknowledged ones
+    #[serde(skip)]
+    pub total: u64,
+    /// First alert not acknowledged yet
+    #[serde(skip)]
+    pending: Option<Instant>,
+}
+
+impl Ack {
+    pub fn add(&mut self, delay: Duration) {
+        self.acked += 1;
+        self.total += delay.as_secs();
+        self.average = Some(self.total / self.acked);
+    }
+}
+
+/// When something happened.
+#[derive(Debug, Serialize)]
+pub struct Stamp {
+    pub time: String,
+    /// Seconds ago
+    pub ago: u64,
+}
+
+impl Stamp {
+    /// Only the time, what happened is not shown, e.g. the chat line read.
+    fn from(value: &Option<(Instant, String)>) -> Option<Self> {
+        value.as_ref().map(|(t, _)| Stamp::at(*t))
+    }
+
+    fn at(instant: Instant) -> Self {
+        let elapsed = instant.elapsed();
+        let time = Local::now() - chrono::Duration::from_std(elapsed).unwrap_or_default();
+        Self {
+            time: time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            ago: elapsed.as_secs(),
+        }
+    }
+}
+
+/// Something that happened a while ago.
+#[derive(Debug, Serialize)]
+pub struct Moment {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    pub detail: String,
+}
+
+impl Moment {
+    fn at(instant: Instant, detail: &str) -> Self {
+        Self {
+            stamp: Stamp::at(instant),
+            detail: detail.to_owned(),
+        }
+    }
+}
+
+/// Last error of a notifier.
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    /// Error kind, e.g. `rejected`, `unreachable` or `device`
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub moment: Moment,
+}
+
+/// Liveness report for uptime monitors.
+#[derive(Debug, Serialize)]
+pub struct Health {
+    /// Whether the watch loop is still turning
+    pub alive: bool,
+    /// Seconds since start
+    pub uptime: u64,
+    /// When the last chat line was read, not the line
+    pub last_line: Option<Stamp>,
+    pub last_sent: Option<Stamp>,
+    /// Last error by notifier
+    pub errors: BTreeMap<String, Failure>,
+    /// Acknowledgment by trigger, including earlier sessions kept in the history
+    pub acks: BTreeMap<String, Ack>,
+    /// Last fault by source, such as a locked chat file
+    pub faults: BTreeMap<String, Moment>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            lines: AtomicU64::new(0),
+            matches: Mutex::new(BTreeMap::new()),
+            sent: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            last_line: Mutex::new(None),
+            last_sent: Mutex::new(None),
+            errors: Mutex::new(BTreeMap::new()),
+            heartbeat: Mutex::new(Instant::now()),
+            acks: Mutex::new(BTreeMap::new()),
+            faults: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The watch loop is considered dead after missing beats this long.
+    const ALIVE: Duration = Duration::from_secs(10);
+
+    /// Marks the watch loop as alive.
+    pub fn beat(&self) {
+        *self.heartbeat.lock().unwrap() = Instant::now();
+    }
+
+    pub fn health(&self) -> Health {
+        Health {
+            alive: self.heartbeat.lock().unwrap().elapsed() < Stats::ALIVE,
+            uptime: self.start.elapsed().as_secs(),
+            last_line: Stamp::from(&self.last_line.lock().unwrap()),
+            last_sent: Stamp::from(&self.last_sent.lock().unwrap()),
+            errors: self
+                .errors
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, (kind, at, detail))| {
+                    (
+                        name.clone(),
+                        Failure {
+                            kind,
+                            moment: Moment::at(*at, detail),
+                        },
+                    )
+                })
+                .collect(),
+            acks: self.acks.lock().unwrap().clone(),
+            faults: self
+                .faults
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(source, (at, detail))| (source.clone(), Moment::at(*at, detail)))
+                .collect(),
+        }
+    }
+
+    pub fn set_last_line(&self, line: &str) {
+        *self.last_line.lock().unwrap() = Some((Instant::now(), line.to_owned()));
+    }
+
+    /// How long ago the last chat line was read, and the line.
+    pub fn last_line(&self) -> (Option<Duration>, String) {
+        match &*self.last_line.lock().unwrap() {
+            Some((t, l)) => (Some(t.elapsed()), l.clone()),
+            None => (None, String::new()),
+        }
+    }
+
+    pub fn add_lines(&self, n: usize) {
+        self.lines.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_match(&self, trigger: &str) {
+        *self
+            .matches
+            .lock()
+            .unwrap()
+            .entry(trigger.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn add_result(&self, notifier: &str, success: bool) {
+        if success {
+            self.sent.fetch_add(1, Ordering::Relaxed);
+            *self.last_sent.lock().unwrap() = Some((Instant::now(), notifier.to_owned()));
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a failed notification and keeps the error for the health report.
+    pub fn add_error(&self, notifier: &str, error: &Error) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.errors.lock().unwrap().insert(
+            notifier.to_owned(),
+            (error.kind(), Instant::now(), error.to_string()),
+        );
+    }
+
+    /// Adds the acknowledgment of earlier sessions.
+    pub fn load_acks(&self, acks: BTreeMap<String, Ack>) {
+        let mut current = self.acks.lock().unwrap();
+        for (name, a) in acks {
+            let c = current.entry(name).or_default();
+            c.matches += a.matches;
+            c.acked += a.acked;
+            c.total += a.total;
+            c.average = (c.acked > 0).then(|| c.total / c.acked);
+        }
+    }
+
+    /// Acknowledges the pending alerts of a trigger, returns how long it took since the first
+    /// one, `None` if nothing was pending.
+    pub fn ack(&self, trigger: &str, now: Instant) -> Option<Duration> {
+        let mut acks = self.acks.lock().unwrap();
+        let a = acks.get_mut(trigger)?;
+        let delay = now.saturating_duration_since(a.pending.take()?);
+        a.add(delay);
+        Some(delay)
+    }
+
+    fn add_pending(&self, trigger: &str, now: Instant) {
+        let mut acks = self.acks.lock().unwrap();
+        let a = acks.entry(trigger.to_owned()).or_default();
+        a.matches += 1;
+        a.pending.get_or_insert(now);
+    }
+
+    pub fn summary(&self) -> String {
+        let matches = self.matches.lock().unwrap();
+        let detail = if matches.is_empty() {
+            "无".to_owned()
+        } else {
+            matches
+                .iter()
+                .map(|(k, v)| format!("{k} {v}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut text = format!(
+            "本次监视: {}\n处理行数: {}\n匹配次数: {}\n通知发送: 成功 {}, 失败 {}",
+            fmt_duration(self.start.elapsed()),
+            self.lines.load(Ordering::Relaxed),
+            detail,
+            self.sent.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed)
+        );
+        let acks: Vec<_> = self
+            .acks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(k, a)| {
+                a.average.map(|s| {
+                    format!(
+                        "{k} {}/{} 平均 {}",
+                        a.acked,
+                        a.matches,
+                        fmt_duration(Duration::from_secs(s))
+                    )
+                })
+            })
+            .collect();
+        if !acks.is_empty() {
+            text.push_str(&format!("\n确认: {}", acks.join(", ")));
+        }
+        text
+    }
+}
+
+impl Sink for Stats {
+    fn handle(&self, signal: &Signal) {
+        match signal {
+            Signal::Lines { count, .. } => self.add_lines(*count),
+            Signal::Record { record, .. } => self.set_last_line(record.msg()),
+            Signal::Matched { trigger, event, .. } => {
+                self.add_match(trigger);
+                // only named triggers can be acknowledged
+                if !event.trigger.is_empty() {
+                    self.add_pending(&event.trigger, Instant::now());
+                }
+            }
+            Signal::Delivered {
+                notifier, result, ..
+            } => match result {
+                Ok(b) => self.add_result(notifier, *b),
+                Err(e) if e.is_deferred() => {}
+                Err(e) => self.add_error(notifier, e),
+            },
+            Signal::Fault { source, detail, .. } => {
+                self.faults
+                    .lock()
+                    .unwrap()
+                    .insert(source.to_string(), (Instant::now(), detail.to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn fmt_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    let (h, m, s) = (s / 3600, s / 60 % 60, s % 60);
+    if h > 0 {
+        format!("{h}小时{m}分{s}秒")
+    } else if m > 0 {
+        format!("{m}分{s}秒")
+    } else {
+        format!("{s}秒")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_lines() {
+        let stats = Stats::new();
+        // lines that are no records, or the same record again, are still read
+        stats.handle(&Signal::Lines {
+            client: "main",
+            count: 3,
+        });
+        let record = crate::chat::record::Record::from("12:00:01丂[世界]盛明兰oO: 收玄铁").unwrap();
+        stats.handle(&Signal::Record {
+            client: "main",
+            record: &record,
+        });
+        assert!(stats.summary().contains("处理行数: 3"));
+        assert!(stats.last_line().0.is_some());
+    }
+
+    #[test]
+    fn test_stats_summary() {
+        let stats = Stats::new();
+        stats.add_lines(10);
+        stats.add_lines(5);
+        stats.add_match("maze");
+        stats.add_match("maze");
+        stats.add_match("leave");
+        stats.add_result("console", true);
+        stats.add_result("dingtalk", false);
+        let summary = stats.summary();
+        println!("{summary}");
+        assert!(summary.contains("处理行数: 15"));
+        assert!(summary.contains("匹配次数: leave 1, maze 2"));
+        assert!(summary.contains("成功 1, 失败 1"));
+    }
+
+    #[test]
+    fn test_stats_health() {
+        let stats = Stats::new();
+        let health = stats.health();
+        assert!(health.alive);
+        assert!(health.last_line.is_none());
+        stats.set_last_line("abc");
+        stats.add_result("console", true);
+        stats.add_result("dingtalk", false);
+        let health = serde_json::to_value(stats.health()).unwrap();
+        assert!(health["last_line"].get("detail").is_none());
+        assert!(!health.to_string().contains("abc"));
+        assert_eq!(health["last_sent"]["ago"], 0);
+        stats.add_error("ringtone", &Error::device("no output"));
+        let health = serde_json::to_value(stats.health()).unwrap();
+        assert_eq!(health["errors"]["ringtone"]["kind"], "device");
+        assert_eq!(
+            health["errors"]["ringtone"]["detail"],
+            "Device missing: no output"
+        );
+        stats.handle(&Signal::Fault {
+            source: "读取聊天记录",
+            detail: "Chat file locked",
+            fatal: false,
+        });
+        let health = serde_json::to_value(stats.health()).unwrap();
+        assert_eq!(
+            health["faults"]["读取聊天记录"]["detail"],
+            "Chat file locked"
+        );
+    }
+
+    #[test]
+    fn test_stats_acks() {
+        let stats = Stats::new();
+        let now = Instant::now();
+        stats.add_pending("leave", now);
+        stats.add_pending("leave", now + Duration::from_secs(10));
+        stats.add_pending("maze", now);
+        assert_eq!(
+            stats.ack("leave", now + Duration::from_secs(30)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(stats.ack("leave", now + Duration::from_secs(40)), None);
+        assert_eq!(stats.ack("card", now), None);
+        stats.add_pending("leave", now + Duration::from_secs(50));
+        stats.ack("leave", now + Duration::from_secs(60));
+
+        let mut old = Ack {
+            matches: 2,
+            ..Default::default()
+        };
+        old.add(Duration::from_secs(80));
+        stats.load_acks(BTreeMap::from([("leave".to_owned(), old)]));
+        let health = serde_json::to_value(stats.health()).unwrap();
+        assert_eq!(health["acks"]["leave"]["matches"], 5);
+        assert_eq!(health["acks"]["leave"]["acked"], 3);
+        assert_eq!(health["acks"]["leave"]["average"], 40);
+        assert!(health["acks"]["maze"]["average"].is_null());
+        assert!(stats.summary().contains("确认: leave 3/5 平均 40秒"));
+    }
+
+    #[test]
+    fn test_fmt_duration() {
+        assert_eq!(fmt_duration(Duration::from_secs(3686)), "1小时1分26秒");
+        assert_eq!(fmt_duration(Duration::from_secs(61)), "1分1秒");
+        assert_eq!(fmt_duration(Duration::from_secs(5)), "5秒");
+    }
+}
@@ -0,0 +1,164 @@
+//! Parsed chat for programs embedding cgaid, without any config file.
+//!
+//! ```no_run
+//! use cgaid::chat::record::Channel;
+//! use cgaid::config::Trigger;
+//! use cgaid::stream;
+//!
+//! let file = std::fs::File::open("Log/chat_240501.txt").unwrap();
+//! let mut leave = Trigger::new(r"(\w+)离开了队伍。");
+//! leave.format = "{time}. {1} 掉线了".into();
+//! for event in stream::from_reader(file)
+//!     .channel(Channel::Common)
+//!     .matching(vec![leave])
+//! {
+//!     println!("{}", event.message);
+//! }
+//! ```
+use super::chat::record::{Channel, Record};
+use super::config::Trigger;
+use super::event::Event;
+use super::template;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+
+/// Records parsed from chat log lines, skipping the lines that are not records.
+pub struct RecordStream<I> {
+    lines: I,
+    channels: Vec<Channel>,
+}
+
+/// Records of a GB18030 chat log, e.g. an opened `chat_xxxxxx.txt`.
+pub fn from_reader<R: Read>(reader: R) -> RecordStream<impl Iterator<Item = String>> {
+    let reader = BufReader::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding_rs::GB18030))
+            .build(reader),
+    );
+    RecordStream::new(reader.lines().map_while(Result::ok))
+}
+
+impl<I, S> RecordStream<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    /// Records of already decoded lines.
+    pub fn new<L: IntoIterator<IntoIter = I>>(lines: L) -> Self {
+        Self {
+            lines: lines.into_iter(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// Only keeps records of `channel`, may be called again to keep several channels.
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Events of the records matching `triggers`, a record matching several triggers yields one
+    /// event for each. The message is the trigger format with `{time}` filled.
+    pub fn matching(self, triggers: Vec<Trigger>) -> Matches<I> {
+        Matches {
+            records: self,
+            triggers,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<I, S> Iterator for RecordStream<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        for line in self.lines.by_ref() {
+            let Some(record) = Record::from(line.as_ref()) else {
+                continue;
+            };
+            if self.channels.is_empty() || self.channels.contains(record.get_channel()) {
+                return Some(record);
+            }
+        }
+        None
+    }
+}
+
+/// Events of the records matching a set of triggers, see [`RecordStream::matching`].
+pub struct Matches<I> {
+    records: RecordStream<I>,
+    triggers: Vec<Trigger>,
+    pending: VecDeque<Event>,
+}
+
+impl<I, S> Iterator for Matches<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        while self.pending.is_empty() {
+            let record = self.records.next()?;
+            for trigger in &self.triggers {
+                if !trigger.accept(record.get_channel()) {
+                    continue;
+                }
+                if let Some(captures) = trigger.try_match(record.msg()) {
+                    let mut values = template::captures(&captures);
+                    values.insert("time", &record.fmt_time());
+                    let message = template::render(trigger.format.text(), &values);
+                    self.pending
+                        .push_back(Event::new(&record, trigger, captures, message));
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const LOG: &str = "12:00:01丂[世界]盛明兰oO: 收玄铁\r\n\
+        不是记录\r\n\
+        12:00:02丂画眉鸟离开了队伍。\r\n\
+        12:00:03丂[地图]路人: 王在(123,456)\r\n";
+
+    #[test]
+    fn test_record_stream() {
+        let records: Vec<_> = RecordStream::new(LOG.lines()).collect();
+        assert_eq!(records.len(), 3);
+        let records: Vec<_> = RecordStream::new(LOG.lines())
+            .channel(Channel::World)
+            .channel(Channel::Region)
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].coord().unwrap().x, 123);
+    }
+
+    #[test]
+    fn test_record_stream_matching() {
+        let data = encoding_rs::GB18030.encode(LOG).0.into_owned();
+        let mut leave = Trigger::new(r"(\w+)离开了队伍。");
+        leave.format = "{time}. {1} 掉线了".into();
+        let mut any = Trigger::new(".+");
+        any.format = "{0}".into();
+        let events: Vec<_> = from_reader(Cursor::new(data))
+            .channel(Channel::Common)
+            .matching(vec![leave, any])
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].message, "12:00:02. 画眉鸟 掉线了");
+        assert_eq!(events[0].captures[1], "画眉鸟");
+        assert_eq!(events[1].message, "画眉鸟离开了队伍。");
+    }
+}
@@ -0,0 +1,157 @@
+use super::config::Trigger;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A temporary keyword trigger added at runtime, kept only in memory.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    #[serde(default)]
+    pub id: u64,
+    pub keyword: String,
+    #[serde(default)]
+    pub channel: String,
+    pub notifier: Vec<String>,
+    /// How long the subscription lives, in minutes
+    pub minutes: u64,
+    /// Unix time in seconds when the subscription expires
+    #[serde(default)]
+    pub expires: u64,
+}
+
+impl Subscription {
+    /// Parses `keyword`, `minutes`, `notifier` (comma separated) and `channel` query parameters.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let keyword = query.get("keyword").ok_or("Missing keyword")?;
+        let minutes = query
+            .get("minutes")
+            .ok_or("Missing minutes")?
+            .parse()
+            .map_err(|_| "Invalid minutes")?;
+        let notifier = query
+            .get("notifier")
+            .ok_or("Missing notifier")?
+            .split(',')
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+            .collect();
+        Ok(Self {
+            id: 0,
+            keyword: keyword.to_owned(),
+            channel: query.get("channel").cloned().unwrap_or_default(),
+            notifier,
+            minutes,
+            expires: 0,
+        })
+    }
+
+    pub fn trigger(&self) -> Trigger {
+        let mut trigger = Trigger::new(&format!(".*{}.*", regex::escape(&self.keyword)));
+        trigger.name = format!("subscription:{}", self.id);
+        trigger.format = "{time}. {0}".into();
+        trigger.channel = self.channel.clone();
+        trigger.notifier = self.notifier.clone();
+        trigger
+    }
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    next: Mutex<u64>,
+    list: Mutex<Vec<Subscription>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, mut sub: Subscription) -> Subscription {
+        let mut next = self.next.lock().unwrap();
+        *next += 1;
+        sub.id = *next;
+        sub.expires = now().saturating_add(sub.minutes.saturating_mul(60));
+        self.list.lock().unwrap().push(sub.clone());
+        log::info!(
+            "Subscribed {} for {} minutes: {:?}",
+            sub.keyword,
+            sub.minutes,
+            sub.notifier
+        );
+        sub
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        let mut list = self.list.lock().unwrap();
+        let len = list.len();
+        list.retain(|s| s.id != id);
+        len != list.len()
+    }
+
+    /// Active subscriptions, expired ones are dropped.
+    pub fn list(&self) -> Vec<Subscription> {
+        let now = now();
+        let mut list = self.list.lock().unwrap();
+        list.retain(|s| s.expires > now);
+        list.clone()
+    }
+
+    pub fn triggers(&self) -> Vec<Trigger> {
+        self.list().iter().map(|s| s.trigger()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::record::Channel;
+
+    fn sub(keyword: &str, minutes: u64) -> Subscription {
+        Subscription {
+            id: 0,
+            keyword: keyword.to_owned(),
+            channel: String::new(),
+            notifier: vec!["ringtone".to_owned()],
+            minutes,
+            expires: 0,
+        }
+    }
+
+    #[test]
+    fn test_subscription_trigger() {
+        let subs = Subscriptions::new();
+        let s = subs.add(sub("玄铁", 120));
+        assert_eq!(s.id, 1);
+        let triggers = subs.triggers();
+        assert_eq!(triggers.len(), 1);
+        let trigger = &triggers[0];
+        assert!(trigger.accept(&Channel::World));
+        let matched = trigger.try_match("[世界]盛明兰oO: 收玄铁 私聊").unwrap();
+        assert_eq!(
+            trigger.format(&matched),
+            "{time}. [世界]盛明兰oO: 收玄铁 私聊"
+        );
+        assert!(trigger
+            .try_match("[世界]盛明兰oO: 半山来个合格车头")
+            .is_none());
+    }
+
+    #[test]
+    fn test_subscription_expire() {
+        let subs = Subscriptions::new();
+        subs.add(sub("玄铁", 0));
+        let s = subs.add(sub("(魔石)", 10));
+        assert_eq!(subs.list().len(), 1);
+        assert!(subs.triggers()[0].try_match("收(魔石)").is_some());
+        assert!(subs.remove(s.id));
+        assert!(subs.list().is_empty());
+    }
+}
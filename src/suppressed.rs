@@ -0,0 +1,181 @@
+use super::bus::{Signal, Sink};
+use super::template;
+use chrono::Local;
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+/// Why a match reached none of its notifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reason {
+    /// Trigger muted from the control API
+    Muted,
+    /// Group disabled, out of its schedule or cooling down
+    Group,
+    /// Same message sent to the notifiers shortly before
+    Dedup,
+    /// Notifier queues full
+    Queue,
+    /// Notifiers over their rate
+    Rate,
+    /// Notifiers off duty by their calendars
+    Calendar,
+    /// Trigger cooling down, or fired once already
+    Cooldown,
+    /// Not sent by the notifier itself, e.g. over the rate of the service or out of its hours
+    Notifier,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Muted => write!(f, "静音"),
+            Self::Group => write!(f, "分组限制"),
+            Self::Dedup => write!(f, "重复"),
+            Self::Queue => write!(f, "队列已满"),
+            Self::Rate => write!(f, "超出频率限制"),
+            Self::Calendar => write!(f, "非工作时段"),
+            Self::Cooldown => write!(f, "冷却中"),
+            Self::Notifier => write!(f, "通知器未发送"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub time: String,
+    pub reason: Reason,
+    pub trigger: String,
+    pub message: String,
+}
+
+/// Matches suppressed since the last summary, so suppression never silently loses them.
+pub struct Suppressed {
+    entries: Mutex<(Vec<Entry>, usize)>,
+}
+
+impl Default for Suppressed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Suppressed {
+    /// Entries kept between summaries, later ones are only counted.
+    const MAX_ENTRIES: usize = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new((Vec::new(), 0)),
+        }
+    }
+
+    pub fn add(&self, reason: Reason, trigger: &str, message: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.1 += 1;
+        if entries.0.len() < Suppressed::MAX_ENTRIES {
+            entries.0.push(Entry {
+                time: Local::now().format("%H:%M:%S").to_string(),
+                reason,
+                trigger: trigger.to_owned(),
+                message: message.to_owned(),
+            });
+        }
+    }
+
+    /// Takes the entries and their total count, including the ones not kept.
+    pub fn take(&self) -> (Vec<Entry>, usize) {
+        std::mem::take(&mut *self.entries.lock().unwrap())
+    }
+
+    /// The summary of what was taken, listing at most `list` entries, `None` when nothing was
+    /// suppressed. `{count}` and `{list}` in `format` are replaced.
+    pub fn summary(format: &str, entries: &[Entry], count: usize, list: usize) -> Option<String> {
+        if count == 0 {
+            return None;
+        }
+        let mut lines: Vec<_> = entries
+            .iter()
+            .take(list)
+            .map(|e| format!("{} [{}] {}", e.time, e.reason, e.message))
+            .collect();
+        if count > lines.len() {
+            lines.push(format!("... 还有 {} 条", count - lines.len()));
+        }
+        let mut values = template::Context::new();
+        values.insert("count", &count);
+        values.insert("list", &lines.join("\n"));
+        Some(template::render(format, &values))
+    }
+}
+
+impl Sink for Suppressed {
+    fn handle(&self, signal: &Signal) {
+        if let Signal::Suppressed {
+            trigger,
+            reason,
+            message,
+        } = signal
+        {
+            self.add(*reason, trigger, message);
+        }
+        // only matches, a summary not sent must not be summarized again
+        if let Signal::Delivered {
+            notifier,
+            event,
+            result: Ok(false),
+            ..
+        } = signal
+        {
+            if !event.trigger.is_empty() {
+                let message = format!("{notifier}: {}", event.message);
+                self.add(Reason::Notifier, &event.trigger, &message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppressed_summary() {
+        let suppressed = Suppressed::new();
+        assert_eq!(suppressed.take().1, 0);
+        suppressed.add(Reason::Dedup, "maze", "挑战赛通道 即将刷新");
+        suppressed.add(Reason::Muted, "leave", "画眉鸟 掉线了");
+        suppressed.add(Reason::Group, "boss", "BOSS 出现了");
+        let (entries, count) = suppressed.take();
+        assert_eq!(count, 3);
+        assert!(suppressed.take().0.is_empty());
+
+        let text = Suppressed::summary("{count} 条被抑制:\n{list}", &entries, count, 2).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "3 条被抑制:");
+        assert!(lines[1].ends_with("[重复] 挑战赛通道 即将刷新"));
+        assert!(lines[2].ends_with("[静音] 画眉鸟 掉线了"));
+        assert_eq!(lines[3], "... 还有 1 条");
+        assert!(Suppressed::summary("{count}", &[], 0, 10).is_none());
+    }
+
+    #[test]
+    fn test_suppressed_by_notifier() {
+        let suppressed = Suppressed::new();
+        let mut event = crate::event::Event::plain("BOSS 出现了");
+        for (trigger, result) in [("boss", Ok(false)), ("boss", Ok(true)), ("", Ok(false))] {
+            event.trigger = trigger.to_owned();
+            suppressed.handle(&Signal::Delivered {
+                notifier: "dingtalk",
+                event: &event,
+                result: &result,
+                elapsed: std::time::Duration::ZERO,
+            });
+        }
+        let (entries, count) = suppressed.take();
+        assert_eq!(count, 1);
+        assert_eq!(entries[0].reason, Reason::Notifier);
+        assert_eq!(entries[0].message, "dingtalk: BOSS 出现了");
+    }
+}
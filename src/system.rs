@@ -0,0 +1,224 @@
+//! Queries about the machine the game runs on.
+
+/// Whether a process with the given executable name (case-insensitive, e.g. `cg.exe`) is running.
+#[cfg(windows)]
+pub fn process_running(name: &str) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+
+    let name = name.to_lowercase();
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut found = false;
+        let mut ok = Process32FirstW(snapshot, &mut entry) != 0;
+        while ok {
+            let len = entry
+                .szExeFile
+                .iter()
+                .position(|c| *c == 0)
+                .unwrap_or(entry.szExeFile.len());
+            if String::from_utf16_lossy(&entry.szExeFile[..len]).to_lowercase() == name {
+                found = true;
+                break;
+            }
+            ok = Process32NextW(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+        found
+    }
+}
+
+/// Whether a process with the given executable name (case-insensitive, e.g. `cg.exe`) is running.
+#[cfg(not(windows))]
+pub fn process_running(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.filter_map(|e| e.ok()).any(|e| {
+        // comm is truncated to 15 bytes, so also look at the command line (e.g. wine cg.exe)
+        let comm = std::fs::read_to_string(e.path().join("comm")).unwrap_or_default();
+        if comm.trim().to_lowercase() == name {
+            return true;
+        }
+        std::fs::read(e.path().join("cmdline"))
+            .unwrap_or_default()
+            .split(|b| *b == 0)
+            .filter_map(|a| std::str::from_utf8(a).ok())
+            .any(|a| {
+                a.rsplit(['/', '\\'])
+                    .next()
+                    .is_some_and(|f| f.to_lowercase() == name)
+            })
+    })
+}
+
+/// Time since the last keyboard or mouse input.
+#[cfg(windows)]
+pub fn idle_time() -> Option<std::time::Duration> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    unsafe {
+        if GetLastInputInfo(&mut info) == 0 {
+            return None;
+        }
+        let ms = GetTickCount().wrapping_sub(info.dwTime);
+        Some(std::time::Duration::from_millis(ms as u64))
+    }
+}
+
+/// Time since the last keyboard or mouse input, not detectable outside Windows.
+#[cfg(not(windows))]
+pub fn idle_time() -> Option<std::time::Duration> {
+    None
+}
+
+/// Visible top level windows whose title contains `filter`, as handle and title.
+#[cfg(windows)]
+pub fn window_titles(filter: &str) -> Vec<(isize, String)> {
+    use windows_sys::Win32::Foundation::{BOOL, HWND, LPARAM};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowTextW, IsWindowVisible,
+    };
+
+    unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let list = &mut *(lparam as *mut Vec<(isize, String)>);
+        if IsWindowVisible(hwnd) != 0 {
+            let mut buf = [0u16; 512];
+            let n = GetWindowTextW(hwnd, buf.as_mut_ptr(), buf.len() as i32);
+            if n > 0 {
+                list.push((hwnd as isize, String::from_utf16_lossy(&buf[..n as usize])));
+            }
+        }
+        1
+    }
+
+    let mut list: Vec<(isize, String)> = Vec::new();
+    unsafe {
+        EnumWindows(Some(collect), &mut list as *mut _ as LPARAM);
+    }
+    list.retain(|(_, t)| t.contains(filter));
+    list
+}
+
+/// Visible top level windows whose title contains `filter`, not available outside Windows.
+#[cfg(not(windows))]
+pub fn window_titles(_filter: &str) -> Vec<(isize, String)> {
+    Vec::new()
+}
+
+/// Title of the window in the foreground.
+#[cfg(windows)]
+pub fn foreground_title() -> Option<String> {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW};
+
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return None;
+        }
+        let mut buf = [0u16; 512];
+        let n = GetWindowTextW(hwnd, buf.as_mut_ptr(), buf.len() as i32);
+        Some(String::from_utf16_lossy(&buf[..n.max(0) as usize]))
+    }
+}
+
+/// Title of the window in the foreground, not available outside Windows.
+#[cfg(not(windows))]
+pub fn foreground_title() -> Option<String> {
+    None
+}
+
+/// Whether the workstation is locked, its input desktop being the secure one then.
+#[cfg(windows)]
+pub fn is_locked() -> bool {
+    use windows_sys::Win32::System::StationsAndDesktops::{
+        CloseDesktop, OpenInputDesktop, DESKTOP_SWITCHDESKTOP,
+    };
+
+    unsafe {
+        let desktop = OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP);
+        if desktop.is_null() {
+            return true;
+        }
+        CloseDesktop(desktop);
+        false
+    }
+}
+
+/// Whether the workstation is locked, not detectable outside Windows.
+#[cfg(not(windows))]
+pub fn is_locked() -> bool {
+    false
+}
+
+/// Password of a generic credential in the Windows Credential Manager, e.g. one added by
+/// `cmdkey /generic:cgaid-dingtalk /user:cgaid /pass:TOKEN`.
+#[cfg(windows)]
+pub fn credential(target: &str) -> Option<String> {
+    use windows_sys::Win32::Security::Credentials::{
+        CredFree, CredReadW, CREDENTIALW, CRED_TYPE_GENERIC,
+    };
+
+    let name: Vec<u16> = target.encode_utf16().chain(Some(0)).collect();
+    let mut cred: *mut CREDENTIALW = std::ptr::null_mut();
+    unsafe {
+        if CredReadW(name.as_ptr(), CRED_TYPE_GENERIC, 0, &mut cred) == 0 {
+            return None;
+        }
+        let blob =
+            std::slice::from_raw_parts((*cred).CredentialBlob, (*cred).CredentialBlobSize as usize);
+        // cmdkey and most tools store UTF-16, some UTF-8
+        let wide: Vec<u16> = blob
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        let text = match blob.len() % 2 {
+            0 => String::from_utf16(&wide).ok(),
+            _ => None,
+        }
+        .filter(|t| !t.contains('\0'))
+        .or_else(|| String::from_utf8(blob.to_vec()).ok());
+        CredFree(cred as *const _);
+        text
+    }
+}
+
+/// Password of a credential, the Windows Credential Manager being missing elsewhere.
+#[cfg(not(windows))]
+pub fn credential(_target: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_running() {
+        let exe = std::env::current_exe().unwrap();
+        let name = exe.file_name().unwrap().to_str().unwrap();
+        assert!(process_running(name));
+        assert!(!process_running("no-such-process.exe"));
+    }
+
+    #[test]
+    fn test_idle_time() {
+        if let Some(d) = idle_time() {
+            assert!(d < std::time::Duration::from_secs(365 * 24 * 3600));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Message templates of triggers and notifiers, rendered with [Tera](https://keats.github.io/tera/docs/):
+//! `{{ sender | default(value="有人") }}`, `{% if hint %}...{% endif %}`, `{{ message | truncate(length=50) }}`.
+//!
+//! A template without `{{` or `{%` is taken as one of the older ones, whose `{name}` of a known
+//! value and `{0}`, `{1}` ... of the captures are filled in, while other braces are kept.
+
+use super::event::Event;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::sync::{Mutex, OnceLock};
+use tera::Tera;
+
+pub use tera::Context;
+
+/// Templates compiled so far, named by their source.
+static TERA: OnceLock<Mutex<Tera>> = OnceLock::new();
+
+/// Compiled templates kept at most, as filled in variables may make every source a new one.
+const CACHE: usize = 256;
+
+/// The values of an event: `message` with its map hint, `sender` empty when there is none.
+pub fn context(event: &Event) -> Context {
+    let mut ctx = Context::from_serialize(event).unwrap_or_default();
+    ctx.insert("message", &event.with_hint());
+    ctx.insert("sender", event.sender.as_deref().unwrap_or_default());
+    ctx
+}
+
+/// Only the message, for notifiers given nothing else.
+pub fn message(message: &str) -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("message", message);
+    ctx
+}
+
+/// Only the captures, `{0}` being the whole match.
+pub fn captures(matched: &[String]) -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("captures", matched);
+    ctx
+}
+
+/// Fills in `template`, logging and keeping it as it is when it fails to compile or render.
+pub fn render(template: &str, ctx: &Context) -> String {
+    let source = upgrade(template, ctx);
+    let mut tera = TERA
+        .get_or_init(|| {
+            let mut tera = Tera::default();
+            tera.autoescape_on(Vec::new());
+            Mutex::new(tera)
+        })
+        .lock()
+        .unwrap();
+    if !tera.templates.contains_key(source.as_ref()) {
+        if tera.templates.len() >= CACHE {
+            tera.templates.clear();
+        }
+        if let Err(e) = tera.add_raw_template(&source, &source) {
+            log::warn!("Invalid template {template:?}: {}", reason(&e));
+            return template.to_owned();
+        }
+    }
+    tera.render(&source, ctx).unwrap_or_else(|e| {
+        log::warn!("Template {template:?} failed: {}", reason(&e));
+        template.to_owned()
+    })
+}
+
+/// Checks that `template` compiles.
+pub fn check(template: &str) -> Result<(), String> {
+    if !is_tera(template) {
+        return Ok(());
+    }
+    Tera::default()
+        .add_raw_template("check", template)
+        .map_err(|e| reason(&e))
+}
+
+fn is_tera(template: &str) -> bool {
+    template.contains("{{") || template.contains("{%")
+}
+
+/// An older template in Tera syntax, with Tera's own delimiters in the text escaped.
+fn upgrade<'a>(template: &'a str, ctx: &Context) -> Cow<'a, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    if is_tera(template) || !template.contains('{') {
+        return Cow::Borrowed(template);
+    }
+    let re = RE.get_or_init(|| Regex::new(r"\{(\w+(?:\.\w+)*)\}|\{#").unwrap());
+    re.replace_all(template, |c: &Captures| {
+        let Some(path) = c.get(1).map(|m| m.as_str()) else {
+            return "{{ '{#' }}".to_owned();
+        };
+        let known = match path.parse::<usize>() {
+            Ok(i) => ctx.get("captures").and_then(|c| c.get(i)).is_some(),
+            Err(_) => ctx.contains_key(path.split('.').next().unwrap_or_default()),
+        };
+        match (known, path.parse::<usize>()) {
+            (false, _) => format!("{{{{ '{{{path}}}' }}}}"),
+            (true, Ok(i)) => format!("{{{{ captures.{i} }}}}"),
+            // empty like before when unset, e.g. a variable
+            (true, Err(_)) => format!("{{{{ {path} | default(value=\"\") }}}}"),
+        }
+    })
+}
+
+/// The error with its causes, as Tera puts the useful part in the source.
+fn reason(e: &tera::Error) -> String {
+    let mut text = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(s) = source {
+        text.push_str(&format!(": {s}"));
+        source = s.source();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_legacy() {
+        let ctx = captures(&["半山来人".to_owned(), "来人".to_owned()]);
+        assert_eq!(render("半山{1} {2} {x} {#", &ctx), "半山来人 {2} {x} {#");
+        let mut event = Event::plain("abc");
+        event.hint = Some("地图".to_owned());
+        assert_eq!(
+            render(
+                "{\"text\": \"{message}\", \"by\": \"{sender}\"}",
+                &context(&event)
+            ),
+            "{\"text\": \"abc\n地图\", \"by\": \"\"}"
+        );
+    }
+
+    #[test]
+    fn test_render_tera() {
+        let mut event = Event::plain("boss 出现了");
+        event.captures = vec!["boss 出现了".to_owned(), "boss".to_owned()];
+        let ctx = context(&event);
+        assert_eq!(
+            render(
+                "{{ captures.1 | upper }}{% if sender %} by {{ sender }}{% endif %} {{ hint | default(value=\"-\") }}",
+                &ctx
+            ),
+            "BOSS -"
+        );
+        assert_eq!(render("{{ message | truncate(length=4) }}", &ctx), "boss…");
+        // kept as it is when broken
+        assert_eq!(
+            render("{{ message | nothing }}", &ctx),
+            "{{ message | nothing }}"
+        );
+        assert!(check("{% if x %}").is_err());
+        assert!(check("{x}").is_ok());
+    }
+}
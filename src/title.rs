@@ -0,0 +1,90 @@
+use super::chat::record::{Channel, Record};
+use super::config;
+use super::system;
+use chrono::Local;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+/// Watches the titles of the game windows, which show the character and server on some clients.
+pub struct TitleWatcher {
+    filter: String,
+    interval: Duration,
+    closed: String,
+    titles: HashMap<isize, String>,
+}
+
+impl TitleWatcher {
+    pub fn new(cfg: &config::Title) -> Self {
+        Self {
+            filter: cfg.filter.clone(),
+            interval: Duration::from_secs(cfg.interval.max(1)),
+            closed: cfg.closed.clone(),
+            titles: HashMap::new(),
+        }
+    }
+
+    /// Messages for the windows whose title changed: the new title, or `closed` plus the old title.
+    fn diff(&mut self, windows: Vec<(isize, String)>) -> Vec<String> {
+        let mut changes = Vec::new();
+        let current: HashMap<_, _> = windows.into_iter().collect();
+        for (id, title) in &self.titles {
+            if !current.contains_key(id) {
+                changes.push(format!("{}{title}", self.closed));
+            }
+        }
+        for (id, title) in &current {
+            if self.titles.get(id) != Some(title) {
+                changes.push(title.clone());
+            }
+        }
+        self.titles = current;
+        changes
+    }
+
+    /// Polls the window titles, handing each change to `on_change` as a record of the title channel.
+    pub fn start<F>(mut self, on_change: F)
+    where
+        F: Fn(Record) + Send + 'static,
+    {
+        log::info!("Watching window titles containing: {}", self.filter);
+        // titles present at start are not changes
+        self.titles = system::window_titles(&self.filter).into_iter().collect();
+        thread::spawn(move || loop {
+            thread::sleep(self.interval);
+            for message in self.diff(system::window_titles(&self.filter)) {
+                log::debug!("Window title: {message}");
+                on_change(Record::new(Local::now().time(), Channel::Title, message));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_diff() {
+        let cfg = config::Title {
+            filter: "魔力宝贝".to_owned(),
+            ..Default::default()
+        };
+        let mut watcher = TitleWatcher::new(&cfg);
+        assert_eq!(
+            watcher.diff(vec![(1, "魔力宝贝 盛明兰".to_owned())]),
+            vec!["魔力宝贝 盛明兰"]
+        );
+        assert!(watcher
+            .diff(vec![(1, "魔力宝贝 盛明兰".to_owned())])
+            .is_empty());
+        assert_eq!(
+            watcher.diff(vec![(1, "魔力宝贝 画眉鸟".to_owned())]),
+            vec!["魔力宝贝 画眉鸟"]
+        );
+        assert_eq!(
+            watcher.diff(Vec::new()),
+            vec!["窗口已关闭: 魔力宝贝 画眉鸟"]
+        );
+    }
+}
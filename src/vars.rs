@@ -0,0 +1,67 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Runtime variables set from trigger captures, referenced in templates as `{{ var.name }}`.
+#[derive(Default)]
+pub struct Vars {
+    values: Mutex<HashMap<String, String>>,
+}
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Variables set before, e.g. restored from the saved state.
+    pub fn with_values(values: BTreeMap<String, String>) -> Self {
+        Self {
+            values: Mutex::new(values.into_iter().collect()),
+        }
+    }
+
+    pub fn values(&self) -> BTreeMap<String, String> {
+        self.values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub fn set(&self, name: &str, value: String) {
+        log::debug!("Var {name} = {value}");
+        self.values.lock().unwrap().insert(name.to_owned(), value);
+    }
+
+    /// Renders `text` with the variables, unset ones of `{var.name}` become empty.
+    pub fn fill(&self, text: &str) -> String {
+        let mut ctx = crate::template::Context::new();
+        self.insert(&mut ctx);
+        crate::template::render(text, &ctx)
+    }
+
+    /// Adds the variables to the values of a template as `var`.
+    pub fn insert(&self, ctx: &mut crate::template::Context) {
+        ctx.insert("var", &*self.values.lock().unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vars() {
+        let vars = Vars::new();
+        vars.set("leader", "画眉鸟".to_owned());
+        assert_eq!(vars.values()["leader"], "画眉鸟");
+        assert_eq!(
+            vars.fill("队长 {var.leader}, 价格 {var.price}"),
+            "队长 画眉鸟, 价格 "
+        );
+        vars.set("leader", "盛明兰".to_owned());
+        assert_eq!(vars.fill("{var.leader}"), "盛明兰");
+        let vars = Vars::with_values(vars.values());
+        assert_eq!(vars.fill("{var.leader}"), "盛明兰");
+    }
+}
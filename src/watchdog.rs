@@ -0,0 +1,202 @@
+//! Alerts about cgaid's own failures, so the monitor is monitored too.
+use super::bus::{Signal, Sink};
+use super::dispatcher::Dispatcher;
+use super::event::Event;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct State {
+    /// Consecutive failures and the last error of each notifier used so far
+    failing: HashMap<String, (u32, String)>,
+    /// Faults by source since the last alert about it
+    faults: HashMap<String, u32>,
+    /// Last alert by source
+    alerted: HashMap<String, Instant>,
+}
+
+/// Watches faults and notification results on the bus, alerting through the fallback notifiers
+/// when they keep happening.
+pub struct Watchdog {
+    /// Sends the alerts, and gives the settings of the running config
+    dispatcher: Dispatcher,
+    state: Mutex<State>,
+}
+
+impl Watchdog {
+    /// Source of the alert about every notifier failing.
+    const NOTIFIERS: &'static str = "notifier";
+
+    pub fn new(dispatcher: Dispatcher) -> Self {
+        Self {
+            dispatcher,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// The alert a signal calls for, if any.
+    fn check(&self, signal: &Signal, now: Instant) -> Option<String> {
+        let cfg = self.dispatcher.cfg();
+        let threshold = cfg.watchdog.failures.max(1);
+        let mut state = self.state.lock().unwrap();
+        let (source, text) = match signal {
+            Signal::Fault {
+                source,
+                detail,
+                fatal,
+            } => {
+                let count = state.faults.entry(source.to_string()).or_default();
+                *count += 1;
+                if !fatal && *count < threshold {
+                    return None;
+                }
+                let text = if *fatal {
+                    format!("{source} 已停止: {detail}")
+                } else {
+                    format!("{source} 连续出错 {count} 次, 最近: {detail}")
+                };
+                (source.to_string(), text)
+            }
+            Signal::Delivered {
+                notifier, result, ..
+            } => {
+                let entry = state
+                    .failing
+                    .entry(notifier.to_string())
+                    .or_insert((0, String::new()));
+                match result {
+                    Ok(true) => {
+                        *entry = (0, String::new());
+                        return None;
+                    }
+                    Ok(false) => *entry = (entry.0 + 1, "未发送".to_owned()),
+                    // still on its way
+                    Err(e) if e.is_deferred() => return None,
+                    Err(e) => *entry = (entry.0 + 1, e.to_string()),
+                }
+                // a single notifier working is enough
+                if state.failing.values().any(|(n, _)| *n < threshold) {
+                    return None;
+                }
+                let mut list: Vec<_> = state
+                    .failing
+                    .iter()
+                    .map(|(name, (_, e))| format!("{name}: {e}"))
+                    .collect();
+                list.sort();
+                (
+                    Watchdog::NOTIFIERS.to_owned(),
+                    format!("所有通知器连续发送失败\n{}", list.join("\n")),
+                )
+            }
+            _ => return None,
+        };
+        let interval = Duration::from_secs(cfg.watchdog.interval * 60);
+        if state
+            .alerted
+            .get(&source)
+            .is_some_and(|t| now.duration_since(*t) < interval)
+        {
+            return None;
+        }
+        state.alerted.insert(source.clone(), now);
+        state.faults.remove(&source);
+        Some(format!("cgaid 自检: {text}"))
+    }
+}
+
+impl Sink for Watchdog {
+    fn handle(&self, signal: &Signal) {
+        let Some(text) = self.check(signal, Instant::now()) else {
+            return;
+        };
+        log::warn!("{text}");
+        let event = Arc::new(Event::plain(&text));
+        // cgaid is about to exit after a fatal fault, queued alerts would be lost
+        let fatal = matches!(signal, Signal::Fault { fatal: true, .. });
+        for name in &self.dispatcher.cfg().watchdog.notifier {
+            if fatal {
+                let _ = self.dispatcher.send(name, &event);
+            } else {
+                let _ = self.dispatcher.dispatch(name, &event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::config;
+    use crate::error::Error;
+
+    fn watchdog() -> Watchdog {
+        let mut cfg = config::Config::load("config.toml").unwrap();
+        cfg.watchdog = config::Watchdog {
+            notifier: vec!["simple".to_owned()],
+            failures: 2,
+            interval: 30,
+        };
+        Watchdog::new(Dispatcher::new(Arc::new(cfg), None, &Arc::new(Bus::new())))
+    }
+
+    #[test]
+    fn test_watchdog_faults() {
+        let watchdog = watchdog();
+        let now = Instant::now();
+        let fault = Signal::Fault {
+            source: "聊天记录",
+            detail: "Access denied",
+            fatal: false,
+        };
+        assert!(watchdog.check(&fault, now).is_none());
+        let text = watchdog.check(&fault, now).unwrap();
+        assert_eq!(
+            text,
+            "cgaid 自检: 聊天记录 连续出错 2 次, 最近: Access denied"
+        );
+        assert!(watchdog.check(&fault, now).is_none());
+        assert!(watchdog.check(&fault, now).is_none());
+        assert!(watchdog
+            .check(&fault, now + Duration::from_secs(1800))
+            .is_some());
+
+        let stopped = Signal::Fault {
+            source: "文件监视",
+            detail: "channel closed",
+            fatal: true,
+        };
+        assert!(watchdog.check(&stopped, now).is_some());
+    }
+
+    #[test]
+    fn test_watchdog_notifiers() {
+        let watchdog = watchdog();
+        let now = Instant::now();
+        let event = Event::plain("abc");
+        let failed: Result<bool, Error> = Err(Error::notifier("timeout"));
+        let rejected: Result<bool, Error> = Ok(false);
+        let sent: Result<bool, Error> = Ok(true);
+        let delivered = |notifier, result| Signal::Delivered {
+            notifier,
+            event: &event,
+            result,
+            elapsed: Duration::ZERO,
+        };
+        assert!(watchdog
+            .check(&delivered("dingtalk", &failed), now)
+            .is_none());
+        assert!(watchdog.check(&delivered("http", &rejected), now).is_none());
+        assert!(watchdog
+            .check(&delivered("dingtalk", &failed), now)
+            .is_none());
+        assert!(watchdog.check(&delivered("http", &sent), now).is_none());
+        assert!(watchdog.check(&delivered("http", &rejected), now).is_none());
+        let text = watchdog.check(&delivered("http", &rejected), now).unwrap();
+        assert!(text.contains("所有通知器连续发送失败"));
+        assert!(text.contains("dingtalk: timeout"));
+        assert!(text.contains("http: 未发送"));
+    }
+}
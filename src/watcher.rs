@@ -0,0 +1,372 @@
+use super::chat::record::Record;
+use super::chat::trade::Parser;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use regex::Regex;
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The chat log of one game client, following the newest `chat_xxxxxx.txt` under its `Log` dir.
+pub struct ChatLog {
+    pub name: String,
+    pub log_dir: PathBuf,
+    /// File name pattern of the followed log
+    pattern: Regex,
+    /// Converts the lines of mail and auction logs to chat lines
+    parser: Option<Arc<Parser>>,
+    file: Option<String>,
+    offset: u64,
+    /// Reads a copy of the log when the game keeps it locked
+    shadow: bool,
+    /// Since when the log could not be opened
+    locked: Option<Instant>,
+    /// When to try the locked log again, and how many tries it has had
+    retry: Option<(Instant, usize)>,
+    /// The last record handled, to skip it when read again
+    pub last: Option<Record>,
+}
+
+impl ChatLog {
+    /// Starts at the end of the current chat file, creating the `Log` dir if needed.
+    pub fn new<P: AsRef<Path>>(name: &str, game_dir: P) -> io::Result<Self> {
+        ChatLog::open(name, game_dir, "chat", None)
+    }
+
+    /// Follows the mail or auction log `{prefix}_xxxxxx.txt`, its lines converted by `parser`.
+    pub fn trade<P: AsRef<Path>>(
+        name: &str,
+        game_dir: P,
+        prefix: &str,
+        parser: Arc<Parser>,
+    ) -> io::Result<Self> {
+        ChatLog::open(name, game_dir, prefix, Some(parser))
+    }
+
+    fn open<P: AsRef<Path>>(
+        name: &str,
+        game_dir: P,
+        prefix: &str,
+        parser: Option<Arc<Parser>>,
+    ) -> io::Result<Self> {
+        let log_dir = game_dir.as_ref().join("Log");
+        if !log_dir.exists() {
+            log::info!("Log dir not exists: {}", log_dir.display());
+            fs::create_dir_all(&log_dir)?;
+        }
+        let pattern = Regex::new(&format!(r"^{}_\d{{6}}\.txt$", regex::escape(prefix)))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let file = find_file(&log_dir, |p| is_match(&pattern, p))?;
+        let mut offset = 0_u64;
+        if let Some(f) = &file {
+            log::info!("Chat file found: {f}");
+            // the size is known even while the game holds the file locked
+            offset = fs::metadata(f)?.len();
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            log_dir,
+            pattern,
+            parser,
+            file,
+            offset,
+            shadow: false,
+            locked: None,
+            retry: None,
+            last: None,
+        })
+    }
+
+    /// Reads a copy of the log when the game keeps it locked even after retrying.
+    pub fn with_shadow(mut self, shadow: bool) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        path.parent() == Some(self.log_dir.as_path())
+    }
+
+    /// Handles a modification of `path` in the log dir and returns the new lines.
+    pub fn on_modify(&mut self, path: &Path) -> io::Result<Vec<String>> {
+        // other files in the dir never need a rescan
+        if !is_match(&self.pattern, path) {
+            return Ok(Vec::new());
+        }
+        if self.file.as_ref().is_none_or(|f| path != Path::new(f)) {
+            self.file = find_file(&self.log_dir, |p| is_match(&self.pattern, p))?;
+            log::info!("Chat file changed: {:?}", self.file);
+        }
+
+        if self.retry.is_some() {
+            // the retry already due reads these lines as well
+            return Ok(Vec::new());
+        }
+        self.read_file()
+    }
+
+    /// How long until the locked log is tried again, `None` when it is not locked.
+    pub fn retry_in(&self, now: Instant) -> Option<Duration> {
+        self.retry.map(|(at, _)| at.saturating_duration_since(now))
+    }
+
+    /// Tries the locked log again once it is time, returning the new lines like [`Self::on_modify`].
+    pub fn retry(&mut self, now: Instant) -> Option<io::Result<Vec<String>>> {
+        match self.retry {
+            Some((at, _)) if at <= now => Some(self.read_file()),
+            _ => None,
+        }
+    }
+
+    fn read_file(&mut self) -> io::Result<Vec<String>> {
+        if let Some(f) = &self.file {
+            let tries = self.retry.map_or(0, |(_, n)| n);
+            // the copy only once the retries are used up
+            let shadow = self.shadow && tries == RETRY.len();
+            let (lines, p) = match read(Path::new(f), self.offset, shadow) {
+                Ok(r) => r,
+                Err(e) if is_locked(&e) => {
+                    if self.locked.is_none() {
+                        log::warn!("Chat file of {} locked: {e}", self.name);
+                        self.locked = Some(Instant::now());
+                    }
+                    // the offset is kept, nothing is lost once it can be read again
+                    self.retry = RETRY.get(tries).map(|d| (Instant::now() + *d, tries + 1));
+                    if self.retry.is_some() {
+                        return Ok(Vec::new());
+                    }
+                    return Err(io::Error::new(e.kind(), format!("Chat file locked: {e}")));
+                }
+                Err(e) => {
+                    self.retry = None;
+                    return Err(e);
+                }
+            };
+            self.retry = None;
+            if let Some(t) = self.locked.take() {
+                log::info!(
+                    "Chat file of {} readable again after {}s",
+                    self.name,
+                    t.elapsed().as_secs()
+                );
+            }
+            log::debug!("{} -> {}", self.offset, p);
+            self.offset = p;
+            Ok(match &self.parser {
+                Some(parser) => lines.iter().filter_map(|l| parser.convert(l)).collect(),
+                None => lines,
+            })
+        } else {
+            log::info!("Chat file not found");
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// How long the watch loop waits for events and the watcher polls, longer once the chat has been
+/// idle for a while.
+pub struct Pacer {
+    idle: Option<Duration>,
+    last: Instant,
+}
+
+impl Pacer {
+    pub const FAST: Duration = Duration::from_millis(500);
+    pub const SLOW: Duration = Duration::from_secs(5);
+
+    /// Slows down after `idle` without activity, never when `None`.
+    pub fn new(idle: Option<Duration>) -> Self {
+        Self {
+            idle,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        if self.timeout(now) == Pacer::SLOW {
+            log::debug!("Chat active again");
+        }
+        self.last = now;
+    }
+
+    pub fn timeout(&self, now: Instant) -> Duration {
+        match self.idle {
+            Some(idle) if now.duration_since(self.last) >= idle => Pacer::SLOW,
+            _ => Pacer::FAST,
+        }
+    }
+}
+
+fn is_match(pattern: &Regex, p: &Path) -> bool {
+    pattern.is_match(p.file_name().and_then(|v| v.to_str()).unwrap_or(""))
+}
+
+fn find_file<P, F>(root: P, filter: F) -> io::Result<Option<String>>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path) -> bool,
+{
+    let mut entries: Vec<PathBuf> = fs::read_dir(root)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|e| e.path())
+        .filter(|p| filter(p))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|f| cmp::Reverse(f.file_name().unwrap().to_owned()));
+    if entries.is_empty() {
+        Ok(None)
+    } else {
+        Ok(entries[0].to_str().map(|v| v.to_owned()))
+    }
+}
+
+/// Delays before trying again to open a log the game holds locked, the watch loop carrying on
+/// in between.
+const RETRY: [Duration; 4] = [
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+    Duration::from_millis(400),
+];
+
+/// ERROR_SHARING_VIOLATION, when another process opened the file without sharing it.
+const SHARING_VIOLATION: i32 = 32;
+
+/// Whether opening failed because another process opened the file without sharing it.
+fn is_locked(e: &io::Error) -> bool {
+    cfg!(windows) && e.raw_os_error() == Some(SHARING_VIOLATION)
+}
+
+/// Opens a log, sharing it with the game for reading, writing and deleting alike.
+fn share(path: &Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE
+        options.share_mode(0x1 | 0x2 | 0x4);
+    }
+    options.open(path)
+}
+
+/// Opens a log. With `shadow` a locked log is copied to the temp dir through a shared handle,
+/// held only while copying, and the copy opened instead.
+fn open_log(path: &Path, shadow: bool) -> io::Result<File> {
+    match share(path) {
+        Err(e) if shadow && is_locked(&e) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let copy = std::env::temp_dir().join(format!("cgaid_shadow_{name}"));
+            // the copy may fail for the same reason, the original error tells more
+            let mut from = share(path).map_err(|_| e)?;
+            io::copy(&mut from, &mut File::create(&copy)?)?;
+            File::open(copy)
+        }
+        r => r,
+    }
+}
+
+fn read(path: &Path, offset: u64, shadow: bool) -> io::Result<(Vec<String>, u64)> {
+    // log::info!("Reading file: {path:?}");
+    let mut f = open_log(path, shadow)?;
+    f.seek(io::SeekFrom::Start(offset))?;
+    let reader = io::BufReader::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding_rs::GB18030))
+            .build(&f),
+    );
+
+    let mut lines = Vec::new();
+    for line in reader.lines() {
+        lines.push(line?);
+    }
+    let p = f.stream_position()?;
+    // println!("Read: {offset} -> {p}");
+    Ok((lines, p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_pacer() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(Some(Duration::from_secs(300)));
+        assert_eq!(pacer.timeout(now), Pacer::FAST);
+        let later = now + Duration::from_secs(301);
+        assert_eq!(pacer.timeout(later), Pacer::SLOW);
+        pacer.touch(later);
+        assert_eq!(pacer.timeout(later), Pacer::FAST);
+        let pacer = Pacer::new(None);
+        assert_eq!(pacer.timeout(later), Pacer::FAST);
+    }
+
+    fn gb(text: &str) -> Vec<u8> {
+        encoding_rs::GB18030.encode(text).0.into_owned()
+    }
+
+    #[test]
+    fn test_chat_log() {
+        let game = std::env::temp_dir().join("cgaid_chat_log");
+        let _ = fs::remove_dir_all(&game);
+        let file = game.join("Log").join("chat_240501.txt");
+        let mut log = ChatLog::new("main", &game).unwrap();
+        assert!(log.contains(&file));
+
+        let mut f = File::create(&file).unwrap();
+        f.write_all(&gb("12:00:01丂队伍已经解散了。\r\n")).unwrap();
+        assert_eq!(
+            log.on_modify(&file).unwrap(),
+            vec!["12:00:01丂队伍已经解散了。"]
+        );
+        f.write_all(&gb("12:00:02丂画眉鸟离开了队伍。\r\n"))
+            .unwrap();
+        assert_eq!(
+            log.on_modify(&file).unwrap(),
+            vec!["12:00:02丂画眉鸟离开了队伍。"]
+        );
+        assert!(log.on_modify(&file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open_log() {
+        assert_eq!(
+            is_locked(&io::Error::from_raw_os_error(SHARING_VIOLATION)),
+            cfg!(windows)
+        );
+        // a file the user may not read is not locked
+        assert!(!is_locked(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+        assert!(!is_locked(&io::Error::from(io::ErrorKind::NotFound)));
+        let path = std::env::temp_dir().join("cgaid_open_log_missing.txt");
+        let e = open_log(&path, true).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_trade_log() {
+        let game = std::env::temp_dir().join("cgaid_trade_log");
+        let _ = fs::remove_dir_all(&game);
+        let parser = Arc::new(Parser::new(&crate::config::Trade::default()).unwrap());
+        let mut log = ChatLog::trade("main", &game, "auction", parser).unwrap();
+        let chat = game.join("Log").join("chat_240501.txt");
+        File::create(&chat).unwrap();
+        assert!(log.on_modify(&chat).unwrap().is_empty());
+
+        let file = game.join("Log").join("auction_240501.txt");
+        let mut f = File::create(&file).unwrap();
+        f.write_all(&gb(
+            "12:00:01丂寄售成功\r\n12:00:02丂您寄售的 玄铁 已售出, 获得 300 魔币\r\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            log.on_modify(&file).unwrap(),
+            vec!["12:00:02丂[拍卖]玄铁 已卖出, 价格 300"]
+        );
+    }
+}